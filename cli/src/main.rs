@@ -2,12 +2,16 @@ use clap::{crate_version, App, Arg};
 use solana_sbpf::{
     aligned_memory::AlignedMemory,
     assembler::assemble,
+    cost_analysis::{CostAnalysis, CostModel},
+    dead_code_analysis::DeadCodeAnalysis,
     ebpf,
     elf::Executable,
+    elf_linter::{lint_elf, Severity},
+    loop_analysis::LoopAnalysis,
     memory_region::{MemoryMapping, MemoryRegion},
     program::BuiltinProgram,
     static_analysis::Analysis,
-    verifier::RequisiteVerifier,
+    verifier::{diagnose, RequisiteVerifier},
     vm::{Config, DynamicAnalysis, EbpfVm},
 };
 use std::{fs::File, io::Read, path::Path, sync::Arc};
@@ -60,7 +64,18 @@ fn main() {
                 .short('u')
                 .long("use")
                 .takes_value(true)
-                .possible_values(&["cfg", "debugger", "disassembler", "interpreter", "jit"])
+                .possible_values(&[
+                    "cfg",
+                    "cost",
+                    "dead-code",
+                    "debugger",
+                    "disassembler",
+                    "interpreter",
+                    "jit",
+                    "lint",
+                    "loops",
+                    "verify",
+                ])
                 .required(true),
         )
         .arg(
@@ -89,8 +104,28 @@ fn main() {
     let loader = Arc::new(BuiltinProgram::new_loader(Config {
         enable_register_tracing: matches.is_present("trace") || matches.is_present("profile"),
         enable_symbol_and_section_labels: true,
+        enable_backtrace: true,
         ..Config::default()
     }));
+    if matches.value_of("use") == Some("lint") {
+        let elf_file_name = matches
+            .value_of("elf")
+            .expect("Linting requires an ELF file");
+        let mut file = File::open(Path::new(elf_file_name)).unwrap();
+        let mut elf = Vec::new();
+        file.read_to_end(&mut elf).unwrap();
+        let findings = lint_elf(&elf, loader);
+        for finding in findings.iter() {
+            println!("{finding}");
+        }
+        if findings
+            .iter()
+            .any(|finding| finding.severity == Severity::Error)
+        {
+            std::process::exit(1);
+        }
+        return;
+    }
     #[allow(unused_mut)]
     let mut executable = match matches.value_of("assembler") {
         Some(asm_file_name) => {
@@ -109,6 +144,20 @@ fn main() {
     }
     .unwrap();
 
+    if matches.value_of("use") == Some("verify") {
+        let diagnostics = diagnose(&executable);
+        for diagnostic in diagnostics.iter() {
+            println!("{diagnostic}");
+        }
+        if diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+        {
+            std::process::exit(1);
+        }
+        return;
+    }
+
     executable.verify::<RequisiteVerifier>().unwrap();
 
     let mut mem = match matches.value_of("input").unwrap().parse::<usize>() {
@@ -168,7 +217,10 @@ fn main() {
     );
 
     let analysis = if matches.value_of("use") == Some("cfg")
+        || matches.value_of("use") == Some("cost")
+        || matches.value_of("use") == Some("dead-code")
         || matches.value_of("use") == Some("disassembler")
+        || matches.value_of("use") == Some("loops")
         || matches.is_present("trace")
         || matches.is_present("profile")
     {
@@ -186,6 +238,23 @@ fn main() {
                 .unwrap();
             return;
         }
+        Some("cost") => {
+            let cost_analysis =
+                CostAnalysis::new(analysis.as_ref().unwrap(), &CostModel::default());
+            for function in cost_analysis.functions.values() {
+                println!("{}: {}", function.name, function.cost);
+                println!("    hot path: {:?}", function.hot_path);
+            }
+            return;
+        }
+        Some("dead-code") => {
+            print!("{}", DeadCodeAnalysis::new(analysis.as_ref().unwrap()));
+            return;
+        }
+        Some("loops") => {
+            print!("{}", LoopAnalysis::new(analysis.as_ref().unwrap()));
+            return;
+        }
         Some("disassembler") => {
             let stdout = std::io::stdout();
             analysis
@@ -198,9 +267,17 @@ fn main() {
         _ => {}
     }
 
-    let (instruction_count, result) =
-        vm.execute_program(&executable, matches.value_of("use").unwrap() != "jit");
+    let (instruction_count, result, backtrace) =
+        vm.execute_program_with_backtrace(&executable, matches.value_of("use").unwrap() != "jit");
     println!("Result: {result:?}");
+    if result.is_err() {
+        if let Some(location) = vm.get_error_location(&executable) {
+            println!("Error Location: {location}");
+        }
+    }
+    if let Some(backtrace) = backtrace {
+        print!("Backtrace:\n{backtrace}");
+    }
     println!("Instruction Count: {instruction_count}");
     if matches.is_present("trace") {
         println!("Trace:\n");
@@ -219,5 +296,11 @@ fn main() {
             .unwrap()
             .visualize_graphically(&mut file, Some(&dynamic_analysis))
             .unwrap();
+        if !dynamic_analysis.source_lines.is_empty() {
+            println!("Profile:\n");
+            for ((file, line), count) in dynamic_analysis.source_lines.iter() {
+                println!("{count:8} {file}:{line}");
+            }
+        }
     }
 }
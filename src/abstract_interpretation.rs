@@ -0,0 +1,941 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Abstract interpretation of register types and value ranges
+//!
+//! Propagates an [AbstractState] through the control-flow graph of an [Analysis] until a fixpoint
+//! is reached and then checks every reachable instruction against the states flowing into it.
+
+use crate::{
+    ebpf, program::SBPFVersion, static_analysis::Analysis, verifier::VerifierError, vm::Config,
+};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+/// How often a basic block is revisited before its intervals are widened to guarantee termination
+const WIDENING_THRESHOLD: usize = 4;
+
+/// Stands in for operations whose result range is not tracked, such as the high half of products
+const OPAQUE_OPERATION: u8 = 0xff;
+
+/// Inclusive range of integers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Interval {
+    /// Lower bound
+    pub min: i128,
+    /// Upper bound
+    pub max: i128,
+}
+
+impl Interval {
+    /// All values a register can hold, interpreted as unsigned
+    pub const SCALAR: Self = Self::new(0, u64::MAX as i128);
+    /// All offsets a pointer can have relative to its region
+    pub const OFFSET: Self = Self::new(i64::MIN as i128, i64::MAX as i128);
+
+    /// Creates an interval from its bounds
+    pub const fn new(min: i128, max: i128) -> Self {
+        Self { min, max }
+    }
+
+    /// Creates an interval containing only the given value
+    pub const fn constant(value: i128) -> Self {
+        Self::new(value, value)
+    }
+
+    /// Returns the value if the interval contains exactly one
+    pub fn as_constant(&self) -> Option<i128> {
+        (self.min == self.max).then_some(self.min)
+    }
+
+    /// Returns true if the value is inside of the interval
+    pub fn contains(&self, value: i128) -> bool {
+        self.min <= value && value <= self.max
+    }
+
+    fn unsigned(bits: u32) -> Self {
+        Self::new(0, (1i128 << bits) - 1)
+    }
+
+    fn union(self, other: Self) -> Self {
+        Self::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    fn intersect(self, other: Self) -> Option<Self> {
+        let result = Self::new(self.min.max(other.min), self.max.min(other.max));
+        (result.min <= result.max).then_some(result)
+    }
+
+    /// Moves every bound which grew from `self` to `other` to the end of the domain
+    fn widen(self, other: Self, domain: Self) -> Self {
+        Self::new(
+            if other.min < self.min {
+                domain.min
+            } else {
+                self.min
+            },
+            if other.max > self.max {
+                domain.max
+            } else {
+                self.max
+            },
+        )
+    }
+
+    /// Wraps around like unsigned integer arithmetic of the given width
+    pub fn wrap(min: i128, max: i128, bits: u32) -> Self {
+        let modulus = 1i128 << bits;
+        if max - min >= modulus {
+            return Self::unsigned(bits);
+        }
+        let base = min.div_euclid(modulus) * modulus;
+        if max - base < modulus {
+            Self::new(min - base, max - base)
+        } else {
+            Self::unsigned(bits)
+        }
+    }
+
+    /// Reinterprets an unsigned 64 bit interval as signed
+    fn to_signed(self) -> Self {
+        if self.max <= i64::MAX as i128 {
+            self
+        } else if self.min > i64::MAX as i128 {
+            Self::new(self.min - (1i128 << 64), self.max - (1i128 << 64))
+        } else {
+            Self::OFFSET
+        }
+    }
+
+    /// Clamps a pointer offset to the domain of offsets
+    fn to_offset(self) -> Self {
+        if Self::OFFSET.contains(self.min) && Self::OFFSET.contains(self.max) {
+            self
+        } else {
+            Self::OFFSET
+        }
+    }
+}
+
+/// Memory region a pointer refers to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PointerRegion {
+    /// The stack, offsets are relative to the frame pointer at the start of the function
+    Stack,
+    /// The input regions, offsets are relative to the start of the first one
+    Input,
+}
+
+/// Abstract value of a register
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegisterType {
+    /// Not written on at least one path
+    Uninitialized,
+    /// Number in the given unsigned range
+    Scalar(Interval),
+    /// Address in a known memory region with the given range of offsets
+    Pointer(PointerRegion, Interval),
+    /// Written, but nothing else is known
+    Unknown,
+}
+
+impl RegisterType {
+    fn join(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Uninitialized, _) | (_, Self::Uninitialized) => Self::Uninitialized,
+            (Self::Scalar(a), Self::Scalar(b)) => Self::Scalar(a.union(b)),
+            (Self::Pointer(region_a, a), Self::Pointer(region_b, b)) if region_a == region_b => {
+                Self::Pointer(region_a, a.union(b))
+            }
+            _ => Self::Unknown,
+        }
+    }
+
+    fn widen(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Scalar(a), Self::Scalar(b)) => Self::Scalar(a.widen(b, Interval::SCALAR)),
+            (Self::Pointer(region_a, a), Self::Pointer(region_b, b)) if region_a == region_b => {
+                Self::Pointer(region_a, a.widen(b, Interval::OFFSET))
+            }
+            _ => other,
+        }
+    }
+}
+
+/// Abstract values of all registers at one point of the program
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AbstractState {
+    /// r0 to r10
+    pub registers: [RegisterType; 11],
+}
+
+impl AbstractState {
+    fn join(&self, other: &Self) -> Self {
+        let mut result = self.clone();
+        for (register, other) in result.registers.iter_mut().zip(other.registers.iter()) {
+            *register = register.join(*other);
+        }
+        result
+    }
+
+    fn widen(&self, other: &Self) -> Self {
+        let mut result = self.clone();
+        for (register, other) in result.registers.iter_mut().zip(other.registers.iter()) {
+            *register = register.widen(*other);
+        }
+        result
+    }
+}
+
+/// Result of the abstract interpretation
+#[derive(Debug)]
+pub struct AbstractInterpretation {
+    /// State at the start of every reachable basic block, keyed by its first pc
+    pub block_states: BTreeMap<usize, AbstractState>,
+    /// Violations of the reachable instructions in the order of their pc
+    pub violations: Vec<VerifierError>,
+}
+
+impl AbstractInterpretation {
+    /// Interprets all functions of the analyzed executable
+    pub fn run(analysis: &Analysis) -> Self {
+        let interpreter = Interpreter::new(analysis);
+        let block_states = interpreter.fixpoint();
+        let mut violations = Vec::new();
+        for (start, state) in block_states.iter() {
+            interpreter.interpret_block(*start, state.clone(), &mut Some(&mut violations));
+        }
+        Self {
+            block_states,
+            violations,
+        }
+    }
+
+    /// Calls `visit` with every reachable instruction and the state right before it
+    ///
+    /// The `analysis` has to be the one this was run on.
+    pub fn replay(&self, analysis: &Analysis, mut visit: impl FnMut(&ebpf::Insn, &AbstractState)) {
+        let interpreter = Interpreter::new(analysis);
+        for (start, state) in self.block_states.iter() {
+            let mut state = state.clone();
+            for insn in &analysis.instructions[analysis.cfg_nodes[start].instructions.clone()] {
+                visit(insn, &state);
+                interpreter.step(&mut state, insn, &mut None);
+            }
+        }
+    }
+}
+
+/// Memory operand of a load, store or atomic instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+    /// Register holding the base address, the offset is in the instruction
+    pub base: u8,
+    /// Number of bytes accessed
+    pub len: u64,
+    /// Reads memory into dst, otherwise the access writes to memory
+    pub is_load: bool,
+    /// Sign extends the loaded value
+    pub sign_extend: bool,
+}
+
+impl MemoryAccess {
+    /// Decodes the memory operand of an instruction, if it has one
+    pub fn decode(insn: &ebpf::Insn, sbpf_version: SBPFVersion) -> Option<Self> {
+        let (len, is_load, sign_extend) = if sbpf_version.move_memory_instruction_classes() {
+            match insn.opc {
+                ebpf::LD_1B_REG => (1, true, false),
+                ebpf::LD_2B_REG => (2, true, false),
+                ebpf::LD_4B_REG => (4, true, false),
+                ebpf::LD_8B_REG => (8, true, false),
+                ebpf::ST_1B_IMM | ebpf::ST_1B_REG => (1, false, false),
+                ebpf::ST_2B_IMM | ebpf::ST_2B_REG => (2, false, false),
+                ebpf::ST_4B_IMM | ebpf::ST_4B_REG => (4, false, false),
+                ebpf::ST_8B_IMM | ebpf::ST_8B_REG => (8, false, false),
+                _ => return None,
+            }
+        } else {
+            match insn.opc {
+                ebpf::LD_B_REG => (1, true, false),
+                ebpf::LD_H_REG => (2, true, false),
+                ebpf::LD_W_REG => (4, true, false),
+                ebpf::LD_DW_REG => (8, true, false),
+                ebpf::LD_B_SX_REG if sbpf_version.enable_ldsx() => (1, true, true),
+                ebpf::LD_H_SX_REG if sbpf_version.enable_ldsx() => (2, true, true),
+                ebpf::LD_W_SX_REG if sbpf_version.enable_ldsx() => (4, true, true),
+                ebpf::ST_B_IMM | ebpf::ST_B_REG => (1, false, false),
+                ebpf::ST_H_IMM | ebpf::ST_H_REG => (2, false, false),
+                ebpf::ST_W_IMM | ebpf::ST_W_REG => (4, false, false),
+                ebpf::ST_DW_IMM | ebpf::ST_DW_REG => (8, false, false),
+                ebpf::ST_W_ATOMIC => (4, false, false),
+                ebpf::ST_DW_ATOMIC => (8, false, false),
+                _ => return None,
+            }
+        };
+        Some(Self {
+            base: if is_load { insn.src } else { insn.dst },
+            len,
+            is_load,
+            sign_extend,
+        })
+    }
+}
+
+struct Interpreter<'a> {
+    analysis: &'a Analysis<'a>,
+    config: &'a Config,
+    sbpf_version: SBPFVersion,
+    function_starts: BTreeSet<usize>,
+    function_keys: BTreeSet<u32>,
+}
+
+impl<'a> Interpreter<'a> {
+    fn new(analysis: &'a Analysis<'a>) -> Self {
+        let executable = analysis.executable;
+        Self {
+            analysis,
+            config: executable.get_config(),
+            sbpf_version: executable.get_sbpf_version(),
+            function_starts: analysis.callable_functions(),
+            function_keys: executable.get_function_registry().keys().collect(),
+        }
+    }
+
+    fn function_start(&self, pc: usize) -> Option<usize> {
+        self.function_starts.range(..=pc).next_back().copied()
+    }
+
+    fn initial_state(&self, function_start: usize) -> AbstractState {
+        let mut registers = [RegisterType::Uninitialized; 11];
+        if function_start == self.analysis.entrypoint {
+            registers[1] = RegisterType::Pointer(PointerRegion::Input, Interval::constant(0));
+        } else {
+            for register in registers.iter_mut().skip(1).take(5) {
+                *register = RegisterType::Unknown;
+            }
+        }
+        registers[ebpf::FRAME_PTR_REG] =
+            RegisterType::Pointer(PointerRegion::Stack, Interval::constant(0));
+        AbstractState { registers }
+    }
+
+    fn fixpoint(&self) -> BTreeMap<usize, AbstractState> {
+        let mut block_states = BTreeMap::new();
+        let mut visits = BTreeMap::<usize, usize>::new();
+        let mut worklist = VecDeque::new();
+        for function_start in self.function_starts.iter() {
+            block_states.insert(*function_start, self.initial_state(*function_start));
+            worklist.push_back(*function_start);
+        }
+        while let Some(start) = worklist.pop_front() {
+            let state = block_states[&start].clone();
+            for (destination, incoming) in self.interpret_block(start, state, &mut None) {
+                if !self.analysis.cfg_nodes.contains_key(&destination) {
+                    continue;
+                }
+                let merged = match block_states.get(&destination) {
+                    None => incoming,
+                    Some(previous) => {
+                        let joined = previous.join(&incoming);
+                        if joined == *previous {
+                            continue;
+                        }
+                        let visit_count = visits.entry(destination).or_default();
+                        *visit_count += 1;
+                        if *visit_count > WIDENING_THRESHOLD {
+                            previous.widen(&joined)
+                        } else {
+                            joined
+                        }
+                    }
+                };
+                block_states.insert(destination, merged);
+                if !worklist.contains(&destination) {
+                    worklist.push_back(destination);
+                }
+            }
+        }
+        block_states
+    }
+
+    /// Applies all instructions of a basic block and returns the states flowing into its successors
+    fn interpret_block(
+        &self,
+        start: usize,
+        mut state: AbstractState,
+        violations: &mut Option<&mut Vec<VerifierError>>,
+    ) -> Vec<(usize, AbstractState)> {
+        let cfg_node = &self.analysis.cfg_nodes[&start];
+        if cfg_node.instructions.is_empty() {
+            return Vec::new();
+        }
+        for insn in &self.analysis.instructions[cfg_node.instructions.clone()] {
+            self.step(&mut state, insn, violations);
+        }
+        let insn = &self.analysis.instructions[cfg_node.instructions.end - 1];
+        let next_pc = self
+            .analysis
+            .instructions
+            .get(cfg_node.instructions.end)
+            .map(|insn| insn.ptr);
+        let target_pc = (insn.ptr as isize + insn.off as isize + 1) as usize;
+        match insn.opc {
+            ebpf::EXIT => Vec::new(),
+            ebpf::JA => vec![(target_pc, state)],
+            _ if self.is_conditional_jump(insn) => {
+                let mut successors = Vec::new();
+                if let Some(next_pc) = next_pc {
+                    if let Some(refined) = self.refine(&state, insn, false) {
+                        successors.push((next_pc, refined));
+                    }
+                }
+                if let Some(refined) = self.refine(&state, insn, true) {
+                    successors.push((target_pc, refined));
+                }
+                successors
+            }
+            _ => next_pc
+                .map(|next_pc| (next_pc, state))
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    fn is_conditional_jump(&self, insn: &ebpf::Insn) -> bool {
+        let class = insn.opc & ebpf::BPF_CLS_MASK;
+        (class == ebpf::BPF_JMP64 || (class == ebpf::BPF_JMP32 && self.sbpf_version.enable_jmp32()))
+            && !matches!(
+                insn.opc & ebpf::BPF_ALU_OP_MASK,
+                ebpf::BPF_JA | ebpf::BPF_CALL | ebpf::BPF_EXIT
+            )
+    }
+
+    /// Narrows the state to the values for which the branch is (not) taken
+    ///
+    /// Returns [None] if the branch can not be (not) taken.
+    fn refine(
+        &self,
+        state: &AbstractState,
+        insn: &ebpf::Insn,
+        taken: bool,
+    ) -> Option<AbstractState> {
+        // Comparing against a number is treated as evidence that the register holds one
+        let range = match state.registers[insn.dst as usize] {
+            RegisterType::Scalar(range) => range,
+            RegisterType::Unknown => Interval::SCALAR,
+            _ => return Some(state.clone()),
+        };
+        let bits = if insn.opc & ebpf::BPF_CLS_MASK == ebpf::BPF_JMP64 {
+            64
+        } else {
+            32
+        };
+        let constant = if insn.opc & ebpf::BPF_X != 0 {
+            match state.registers[insn.src as usize] {
+                RegisterType::Scalar(src) => src.as_constant(),
+                _ => None,
+            }
+        } else {
+            Some(Interval::wrap(insn.imm as i128, insn.imm as i128, bits).min)
+        };
+        let all = Interval::unsigned(bits);
+        let (Some(constant), true) = (constant, range.max <= all.max) else {
+            return Some(state.clone());
+        };
+        let positive = Interval::unsigned(bits - 1);
+        let mut operation = insn.opc & ebpf::BPF_ALU_OP_MASK;
+        if matches!(
+            operation,
+            ebpf::BPF_JSGT | ebpf::BPF_JSGE | ebpf::BPF_JSLT | ebpf::BPF_JSLE
+        ) {
+            // Signed comparisons only behave like unsigned ones for non-negative values
+            if range.max > positive.max || constant > positive.max {
+                return Some(state.clone());
+            }
+            operation = match operation {
+                ebpf::BPF_JSGT => ebpf::BPF_JGT,
+                ebpf::BPF_JSGE => ebpf::BPF_JGE,
+                ebpf::BPF_JSLT => ebpf::BPF_JLT,
+                _ => ebpf::BPF_JLE,
+            };
+        }
+        let (operation, taken) = match (operation, taken) {
+            (ebpf::BPF_JNE, taken) => (ebpf::BPF_JEQ, !taken),
+            (ebpf::BPF_JGT, false) => (ebpf::BPF_JLE, true),
+            (ebpf::BPF_JGE, false) => (ebpf::BPF_JLT, true),
+            (ebpf::BPF_JLT, false) => (ebpf::BPF_JGE, true),
+            (ebpf::BPF_JLE, false) => (ebpf::BPF_JGT, true),
+            other => other,
+        };
+        let refined = match (operation, taken) {
+            (ebpf::BPF_JEQ, true) => range.intersect(Interval::constant(constant)),
+            (ebpf::BPF_JEQ, false) => {
+                if range.as_constant() == Some(constant) {
+                    None
+                } else if range.min == constant {
+                    Some(Interval::new(range.min + 1, range.max))
+                } else if range.max == constant {
+                    Some(Interval::new(range.min, range.max - 1))
+                } else {
+                    Some(range)
+                }
+            }
+            (ebpf::BPF_JGT, _) => range.intersect(Interval::new(constant + 1, all.max)),
+            (ebpf::BPF_JGE, _) => range.intersect(Interval::new(constant, all.max)),
+            (ebpf::BPF_JLT, _) => range.intersect(Interval::new(0, constant - 1)),
+            (ebpf::BPF_JLE, _) => range.intersect(Interval::new(0, constant)),
+            _ => Some(range),
+        }?;
+        let mut state = state.clone();
+        state.registers[insn.dst as usize] = RegisterType::Scalar(refined);
+        Some(state)
+    }
+
+    fn read(
+        &self,
+        state: &AbstractState,
+        register: u8,
+        pc: usize,
+        violations: &mut Option<&mut Vec<VerifierError>>,
+    ) -> RegisterType {
+        match state.registers.get(register as usize) {
+            Some(RegisterType::Uninitialized) => {
+                if let Some(violations) = violations {
+                    violations.push(VerifierError::UninitializedRegister(pc, register));
+                }
+                RegisterType::Unknown
+            }
+            Some(value) => *value,
+            None => RegisterType::Unknown,
+        }
+    }
+
+    fn write(state: &mut AbstractState, register: u8, value: RegisterType) {
+        if let Some(slot) = state.registers.get_mut(register as usize) {
+            *slot = value;
+        }
+    }
+
+    /// Valid offsets relative to the frame pointer at the start of the function
+    fn stack_bounds(&self, pc: usize) -> Interval {
+        if !self.sbpf_version.manual_stack_frame_bump() {
+            // Anything beyond the own frame belongs to callers or is clobbered by the next call
+            return Interval::new(-(self.config.stack_frame_size as i128), 0);
+        }
+        let stack_size = self.config.stack_size() as i128;
+        if self.function_start(pc) == Some(self.analysis.entrypoint) {
+            Interval::new(-stack_size, 0)
+        } else {
+            // The depth of the caller is unknown, so only the size of the entire stack is a bound
+            Interval::new(-stack_size, stack_size)
+        }
+    }
+
+    fn check_memory_access(
+        &self,
+        base: RegisterType,
+        insn: &ebpf::Insn,
+        len: i128,
+        violations: &mut Option<&mut Vec<VerifierError>>,
+    ) {
+        let Some(violations) = violations else {
+            return;
+        };
+        let (region, offset, bounds) = match base {
+            RegisterType::Pointer(PointerRegion::Stack, offset) => {
+                ("stack", offset, self.stack_bounds(insn.ptr))
+            }
+            RegisterType::Pointer(PointerRegion::Input, offset) => {
+                let number_of_input_regions =
+                    self.config.address_space_layout.number_of_input_regions as i128;
+                (
+                    "input",
+                    offset,
+                    Interval::new(0, number_of_input_regions * ebpf::MM_REGION_SIZE as i128),
+                )
+            }
+            RegisterType::Scalar(address) => (
+                "unmapped memory",
+                address,
+                Interval::new(0, self.config.address_space_layout.end() as i128),
+            ),
+            _ => return,
+        };
+        // The access is [address, address + len) and has to be inside of [bounds.min, bounds.max)
+        let first = offset.min + insn.off as i128;
+        let last = offset.max + insn.off as i128;
+        if last < bounds.min || first + len > bounds.max {
+            violations.push(VerifierError::OutOfBoundsAccess(insn.ptr, region));
+        }
+    }
+
+    /// Value of a register after loading `len` bytes from memory into it
+    fn loaded_value(len: i128, sign_extend: bool) -> RegisterType {
+        if len == 8 {
+            // Could be a spilled pointer
+            RegisterType::Unknown
+        } else if sign_extend {
+            RegisterType::Scalar(Interval::SCALAR)
+        } else {
+            RegisterType::Scalar(Interval::unsigned(len as u32 * 8))
+        }
+    }
+
+    /// Applies one instruction to the state, reporting violations if requested
+    fn step(
+        &self,
+        state: &mut AbstractState,
+        insn: &ebpf::Insn,
+        violations: &mut Option<&mut Vec<VerifierError>>,
+    ) {
+        let sbpf_version = self.sbpf_version;
+        let pc = insn.ptr;
+        if insn.opc == ebpf::LD_DW_IMM && !sbpf_version.disable_lddw() {
+            let value = RegisterType::Scalar(Interval::constant(insn.imm as u64 as i128));
+            Self::write(state, insn.dst, value);
+            return;
+        }
+        if let Some(access) = MemoryAccess::decode(insn, sbpf_version) {
+            let len = access.len as i128;
+            let base = self.read(state, access.base, pc, violations);
+            if access.is_load {
+                self.check_memory_access(base, insn, len, violations);
+                Self::write(state, insn.dst, Self::loaded_value(len, access.sign_extend));
+                return;
+            }
+            if insn.opc & ebpf::BPF_X != 0 || insn.opc & ebpf::BPF_CLS_MASK == ebpf::BPF_STX {
+                self.read(state, insn.src, pc, violations);
+            }
+            self.check_memory_access(base, insn, len, violations);
+            if insn.opc == ebpf::ST_W_ATOMIC || insn.opc == ebpf::ST_DW_ATOMIC {
+                match insn.imm {
+                    ebpf::ATOMIC_FETCH_ADD | ebpf::ATOMIC_XCHG => {
+                        Self::write(state, insn.src, Self::loaded_value(len, false));
+                    }
+                    ebpf::ATOMIC_CMPXCHG => {
+                        self.read(state, 0, pc, violations);
+                        Self::write(state, 0, Self::loaded_value(len, false));
+                    }
+                    _ => {}
+                }
+            }
+            return;
+        }
+        let class = insn.opc & ebpf::BPF_CLS_MASK;
+        if class == ebpf::BPF_JMP64 || (class == ebpf::BPF_JMP32 && sbpf_version.enable_jmp32()) {
+            self.step_jump(state, insn, violations);
+            return;
+        }
+        match class {
+            ebpf::BPF_ALU32_LOAD => self.step_alu(state, insn, 32, violations),
+            ebpf::BPF_ALU64_STORE => self.step_alu(state, insn, 64, violations),
+            ebpf::BPF_PQR if sbpf_version.enable_pqr() => {
+                let bits = if insn.opc & ebpf::BPF_B != 0 { 64 } else { 32 };
+                self.step_alu(state, insn, bits, violations)
+            }
+            _ => {}
+        }
+    }
+
+    fn step_jump(
+        &self,
+        state: &mut AbstractState,
+        insn: &ebpf::Insn,
+        violations: &mut Option<&mut Vec<VerifierError>>,
+    ) {
+        let pc = insn.ptr;
+        match insn.opc {
+            ebpf::JA => {}
+            ebpf::CALL_IMM => {
+                let internal = if self.sbpf_version.static_syscalls() {
+                    insn.src == 1
+                } else {
+                    self.function_keys.contains(&(insn.imm as u32))
+                };
+                if internal {
+                    for register in 0..=5 {
+                        Self::write(state, register, RegisterType::Unknown);
+                    }
+                } else {
+                    Self::write(state, 0, RegisterType::Scalar(Interval::SCALAR));
+                }
+            }
+            ebpf::CALL_REG => {
+                let target = if self.sbpf_version.callx_uses_src_reg() {
+                    insn.src
+                } else if self.sbpf_version.callx_uses_dst_reg() {
+                    insn.dst
+                } else {
+                    insn.imm as u8
+                };
+                self.read(state, target, pc, violations);
+                for register in 0..=5 {
+                    Self::write(state, register, RegisterType::Unknown);
+                }
+            }
+            ebpf::EXIT => {
+                if self.function_start(pc) == Some(self.analysis.entrypoint) {
+                    self.read(state, 0, pc, violations);
+                }
+            }
+            _ => {
+                self.read(state, insn.dst, pc, violations);
+                if insn.opc & ebpf::BPF_X != 0 {
+                    self.read(state, insn.src, pc, violations);
+                }
+            }
+        }
+    }
+
+    fn step_alu(
+        &self,
+        state: &mut AbstractState,
+        insn: &ebpf::Insn,
+        bits: u32,
+        violations: &mut Option<&mut Vec<VerifierError>>,
+    ) {
+        let sbpf_version = self.sbpf_version;
+        let pc = insn.ptr;
+        let is_pqr = insn.opc & ebpf::BPF_CLS_MASK == ebpf::BPF_PQR;
+        let operation = insn.opc & ebpf::BPF_ALU_OP_MASK;
+        let all = Interval::unsigned(bits);
+
+        // Unary operations
+        if insn.opc == ebpf::BSWAP && sbpf_version.enable_bswap()
+            || (!is_pqr && operation == ebpf::BPF_END && bits == 32)
+        {
+            let value = self.read(state, insn.dst, pc, violations);
+            let size = insn.imm.clamp(16, 64) as u32;
+            let result = match value {
+                RegisterType::Scalar(range)
+                    if insn.opc == ebpf::LE && range.max < 1i128 << size =>
+                {
+                    range
+                }
+                _ => Interval::unsigned(size),
+            };
+            Self::write(state, insn.dst, RegisterType::Scalar(result));
+            return;
+        }
+        if !is_pqr && operation == ebpf::BPF_NEG {
+            let result = match self.read(state, insn.dst, pc, violations) {
+                RegisterType::Scalar(range) => Interval::wrap(-range.max, -range.min, 64),
+                _ => Interval::SCALAR,
+            };
+            let result = if bits == 32 {
+                Interval::wrap(result.min, result.max, 32)
+            } else {
+                result
+            };
+            Self::write(state, insn.dst, RegisterType::Scalar(result));
+            return;
+        }
+        if insn.opc == ebpf::HOR64_IMM && sbpf_version.disable_lddw() {
+            let result = match self.read(state, insn.dst, pc, violations) {
+                RegisterType::Scalar(range) if range.max <= u32::MAX as i128 => {
+                    let upper = (insn.imm as u32 as i128) << 32;
+                    Interval::new(range.min + upper, range.max + upper)
+                }
+                _ => Interval::SCALAR,
+            };
+            Self::write(state, insn.dst, RegisterType::Scalar(result));
+            return;
+        }
+
+        // Binary operations
+        let src = if insn.opc & ebpf::BPF_X != 0 {
+            self.read(state, insn.src, pc, violations)
+        } else if bits == 32 {
+            RegisterType::Scalar(Interval::constant(insn.imm as u32 as i128))
+        } else {
+            RegisterType::Scalar(Interval::constant(insn.imm as u64 as i128))
+        };
+        let dst = if !is_pqr && operation == ebpf::BPF_MOV {
+            RegisterType::Unknown
+        } else {
+            self.read(state, insn.dst, pc, violations)
+        };
+        let (operation, signed) = if is_pqr {
+            match operation {
+                ebpf::BPF_LMUL => (ebpf::BPF_MUL, false),
+                ebpf::BPF_UHMUL => (OPAQUE_OPERATION, false),
+                ebpf::BPF_UDIV => (ebpf::BPF_DIV, false),
+                ebpf::BPF_UREM => (ebpf::BPF_MOD, false),
+                ebpf::BPF_SDIV => (ebpf::BPF_DIV, true),
+                ebpf::BPF_SREM => (ebpf::BPF_MOD, true),
+                _ => (OPAQUE_OPERATION, false),
+            }
+        } else {
+            let signed_division = sbpf_version.enable_signed_division() && insn.off == 1;
+            (operation, signed_division)
+        };
+        if matches!(operation, ebpf::BPF_DIV | ebpf::BPF_MOD)
+            && insn.opc & ebpf::BPF_X != 0
+            && !sbpf_version.non_trapping_division()
+        {
+            let divisor_may_be_zero = match src {
+                RegisterType::Scalar(range) => Interval::wrap(range.min, range.max, bits).min == 0,
+                _ => true,
+            };
+            if let (true, Some(violations)) = (divisor_may_be_zero, violations.as_mut()) {
+                violations.push(VerifierError::DivisorMayBeZero(pc));
+            }
+        }
+
+        let sign_extending_move = !is_pqr
+            && operation == ebpf::BPF_MOV
+            && insn.opc & ebpf::BPF_X != 0
+            && sbpf_version.enable_movsx()
+            && insn.off != 0;
+
+        // Moves and pointer arithmetic
+        if bits == 64 && !is_pqr {
+            let swapped_operands =
+                insn.opc == ebpf::SUB64_IMM && sbpf_version.swap_sub_reg_imm_operands();
+            let pointer_result = match (operation, dst, src) {
+                (ebpf::BPF_MOV, _, RegisterType::Scalar(range)) if sign_extending_move => {
+                    let bits = insn.off as u32;
+                    Some(RegisterType::Scalar(if range.max < 1i128 << (bits - 1) {
+                        range
+                    } else {
+                        Interval::SCALAR
+                    }))
+                }
+                (ebpf::BPF_MOV, _, src) if !sign_extending_move => Some(src),
+                (
+                    ebpf::BPF_ADD,
+                    RegisterType::Pointer(region, offset),
+                    RegisterType::Scalar(range),
+                )
+                | (
+                    ebpf::BPF_ADD,
+                    RegisterType::Scalar(range),
+                    RegisterType::Pointer(region, offset),
+                ) => {
+                    let range = range.to_signed();
+                    Some(RegisterType::Pointer(
+                        region,
+                        Interval::new(offset.min + range.min, offset.max + range.max).to_offset(),
+                    ))
+                }
+                (
+                    ebpf::BPF_SUB,
+                    RegisterType::Pointer(region, offset),
+                    RegisterType::Scalar(range),
+                ) if !swapped_operands => {
+                    let range = range.to_signed();
+                    Some(RegisterType::Pointer(
+                        region,
+                        Interval::new(offset.min - range.max, offset.max - range.min).to_offset(),
+                    ))
+                }
+                (
+                    ebpf::BPF_SUB,
+                    RegisterType::Pointer(region_a, a),
+                    RegisterType::Pointer(region_b, b),
+                ) if region_a == region_b => Some(RegisterType::Scalar(Interval::wrap(
+                    a.min - b.max,
+                    a.max - b.min,
+                    64,
+                ))),
+                (_, RegisterType::Scalar(_), RegisterType::Scalar(_)) => None,
+                _ => Some(RegisterType::Unknown),
+            };
+            if let Some(result) = pointer_result {
+                Self::write(state, insn.dst, result);
+                return;
+            }
+        }
+
+        // Scalar arithmetic
+        let as_scalar = |value: RegisterType| match value {
+            RegisterType::Scalar(range) => Interval::wrap(range.min, range.max, bits),
+            _ => all,
+        };
+        let (dst_range, src_range) = if insn.opc == ebpf::SUB32_IMM || insn.opc == ebpf::SUB64_IMM {
+            if sbpf_version.swap_sub_reg_imm_operands() {
+                (as_scalar(src), as_scalar(dst))
+            } else {
+                (as_scalar(dst), as_scalar(src))
+            }
+        } else {
+            (as_scalar(dst), as_scalar(src))
+        };
+        let result = if sign_extending_move {
+            let from_bits = insn.off as u32;
+            if src_range.max < 1i128 << (from_bits - 1) {
+                src_range
+            } else {
+                all
+            }
+        } else {
+            Self::scalar_operation(operation, signed, dst_range, src_range, bits)
+        };
+        let sign_extend = bits == 32
+            && if is_pqr {
+                false
+            } else if matches!(operation, ebpf::BPF_ADD | ebpf::BPF_SUB | ebpf::BPF_MUL) {
+                sbpf_version.implicit_sign_extension_of_results()
+            } else {
+                insn.opc == ebpf::MOV32_REG
+                    && sbpf_version.explicit_sign_extension_of_results()
+                    && insn.off == 0
+            };
+        let result = if !sign_extend || result.max <= i32::MAX as i128 {
+            result
+        } else if result.min > i32::MAX as i128 {
+            let extension = (1i128 << 64) - (1i128 << 32);
+            Interval::new(result.min + extension, result.max + extension)
+        } else {
+            Interval::SCALAR
+        };
+        Self::write(state, insn.dst, RegisterType::Scalar(result));
+    }
+
+    /// Range of `dst op src` for unsigned operands of the given width
+    fn scalar_operation(
+        operation: u8,
+        signed: bool,
+        dst: Interval,
+        src: Interval,
+        bits: u32,
+    ) -> Interval {
+        let all = Interval::unsigned(bits);
+        let positive = Interval::unsigned(bits - 1);
+        if signed && (dst.max > positive.max || src.max > positive.max) {
+            // Signed operations only behave like unsigned ones for non-negative values
+            return all;
+        }
+        let shift = src.as_constant().map(|shift| (shift % bits as i128) as u32);
+        match operation {
+            ebpf::BPF_ADD => Interval::wrap(dst.min + src.min, dst.max + src.max, bits),
+            ebpf::BPF_SUB => Interval::wrap(dst.min - src.max, dst.max - src.min, bits),
+            ebpf::BPF_MUL => match dst.max.checked_mul(src.max) {
+                Some(max) if max <= all.max => Interval::new(dst.min * src.min, max),
+                _ => all,
+            },
+            ebpf::BPF_DIV if src.min > 0 => Interval::new(dst.min / src.max, dst.max / src.min),
+            ebpf::BPF_DIV => Interval::new(0, dst.max),
+            ebpf::BPF_MOD if src.min > 0 => Interval::new(0, dst.max.min(src.max - 1)),
+            ebpf::BPF_MOD => Interval::new(0, dst.max),
+            ebpf::BPF_AND => Interval::new(0, dst.max.min(src.max)),
+            ebpf::BPF_OR | ebpf::BPF_XOR => {
+                let highest = dst.max.max(src.max) as u128;
+                Interval::new(0, (highest + 1).next_power_of_two() as i128 - 1)
+            }
+            ebpf::BPF_LSH => match shift {
+                Some(shift) if dst.max << shift <= all.max => {
+                    Interval::new(dst.min << shift, dst.max << shift)
+                }
+                _ => all,
+            },
+            ebpf::BPF_RSH => match shift {
+                Some(shift) => Interval::new(dst.min >> shift, dst.max >> shift),
+                None => Interval::new(0, dst.max),
+            },
+            ebpf::BPF_ARSH if dst.max <= positive.max => match shift {
+                Some(shift) => Interval::new(dst.min >> shift, dst.max >> shift),
+                None => Interval::new(0, dst.max),
+            },
+            ebpf::BPF_MOV => src,
+            _ => all,
+        }
+    }
+}
@@ -8,8 +8,8 @@
 //! This module translates eBPF assembly language to binary.
 
 use self::InstructionType::{
-    AluBinary, AluUnary, CallImm, CallReg, Endian, JumpConditional, JumpUnconditional, LoadDwImm,
-    LoadReg, NoOperand, StoreImm, StoreReg, Syscall,
+    AluBinary, AluBinaryVariant, AluUnary, Atomic, CallImm, CallReg, Endian, JumpConditional,
+    JumpUnconditional, LoadDwImm, LoadReg, NoOperand, StoreImm, StoreReg, Syscall,
 };
 use crate::{
     asm_parser::{
@@ -20,7 +20,7 @@ use crate::{
     ebpf::{self, Insn},
     elf::Executable,
     program::{BuiltinProgram, FunctionRegistry, SBPFVersion},
-    vm::ContextObject,
+    vm::{Config, ContextObject},
 };
 use std::collections::HashMap;
 
@@ -33,11 +33,13 @@ use shuttle::sync::Arc;
 #[derive(Clone, Copy, Debug, PartialEq)]
 enum InstructionType {
     AluBinary,
+    AluBinaryVariant(i64),
     AluUnary,
     LoadDwImm,
     LoadReg,
     StoreImm,
     StoreReg,
+    Atomic(i64),
     JumpUnconditional,
     JumpConditional,
     Syscall,
@@ -47,7 +49,10 @@ enum InstructionType {
     NoOperand,
 }
 
-fn make_instruction_map(sbpf_version: SBPFVersion) -> HashMap<String, (InstructionType, u8)> {
+fn make_instruction_map(
+    sbpf_version: SBPFVersion,
+    config: &Config,
+) -> HashMap<String, (InstructionType, u8)> {
     let mut result = HashMap::new();
 
     let alu_binary_ops = [
@@ -209,6 +214,65 @@ fn make_instruction_map(sbpf_version: SBPFVersion) -> HashMap<String, (Instructi
         );
         entry("srem32", AluBinary, ebpf::BPF_PQR | ebpf::BPF_SREM);
 
+        // Linux eBPF: variants selected by the offset field.
+        if sbpf_version.enable_signed_division() {
+            for &(name, opc) in &[("sdiv", ebpf::BPF_DIV), ("smod", ebpf::BPF_MOD)] {
+                entry(name, AluBinaryVariant(1), ebpf::BPF_ALU64_STORE | opc);
+                entry(
+                    &format!("{name}32"),
+                    AluBinaryVariant(1),
+                    ebpf::BPF_ALU32_LOAD | opc,
+                );
+                entry(
+                    &format!("{name}64"),
+                    AluBinaryVariant(1),
+                    ebpf::BPF_ALU64_STORE | opc,
+                );
+            }
+        }
+        if sbpf_version.enable_movsx() {
+            for &(suffix, bits) in &[("b", 8), ("h", 16), ("w", 32)] {
+                entry(
+                    &format!("movsx{suffix}"),
+                    AluBinaryVariant(bits),
+                    ebpf::MOV64_REG,
+                );
+                entry(
+                    &format!("movsx{suffix}64"),
+                    AluBinaryVariant(bits),
+                    ebpf::MOV64_REG,
+                );
+                if bits < 32 {
+                    entry(
+                        &format!("movsx{suffix}32"),
+                        AluBinaryVariant(bits),
+                        ebpf::MOV32_REG,
+                    );
+                }
+            }
+        }
+        if sbpf_version.enable_ldsx() {
+            entry("ldxsb", LoadReg, ebpf::LD_B_SX_REG);
+            entry("ldxsh", LoadReg, ebpf::LD_H_SX_REG);
+            entry("ldxsw", LoadReg, ebpf::LD_W_SX_REG);
+        }
+        if sbpf_version.enable_bswap() {
+            for &size in &[16, 32, 64] {
+                entry(&format!("bswap{size}"), Endian(size), ebpf::BSWAP);
+            }
+        }
+        if sbpf_version.enable_atomics() && config.enable_atomic_instructions {
+            for &(name, op) in &[
+                ("lockadd", ebpf::ATOMIC_ADD),
+                ("fetchadd", ebpf::ATOMIC_FETCH_ADD),
+                ("xchg", ebpf::ATOMIC_XCHG),
+                ("cmpxchg", ebpf::ATOMIC_CMPXCHG),
+            ] {
+                entry(&format!("{name}32"), Atomic(op), ebpf::ST_W_ATOMIC);
+                entry(&format!("{name}64"), Atomic(op), ebpf::ST_DW_ATOMIC);
+            }
+        }
+
         // Memory
         if sbpf_version.move_memory_instruction_classes() {
             for &(prefix, class, _, opcode) in &mem_classes {
@@ -327,7 +391,7 @@ pub fn assemble<C: ContextObject>(
     let sbpf_version = *loader.get_config().enabled_sbpf_versions.end();
 
     let statements = parse(src)?;
-    let instruction_map = make_instruction_map(sbpf_version);
+    let instruction_map = make_instruction_map(sbpf_version, loader.get_config());
     let mut insn_ptr = 0;
     let mut function_registry = FunctionRegistry::default();
     let mut labels = HashMap::new();
@@ -400,11 +464,22 @@ pub fn assemble<C: ContextObject>(
                             (AluBinary, [Register(dst), Integer(imm)]) => {
                                 insn(opc | ebpf::BPF_K, *dst, 0, 0, *imm)
                             }
+                            (AluBinaryVariant(off), [Register(dst), Register(src)]) => {
+                                insn(opc | ebpf::BPF_X, *dst, *src, off, 0)
+                            }
+                            (AluBinaryVariant(off), [Register(dst), Integer(imm)])
+                                if opc & ebpf::BPF_ALU_OP_MASK != ebpf::BPF_MOV =>
+                            {
+                                insn(opc | ebpf::BPF_K, *dst, 0, off, *imm)
+                            }
                             (AluUnary, [Register(dst)]) => insn(opc, *dst, 0, 0, 0),
                             (LoadReg, [Register(dst), Memory(src, off)])
                             | (StoreReg, [Memory(dst, off), Register(src)]) => {
                                 insn(opc, *dst, *src, *off, 0)
                             }
+                            (Atomic(op), [Memory(dst, off), Register(src)]) => {
+                                insn(opc, *dst, *src, *off, op)
+                            }
                             (StoreImm, [Memory(dst, off), Integer(imm)]) => {
                                 insn(opc, *dst, 0, *off, *imm)
                             }
@@ -447,13 +522,23 @@ pub fn assemble<C: ContextObject>(
                                 }
                                 insn(opc, 0, sbpf_version.static_syscalls() as i64, 0, target_pc)
                             }
-                            (Syscall, [Label(label)]) => insn(
-                                opc,
-                                0,
-                                0,
-                                0,
-                                ebpf::hash_symbol_name(label.as_bytes()) as i32 as i64,
-                            ),
+                            (Syscall, [Label(label)]) => {
+                                // Linux eBPF helpers are registered under explicit ids, so they
+                                // are found by name. Everything else is keyed by the hash of it.
+                                let key = if sbpf_version == SBPFVersion::Linux {
+                                    loader
+                                        .get_function_registry()
+                                        .iter()
+                                        .find(|(_key, (function_name, _))| {
+                                            *function_name == label.as_bytes()
+                                        })
+                                        .map(|(key, _)| key)
+                                } else {
+                                    None
+                                }
+                                .unwrap_or_else(|| ebpf::hash_symbol_name(label.as_bytes()));
+                                insn(opc, 0, 0, 0, key as i32 as i64)
+                            }
                             (Syscall, [Integer(imm)]) => insn(opc, 0, 0, 0, *imm),
                             (CallReg, [Register(dst)]) => {
                                 if sbpf_version.callx_uses_src_reg() {
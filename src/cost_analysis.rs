@@ -0,0 +1,379 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Static worst-case estimate of the executed instructions
+//!
+//! Every function is bounded by the longest path through the condensation of its control-flow
+//! graph. Loops (strongly connected components) can not be bounded statically, so they contribute
+//! their body multiplied by a symbolic trip count, which can later be substituted by annotations.
+
+use crate::{ebpf, static_analysis::Analysis};
+use rustc_demangle::demangle;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+/// Upper bound of executed instructions as a polynomial in the trip counts of loops
+///
+/// A trip count is identified by the lowest pc of its loop and bounds how often any single
+/// instruction of the loop runs each time the loop is entered.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cost {
+    /// Coefficients keyed by the loops whose trip counts they are multiplied with
+    pub terms: BTreeMap<Vec<usize>, u64>,
+    /// Reaches recursion or indirect calls, which have no static bound
+    pub unbounded: bool,
+}
+
+impl Cost {
+    /// Creates a cost which does not depend on any loop
+    pub fn constant(value: u64) -> Self {
+        let mut cost = Self::default();
+        cost.add_term(Vec::new(), value);
+        cost
+    }
+
+    /// Creates a cost which has no static bound
+    pub fn unbounded() -> Self {
+        Self {
+            terms: BTreeMap::new(),
+            unbounded: true,
+        }
+    }
+
+    fn add_term(&mut self, loops: Vec<usize>, coefficient: u64) {
+        if coefficient > 0 {
+            let term = self.terms.entry(loops).or_default();
+            *term = term.saturating_add(coefficient);
+        }
+    }
+
+    /// Adds another cost to this one
+    pub fn add(&mut self, other: &Self) {
+        for (loops, coefficient) in other.terms.iter() {
+            self.add_term(loops.clone(), *coefficient);
+        }
+        self.unbounded |= other.unbounded;
+    }
+
+    /// Upper bound of both costs, which is exact if one of them dominates every term
+    pub fn max(&self, other: &Self) -> Self {
+        let mut result = self.clone();
+        for (loops, coefficient) in other.terms.iter() {
+            let term = result.terms.entry(loops.clone()).or_default();
+            *term = (*term).max(*coefficient);
+        }
+        result.unbounded |= other.unbounded;
+        result
+    }
+
+    /// Multiplies every term with the trip count of the given loop
+    pub fn repeat(&self, loop_pc: usize) -> Self {
+        let mut result = Self {
+            terms: BTreeMap::new(),
+            unbounded: self.unbounded,
+        };
+        for (loops, coefficient) in self.terms.iter() {
+            let mut loops = loops.clone();
+            loops.push(loop_pc);
+            loops.sort_unstable();
+            result.add_term(loops, *coefficient);
+        }
+        result
+    }
+
+    /// Returns the loops whose trip counts are required to evaluate this cost
+    pub fn loops(&self) -> BTreeSet<usize> {
+        self.terms.keys().flatten().copied().collect()
+    }
+
+    /// Substitutes the trip counts and returns the number of instructions
+    ///
+    /// Returns [None] if the cost is unbounded or the trip count of a loop is missing.
+    pub fn evaluate(&self, trip_counts: &BTreeMap<usize, u64>) -> Option<u64> {
+        if self.unbounded {
+            return None;
+        }
+        let mut result = 0u64;
+        for (loops, coefficient) in self.terms.iter() {
+            let mut term = *coefficient;
+            for loop_pc in loops {
+                term = term.saturating_mul(*trip_counts.get(loop_pc)?);
+            }
+            result = result.saturating_add(term);
+        }
+        Some(result)
+    }
+
+    /// Evaluates the cost assuming every loop runs exactly once, which is used to rank paths
+    fn weight(&self) -> u64 {
+        self.terms
+            .values()
+            .fold(0, |sum, coefficient| sum.saturating_add(*coefficient))
+    }
+}
+
+impl fmt::Display for Cost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.unbounded {
+            return write!(f, "unbounded");
+        }
+        if self.terms.is_empty() {
+            return write!(f, "0");
+        }
+        for (index, (loops, coefficient)) in self.terms.iter().enumerate() {
+            if index > 0 {
+                write!(f, " + ")?;
+            }
+            write!(f, "{coefficient}")?;
+            for loop_pc in loops {
+                write!(f, " * n_{loop_pc}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Costs of the instructions which are not accounted for by the instruction meter alone
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CostModel {
+    /// Compute units consumed by syscalls in addition to their call instruction, keyed by name
+    pub syscall_costs: BTreeMap<String, u64>,
+    /// Compute units consumed by syscalls not listed in `syscall_costs`
+    pub default_syscall_cost: u64,
+}
+
+/// Worst-case estimate of a single function
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionCost {
+    /// Name of the function
+    pub name: String,
+    /// Upper bound of the executed instructions, including all callees
+    pub cost: Cost,
+    /// Basic blocks (by their first pc) along the most expensive path
+    pub hot_path: Vec<usize>,
+}
+
+/// Result of the cost analysis
+#[derive(Debug)]
+pub struct CostAnalysis {
+    /// Estimates of all functions, keyed by their first pc
+    pub functions: BTreeMap<usize, FunctionCost>,
+    /// Basic blocks of all loops, keyed by the lowest pc of the loop
+    pub loops: BTreeMap<usize, Vec<usize>>,
+    /// First pc of the entrypoint function
+    pub entrypoint: usize,
+}
+
+impl CostAnalysis {
+    /// Estimates the costs of all functions of the analyzed executable
+    pub fn new(analysis: &Analysis, cost_model: &CostModel) -> Self {
+        let executable = analysis.executable;
+        let function_registry = executable.get_function_registry();
+        let mut function_names = function_registry
+            .iter()
+            .filter(|(_key, (_name, pc))| analysis.cfg_nodes.contains_key(pc))
+            .map(|(_key, (name, pc))| (pc, demangle(&String::from_utf8_lossy(name)).to_string()))
+            .collect::<BTreeMap<_, _>>();
+        function_names
+            .entry(analysis.entrypoint)
+            .or_insert_with(|| "entrypoint".to_string());
+        for function_start in analysis.callable_functions() {
+            function_names
+                .entry(function_start)
+                .or_insert_with(|| analysis.functions[&function_start].1.clone());
+        }
+        let mut estimator = Estimator {
+            analysis,
+            cost_model,
+            loops: BTreeMap::new(),
+            functions: BTreeMap::new(),
+            in_progress: BTreeSet::new(),
+        };
+        for function_start in function_names.keys() {
+            estimator.function_cost(*function_start);
+        }
+        Self {
+            functions: estimator
+                .functions
+                .into_iter()
+                .map(|(function_start, (cost, hot_path))| {
+                    let name = function_names
+                        .get(&function_start)
+                        .cloned()
+                        .unwrap_or_else(|| format!("function_{function_start}"));
+                    (
+                        function_start,
+                        FunctionCost {
+                            name,
+                            cost,
+                            hot_path,
+                        },
+                    )
+                })
+                .collect(),
+            loops: estimator.loops,
+            entrypoint: analysis.entrypoint,
+        }
+    }
+
+    /// Upper bound of the instructions executed by the entire program
+    ///
+    /// Returns [None] if the bound depends on a loop which has no trip count or is unbounded.
+    pub fn worst_case(&self, trip_counts: &BTreeMap<usize, u64>) -> Option<u64> {
+        self.functions
+            .get(&self.entrypoint)
+            .and_then(|function| function.cost.evaluate(trip_counts))
+    }
+}
+
+struct Estimator<'a> {
+    analysis: &'a Analysis<'a>,
+    cost_model: &'a CostModel,
+    loops: BTreeMap<usize, Vec<usize>>,
+    functions: BTreeMap<usize, (Cost, Vec<usize>)>,
+    in_progress: BTreeSet<usize>,
+}
+
+impl Estimator<'_> {
+    fn function_cost(&mut self, function_start: usize) -> Cost {
+        if let Some((cost, _hot_path)) = self.functions.get(&function_start) {
+            return cost.clone();
+        }
+        if !self.in_progress.insert(function_start) {
+            // Recursion
+            return Cost::unbounded();
+        }
+        let result = self.estimate_function(function_start);
+        self.in_progress.remove(&function_start);
+        let cost = result.0.clone();
+        self.functions.insert(function_start, result);
+        cost
+    }
+
+    fn block_cost(&mut self, cfg_node_start: usize) -> Cost {
+        let analysis = self.analysis;
+        let executable = analysis.executable;
+        let sbpf_version = executable.get_sbpf_version();
+        let instructions =
+            &analysis.instructions[analysis.cfg_nodes[&cfg_node_start].instructions.clone()];
+        let mut cost = Cost::constant(instructions.len() as u64);
+        for insn in instructions {
+            match insn.opc {
+                ebpf::CALL_IMM => {
+                    let key = sbpf_version.calculate_call_imm_target_pc(insn.ptr, insn.imm);
+                    let is_internal = !sbpf_version.static_syscalls() || insn.src != 0;
+                    if let Some((_name, target_pc)) = executable
+                        .get_function_registry()
+                        .lookup_by_key(key)
+                        .filter(|_| is_internal)
+                    {
+                        let callee_cost = if analysis.cfg_nodes.contains_key(&target_pc) {
+                            self.function_cost(target_pc)
+                        } else {
+                            Cost::unbounded()
+                        };
+                        cost.add(&callee_cost);
+                    } else {
+                        let syscall_cost = executable
+                            .get_loader()
+                            .get_function_registry()
+                            .lookup_by_key(insn.imm as u32)
+                            .and_then(|(name, _function)| {
+                                self.cost_model
+                                    .syscall_costs
+                                    .get(String::from_utf8_lossy(name).as_ref())
+                            })
+                            .copied()
+                            .unwrap_or(self.cost_model.default_syscall_cost);
+                        cost.add(&Cost::constant(syscall_cost));
+                    }
+                }
+                ebpf::CALL_REG => match analysis.indirect_call_targets.get(&insn.ptr) {
+                    Some(Some(target_pcs)) => {
+                        let mut callee_cost = Cost::constant(0);
+                        for target_pc in target_pcs {
+                            callee_cost = callee_cost.max(&self.function_cost(*target_pc));
+                        }
+                        cost.add(&callee_cost);
+                    }
+                    _ => cost.add(&Cost::unbounded()),
+                },
+                _ => {}
+            }
+        }
+        cost
+    }
+
+    fn estimate_function(&mut self, function_start: usize) -> (Cost, Vec<usize>) {
+        let analysis = self.analysis;
+        let cfg_nodes = &analysis.cfg_nodes;
+        // Collect the basic blocks of the function grouped by their strongly connected component
+        let mut components = BTreeMap::<usize, Vec<usize>>::new();
+        let mut visited = BTreeSet::new();
+        let mut stack = vec![function_start];
+        while let Some(cfg_node_start) = stack.pop() {
+            if !visited.insert(cfg_node_start) {
+                continue;
+            }
+            let cfg_node = &cfg_nodes[&cfg_node_start];
+            components
+                .entry(cfg_node.topo_index.scc_id)
+                .or_default()
+                .push(cfg_node_start);
+            stack.extend(cfg_node.destinations.iter().copied());
+        }
+        // Tarjan numbers the components in reverse topological order, so successors come first
+        let mut worst = BTreeMap::<usize, (Cost, Option<usize>)>::new();
+        for (scc_id, blocks) in components.iter_mut() {
+            blocks.sort_unstable();
+            let mut body = Cost::default();
+            for cfg_node_start in blocks.iter() {
+                let block_cost = self.block_cost(*cfg_node_start);
+                body.add(&block_cost);
+            }
+            let is_loop =
+                blocks.len() > 1 || cfg_nodes[&blocks[0]].destinations.contains(&blocks[0]);
+            if is_loop {
+                body = body.repeat(blocks[0]);
+                self.loops.insert(blocks[0], blocks.clone());
+            }
+            let mut successor: Option<(Cost, usize)> = None;
+            for cfg_node_start in blocks.iter() {
+                for destination in cfg_nodes[cfg_node_start].destinations.iter() {
+                    let destination_scc_id = cfg_nodes[destination].topo_index.scc_id;
+                    if destination_scc_id == *scc_id {
+                        continue;
+                    }
+                    let (cost, _) = &worst[&destination_scc_id];
+                    successor = Some(match successor {
+                        None => (cost.clone(), destination_scc_id),
+                        Some((best, best_scc_id)) => {
+                            let hot_scc_id = if cost.weight() > best.weight() {
+                                destination_scc_id
+                            } else {
+                                best_scc_id
+                            };
+                            (best.max(cost), hot_scc_id)
+                        }
+                    });
+                }
+            }
+            let hot_successor = successor.as_ref().map(|(_cost, scc_id)| *scc_id);
+            if let Some((cost, _scc_id)) = successor {
+                body.add(&cost);
+            }
+            worst.insert(*scc_id, (body, hot_successor));
+        }
+        let mut scc_id = cfg_nodes[&function_start].topo_index.scc_id;
+        let cost = worst[&scc_id].0.clone();
+        let mut hot_path = Vec::new();
+        loop {
+            hot_path.extend(components[&scc_id].iter().copied());
+            match worst[&scc_id].1 {
+                Some(next_scc_id) => scc_id = next_scc_id,
+                None => break,
+            }
+        }
+        (cost, hot_path)
+    }
+}
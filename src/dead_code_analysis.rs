@@ -0,0 +1,406 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Code which is unreachable from the entrypoint
+//!
+//! Follows the jumps, calls and resolved `callx` targets from the entrypoint through the basic
+//! blocks of [Analysis]. Instead of the abnormal edges of the super root, which would make every
+//! function reachable, only functions whose address is loaded by `lddw` or stored in the read-only
+//! data are assumed to be called by the `callx` instructions which could not be resolved.
+
+use crate::{
+    ebpf,
+    elf::{ElfError, Executable},
+    error::EbpfError,
+    program::FunctionRegistry,
+    static_analysis::Analysis,
+    verifier::RequisiteVerifier,
+    vm::ContextObject,
+};
+use byteorder::{ByteOrder, LittleEndian};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    fmt,
+    ops::Range,
+};
+
+/// Reachability of a function
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionLiveness {
+    /// Name of the function
+    pub name: String,
+    /// The function is in the function registry, otherwise it was only labeled by the analysis
+    pub registered: bool,
+    /// Instructions of the function
+    pub pcs: Range<usize>,
+    /// None of the instructions is reachable
+    pub dead: bool,
+    /// Size of the unreachable basic blocks in bytes
+    pub dead_bytes: usize,
+}
+
+/// Result of the dead code analysis
+#[derive(Debug)]
+pub struct DeadCodeAnalysis {
+    /// First pcs of the basic blocks which are unreachable and their number of instruction slots
+    pub unreachable_blocks: BTreeMap<usize, usize>,
+    /// All functions, keyed by their first pc
+    pub functions: BTreeMap<usize, FunctionLiveness>,
+    /// Instructions whose address is taken, treated as reachable
+    pub address_taken: BTreeSet<usize>,
+}
+
+impl DeadCodeAnalysis {
+    /// Finds the code of the analyzed executable which is unreachable from the entrypoint
+    pub fn new(analysis: &Analysis) -> Self {
+        let executable = analysis.executable;
+        let sbpf_version = executable.get_sbpf_version();
+        let (text_vaddr, text) = executable.get_text_bytes();
+        let slots = text.len() / ebpf::INSN_SIZE;
+        let pc_of_address = |address: u64| {
+            let offset = address.checked_sub(text_vaddr)?;
+            let pc = (offset / ebpf::INSN_SIZE as u64) as usize;
+            (offset % ebpf::INSN_SIZE as u64 == 0 && pc < slots).then_some(pc)
+        };
+        let block_of = |pc: usize| {
+            analysis
+                .cfg_nodes
+                .range(..=pc)
+                .next_back()
+                .map(|(cfg_node_start, _cfg_node)| *cfg_node_start)
+        };
+
+        let mut address_taken = analysis
+            .instructions
+            .iter()
+            .filter(|insn| insn.opc == ebpf::LD_DW_IMM && !sbpf_version.disable_lddw())
+            .filter_map(|insn| pc_of_address(insn.imm as u64))
+            .collect::<BTreeSet<_>>();
+        for (_vm_addr, data) in executable.get_ro_section_contents() {
+            address_taken.extend(
+                data.chunks_exact(8)
+                    .filter(|word| !is_instruction(text, word))
+                    .filter_map(|word| pc_of_address(LittleEndian::read_u64(word))),
+            );
+        }
+
+        let mut reachable = BTreeSet::new();
+        let mut called = BTreeSet::new();
+        let mut worklist = std::iter::once(analysis.entrypoint)
+            .chain(address_taken.iter().copied())
+            .filter_map(block_of)
+            .collect::<Vec<_>>();
+        while let Some(cfg_node_start) = worklist.pop() {
+            if !reachable.insert(cfg_node_start) {
+                continue;
+            }
+            let cfg_node = &analysis.cfg_nodes[&cfg_node_start];
+            worklist.extend(
+                cfg_node
+                    .destinations
+                    .iter()
+                    .filter(|destination| **destination < slots),
+            );
+            for insn in &analysis.instructions[cfg_node.instructions.clone()] {
+                match insn.opc {
+                    ebpf::CALL_IMM => {
+                        if let Some(target_pc) = call_imm_target(analysis, insn) {
+                            called.insert(target_pc);
+                            worklist.extend(block_of(target_pc));
+                        }
+                    }
+                    ebpf::CALL_REG => {
+                        if let Some(Some(target_pcs)) =
+                            analysis.indirect_call_targets.get(&insn.ptr)
+                        {
+                            called.extend(target_pcs.iter().copied());
+                            worklist.extend(target_pcs.iter().filter_map(|pc| block_of(*pc)));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let block_sizes = analysis
+            .cfg_nodes
+            .keys()
+            .copied()
+            .filter(|cfg_node_start| *cfg_node_start < slots)
+            .chain(std::iter::once(slots))
+            .collect::<Vec<_>>();
+        let unreachable_blocks = block_sizes
+            .windows(2)
+            .filter(|pcs| !reachable.contains(&pcs[0]))
+            .map(|pcs| (pcs[0], pcs[1] - pcs[0]))
+            .collect::<BTreeMap<_, _>>();
+
+        // The analysis labels unreachable code as functions too, but only that following an
+        // `exit` is likely to be one and not a part of the function in front of it
+        let registered = executable
+            .get_function_registry()
+            .iter()
+            .map(|(_key, (_name, pc))| pc)
+            .collect::<BTreeSet<_>>();
+        let follows_exit = |pc: usize| {
+            let index = analysis.instructions.partition_point(|insn| insn.ptr < pc);
+            index > 0 && analysis.instructions[index - 1].opc == ebpf::EXIT
+        };
+        let function_starts = registered
+            .iter()
+            .chain(called.iter())
+            .chain(address_taken.iter())
+            .chain([analysis.entrypoint, 0].iter())
+            .copied()
+            .chain(
+                analysis
+                    .functions
+                    .keys()
+                    .copied()
+                    .filter(|pc| follows_exit(*pc)),
+            )
+            .filter(|pc| *pc < slots)
+            .map(|pc| {
+                let name = match analysis.functions.get(&pc) {
+                    Some((_key, name)) => name.clone(),
+                    None if pc == analysis.entrypoint => "entrypoint".to_string(),
+                    None => format!("function_{pc}"),
+                };
+                (pc, name)
+            })
+            .collect::<BTreeMap<_, _>>();
+        let mut functions = BTreeMap::new();
+        let ends = function_starts
+            .keys()
+            .skip(1)
+            .copied()
+            .chain(std::iter::once(slots))
+            .collect::<Vec<_>>();
+        for ((function_start, name), end) in function_starts.into_iter().zip(ends) {
+            let pcs = function_start..end;
+            let dead_slots = unreachable_blocks
+                .range(pcs.clone())
+                .map(|(_cfg_node_start, slots)| slots)
+                .sum::<usize>();
+            functions.insert(
+                function_start,
+                FunctionLiveness {
+                    name,
+                    registered: registered.contains(&function_start),
+                    dead: dead_slots == pcs.len(),
+                    dead_bytes: dead_slots * ebpf::INSN_SIZE,
+                    pcs,
+                },
+            );
+        }
+        Self {
+            unreachable_blocks,
+            functions,
+            address_taken,
+        }
+    }
+
+    /// Size of all unreachable basic blocks in bytes
+    pub fn dead_bytes(&self) -> usize {
+        self.functions
+            .values()
+            .map(|function| function.dead_bytes)
+            .sum()
+    }
+}
+
+impl fmt::Display for DeadCodeAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} bytes of dead code", self.dead_bytes())?;
+        for function in self.functions.values() {
+            if function.dead {
+                writeln!(f, "{}: dead, {} bytes", function.name, function.dead_bytes)?;
+            } else if function.dead_bytes > 0 {
+                writeln!(
+                    f,
+                    "{}: {} bytes unreachable",
+                    function.name, function.dead_bytes
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether the bytes of the read-only data belong to the text section, which can be mapped as both
+fn is_instruction(text: &[u8], word: &[u8]) -> bool {
+    text.as_ptr_range().contains(&word.as_ptr())
+}
+
+/// Target of an internal `call`, [None] for syscalls
+fn call_imm_target(analysis: &Analysis, insn: &ebpf::Insn) -> Option<usize> {
+    let executable = analysis.executable;
+    let sbpf_version = executable.get_sbpf_version();
+    if sbpf_version.static_syscalls() {
+        // Functions are not necessarily registered
+        let (_text_vaddr, text) = executable.get_text_bytes();
+        (insn.src == 1)
+            .then(|| (insn.ptr as i64).saturating_add(insn.imm).saturating_add(1))
+            .and_then(|target_pc| usize::try_from(target_pc).ok())
+            .filter(|target_pc| ebpf::is_pc_in_program(text, *target_pc))
+    } else if executable
+        .get_loader()
+        .get_function_registry()
+        .lookup_by_key(insn.imm as u32)
+        .is_some()
+    {
+        None
+    } else {
+        executable
+            .get_function_registry()
+            .lookup_by_key(insn.imm as u32)
+            .map(|(_name, target_pc)| target_pc)
+    }
+}
+
+/// Error of [strip_dead_code]
+#[derive(Debug, thiserror::Error)]
+pub enum StripError {
+    /// The targets of a reachable `callx` could not be resolved
+    #[error("targets of the callx at pc {0} are unknown")]
+    UnresolvedCallTarget(usize),
+    /// The address of a `callx` target is computed instead of loaded and can not be moved along
+    #[error("targets of the callx at pc {0} can not be relocated")]
+    UnrelocatableCallTarget(usize),
+    /// Loading or verifying the stripped executable failed
+    #[error("{0}")]
+    EbpfError(#[from] EbpfError),
+}
+
+/// Removes the dead functions and all other unreachable basic blocks of an executable
+///
+/// The remaining instructions move up, after which jumps, calls and the function registry are
+/// renumbered. The addresses of the resolved `callx` targets, loaded by `lddw` or stored as 8 byte
+/// aligned words in the read-only data, are moved along. Refuses to strip if the targets of a
+/// reachable `callx` are unknown or do not match after stripping. Only supported for versions with
+/// the stricter ELF headers, because the rodata and the bytecode are laid out independently there.
+pub fn strip_dead_code<C: ContextObject>(
+    executable: &Executable<C>,
+) -> Result<Executable<C>, StripError> {
+    let sbpf_version = executable.get_sbpf_version();
+    if !sbpf_version.enable_stricter_elf_headers() || !executable.get_writable_sections().is_empty()
+    {
+        return Err(EbpfError::ElfError(ElfError::UnsupportedSBPFVersion).into());
+    }
+    let analysis = Analysis::from_executable(executable)?;
+    let dead_code = DeadCodeAnalysis::new(&analysis);
+    let (text_vaddr, text) = executable.get_text_bytes();
+    let slots = text.len() / ebpf::INSN_SIZE;
+
+    // Every pc maps to the number of remaining instructions in front of it
+    let mut removed = vec![false; slots];
+    for (cfg_node_start, len) in dead_code.unreachable_blocks.iter() {
+        removed[*cfg_node_start..*cfg_node_start + len].fill(true);
+    }
+    let mut new_pc_of = Vec::with_capacity(slots + 1);
+    let mut new_pc = 0;
+    for removed in removed.iter() {
+        new_pc_of.push(new_pc);
+        if !removed {
+            new_pc += 1;
+        }
+    }
+    new_pc_of.push(new_pc);
+
+    // Only the addresses of the functions which reachable callx instructions call are moved along
+    let mut call_targets = BTreeMap::new();
+    for (pc, targets) in analysis.indirect_call_targets.iter() {
+        if removed[*pc] {
+            continue;
+        }
+        let targets = targets
+            .as_ref()
+            .ok_or(StripError::UnresolvedCallTarget(*pc))?;
+        for target_pc in targets.iter() {
+            call_targets.insert(
+                text_vaddr + (*target_pc * ebpf::INSN_SIZE) as u64,
+                text_vaddr + (new_pc_of[*target_pc] * ebpf::INSN_SIZE) as u64,
+            );
+        }
+    }
+    let relocate = |address: u64| call_targets.get(&address).copied().unwrap_or(address);
+
+    let mut stripped_text = Vec::with_capacity(new_pc * ebpf::INSN_SIZE);
+    for insn in analysis.instructions.iter() {
+        if removed[insn.ptr] {
+            continue;
+        }
+        let new_pc = new_pc_of[insn.ptr];
+        let class = insn.opc & ebpf::BPF_CLS_MASK;
+        let mut insn = insn.clone();
+        if insn.opc == ebpf::CALL_IMM && insn.src == 1 {
+            let target_pc = (insn.ptr as i64 + 1 + insn.imm).clamp(0, slots as i64) as usize;
+            insn.imm = new_pc_of[target_pc] as i64 - new_pc as i64 - 1;
+        } else if (class == ebpf::BPF_JMP64
+            || (class == ebpf::BPF_JMP32 && sbpf_version.enable_jmp32()))
+            && !matches!(insn.opc, ebpf::CALL_IMM | ebpf::CALL_REG | ebpf::EXIT)
+        {
+            let target_pc = (insn.ptr as i64 + 1 + insn.off as i64).clamp(0, slots as i64) as usize;
+            // Removing code only shortens the distances
+            insn.off = (new_pc_of[target_pc] as i64 - new_pc as i64 - 1) as i16;
+        } else if insn.opc == ebpf::LD_DW_IMM {
+            let address = relocate(insn.imm as u64);
+            stripped_text.extend_from_slice(
+                &ebpf::Insn {
+                    imm: address as u32 as i32 as i64,
+                    ..insn.clone()
+                }
+                .to_array(),
+            );
+            insn = ebpf::get_insn_unchecked(text, insn.ptr + 1);
+            insn.imm = (address >> 32) as u32 as i32 as i64;
+        }
+        stripped_text.extend_from_slice(&insn.to_array());
+    }
+
+    let ro_section = executable.get_ro_section();
+    let mut rodata = ro_section.to_vec();
+    for (word, original) in rodata.chunks_exact_mut(8).zip(ro_section.chunks_exact(8)) {
+        if !is_instruction(text, original) {
+            LittleEndian::write_u64(word, relocate(LittleEndian::read_u64(original)));
+        }
+    }
+
+    let mut function_registry = FunctionRegistry::default();
+    for (_key, (name, pc)) in executable.get_function_registry().iter() {
+        if pc < slots && !removed[pc] {
+            let new_pc = new_pc_of[pc];
+            function_registry
+                .register_function(new_pc as u32, name, new_pc)
+                .map_err(EbpfError::ElfError)?;
+        }
+    }
+    let stripped = Executable::new_from_segments(
+        &rodata,
+        &stripped_text,
+        executable.get_loader().clone(),
+        sbpf_version,
+        new_pc_of[executable.get_entrypoint_instruction_offset().min(slots)],
+        function_registry,
+    );
+    stripped.verify::<RequisiteVerifier>()?;
+    {
+        // Addresses which are computed instead of loaded were not moved along
+        let stripped_analysis = Analysis::from_executable(&stripped)?;
+        for (pc, targets) in analysis.indirect_call_targets.iter() {
+            if removed[*pc] {
+                continue;
+            }
+            let relocated_targets = targets
+                .iter()
+                .flatten()
+                .map(|target_pc| new_pc_of[*target_pc])
+                .collect::<BTreeSet<_>>();
+            if stripped_analysis.indirect_call_targets.get(&new_pc_of[*pc])
+                != Some(&Some(relocated_targets))
+            {
+                return Err(StripError::UnrelocatableCallTarget(*pc));
+            }
+        }
+    }
+    Ok(stripped)
+}
@@ -648,6 +648,7 @@ impl std::fmt::Display for GdbStubArch {
             SBPFVersion::V2 => "sbpfv2",
             SBPFVersion::V3 => "sbpfv3",
             SBPFVersion::V4 => "sbpfv4",
+            SBPFVersion::Linux => "bpf",
             SBPFVersion::Reserved => "sbpfreserved",
         };
         write!(f, "{}", gdbstub_arch)
@@ -124,6 +124,42 @@ pub fn disassemble_instruction<C: ContextObject>(
         // BPF_LD class
         ebpf::LD_DW_IMM  => { name = "lddw"; desc = format!("{} r{:}, {:#x}", name, insn.dst, insn.imm); },
 
+        // Linux eBPF
+        ebpf::LD_B_SX_REG if sbpf_version.enable_ldsx() => { name = "ldxsb"; desc = ld_reg_str(name, insn); },
+        ebpf::LD_H_SX_REG if sbpf_version.enable_ldsx() => { name = "ldxsh"; desc = ld_reg_str(name, insn); },
+        ebpf::LD_W_SX_REG if sbpf_version.enable_ldsx() => { name = "ldxsw"; desc = ld_reg_str(name, insn); },
+        ebpf::DIV32_IMM  if sbpf_version.enable_signed_division() && insn.off == 1 => { name = "sdiv32"; desc = alu_imm_str(name, insn); },
+        ebpf::DIV32_REG  if sbpf_version.enable_signed_division() && insn.off == 1 => { name = "sdiv32"; desc = alu_reg_str(name, insn); },
+        ebpf::MOD32_IMM  if sbpf_version.enable_signed_division() && insn.off == 1 => { name = "smod32"; desc = alu_imm_str(name, insn); },
+        ebpf::MOD32_REG  if sbpf_version.enable_signed_division() && insn.off == 1 => { name = "smod32"; desc = alu_reg_str(name, insn); },
+        ebpf::DIV64_IMM  if sbpf_version.enable_signed_division() && insn.off == 1 => { name = "sdiv64"; desc = alu_imm_str(name, insn); },
+        ebpf::DIV64_REG  if sbpf_version.enable_signed_division() && insn.off == 1 => { name = "sdiv64"; desc = alu_reg_str(name, insn); },
+        ebpf::MOD64_IMM  if sbpf_version.enable_signed_division() && insn.off == 1 => { name = "smod64"; desc = alu_imm_str(name, insn); },
+        ebpf::MOD64_REG  if sbpf_version.enable_signed_division() && insn.off == 1 => { name = "smod64"; desc = alu_reg_str(name, insn); },
+        ebpf::MOV32_REG  if sbpf_version.enable_movsx() && insn.off != 0 => {
+            name = if insn.off == 8 { "movsxb32" } else { "movsxh32" };
+            desc = alu_reg_str(name, insn);
+        },
+        ebpf::MOV64_REG  if sbpf_version.enable_movsx() && insn.off != 0 => {
+            name = match insn.off { 8 => "movsxb64", 16 => "movsxh64", _ => "movsxw64" };
+            desc = alu_reg_str(name, insn);
+        },
+        ebpf::BSWAP      if sbpf_version.enable_bswap() => { name = "bswap"; desc = byteswap_str(name, insn); },
+        ebpf::ST_W_ATOMIC | ebpf::ST_DW_ATOMIC if sbpf_version.enable_atomics() && loader.get_config().enable_atomic_instructions => {
+            name = match (insn.opc == ebpf::ST_W_ATOMIC, insn.imm) {
+                (true,  ebpf::ATOMIC_ADD) => "lockadd32",
+                (false, ebpf::ATOMIC_ADD) => "lockadd64",
+                (true,  ebpf::ATOMIC_FETCH_ADD) => "fetchadd32",
+                (false, ebpf::ATOMIC_FETCH_ADD) => "fetchadd64",
+                (true,  ebpf::ATOMIC_XCHG) => "xchg32",
+                (false, ebpf::ATOMIC_XCHG) => "xchg64",
+                (true,  ebpf::ATOMIC_CMPXCHG) => "cmpxchg32",
+                (false, ebpf::ATOMIC_CMPXCHG) => "cmpxchg64",
+                _ => "unknown",
+            };
+            desc = st_reg_str(name, insn);
+        },
+
         // BPF_LDX class
         ebpf::LD_B_REG  if !sbpf_version.move_memory_instruction_classes() => { name = "ldxb";  desc = ld_reg_str(name, insn); },
         ebpf::LD_H_REG  if !sbpf_version.move_memory_instruction_classes() => { name = "ldxh";  desc = ld_reg_str(name, insn); },
@@ -293,7 +329,7 @@ pub fn disassemble_instruction<C: ContextObject>(
             let key = sbpf_version.calculate_call_imm_target_pc(pc, insn.imm);
             let mut name = "call";
             let mut function_name = function_registry.lookup_by_key(key).map(|(function_name, _)| String::from_utf8_lossy(function_name).to_string());
-            if (function_name.is_none() && !sbpf_version.static_syscalls()) || insn.src == 0 {
+            if (function_name.is_none() && !sbpf_version.static_syscalls()) || (sbpf_version.static_syscalls() && insn.src == 0) {
                 name = "syscall";
                 function_name = loader.get_function_registry().lookup_by_key(insn.imm as u32).map(|(function_name, _)| String::from_utf8_lossy(function_name).to_string());
             }
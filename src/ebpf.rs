@@ -107,9 +107,11 @@ pub const BPF_ABS: u8 = 0x20;
 pub const BPF_IND: u8 = 0x40;
 /// BPF mode modifier: load from / store to memory. [DEPRECATED]
 pub const BPF_MEM: u8 = 0x60;
-// [ 0x80 reserved ]
+/// BPF mode modifier: sign-extending load from memory. [Linux eBPF]
+pub const BPF_MEMSX: u8 = 0x80;
 // [ 0xa0 reserved ]
-// [ 0xc0 reserved ]
+/// BPF mode modifier: atomic read-modify-write of memory, operation in imm.
+pub const BPF_ATOMIC: u8 = 0xc0;
 
 // For arithmetic (BPF_ALU/BPF_ALU64_STORE) and jump (BPF_JMP64) instructions:
 // +----------------+--------+--------+
@@ -175,6 +177,22 @@ pub const BPF_SDIV: u8 = 0xC0;
 /// BPF PQR operation code: signed division remainder.
 pub const BPF_SREM: u8 = 0xE0;
 
+// Operation codes -- BPF_ATOMIC mode, encoded in the immediate:
+/// BPF ATOMIC operation modifier: the src register receives the previous value.
+pub const BPF_FETCH: u8 = 0x01;
+/// BPF ATOMIC operation code: exchange.
+pub const BPF_XCHG: u8 = 0xe0;
+/// BPF ATOMIC operation code: compare and exchange.
+pub const BPF_CMPXCHG: u8 = 0xf0;
+/// BPF ATOMIC immediate: `lock *(dst + off) += src`.
+pub const ATOMIC_ADD: i64 = BPF_ADD as i64;
+/// BPF ATOMIC immediate: `src = atomic_fetch_add(dst + off, src)`.
+pub const ATOMIC_FETCH_ADD: i64 = (BPF_ADD | BPF_FETCH) as i64;
+/// BPF ATOMIC immediate: `src = atomic_xchg(dst + off, src)`.
+pub const ATOMIC_XCHG: i64 = (BPF_XCHG | BPF_FETCH) as i64;
+/// BPF ATOMIC immediate: `r0 = atomic_cmpxchg(dst + off, r0, src)`.
+pub const ATOMIC_CMPXCHG: i64 = (BPF_CMPXCHG | BPF_FETCH) as i64;
+
 // Operation codes -- BPF_JMP32 and BPF_JMP64 classes:
 /// BPF JMP operation code: jump.
 pub const BPF_JA: u8 = 0x00;
@@ -219,6 +237,16 @@ pub const LD_H_REG: u8 = BPF_LDX | BPF_MEM | BPF_H;
 pub const LD_W_REG: u8 = BPF_LDX | BPF_MEM | BPF_W;
 /// BPF opcode: `ldxdw dst, [src + off]` /// `dst = (src + off) as u64`.
 pub const LD_DW_REG: u8 = BPF_LDX | BPF_MEM | BPF_DW;
+/// BPF opcode: `ldxsb dst, [src + off]` /// `dst = (src + off) as i8 as i64`. [Linux eBPF]
+pub const LD_B_SX_REG: u8 = BPF_LDX | BPF_MEMSX | BPF_B;
+/// BPF opcode: `ldxsh dst, [src + off]` /// `dst = (src + off) as i16 as i64`. [Linux eBPF]
+pub const LD_H_SX_REG: u8 = BPF_LDX | BPF_MEMSX | BPF_H;
+/// BPF opcode: `ldxsw dst, [src + off]` /// `dst = (src + off) as i32 as i64`. [Linux eBPF]
+pub const LD_W_SX_REG: u8 = BPF_LDX | BPF_MEMSX | BPF_W;
+/// BPF opcode: `atomic_*32 [dst + off], src` /// 32 bit read-modify-write of `(dst + off)`, operation in imm. [Linux eBPF]
+pub const ST_W_ATOMIC: u8 = BPF_STX | BPF_ATOMIC | BPF_W;
+/// BPF opcode: `atomic_*64 [dst + off], src` /// 64 bit read-modify-write of `(dst + off)`, operation in imm. [Linux eBPF]
+pub const ST_DW_ATOMIC: u8 = BPF_STX | BPF_ATOMIC | BPF_DW;
 /// BPF opcode: `stb [dst + off], imm` /// `(dst + offset) as u8 = imm`.
 pub const ST_B_IMM: u8 = BPF_ST | BPF_MEM | BPF_B;
 /// BPF opcode: `sth [dst + off], imm` /// `(dst + offset) as u16 = imm`.
@@ -345,6 +373,8 @@ pub const SREM32_REG: u8 = BPF_PQR | BPF_X | BPF_SREM;
 pub const LE: u8 = BPF_ALU32_LOAD | BPF_K | BPF_END;
 /// BPF opcode: `be dst` /// `dst = htobe<imm>(dst), with imm in {16, 32, 64}`.
 pub const BE: u8 = BPF_ALU32_LOAD | BPF_X | BPF_END;
+/// BPF opcode: `bswap dst` /// `dst = bswap<imm>(dst), with imm in {16, 32, 64}`. [Linux eBPF]
+pub const BSWAP: u8 = BPF_ALU64_STORE | BPF_K | BPF_END;
 
 /// BPF opcode: `add64 dst, imm` /// `dst += imm`.
 pub const ADD64_IMM: u8 = BPF_ALU64_STORE | BPF_K | BPF_ADD;
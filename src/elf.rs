@@ -11,10 +11,12 @@ use crate::{
     ebpf::{self, HOST_ALIGN, INSN_SIZE},
     elf_parser::{
         consts::{
-            ELFCLASS64, ELFDATA2LSB, ELFOSABI_NONE, EM_BPF, EM_SBPF, ET_DYN, R_X86_64_32,
-            R_X86_64_64, R_X86_64_NONE, R_X86_64_RELATIVE,
+            ELFCLASS64, ELFDATA2LSB, ELFOSABI_NONE, EM_BPF, EM_SBPF, ET_DYN, R_BPF_64_ABS32,
+            R_BPF_64_ABS64, R_BPF_64_NODYLD32, R_X86_64_32, R_X86_64_64, R_X86_64_NONE,
+            R_X86_64_RELATIVE, SHF_ALLOC, SHN_ABS,
         },
-        types::{Elf64Phdr, Elf64Shdr, Elf64Word},
+        dwarf::{DebugInfo, DwarfSections, SourceLocation},
+        types::{Elf64Phdr, Elf64Rel, Elf64Shdr, Elf64Word},
         Elf64, ElfParserError,
     },
     error::EbpfError,
@@ -77,7 +79,7 @@ pub enum ElfError {
     /// Not one text section
     #[error("Multiple or no text sections, consider removing llc option: -function-sections")]
     NotOneTextSection,
-    /// Read-write data not supported
+    /// Read-write data not enabled in the config or not supported by the memory mapping
     #[error("Found writable section ({0}) in ELF, read-write data not supported")]
     WritableSectionNotSupported(String),
     /// Relocation failed, no loadable section contains virtual address
@@ -123,6 +125,7 @@ impl From<ElfParserError> for ElfError {
             | ElfParserError::InvalidAlignment
             | ElfParserError::NoStringTable
             | ElfParserError::NoDynamicStringTable
+            | ElfParserError::InvalidDebugInfo
             | ElfParserError::InvalidFileHeader
             | ElfParserError::StringTooLong(_, _) => ElfError::FailedToParse(err.to_string()),
             ElfParserError::InvalidProgramHeader => ElfError::InvalidProgramHeader,
@@ -168,7 +171,7 @@ const BYTE_LENGTH_IMMEDIATE: usize = 4;
 /// BPF relocation types.
 #[allow(non_camel_case_types)]
 #[derive(Debug, PartialEq, Copy, Clone)]
-enum BpfRelocationType {
+pub(crate) enum BpfRelocationType {
     /// No relocation, placeholder
     R_Bpf_None = 0,
     /// R_BPF_64_64 relocation type is used for ld_imm64 instruction.
@@ -177,6 +180,15 @@ enum BpfRelocationType {
     /// (4 bytes). The relocation can be resolved with the symbol
     /// value plus implicit addend.
     R_Bpf_64_64 = 1,
+    /// 64 bit absolute address in data, e.g. a pointer in `.data`. The
+    /// implicit addend is stored at r_offset.
+    R_Bpf_64_Abs64 = 2,
+    /// 32 bit absolute address in data, only representable if the symbol
+    /// is absolute, as all other addresses are beyond 32 bits in the VM.
+    R_Bpf_64_Abs32 = 3,
+    /// 32 bit address in data which loaders do not resolve, used for
+    /// sections like `.BTF.ext`.
+    R_Bpf_64_NoDyld32 = 4,
     /// 64 bit relocation of a ldxdw instruction.  The ldxdw
     /// instruction occupies two instruction slots. The 64-bit address
     /// to load from is split into the 32-bit imm field of each
@@ -202,19 +214,22 @@ enum BpfRelocationType {
     R_Bpf_64_32 = 10,
 }
 impl BpfRelocationType {
-    fn from_x86_relocation_type(from: u32) -> Option<BpfRelocationType> {
+    pub(crate) fn from_x86_relocation_type(from: u32) -> Option<BpfRelocationType> {
         match from {
             R_X86_64_NONE => Some(BpfRelocationType::R_Bpf_None),
             R_X86_64_64 => Some(BpfRelocationType::R_Bpf_64_64),
             R_X86_64_RELATIVE => Some(BpfRelocationType::R_Bpf_64_Relative),
             R_X86_64_32 => Some(BpfRelocationType::R_Bpf_64_32),
+            R_BPF_64_ABS64 => Some(BpfRelocationType::R_Bpf_64_Abs64),
+            R_BPF_64_ABS32 => Some(BpfRelocationType::R_Bpf_64_Abs32),
+            R_BPF_64_NODYLD32 => Some(BpfRelocationType::R_Bpf_64_NoDyld32),
             _ => None,
         }
     }
 }
 
 /// ELF section
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Section {
     /// Owned section data.
     ///
@@ -229,15 +244,98 @@ pub enum Section {
     Borrowed(usize, Range<usize>),
 }
 
+/// Writable ELF section (`.data` or `.bss`)
+///
+/// Programs can modify these, so every execution needs its own copy, see [WritableSections].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct WritableSection {
+    /// Virtual address of the section
+    pub vm_addr: u64,
+    /// Range of the initial contents in the ELF buffer, empty for `.bss`
+    pub initial_contents: Range<usize>,
+    /// Length of the section, the part beyond the initial contents is zero filled
+    pub len: usize,
+}
+
+/// Per execution copies of the [WritableSection]s of an [Executable]
+#[derive(Debug)]
+pub struct WritableSections {
+    /// Virtual address and host memory of every section
+    sections: Vec<(u64, AlignedMemory<{ HOST_ALIGN }>)>,
+}
+
+impl WritableSections {
+    /// Allocates and initializes the writable sections of an executable
+    pub fn new<C: ContextObject>(executable: &Executable<C>) -> Self {
+        let elf_bytes = executable.elf_bytes.as_slice();
+        let sections = executable
+            .writable_sections
+            .iter()
+            .map(|section| {
+                let mut memory = AlignedMemory::zero_filled(section.len);
+                let initial_contents = &elf_bytes[section.initial_contents.clone()];
+                memory.as_slice_mut()[..initial_contents.len()].copy_from_slice(initial_contents);
+                (section.vm_addr, memory)
+            })
+            .collect();
+        Self { sections }
+    }
+
+    /// Restores the initial contents, so that the memory can be used for another execution
+    pub fn reset<C: ContextObject>(&mut self, executable: &Executable<C>) {
+        let elf_bytes = executable.elf_bytes.as_slice();
+        for ((_vm_addr, memory), section) in self
+            .sections
+            .iter_mut()
+            .zip(executable.writable_sections.iter())
+        {
+            let initial_contents = &elf_bytes[section.initial_contents.clone()];
+            let (initialized, zeroed) = memory.as_slice_mut().split_at_mut(initial_contents.len());
+            initialized.copy_from_slice(initial_contents);
+            zeroed.fill(0);
+        }
+    }
+
+    /// Get the memory regions to be mapped next to [Executable::get_ro_regions]
+    pub fn get_regions(&mut self) -> Vec<MemoryRegion> {
+        self.sections
+            .iter_mut()
+            .map(|(vm_addr, memory)| MemoryRegion::new_writable(memory.as_slice_mut(), *vm_addr))
+            .collect()
+    }
+}
+
+/// The bytes an [Executable] was loaded from
+#[derive(Debug, PartialEq)]
+enum ElfBytes {
+    /// Copied into memory owned by the executable
+    Owned(AlignedMemory<{ HOST_ALIGN }>),
+    /// Shared with the caller, see [Executable::load_shared]
+    Shared(std::sync::Arc<[u8]>),
+}
+
+impl ElfBytes {
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            Self::Owned(memory) => memory.as_slice(),
+            Self::Shared(bytes) => bytes,
+        }
+    }
+}
+
 /// Elf loader/relocator
 #[derive(Debug, PartialEq)]
 pub struct Executable<C: ContextObject> {
     /// Loaded and executable elf
-    elf_bytes: AlignedMemory<{ HOST_ALIGN }>,
+    elf_bytes: ElfBytes,
     /// Required SBPF capabilities
     sbpf_version: SBPFVersion,
     /// Read-only section
     ro_section: Section,
+    /// Read-only sections, each at its own virtual address
+    ro_sections: Vec<Section>,
+    /// Writable sections
+    writable_sections: Vec<WritableSection>,
     /// Text section virtual address
     text_section_vaddr: u64,
     /// Text section range in `elf_bytes`
@@ -246,6 +344,8 @@ pub struct Executable<C: ContextObject> {
     entry_pc: usize,
     /// Call resolution map (hash, pc, name)
     function_registry: FunctionRegistry<usize>,
+    /// Source locations and function names, only loaded with `enable_symbol_and_section_labels`
+    debug_info: Option<DebugInfo>,
     /// Loader built-in program
     loader: Arc<BuiltinProgram<C>>,
     /// Compiled program and argument
@@ -287,11 +387,81 @@ impl<C: ContextObject> Executable<C> {
         get_ro_region(&self.ro_section, self.elf_bytes.as_slice())
     }
 
+    /// Get one memory region per readonly section
+    ///
+    /// Unlike [Executable::get_ro_region] the gaps between the sections stay unmapped,
+    /// which leaves room for the regions of the [WritableSections].
+    pub fn get_ro_regions(&self) -> Vec<MemoryRegion> {
+        if self.get_config().aligned_memory_mapping || self.sbpf_version >= SBPFVersion::V4 {
+            // Only one region fits into the slot of the aligned memory mapping
+            return vec![self.get_ro_region()];
+        }
+        self.ro_sections
+            .iter()
+            .map(|section| get_ro_region(section, self.elf_bytes.as_slice()))
+            .collect()
+    }
+
+    /// Get the writable sections
+    pub fn get_writable_sections(&self) -> &[WritableSection] {
+        &self.writable_sections
+    }
+
+    /// Get the virtual address and contents of each readonly section
+    pub(crate) fn get_ro_section_contents(&self) -> Vec<(u64, &[u8])> {
+        self.ro_sections
+            .iter()
+            .map(|section| match section {
+                Section::Owned(vm_addr, data) => (*vm_addr as u64, data.as_slice()),
+                Section::Borrowed(vm_addr, byte_range) => (
+                    *vm_addr as u64,
+                    &self.elf_bytes.as_slice()[byte_range.clone()],
+                ),
+            })
+            .collect()
+    }
+
+    /// Get the initial contents of a writable section, empty for `.bss`
+    pub(crate) fn get_writable_section_contents(&self, section: &WritableSection) -> &[u8] {
+        &self.elf_bytes.as_slice()[section.initial_contents.clone()]
+    }
+
     /// Get the entry point offset into the text section
     pub fn get_entrypoint_instruction_offset(&self) -> usize {
         self.entry_pc
     }
 
+    /// Get the virtual addresses patched by relocations and the types of these relocations
+    ///
+    /// Returns `None` if the executable was not loaded from an ELF. Without its relocations,
+    /// addresses can not be told apart from numbers.
+    pub(crate) fn get_relocations(&self) -> Option<BTreeMap<u64, BpfRelocationType>> {
+        let elf = Elf64::parse(self.elf_bytes.as_slice()).ok()?;
+        let relocations = Self::collect_relocations(self.get_config(), &elf).ok()?;
+        Some(
+            relocations
+                .into_iter()
+                .filter_map(|(r_offset, relocation, _is_dynamic)| {
+                    let relocation_type =
+                        BpfRelocationType::from_x86_relocation_type(relocation.r_type())?;
+                    let section_header = elf.section_header_table().iter().find(|header| {
+                        header.sh_flags & SHF_ALLOC != 0
+                            && header
+                                .file_range()
+                                .is_some_and(|range| range.contains(&r_offset))
+                    })?;
+                    let mut vm_addr = section_header
+                        .sh_addr
+                        .saturating_add((r_offset as u64).saturating_sub(section_header.sh_offset));
+                    if vm_addr < ebpf::MM_REGION_SIZE {
+                        vm_addr = vm_addr.saturating_add(ebpf::MM_REGION_SIZE);
+                    }
+                    Some((vm_addr, relocation_type))
+                })
+                .collect(),
+        )
+    }
+
     /// Get the text section offset in the ELF file
     #[cfg(feature = "debugger")]
     pub fn get_text_section_offset(&self) -> u64 {
@@ -311,13 +481,7 @@ impl<C: ContextObject> Executable<C> {
 
     /// Verify the executable
     pub fn verify<V: Verifier>(&self) -> Result<(), EbpfError> {
-        <V as Verifier>::verify(
-            self.get_text_bytes().1,
-            self.get_config(),
-            self.get_sbpf_version(),
-            self.get_function_registry(),
-            self.loader.get_function_registry(),
-        )?;
+        <V as Verifier>::verify_executable(self)?;
         Ok(())
     }
 
@@ -329,11 +493,38 @@ impl<C: ContextObject> Executable<C> {
         Ok(())
     }
 
+    /// JIT compile the executable, relying on facts which the verifier proved about it
+    ///
+    /// # Safety
+    ///
+    /// Unsafe since the JIT leaves out the runtime checks which the facts make redundant. They
+    /// have to hold in every execution, like those of [crate::verifier::VerifiedFacts::new] do,
+    /// unless `Config::assert_verified_facts` is set.
+    #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
+    pub unsafe fn jit_compile_with_verified_facts(
+        &mut self,
+        verified_facts: &crate::verifier::VerifiedFacts,
+    ) -> Result<(), crate::error::EbpfError> {
+        let jit = JitCompiler::<C>::new(self)?.with_verified_facts(verified_facts);
+        self.compiled_program = Some(jit.compile()?);
+        Ok(())
+    }
+
     /// Get the function registry
     pub fn get_function_registry(&self) -> &FunctionRegistry<usize> {
         &self.function_registry
     }
 
+    /// Get the DWARF line tables and function names, if they were loaded
+    pub fn get_debug_info(&self) -> Option<&DebugInfo> {
+        self.debug_info.as_ref()
+    }
+
+    /// Get the source location of the instruction at `pc`
+    pub fn get_source_location(&self, pc: usize) -> Option<SourceLocation<'_>> {
+        self.debug_info.as_ref()?.source_location(pc)
+    }
+
     /// Create from raw text section bytes (list of instructions)
     pub fn new_from_text_bytes(
         text_bytes: &[u8],
@@ -341,7 +532,7 @@ impl<C: ContextObject> Executable<C> {
         sbpf_version: SBPFVersion,
         mut function_registry: FunctionRegistry<usize>,
     ) -> Result<Self, ElfError> {
-        let elf_bytes = AlignedMemory::from_slice(text_bytes);
+        let elf_bytes = ElfBytes::Owned(AlignedMemory::from_slice(text_bytes));
         let entry_pc = if let Some((_name, pc)) = function_registry.lookup_by_name(b"entrypoint") {
             pc
         } else {
@@ -353,29 +544,89 @@ impl<C: ContextObject> Executable<C> {
             )?;
             0
         };
+        let ro_section = Section::Borrowed(
+            if sbpf_version.enable_lower_rodata_vaddr() {
+                ebpf::MM_RODATA_START
+            } else {
+                ebpf::MM_BYTECODE_START
+            } as usize,
+            0..text_bytes.len(),
+        );
         Ok(Self {
             elf_bytes,
             sbpf_version,
-            ro_section: Section::Borrowed(
-                if sbpf_version.enable_lower_rodata_vaddr() {
-                    ebpf::MM_RODATA_START
-                } else {
-                    ebpf::MM_BYTECODE_START
-                } as usize,
-                0..text_bytes.len(),
-            ),
+            ro_sections: vec![ro_section.clone()],
+            ro_section,
+            writable_sections: Vec::new(),
             text_section_vaddr: ebpf::MM_BYTECODE_START,
             text_section_range: 0..text_bytes.len(),
             entry_pc,
             function_registry,
+            debug_info: None,
             loader,
             #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
             compiled_program: None,
         })
     }
 
+    /// Create from the segments of the strict ELF layout
+    ///
+    /// The rodata is mapped at [ebpf::MM_RODATA_START] and the bytecode at
+    /// [ebpf::MM_BYTECODE_START], like [Executable::load_with_strict_parser] does.
+    pub(crate) fn new_from_segments(
+        rodata: &[u8],
+        text_bytes: &[u8],
+        loader: Arc<BuiltinProgram<C>>,
+        sbpf_version: SBPFVersion,
+        entry_pc: usize,
+        function_registry: FunctionRegistry<usize>,
+    ) -> Self {
+        let elf_bytes = ElfBytes::Owned(AlignedMemory::from_slice(&[rodata, text_bytes].concat()));
+        let ro_section = Section::Borrowed(ebpf::MM_RODATA_START as usize, 0..rodata.len());
+        Self {
+            elf_bytes,
+            sbpf_version,
+            ro_sections: vec![ro_section.clone()],
+            ro_section,
+            writable_sections: Vec::new(),
+            text_section_vaddr: ebpf::MM_BYTECODE_START,
+            text_section_range: rodata.len()..rodata.len().saturating_add(text_bytes.len()),
+            entry_pc,
+            function_registry,
+            debug_info: None,
+            loader,
+            #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
+            compiled_program: None,
+        }
+    }
+
     /// Fully loads an ELF
     pub fn load(bytes: &[u8], loader: Arc<BuiltinProgram<C>>) -> Result<Self, ElfError> {
+        Self::load_impl(bytes, None, loader)
+    }
+
+    /// Fully loads an ELF, sharing its bytes with the caller instead of copying them
+    ///
+    /// SBPF v3 and later ELFs are not relocated, so their executables can refer to the bytes in
+    /// place, e.g. to a memory mapped file of a program cache. That requires the bytes to be
+    /// aligned to [HOST_ALIGN]. Otherwise, and for earlier SBPF versions, the bytes are copied
+    /// like by [Executable::load]. See [Executable::mem_size_shared].
+    ///
+    /// A plain `Arc<[u8]>` does not guarantee any alignment of its data beyond that of `u8`, it
+    /// depends on the allocator. If the bytes turn out to be misaligned, they are silently
+    /// copied, which [Executable::mem_size_shared] returning zero reveals.
+    pub fn load_shared(
+        bytes: std::sync::Arc<[u8]>,
+        loader: Arc<BuiltinProgram<C>>,
+    ) -> Result<Self, ElfError> {
+        Self::load_impl(&bytes, Some(&bytes), loader)
+    }
+
+    fn load_impl(
+        bytes: &[u8],
+        shared_bytes: Option<&std::sync::Arc<[u8]>>,
+        loader: Arc<BuiltinProgram<C>>,
+    ) -> Result<Self, ElfError> {
         const E_FLAGS_OFFSET: usize = 48;
         let e_flags = LittleEndian::read_u32(
             bytes
@@ -396,7 +647,15 @@ impl<C: ContextObject> Executable<C> {
         }
 
         let mut executable = if sbpf_version.enable_stricter_elf_headers() {
-            Self::load_with_strict_parser(bytes, loader)?
+            let elf_bytes = match shared_bytes {
+                Some(shared_bytes)
+                    if is_memory_aligned(shared_bytes.as_ptr() as usize, HOST_ALIGN) =>
+                {
+                    ElfBytes::Shared(shared_bytes.clone())
+                }
+                _ => ElfBytes::Owned(AlignedMemory::from_slice(bytes)),
+            };
+            Self::parse_with_strict_parser(elf_bytes, loader)?
         } else {
             Self::load_with_lenient_parser(bytes, loader)?
         };
@@ -408,14 +667,20 @@ impl<C: ContextObject> Executable<C> {
     pub fn load_with_strict_parser(
         bytes: &[u8],
         loader: Arc<BuiltinProgram<C>>,
+    ) -> Result<Self, ElfParserError> {
+        Self::parse_with_strict_parser(ElfBytes::Owned(AlignedMemory::from_slice(bytes)), loader)
+    }
+
+    fn parse_with_strict_parser(
+        bytes: ElfBytes,
+        loader: Arc<BuiltinProgram<C>>,
     ) -> Result<Self, ElfParserError> {
         use crate::elf_parser::{
             consts::{ELFMAG, EV_CURRENT, PF_R, PF_X, PT_LOAD, SHN_UNDEF, STT_FUNC},
             types::{Elf64Ehdr, Elf64Sym},
         };
 
-        let aligned_memory = AlignedMemory::<{ HOST_ALIGN }>::from_slice(bytes);
-        let elf_bytes = aligned_memory.as_slice();
+        let elf_bytes = bytes.as_slice();
 
         let (file_header_range, file_header) = Elf64::parse_file_header(elf_bytes)?;
         let program_header_table_range = mem::size_of::<Elf64Ehdr>()
@@ -516,6 +781,7 @@ impl<C: ContextObject> Executable<C> {
             .unwrap_or_default() as usize;
 
         let mut function_registry = FunctionRegistry::<usize>::default();
+        let mut debug_info = None;
         let config = loader.get_config();
         if config.enable_symbol_and_section_labels {
             let (_section_header_table_range, section_header_table) =
@@ -551,6 +817,14 @@ impl<C: ContextObject> Executable<C> {
                     symbol_table_section_header = Some(section_header);
                 }
             }
+            debug_info = parse_debug_info(
+                &DwarfSections::from_section_headers(
+                    elf_bytes,
+                    section_header_table,
+                    section_names_section_header,
+                ),
+                bytecode_header.vm_range(),
+            );
             let symbol_names_section_header = symbol_names_section_header.unwrap();
             let symbol_table: &[Elf64Sym] =
                 Elf64::slice_from_section_header(elf_bytes, symbol_table_section_header.unwrap())
@@ -578,13 +852,16 @@ impl<C: ContextObject> Executable<C> {
         }
 
         Ok(Self {
-            elf_bytes: aligned_memory,
+            elf_bytes: bytes,
             sbpf_version: SBPFVersion::Reserved, // Is set in Self::load()
+            ro_sections: vec![ro_section.clone()],
             ro_section,
+            writable_sections: Vec::new(),
             text_section_vaddr,
             text_section_range,
             entry_pc,
             function_registry,
+            debug_info,
             loader,
             #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
             compiled_program: None,
@@ -670,14 +947,28 @@ impl<C: ContextObject> Executable<C> {
             debug_assert_eq!(ro_section_vaddr, ebpf::MM_REGION_SIZE);
         }
 
+        let (ro_sections, writable_sections) = Self::parse_sections_separately(
+            config,
+            elf.section_header_table()
+                .iter()
+                .map(|s| (elf.section_name(s.sh_name).ok(), s)),
+            elf_bytes.as_slice(),
+        )?;
+
         Ok(Self {
-            elf_bytes,
+            elf_bytes: ElfBytes::Owned(elf_bytes),
             sbpf_version: SBPFVersion::Reserved, // Is set in Self::load()
             ro_section,
+            ro_sections,
+            writable_sections,
             text_section_vaddr,
             text_section_range: text_section.file_range().unwrap_or_default(),
             entry_pc,
             function_registry,
+            debug_info: config
+                .enable_symbol_and_section_labels
+                .then(|| parse_debug_info(&elf.dwarf_sections(), text_section.vm_range()))
+                .flatten(),
             loader,
             #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
             compiled_program: None,
@@ -685,20 +976,30 @@ impl<C: ContextObject> Executable<C> {
     }
 
     /// Calculate the total memory size of the executable
+    ///
+    /// Does not include the ELF bytes shared with the caller, see [Executable::mem_size_shared].
     #[rustfmt::skip]
     #[allow(clippy::size_of_ref)]
     pub fn mem_size(&self) -> usize {
         let mut total = mem::size_of::<Self>();
         total = total
             // elf bytes
-            .saturating_add(self.elf_bytes.mem_size())
+            .saturating_add(match &self.elf_bytes {
+                ElfBytes::Owned(memory) => memory.mem_size(),
+                ElfBytes::Shared(_bytes) => 0,
+            })
             // ro section
             .saturating_add(match &self.ro_section {
                 Section::Owned(_, data) => data.capacity(),
                 Section::Borrowed(_, _) => 0,
             })
+            // separate sections
+            .saturating_add(self.ro_sections.capacity().saturating_mul(mem::size_of::<Section>()))
+            .saturating_add(self.writable_sections.capacity().saturating_mul(mem::size_of::<WritableSection>()))
             // bpf functions
-            .saturating_add(self.function_registry.mem_size());
+            .saturating_add(self.function_registry.mem_size())
+            // debug info
+            .saturating_add(self.debug_info.as_ref().map_or(0, |debug_info| debug_info.mem_size()));
 
         #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
         {
@@ -709,6 +1010,16 @@ impl<C: ContextObject> Executable<C> {
         total
     }
 
+    /// Calculate the memory size of the ELF bytes shared with the caller
+    ///
+    /// Only non zero for executables loaded by [Executable::load_shared] without copying.
+    pub fn mem_size_shared(&self) -> usize {
+        match &self.elf_bytes {
+            ElfBytes::Owned(_memory) => 0,
+            ElfBytes::Shared(bytes) => bytes.len(),
+        }
+    }
+
     // Functions exposed for tests
 
     /// Validates the ELF
@@ -746,19 +1057,10 @@ impl<C: ContextObject> Executable<C> {
         }
 
         for section_header in elf.section_header_table().iter() {
-            if let Ok(name) = elf.section_name(section_header.sh_name) {
-                if name.starts_with(b".bss")
-                    || (section_header.is_writable()
-                        && (name.starts_with(b".data") && !name.starts_with(b".data.rel")))
-                {
-                    return Err(ElfError::WritableSectionNotSupported(
-                        String::from_utf8_lossy(name).to_string(),
-                    ));
-                }
+            if section_header.file_range().is_none() {
+                // .bss occupies no space in the file
+                continue;
             }
-        }
-
-        for section_header in elf.section_header_table().iter() {
             let start = section_header.sh_offset as usize;
             let end = section_header
                 .sh_offset
@@ -902,6 +1204,110 @@ impl<C: ContextObject> Executable<C> {
         Ok(ro_section)
     }
 
+    /// Collects the readonly and writable sections without merging them
+    ///
+    /// Every section keeps its own virtual address range, so no copies are needed.
+    pub fn parse_sections_separately<
+        'a,
+        S: IntoIterator<Item = (Option<&'a [u8]>, &'a Elf64Shdr)>,
+    >(
+        config: &Config,
+        sections: S,
+        elf_bytes: &[u8],
+    ) -> Result<(Vec<Section>, Vec<WritableSection>), ElfError> {
+        let mut ro_sections = Vec::new();
+        let mut writable_sections = Vec::new();
+        let mut vm_ranges = Vec::new();
+        for (name, section_header) in sections {
+            let name = match name {
+                Some(name) => name,
+                None => continue,
+            };
+            let is_ro = name == b".text"
+                || name == b".rodata"
+                || name == b".data.rel.ro"
+                || name == b".eh_frame";
+            let is_writable = name.starts_with(b".bss")
+                || (section_header.is_writable()
+                    && name.starts_with(b".data")
+                    && !name.starts_with(b".data.rel"));
+            if (!is_ro && !is_writable) || section_header.sh_size == 0 {
+                continue;
+            }
+            let vm_addr = if section_header.sh_addr < ebpf::MM_REGION_SIZE {
+                section_header.sh_addr.saturating_add(ebpf::MM_REGION_SIZE)
+            } else {
+                section_header.sh_addr
+            };
+            let vm_end = vm_addr.saturating_add(section_header.sh_size);
+            if vm_end > ebpf::MM_STACK_START {
+                return Err(ElfError::ValueOutOfBounds);
+            }
+            let file_range = section_header.file_range().unwrap_or_default();
+            if elf_bytes.get(file_range.clone()).is_none() {
+                return Err(ElfError::ValueOutOfBounds);
+            }
+            vm_ranges.push(vm_addr..vm_end);
+            if is_ro {
+                ro_sections.push(Section::Borrowed(vm_addr as usize, file_range));
+            } else {
+                if !config.enable_writable_sections || config.aligned_memory_mapping {
+                    return Err(ElfError::WritableSectionNotSupported(
+                        String::from_utf8_lossy(name).to_string(),
+                    ));
+                }
+                writable_sections.push(WritableSection {
+                    vm_addr,
+                    initial_contents: file_range,
+                    len: section_header.sh_size as usize,
+                });
+            }
+        }
+        vm_ranges.sort_by_key(|range| range.start);
+        if vm_ranges.windows(2).any(|pair| pair[0].end > pair[1].start) {
+            return Err(ElfError::ValueOutOfBounds);
+        }
+        Ok((ro_sections, writable_sections))
+    }
+
+    /// Lists the relocations with their offsets into the file and whether they are dynamic
+    fn collect_relocations<'a>(
+        config: &Config,
+        elf: &'a Elf64,
+    ) -> Result<Vec<(usize, &'a Elf64Rel, bool)>, ElfError> {
+        // Static relocation tables are only applied if there are no dynamic relocations,
+        // otherwise the linker has resolved them already
+        let static_relocation_tables =
+            if config.enable_upstream_relocations && elf.dynamic_relocations_table().is_none() {
+                elf.static_relocation_tables()?
+            } else {
+                Vec::new()
+            };
+        let mut relocations = elf
+            .dynamic_relocations_table()
+            .unwrap_or_default()
+            .iter()
+            .map(|relocation| (relocation.r_offset as usize, relocation, true))
+            .collect::<Vec<_>>();
+        for (section_header, relocation_table) in static_relocation_tables {
+            // Sections which are not loaded, like debug info, are left as they are
+            if section_header.sh_flags & SHF_ALLOC == 0 {
+                continue;
+            }
+            for relocation in relocation_table {
+                if !section_header.vm_range().contains(&relocation.r_offset) {
+                    return Err(ElfError::ValueOutOfBounds);
+                }
+                let r_offset = relocation
+                    .r_offset
+                    .saturating_sub(section_header.sh_addr)
+                    .saturating_add(section_header.sh_offset);
+                relocations.push((r_offset as usize, relocation, false));
+            }
+        }
+        Ok(relocations)
+    }
+
     /// Relocates the ELF in-place
     fn relocate(
         function_registry: &mut FunctionRegistry<usize>,
@@ -949,9 +1355,18 @@ impl<C: ContextObject> Executable<C> {
             }
         }
 
-        // Fixup all the relocations in the relocation section if exists
-        for relocation in elf.dynamic_relocations_table().unwrap_or_default().iter() {
-            let r_offset = relocation.r_offset as usize;
+        // Fixup all the relocations
+        for (r_offset, relocation, is_dynamic) in Self::collect_relocations(config, elf)? {
+            // Static relocations refer to the symbol table instead of the dynamic one
+            let symbol = || {
+                if is_dynamic {
+                    elf.dynamic_symbol_table()
+                } else {
+                    elf.symbol_table().ok().flatten()
+                }
+                .and_then(|table| table.get(relocation.r_sym() as usize).cloned())
+                .ok_or_else(|| ElfError::UnknownSymbol(relocation.r_sym() as usize))
+            };
 
             match BpfRelocationType::from_x86_relocation_type(relocation.r_type()) {
                 Some(BpfRelocationType::R_Bpf_64_64) => {
@@ -965,10 +1380,7 @@ impl<C: ContextObject> Executable<C> {
                         .ok_or(ElfError::ValueOutOfBounds)?;
                     let refd_addr = LittleEndian::read_u32(checked_slice) as u64;
 
-                    let symbol = elf
-                        .dynamic_symbol_table()
-                        .and_then(|table| table.get(relocation.r_sym() as usize).cloned())
-                        .ok_or_else(|| ElfError::UnknownSymbol(relocation.r_sym() as usize))?;
+                    let symbol = symbol()?;
 
                     // The relocated address is relative to the address of the
                     // symbol at index `r_sym`
@@ -1103,14 +1515,14 @@ impl<C: ContextObject> Executable<C> {
                     // Offset of the immediate field
                     let imm_offset = r_offset.saturating_add(BYTE_OFFSET_IMMEDIATE);
 
-                    let symbol = elf
-                        .dynamic_symbol_table()
-                        .and_then(|table| table.get(relocation.r_sym() as usize).cloned())
-                        .ok_or_else(|| ElfError::UnknownSymbol(relocation.r_sym() as usize))?;
+                    let symbol = symbol()?;
 
-                    let name = elf
-                        .dynamic_symbol_name(symbol.st_name as Elf64Word)
-                        .map_err(|_| ElfError::UnknownSymbol(symbol.st_name as usize))?;
+                    let name = if is_dynamic {
+                        elf.dynamic_symbol_name(symbol.st_name as Elf64Word)
+                    } else {
+                        elf.symbol_name(symbol.st_name as Elf64Word)
+                    }
+                    .map_err(|_| ElfError::UnknownSymbol(symbol.st_name as usize))?;
 
                     // If the symbol is defined, this is a bpf-to-bpf call
                     let key = if symbol.is_function() && symbol.st_value != 0 {
@@ -1145,6 +1557,34 @@ impl<C: ContextObject> Executable<C> {
                         .ok_or(ElfError::ValueOutOfBounds)?;
                     LittleEndian::write_u32(checked_slice, key);
                 }
+                Some(
+                    relocation_type @ (BpfRelocationType::R_Bpf_64_Abs64
+                    | BpfRelocationType::R_Bpf_64_Abs32),
+                ) if config.enable_upstream_relocations => {
+                    let size = if relocation_type == BpfRelocationType::R_Bpf_64_Abs64 {
+                        mem::size_of::<u64>()
+                    } else {
+                        mem::size_of::<u32>()
+                    };
+                    let checked_slice = elf_bytes
+                        .get_mut(r_offset..r_offset.saturating_add(size))
+                        .ok_or(ElfError::ValueOutOfBounds)?;
+                    let symbol = symbol()?;
+                    let mut addr = symbol
+                        .st_value
+                        .saturating_add(LittleEndian::read_uint(checked_slice, size));
+                    // Like for R_Bpf_64_64, the address has to be moved into the VM address
+                    // space, unless the symbol is an absolute value
+                    if symbol.st_shndx != SHN_ABS && addr < ebpf::MM_REGION_SIZE {
+                        addr = ebpf::MM_REGION_SIZE.saturating_add(addr);
+                    }
+                    if size == mem::size_of::<u32>() && addr > u32::MAX as u64 {
+                        return Err(ElfError::ValueOutOfBounds);
+                    }
+                    LittleEndian::write_uint(checked_slice, addr, size);
+                }
+                Some(BpfRelocationType::R_Bpf_64_NoDyld32)
+                    if config.enable_upstream_relocations => {}
                 _ => return Err(ElfError::UnknownRelocation(relocation.r_type())),
             }
         }
@@ -1186,6 +1626,14 @@ impl<C: ContextObject> Executable<C> {
     }
 }
 
+/// Debug information does not affect the execution, so broken one is dropped instead of
+/// failing the load
+fn parse_debug_info(sections: &DwarfSections, text_address: Range<u64>) -> Option<DebugInfo> {
+    DebugInfo::parse(sections, text_address)
+        .ok()
+        .filter(|debug_info| !debug_info.is_empty())
+}
+
 /// Creates a [MemoryRegion] for the given [Section]
 pub fn get_ro_region(ro_section: &Section, elf: &[u8]) -> MemoryRegion {
     let (offset, ro_data) = match ro_section {
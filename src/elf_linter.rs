@@ -0,0 +1,874 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! This module lints ELF files
+//!
+//! Unlike [Executable::load], which stops at the first problem, the linter walks
+//! the whole file and reports every problem it finds.
+
+use crate::{
+    aligned_memory::AlignedMemory,
+    ebpf::{self, HOST_ALIGN, INSN_SIZE},
+    elf::{BpfRelocationType, ElfError, Executable},
+    elf_parser::{
+        consts::{
+            ELFCLASS64, ELFDATA2LSB, ELFMAG, ELFOSABI_NONE, EM_BPF, EM_SBPF, ET_DYN, EV_CURRENT,
+            PF_R, PF_X, PT_LOAD, SHN_UNDEF, SHT_NOBITS, SHT_NULL, STT_FUNC,
+        },
+        types::{Elf64Ehdr, Elf64Phdr, Elf64Rel, Elf64Shdr, Elf64Sym, Elf64Word, ElfIdent},
+        Elf64, SECTION_NAME_LENGTH_MAXIMUM,
+    },
+    program::{BuiltinProgram, SBPFVersion},
+    vm::ContextObject,
+};
+use std::{fmt, mem, ops::Range};
+
+#[cfg(not(feature = "shuttle-test"))]
+use std::sync::Arc;
+
+#[cfg(feature = "shuttle-test")]
+use shuttle::sync::Arc;
+
+/// Severity of a [Finding] or a [crate::verifier::Diagnostic]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Accepted by the loader or verifier, but suspicious, not canonical or rejected by newer SBPF versions
+    Warning,
+    /// Rejected by the loader or verifier
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found by [lint_elf]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// How severe the problem is
+    pub severity: Severity,
+    /// Offset of the offending bytes in the ELF file
+    pub offset: usize,
+    /// Description of the problem
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at {:#x}: {}",
+            self.severity, self.offset, self.message
+        )
+    }
+}
+
+/// Lints an ELF file and returns all findings, ordered by their offset
+///
+/// The SBPF version is taken from `e_flags`, like [Executable::load] does. Problems
+/// which would only be rejected by the stricter ELF headers of SBPFv3 and later
+/// are reported as warnings for older versions.
+pub fn lint_elf<C: ContextObject>(
+    elf_bytes: &[u8],
+    loader: Arc<BuiltinProgram<C>>,
+) -> Vec<Finding> {
+    let aligned_memory = AlignedMemory::<{ HOST_ALIGN }>::from_slice(elf_bytes);
+    let mut linter = Linter {
+        elf_bytes: aligned_memory.as_slice(),
+        loader: &loader,
+        sbpf_version: SBPFVersion::Reserved,
+        findings: Vec::new(),
+    };
+    linter.lint();
+    let mut findings = linter.findings;
+    // Catch whatever the lints above do not cover
+    if findings
+        .iter()
+        .all(|finding| finding.severity != Severity::Error)
+    {
+        if let Err(err) = Executable::load(elf_bytes, loader.clone()) {
+            findings.push(Finding {
+                severity: Severity::Error,
+                offset: 0,
+                message: format!("rejected by the loader: {err}"),
+            });
+        }
+    }
+    findings.sort_by_key(|finding| finding.offset);
+    findings
+}
+
+struct Linter<'a, C: ContextObject> {
+    elf_bytes: &'a [u8],
+    loader: &'a BuiltinProgram<C>,
+    sbpf_version: SBPFVersion,
+    findings: Vec<Finding>,
+}
+
+impl<'a, C: ContextObject> Linter<'a, C> {
+    fn report(&mut self, severity: Severity, offset: usize, message: impl Into<String>) {
+        self.findings.push(Finding {
+            severity,
+            offset,
+            message: message.into(),
+        });
+    }
+
+    fn strict(&self) -> bool {
+        self.sbpf_version.enable_stricter_elf_headers()
+    }
+
+    /// Reports an error if the parser of the detected version rejects the value,
+    /// or a warning if only the strict parser would
+    fn check(&mut self, offset: usize, lenient_ok: bool, strict_ok: bool, message: &str) {
+        if self.strict() {
+            if !strict_ok {
+                self.report(Severity::Error, offset, message);
+            }
+        } else if !lenient_ok {
+            self.report(Severity::Error, offset, message);
+        } else if !strict_ok {
+            self.report(
+                Severity::Warning,
+                offset,
+                format!("{message}, which SBPFv3 and later reject"),
+            );
+        }
+    }
+
+    /// Severity of problems with the section headers, which the strict parser
+    /// only looks at for symbols
+    fn section_severity(&self, needed_for_symbols: bool) -> Severity {
+        let config = self.loader.get_config();
+        if !self.strict() || (needed_for_symbols && config.enable_symbol_and_section_labels) {
+            Severity::Error
+        } else {
+            Severity::Warning
+        }
+    }
+
+    /// Offset of a structure which was parsed from the ELF bytes
+    fn offset_of<T>(&self, value: &T) -> usize {
+        (value as *const T as usize).saturating_sub(self.elf_bytes.as_ptr() as usize)
+    }
+
+    fn lint(&mut self) {
+        let file_header = match Elf64::parse_file_header(self.elf_bytes) {
+            Ok((_range, file_header)) => file_header,
+            Err(err) => {
+                self.report(Severity::Error, 0, format!("invalid file header: {err}"));
+                return;
+            }
+        };
+        self.lint_file_header(file_header);
+        let program_header_table = self.lint_program_headers(file_header);
+        let sections = self.lint_section_headers(file_header, program_header_table.as_ref());
+        if self.strict() {
+            if let Some((_range, program_header_table)) = program_header_table {
+                self.lint_bytecode(file_header, program_header_table);
+            }
+            if self.loader.get_config().enable_symbol_and_section_labels {
+                self.lint_symbols(&sections);
+            }
+        } else {
+            self.lint_sections(file_header, &sections);
+            self.lint_relocations();
+        }
+    }
+
+    fn lint_file_header(&mut self, file_header: &Elf64Ehdr) {
+        let e_flags_offset = mem::offset_of!(Elf64Ehdr, e_flags);
+        self.sbpf_version = match file_header.e_flags {
+            0 => SBPFVersion::V0,
+            1 => SBPFVersion::V1,
+            2 => SBPFVersion::V2,
+            3 => SBPFVersion::V3,
+            4 => SBPFVersion::V4,
+            e_flags => {
+                self.report(
+                    Severity::Error,
+                    e_flags_offset,
+                    format!("unknown SBPF version {e_flags}"),
+                );
+                SBPFVersion::Reserved
+            }
+        };
+        if self.sbpf_version != SBPFVersion::Reserved
+            && !self
+                .loader
+                .get_config()
+                .enabled_sbpf_versions
+                .contains(&self.sbpf_version)
+        {
+            self.report(
+                Severity::Error,
+                e_flags_offset,
+                format!("SBPF version {:?} is not enabled", self.sbpf_version),
+            );
+        }
+
+        let ident = |offset: usize| mem::offset_of!(Elf64Ehdr, e_ident) + offset;
+        let e_ident = &file_header.e_ident;
+        let names_needed = self.loader.get_config().enable_symbol_and_section_labels;
+        #[rustfmt::skip]
+        let checks = [
+            (ident(mem::offset_of!(ElfIdent, ei_mag)), e_ident.ei_mag == ELFMAG, e_ident.ei_mag == ELFMAG, "invalid magic number"),
+            (ident(mem::offset_of!(ElfIdent, ei_class)), e_ident.ei_class == ELFCLASS64, e_ident.ei_class == ELFCLASS64, "class is not ELFCLASS64"),
+            (ident(mem::offset_of!(ElfIdent, ei_data)), e_ident.ei_data == ELFDATA2LSB, e_ident.ei_data == ELFDATA2LSB, "data encoding is not little endian"),
+            (ident(mem::offset_of!(ElfIdent, ei_version)), e_ident.ei_version == EV_CURRENT as u8, e_ident.ei_version == EV_CURRENT as u8, "ident version is not EV_CURRENT"),
+            (ident(mem::offset_of!(ElfIdent, ei_osabi)), e_ident.ei_osabi == ELFOSABI_NONE, e_ident.ei_osabi == ELFOSABI_NONE, "OS ABI is not ELFOSABI_NONE"),
+            (ident(mem::offset_of!(ElfIdent, ei_abiversion)), true, e_ident.ei_abiversion == 0, "ABI version is not 0"),
+            (ident(mem::offset_of!(ElfIdent, ei_pad)), true, e_ident.ei_pad == [0; 7], "ident padding is not zeroed"),
+            (mem::offset_of!(Elf64Ehdr, e_type), file_header.e_type == ET_DYN, true, "type is not ET_DYN"),
+            (mem::offset_of!(Elf64Ehdr, e_machine), file_header.e_machine == EM_BPF || file_header.e_machine == EM_SBPF, file_header.e_machine == EM_BPF, "machine is not EM_BPF"),
+            (mem::offset_of!(Elf64Ehdr, e_version), file_header.e_version == EV_CURRENT, file_header.e_version == EV_CURRENT, "version is not EV_CURRENT"),
+            (mem::offset_of!(Elf64Ehdr, e_phoff), true, file_header.e_phoff == mem::size_of::<Elf64Ehdr>() as u64, "program header table does not follow the file header"),
+            (mem::offset_of!(Elf64Ehdr, e_ehsize), file_header.e_ehsize == mem::size_of::<Elf64Ehdr>() as u16, file_header.e_ehsize == mem::size_of::<Elf64Ehdr>() as u16, "invalid file header size"),
+            (mem::offset_of!(Elf64Ehdr, e_phentsize), file_header.e_phentsize == mem::size_of::<Elf64Phdr>() as u16, file_header.e_phentsize == mem::size_of::<Elf64Phdr>() as u16, "invalid program header size"),
+            (mem::offset_of!(Elf64Ehdr, e_phnum), true, file_header.e_phnum != 0, "no program headers"),
+            (mem::offset_of!(Elf64Ehdr, e_shentsize), file_header.e_shentsize == mem::size_of::<Elf64Shdr>() as u16, true, "invalid section header size"),
+            (mem::offset_of!(Elf64Ehdr, e_shstrndx), file_header.e_shstrndx < file_header.e_shnum, !names_needed || (file_header.e_shstrndx != SHN_UNDEF && file_header.e_shstrndx < file_header.e_shnum), "invalid section name string table index"),
+        ];
+        for (offset, lenient_ok, strict_ok, message) in checks {
+            self.check(offset, lenient_ok, strict_ok, message);
+        }
+    }
+
+    fn lint_program_headers(
+        &mut self,
+        file_header: &Elf64Ehdr,
+    ) -> Option<(Range<usize>, &'a [Elf64Phdr])> {
+        let e_phoff_offset = mem::offset_of!(Elf64Ehdr, e_phoff);
+        let range = (file_header.e_phoff as usize)
+            ..(file_header.e_phoff as usize)
+                .saturating_add(mem::size_of::<Elf64Phdr>() * file_header.e_phnum as usize);
+        if !range.is_empty() && range.start < mem::size_of::<Elf64Ehdr>() {
+            self.report(
+                Severity::Error,
+                e_phoff_offset,
+                "program header table overlaps the file header",
+            );
+            return None;
+        }
+        let program_header_table =
+            match Elf64::slice_from_bytes::<Elf64Phdr>(self.elf_bytes, range.clone()) {
+                Ok(program_header_table) => program_header_table,
+                Err(err) => {
+                    self.report(
+                        Severity::Error,
+                        e_phoff_offset,
+                        format!("invalid program header table: {err}"),
+                    );
+                    return None;
+                }
+            };
+
+        // What the lenient parser checks
+        let mut vaddr = 0;
+        for program_header in program_header_table {
+            if program_header.p_type != PT_LOAD {
+                continue;
+            }
+            let offset = self.offset_of(program_header);
+            self.check(
+                offset + mem::offset_of!(Elf64Phdr, p_vaddr),
+                program_header.p_vaddr >= vaddr,
+                true,
+                "loadable segments are not in ascending order",
+            );
+            self.check(
+                offset + mem::offset_of!(Elf64Phdr, p_filesz),
+                program_header
+                    .p_offset
+                    .checked_add(program_header.p_filesz)
+                    .is_some_and(|end| end <= self.elf_bytes.len() as u64),
+                true,
+                "segment is out of bounds",
+            );
+            vaddr = program_header.p_vaddr;
+        }
+
+        // What the strict parser expects
+        let Some(first_program_header) = program_header_table.first() else {
+            return Some((range, program_header_table));
+        };
+        let mut expected_program_headers = [
+            ("readonly", PF_R, ebpf::MM_RODATA_START),
+            ("bytecode", PF_X, ebpf::MM_BYTECODE_START),
+        ]
+        .iter();
+        if first_program_header.p_flags != PF_R {
+            expected_program_headers.next();
+        } else if program_header_table.len() < 2 {
+            self.check(
+                mem::offset_of!(Elf64Ehdr, e_phnum),
+                true,
+                false,
+                "readonly segment is not followed by a bytecode segment",
+            );
+        }
+        let mut expected_offset = range.end as u64;
+        for (program_header, (kind, p_flags, p_vaddr)) in
+            program_header_table.iter().zip(expected_program_headers)
+        {
+            let mut fields = Vec::new();
+            if program_header.p_type != PT_LOAD {
+                fields.push("p_type");
+            }
+            if program_header.p_flags != *p_flags {
+                fields.push("p_flags");
+            }
+            if program_header.p_offset != expected_offset
+                || program_header.p_offset >= self.elf_bytes.len() as u64
+                || program_header.p_offset.checked_rem(INSN_SIZE as u64) != Some(0)
+            {
+                fields.push("p_offset");
+            }
+            if program_header.p_vaddr != *p_vaddr {
+                fields.push("p_vaddr");
+            }
+            if program_header.p_paddr != *p_vaddr {
+                fields.push("p_paddr");
+            }
+            if program_header.p_filesz
+                > (self.elf_bytes.len() as u64).saturating_sub(program_header.p_offset)
+                || program_header.p_filesz.checked_rem(INSN_SIZE as u64) != Some(0)
+            {
+                fields.push("p_filesz");
+            }
+            if program_header.p_filesz != program_header.p_memsz
+                || program_header.p_memsz >= ebpf::MM_REGION_SIZE
+            {
+                fields.push("p_memsz");
+            }
+            if !fields.is_empty() {
+                let offset = self.offset_of(program_header);
+                self.check(
+                    offset,
+                    true,
+                    false,
+                    &format!(
+                        "non-canonical {kind} program header ({})",
+                        fields.join(", ")
+                    ),
+                );
+            }
+            expected_offset = expected_offset.saturating_add(program_header.p_filesz);
+        }
+        Some((range, program_header_table))
+    }
+
+    fn lint_section_headers(
+        &mut self,
+        file_header: &Elf64Ehdr,
+        program_header_table: Option<&(Range<usize>, &[Elf64Phdr])>,
+    ) -> Vec<(Option<&'a [u8]>, &'a Elf64Shdr)> {
+        let e_shoff_offset = mem::offset_of!(Elf64Ehdr, e_shoff);
+        let file_header_range = 0..mem::size_of::<Elf64Ehdr>();
+        let program_header_table_range = program_header_table
+            .map(|(range, _table)| range.clone())
+            .unwrap_or_default();
+        let range = (file_header.e_shoff as usize)
+            ..(file_header.e_shoff as usize)
+                .saturating_add(mem::size_of::<Elf64Shdr>() * file_header.e_shnum as usize);
+        if overlaps(&range, &file_header_range) || overlaps(&range, &program_header_table_range) {
+            self.report(
+                self.section_severity(true),
+                e_shoff_offset,
+                "section header table overlaps the file or program headers",
+            );
+            return Vec::new();
+        }
+        let section_header_table =
+            match Elf64::slice_from_bytes::<Elf64Shdr>(self.elf_bytes, range.clone()) {
+                Ok(section_header_table) => section_header_table,
+                Err(err) => {
+                    self.report(
+                        self.section_severity(true),
+                        e_shoff_offset,
+                        format!("invalid section header table: {err}"),
+                    );
+                    return Vec::new();
+                }
+            };
+        if section_header_table
+            .first()
+            .is_some_and(|section_header| section_header.sh_type != SHT_NULL)
+        {
+            self.report(
+                self.section_severity(false),
+                range.start,
+                "first section is not SHT_NULL",
+            );
+        }
+
+        let section_names_section_header =
+            section_header_table.get(file_header.e_shstrndx as usize);
+        let maximum_name_length = if self.strict() {
+            64
+        } else {
+            SECTION_NAME_LENGTH_MAXIMUM
+        };
+        let mut sections = Vec::with_capacity(section_header_table.len());
+        let mut end_of_previous_section = 0;
+        for section_header in section_header_table {
+            let offset = self.offset_of(section_header);
+            let name = section_names_section_header.and_then(|section_names_section_header| {
+                match Elf64::get_string_in_section(
+                    self.elf_bytes,
+                    section_names_section_header,
+                    section_header.sh_name,
+                    maximum_name_length,
+                ) {
+                    Ok(name) => Some(name),
+                    Err(err) => {
+                        self.report(
+                            self.section_severity(true),
+                            offset + mem::offset_of!(Elf64Shdr, sh_name),
+                            format!("invalid section name: {err}"),
+                        );
+                        None
+                    }
+                }
+            });
+            sections.push((name, section_header));
+            if section_header.sh_type == SHT_NOBITS {
+                continue;
+            }
+            let display_name = String::from_utf8_lossy(name.unwrap_or_default());
+            let section_range = section_header.sh_offset as usize
+                ..(section_header.sh_offset as usize)
+                    .saturating_add(section_header.sh_size as usize);
+            if section_range.end > self.elf_bytes.len() {
+                self.report(
+                    self.section_severity(false),
+                    offset + mem::offset_of!(Elf64Shdr, sh_size),
+                    format!("section {display_name} is out of bounds"),
+                );
+            }
+            if overlaps(&section_range, &file_header_range)
+                || overlaps(&section_range, &program_header_table_range)
+                || overlaps(&section_range, &range)
+            {
+                self.report(
+                    self.section_severity(false),
+                    offset + mem::offset_of!(Elf64Shdr, sh_offset),
+                    format!("section {display_name} overlaps the file, program or section headers"),
+                );
+            }
+            if section_range.start < end_of_previous_section {
+                self.report(
+                    self.section_severity(false),
+                    offset + mem::offset_of!(Elf64Shdr, sh_offset),
+                    format!("section {display_name} overlaps the previous section or is not in ascending order"),
+                );
+            }
+            end_of_previous_section = end_of_previous_section.max(section_range.end);
+        }
+        sections
+    }
+
+    /// Lints the sections as the lenient parser loads them
+    fn lint_sections(&mut self, file_header: &Elf64Ehdr, sections: &[(Option<&[u8]>, &Elf64Shdr)]) {
+        let config = self.loader.get_config();
+        let text_sections = sections
+            .iter()
+            .filter(|(name, _section_header)| *name == Some(b".text".as_slice()))
+            .collect::<Vec<_>>();
+        if text_sections.len() != 1 {
+            self.report(
+                Severity::Error,
+                mem::offset_of!(Elf64Ehdr, e_shoff),
+                format!("expected one .text section, found {}", text_sections.len()),
+            );
+        }
+        if let Some((_name, text_section)) = text_sections.first() {
+            let offset = self.offset_of(*text_section);
+            if config.reject_broken_elfs && text_section.sh_addr != text_section.sh_offset {
+                self.report(
+                    Severity::Error,
+                    offset + mem::offset_of!(Elf64Shdr, sh_addr),
+                    "address of .text does not match its file offset",
+                );
+            }
+            let entrypoint_ok = text_section.vm_range().contains(&file_header.e_entry)
+                && (file_header.e_entry - text_section.sh_addr).checked_rem(INSN_SIZE as u64)
+                    == Some(0);
+            self.check(
+                mem::offset_of!(Elf64Ehdr, e_entry),
+                entrypoint_ok,
+                true,
+                "entrypoint is not an instruction of .text",
+            );
+        }
+
+        let mut vm_ranges = Vec::new();
+        for (name, section_header) in sections {
+            let Some(name) = name else {
+                continue;
+            };
+            let offset = self.offset_of(*section_header);
+            let display_name = String::from_utf8_lossy(name);
+            // Classify the section the same way the loader does
+            match Executable::<C>::parse_sections_separately(
+                config,
+                [(Some(*name), *section_header)],
+                self.elf_bytes,
+            ) {
+                Ok((ro_sections, writable_sections)) => {
+                    if ro_sections.is_empty() && writable_sections.is_empty() {
+                        continue;
+                    }
+                    if !ro_sections.is_empty() && section_header.sh_addr != section_header.sh_offset
+                    {
+                        self.report(
+                            if config.reject_broken_elfs {
+                                Severity::Error
+                            } else {
+                                Severity::Warning
+                            },
+                            offset + mem::offset_of!(Elf64Shdr, sh_addr),
+                            format!("address of {display_name} does not match its file offset"),
+                        );
+                    }
+                    if !writable_sections.is_empty() {
+                        self.report(
+                            Severity::Warning,
+                            offset,
+                            format!(
+                                "writable section {display_name}, which SBPFv3 and later reject"
+                            ),
+                        );
+                    }
+                }
+                Err(ElfError::WritableSectionNotSupported(_)) => {
+                    self.report(
+                        Severity::Error,
+                        offset,
+                        format!("writable section {display_name} requires enable_writable_sections and the unaligned memory mapping"),
+                    );
+                }
+                Err(_) => {
+                    self.report(
+                        Severity::Error,
+                        offset + mem::offset_of!(Elf64Shdr, sh_addr),
+                        format!("section {display_name} is outside of the readonly region"),
+                    );
+                    continue;
+                }
+            }
+            let vm_addr = section_header.sh_addr;
+            vm_ranges.push((
+                vm_addr..vm_addr.saturating_add(section_header.sh_size),
+                offset,
+            ));
+        }
+        vm_ranges.sort_by_key(|(vm_range, _offset)| vm_range.start);
+        for pair in vm_ranges.windows(2) {
+            if pair[0].0.end > pair[1].0.start {
+                self.report(
+                    Severity::Error,
+                    pair[1].1 + mem::offset_of!(Elf64Shdr, sh_addr),
+                    "section overlaps another section in memory",
+                );
+            }
+        }
+    }
+
+    /// Lints the relocations, relative calls and symbols the lenient parser processes
+    fn lint_relocations(&mut self) {
+        let elf = match Elf64::parse(self.elf_bytes) {
+            Ok(elf) => elf,
+            Err(err) => {
+                // Avoid repeating what the other lints already found
+                if self
+                    .findings
+                    .iter()
+                    .all(|finding| finding.severity != Severity::Error)
+                {
+                    self.report(Severity::Error, 0, format!("failed to parse: {err}"));
+                }
+                return;
+            }
+        };
+        let config = self.loader.get_config();
+        let text_section = elf.section_header_table().iter().find(|section_header| {
+            elf.section_name(section_header.sh_name).ok() == Some(b".text".as_slice())
+        });
+
+        if let Some(text_section) = text_section {
+            let text_range = text_section.file_range().unwrap_or_default();
+            let text_bytes = self.elf_bytes.get(text_range.clone()).unwrap_or_default();
+            let instruction_count = text_bytes.len() / INSN_SIZE;
+            for pc in 0..instruction_count {
+                let insn = ebpf::get_insn(text_bytes, pc);
+                if insn.opc == ebpf::CALL_IMM && insn.imm != -1 {
+                    let target_pc = pc as i64 + 1 + insn.imm;
+                    if target_pc < 0 || target_pc >= instruction_count as i64 {
+                        self.report(
+                            Severity::Error,
+                            text_range.start + pc * INSN_SIZE,
+                            format!("relative call at instruction #{pc} leaves .text"),
+                        );
+                    }
+                }
+            }
+        }
+
+        let relocations = elf.dynamic_relocations_table().unwrap_or_default();
+        if let Some(relocation) = relocations.first() {
+            self.report(
+                Severity::Warning,
+                self.offset_of(relocation),
+                format!(
+                    "{} dynamic relocations, which SBPFv3 and later do not support",
+                    relocations.len()
+                ),
+            );
+        }
+        for relocation in relocations {
+            self.lint_relocation(&elf, text_section, relocation);
+        }
+
+        if config.enable_symbol_and_section_labels {
+            for symbol in elf.symbol_table().ok().flatten().unwrap_or_default() {
+                if symbol.st_info & 0xEF != STT_FUNC {
+                    continue;
+                }
+                if !text_section
+                    .is_some_and(|text_section| text_section.vm_range().contains(&symbol.st_value))
+                {
+                    self.report(
+                        Severity::Error,
+                        self.offset_of(symbol) + mem::offset_of!(Elf64Sym, st_value),
+                        "function symbol is outside of .text",
+                    );
+                }
+            }
+        }
+    }
+
+    fn lint_relocation(
+        &mut self,
+        elf: &Elf64,
+        text_section: Option<&Elf64Shdr>,
+        relocation: &Elf64Rel,
+    ) {
+        let config = self.loader.get_config();
+        let offset = self.offset_of(relocation);
+        let relocation_type = match BpfRelocationType::from_x86_relocation_type(relocation.r_type())
+        {
+            Some(relocation_type) => relocation_type,
+            None => {
+                self.report(
+                    Severity::Error,
+                    offset + mem::offset_of!(Elf64Rel, r_info),
+                    format!("unknown relocation type {}", relocation.r_type()),
+                );
+                return;
+            }
+        };
+        if !config.enable_upstream_relocations
+            && matches!(
+                relocation_type,
+                BpfRelocationType::R_Bpf_64_Abs64
+                    | BpfRelocationType::R_Bpf_64_Abs32
+                    | BpfRelocationType::R_Bpf_64_NoDyld32
+            )
+        {
+            self.report(
+                Severity::Error,
+                offset + mem::offset_of!(Elf64Rel, r_info),
+                format!(
+                    "relocation type {} requires enable_upstream_relocations",
+                    relocation.r_type()
+                ),
+            );
+            return;
+        }
+        if matches!(
+            relocation_type,
+            BpfRelocationType::R_Bpf_None | BpfRelocationType::R_Bpf_64_NoDyld32
+        ) {
+            return;
+        }
+        let target_len = match relocation_type {
+            BpfRelocationType::R_Bpf_64_32 => INSN_SIZE,
+            BpfRelocationType::R_Bpf_64_Abs64 => mem::size_of::<u64>(),
+            BpfRelocationType::R_Bpf_64_Abs32 => mem::size_of::<u32>(),
+            // lddw spans two instruction slots
+            _ => INSN_SIZE * 2,
+        };
+        if (relocation.r_offset as usize).saturating_add(target_len) > self.elf_bytes.len() {
+            self.report(
+                Severity::Error,
+                offset + mem::offset_of!(Elf64Rel, r_offset),
+                "relocation target is out of bounds",
+            );
+            return;
+        }
+        if relocation_type == BpfRelocationType::R_Bpf_64_Relative {
+            return;
+        }
+        let Some(symbol) = elf
+            .dynamic_symbol_table()
+            .and_then(|table| table.get(relocation.r_sym() as usize))
+        else {
+            self.report(
+                Severity::Error,
+                offset + mem::offset_of!(Elf64Rel, r_info),
+                format!("unknown symbol with index {}", relocation.r_sym()),
+            );
+            return;
+        };
+        if relocation_type != BpfRelocationType::R_Bpf_64_32 {
+            return;
+        }
+        let name = match elf.dynamic_symbol_name(symbol.st_name as Elf64Word) {
+            Ok(name) => name,
+            Err(err) => {
+                self.report(
+                    Severity::Error,
+                    self.offset_of(symbol),
+                    format!("invalid symbol name: {err}"),
+                );
+                return;
+            }
+        };
+        let display_name = String::from_utf8_lossy(name);
+        if symbol.is_function() && symbol.st_value != 0 {
+            if !text_section
+                .is_some_and(|text_section| text_section.vm_range().contains(&symbol.st_value))
+            {
+                self.report(
+                    Severity::Error,
+                    self.offset_of(symbol) + mem::offset_of!(Elf64Sym, st_value),
+                    format!("function {display_name} is outside of .text"),
+                );
+            }
+        } else if self
+            .loader
+            .get_function_registry()
+            .lookup_by_key(ebpf::hash_symbol_name(name))
+            .is_none()
+        {
+            self.report(
+                if config.reject_broken_elfs {
+                    Severity::Error
+                } else {
+                    Severity::Warning
+                },
+                relocation.r_offset as usize,
+                format!("unresolved symbol {display_name}"),
+            );
+        }
+    }
+
+    /// Lints the static syscalls in the bytecode segment
+    fn lint_bytecode(&mut self, file_header: &Elf64Ehdr, program_header_table: &[Elf64Phdr]) {
+        let Some(bytecode_header) = program_header_table
+            .iter()
+            .find(|program_header| program_header.p_flags == PF_X)
+        else {
+            return;
+        };
+        let entrypoint_ok = bytecode_header.vm_range().contains(
+            &file_header
+                .e_entry
+                .saturating_add(INSN_SIZE as u64)
+                .saturating_sub(1),
+        ) && file_header.e_entry.checked_rem(INSN_SIZE as u64) == Some(0);
+        self.check(
+            mem::offset_of!(Elf64Ehdr, e_entry),
+            true,
+            entrypoint_ok,
+            "entrypoint is not an instruction of the bytecode segment",
+        );
+        let text_range = bytecode_header.file_range().unwrap_or_default();
+        let Some(text_bytes) = self.elf_bytes.get(text_range.clone()) else {
+            return;
+        };
+        for pc in 0..text_bytes.len() / INSN_SIZE {
+            let insn = ebpf::get_insn(text_bytes, pc);
+            if insn.opc == ebpf::CALL_IMM
+                && insn.src == 0
+                && self
+                    .loader
+                    .get_function_registry()
+                    .lookup_by_key(insn.imm as u32)
+                    .is_none()
+            {
+                // The loader accepts these, but they fail at runtime
+                self.report(
+                    Severity::Warning,
+                    text_range.start + pc * INSN_SIZE,
+                    format!(
+                        "unresolved syscall {:#x} at instruction #{pc}",
+                        insn.imm as u32
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Lints the symbol table the strict parser reads for function names
+    fn lint_symbols(&mut self, sections: &[(Option<&[u8]>, &Elf64Shdr)]) {
+        let find_section = |section_name: &[u8]| {
+            sections
+                .iter()
+                .find(|(name, _section_header)| *name == Some(section_name))
+                .map(|(_name, section_header)| *section_header)
+        };
+        let (Some(symbol_names_section_header), Some(symbol_table_section_header)) =
+            (find_section(b".strtab"), find_section(b".symtab"))
+        else {
+            self.report(
+                Severity::Error,
+                mem::offset_of!(Elf64Ehdr, e_shoff),
+                "symbol labels need a .symtab and a .strtab section",
+            );
+            return;
+        };
+        let symbol_table = match Elf64::slice_from_section_header::<Elf64Sym>(
+            self.elf_bytes,
+            symbol_table_section_header,
+        ) {
+            Ok(symbol_table) => symbol_table,
+            Err(err) => {
+                self.report(
+                    Severity::Error,
+                    self.offset_of(symbol_table_section_header),
+                    format!("invalid symbol table: {err}"),
+                );
+                return;
+            }
+        };
+        for symbol in symbol_table {
+            if symbol.st_info & STT_FUNC == 0 {
+                continue;
+            }
+            if let Err(err) = Elf64::get_string_in_section(
+                self.elf_bytes,
+                symbol_names_section_header,
+                symbol.st_name as Elf64Word,
+                u8::MAX as usize,
+            ) {
+                self.report(
+                    Severity::Error,
+                    self.offset_of(symbol),
+                    format!("invalid symbol name: {err}"),
+                );
+            }
+        }
+    }
+}
+
+fn overlaps(range_a: &Range<usize>, range_b: &Range<usize>) -> bool {
+    !range_a.is_empty()
+        && !range_b.is_empty()
+        && range_a.start < range_b.end
+        && range_b.start < range_a.end
+}
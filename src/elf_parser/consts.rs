@@ -72,6 +72,7 @@ pub const SHF_GROUP: Elf64Xword = 0x200;
 pub const SHF_TLS: Elf64Xword = 0x400;
 
 pub const SHN_UNDEF: Elf64Half = 0;
+pub const SHN_ABS: Elf64Half = 0xfff1;
 
 pub const DT_NULL: Elf64Xword = 0;
 pub const DT_NEEDED: Elf64Xword = 1;
@@ -124,6 +125,18 @@ pub const STT_HIOS: u8 = 12;
 pub const STT_LOPROC: u8 = 13;
 pub const STT_HIPROC: u8 = 15;
 
+pub const STB_LOCAL: u8 = 0;
+pub const STB_GLOBAL: u8 = 1;
+pub const STB_WEAK: u8 = 2;
+
+pub const R_BPF_NONE: u32 = 0;
+pub const R_BPF_64_64: u32 = 1;
+pub const R_BPF_64_ABS64: u32 = 2;
+pub const R_BPF_64_ABS32: u32 = 3;
+pub const R_BPF_64_NODYLD32: u32 = 4;
+pub const R_BPF_64_RELATIVE: u32 = 8;
+pub const R_BPF_64_32: u32 = 10;
+
 pub const R_X86_64_NONE: u32 = 0;
 pub const R_X86_64_64: u32 = 1;
 pub const R_X86_64_PC32: u32 = 2;
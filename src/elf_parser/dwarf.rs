@@ -0,0 +1,948 @@
+//! Parser for the DWARF sections which map instructions back to the source code
+//!
+//! Only the line number programs of `.debug_line` and the names and address ranges of the
+//! subprograms in `.debug_info` are extracted. Versions 2 to 5 are supported, split units
+//! and type units are skipped.
+
+use super::{types::Elf64Shdr, Elf64, ElfParserError};
+use std::{
+    collections::HashMap,
+    convert::{TryFrom, TryInto},
+    fmt,
+    ops::Range,
+};
+
+/// Longest section name the DWARF sections are looked up by
+const DWARF_SECTION_NAME_LENGTH_MAXIMUM: usize = 32;
+
+const DW_UT_COMPILE: u8 = 0x01;
+const DW_UT_PARTIAL: u8 = 0x03;
+const DW_UT_SKELETON: u8 = 0x04;
+const DW_UT_SPLIT_COMPILE: u8 = 0x05;
+
+const DW_TAG_SUBPROGRAM: u64 = 0x2e;
+
+const DW_AT_NAME: u64 = 0x03;
+const DW_AT_LOW_PC: u64 = 0x11;
+const DW_AT_HIGH_PC: u64 = 0x12;
+const DW_AT_ABSTRACT_ORIGIN: u64 = 0x31;
+const DW_AT_SPECIFICATION: u64 = 0x47;
+const DW_AT_LINKAGE_NAME: u64 = 0x6e;
+const DW_AT_STR_OFFSETS_BASE: u64 = 0x72;
+const DW_AT_ADDR_BASE: u64 = 0x73;
+const DW_AT_MIPS_LINKAGE_NAME: u64 = 0x2007;
+
+const DW_FORM_ADDR: u64 = 0x01;
+const DW_FORM_BLOCK2: u64 = 0x03;
+const DW_FORM_BLOCK4: u64 = 0x04;
+const DW_FORM_DATA2: u64 = 0x05;
+const DW_FORM_DATA4: u64 = 0x06;
+const DW_FORM_DATA8: u64 = 0x07;
+const DW_FORM_STRING: u64 = 0x08;
+const DW_FORM_BLOCK: u64 = 0x09;
+const DW_FORM_BLOCK1: u64 = 0x0a;
+const DW_FORM_DATA1: u64 = 0x0b;
+const DW_FORM_FLAG: u64 = 0x0c;
+const DW_FORM_SDATA: u64 = 0x0d;
+const DW_FORM_STRP: u64 = 0x0e;
+const DW_FORM_UDATA: u64 = 0x0f;
+const DW_FORM_REF_ADDR: u64 = 0x10;
+const DW_FORM_REF1: u64 = 0x11;
+const DW_FORM_REF2: u64 = 0x12;
+const DW_FORM_REF4: u64 = 0x13;
+const DW_FORM_REF8: u64 = 0x14;
+const DW_FORM_REF_UDATA: u64 = 0x15;
+const DW_FORM_INDIRECT: u64 = 0x16;
+const DW_FORM_SEC_OFFSET: u64 = 0x17;
+const DW_FORM_EXPRLOC: u64 = 0x18;
+const DW_FORM_FLAG_PRESENT: u64 = 0x19;
+const DW_FORM_STRX: u64 = 0x1a;
+const DW_FORM_ADDRX: u64 = 0x1b;
+const DW_FORM_REF_SUP4: u64 = 0x1c;
+const DW_FORM_STRP_SUP: u64 = 0x1d;
+const DW_FORM_DATA16: u64 = 0x1e;
+const DW_FORM_LINE_STRP: u64 = 0x1f;
+const DW_FORM_REF_SIG8: u64 = 0x20;
+const DW_FORM_IMPLICIT_CONST: u64 = 0x21;
+const DW_FORM_LOCLISTX: u64 = 0x22;
+const DW_FORM_RNGLISTX: u64 = 0x23;
+const DW_FORM_REF_SUP8: u64 = 0x24;
+const DW_FORM_STRX1: u64 = 0x25;
+const DW_FORM_STRX4: u64 = 0x28;
+const DW_FORM_ADDRX1: u64 = 0x29;
+const DW_FORM_ADDRX4: u64 = 0x2c;
+
+const DW_LNS_COPY: u8 = 0x01;
+const DW_LNS_ADVANCE_PC: u8 = 0x02;
+const DW_LNS_ADVANCE_LINE: u8 = 0x03;
+const DW_LNS_SET_FILE: u8 = 0x04;
+const DW_LNS_SET_COLUMN: u8 = 0x05;
+const DW_LNS_CONST_ADD_PC: u8 = 0x08;
+const DW_LNS_FIXED_ADVANCE_PC: u8 = 0x09;
+
+const DW_LNE_END_SEQUENCE: u8 = 0x01;
+const DW_LNE_SET_ADDRESS: u8 = 0x02;
+const DW_LNE_DEFINE_FILE: u8 = 0x03;
+
+const DW_LNCT_PATH: u64 = 0x1;
+const DW_LNCT_DIRECTORY_INDEX: u64 = 0x2;
+
+/// The DWARF sections of an ELF, missing ones are empty
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DwarfSections<'a> {
+    /// `.debug_abbrev`
+    pub debug_abbrev: &'a [u8],
+    /// `.debug_addr`
+    pub debug_addr: &'a [u8],
+    /// `.debug_info`
+    pub debug_info: &'a [u8],
+    /// `.debug_line`
+    pub debug_line: &'a [u8],
+    /// `.debug_line_str`
+    pub debug_line_str: &'a [u8],
+    /// `.debug_str`
+    pub debug_str: &'a [u8],
+    /// `.debug_str_offsets`
+    pub debug_str_offsets: &'a [u8],
+}
+
+impl<'a> DwarfSections<'a> {
+    /// Looks the sections up by their names
+    ///
+    /// Unlike [Elf64::section_name] this accepts names longer than
+    /// [SECTION_NAME_LENGTH_MAXIMUM](super::SECTION_NAME_LENGTH_MAXIMUM).
+    pub fn from_section_headers(
+        elf_bytes: &'a [u8],
+        section_header_table: &'a [Elf64Shdr],
+        section_names_section_header: &Elf64Shdr,
+    ) -> Self {
+        let mut sections = Self::default();
+        for section_header in section_header_table {
+            let Ok(name) = Elf64::get_string_in_section(
+                elf_bytes,
+                section_names_section_header,
+                section_header.sh_name,
+                DWARF_SECTION_NAME_LENGTH_MAXIMUM,
+            ) else {
+                continue;
+            };
+            let Some(contents) = section_header
+                .file_range()
+                .and_then(|range| elf_bytes.get(range))
+            else {
+                continue;
+            };
+            let section = match name {
+                b".debug_abbrev" => &mut sections.debug_abbrev,
+                b".debug_addr" => &mut sections.debug_addr,
+                b".debug_info" => &mut sections.debug_info,
+                b".debug_line" => &mut sections.debug_line,
+                b".debug_line_str" => &mut sections.debug_line_str,
+                b".debug_str" => &mut sections.debug_str,
+                b".debug_str_offsets" => &mut sections.debug_str_offsets,
+                _ => continue,
+            };
+            *section = contents;
+        }
+        sections
+    }
+}
+
+/// A position in the source code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SourceLocation<'a> {
+    /// Path of the source file
+    pub file: &'a str,
+    /// Line number, starting at 1
+    pub line: u32,
+    /// Column number, starting at 1 or 0 if unknown
+    pub column: u32,
+}
+
+impl fmt::Display for SourceLocation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)?;
+        if self.column != 0 {
+            write!(f, ":{}", self.column)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Row {
+    file: usize,
+    line: u32,
+    column: u32,
+}
+
+/// Source locations and function names of the instructions in the text section
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DebugInfo {
+    files: Vec<String>,
+    /// Sorted by pc, `None` marks the end of a sequence
+    rows: Vec<(usize, Option<Row>)>,
+    /// Sorted by the start of the range
+    functions: Vec<(Range<usize>, String)>,
+}
+
+impl DebugInfo {
+    /// Parses the line tables and subprograms of the text section at `text_address`
+    ///
+    /// Addresses are converted into instruction offsets relative to `text_address`,
+    /// everything outside of the text section is dropped.
+    pub fn parse(
+        sections: &DwarfSections,
+        text_address: Range<u64>,
+    ) -> Result<Self, ElfParserError> {
+        let mut parser = Parser {
+            sections,
+            text_address,
+            files: HashMap::new(),
+            debug_info: Self::default(),
+        };
+        parser.parse_line_programs()?;
+        parser.parse_units()?;
+        let mut debug_info = parser.debug_info;
+        debug_info
+            .rows
+            .sort_by_key(|(pc, row)| (*pc, row.is_some()));
+        debug_info
+            .functions
+            .sort_by_key(|(pcs, _name)| (pcs.start, pcs.end));
+        Ok(debug_info)
+    }
+
+    /// Returns true if there is neither a line table nor a function
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty() && self.functions.is_empty()
+    }
+
+    /// The source location the instruction at `pc` was generated from
+    pub fn source_location(&self, pc: usize) -> Option<SourceLocation<'_>> {
+        let index = self
+            .rows
+            .partition_point(|(row_pc, _row)| *row_pc <= pc)
+            .checked_sub(1)?;
+        let row = self.rows.get(index)?.1?;
+        // Line 0 marks instructions which are not attributable to any line
+        (row.line != 0).then(|| SourceLocation {
+            file: &self.files[row.file],
+            line: row.line,
+            column: row.column,
+        })
+    }
+
+    /// The name of the function which contains the instruction at `pc`
+    pub fn function_name(&self, pc: usize) -> Option<&str> {
+        let index = self
+            .functions
+            .partition_point(|(pcs, _name)| pcs.start <= pc)
+            .checked_sub(1)?;
+        let (pcs, name) = self.functions.get(index)?;
+        pcs.contains(&pc).then_some(name.as_str())
+    }
+
+    /// Estimate of the heap memory in bytes
+    pub fn mem_size(&self) -> usize {
+        self.files
+            .iter()
+            .chain(self.functions.iter().map(|(_pcs, name)| name))
+            .fold(0usize, |size, string| {
+                size.saturating_add(string.capacity())
+            })
+            .saturating_add(
+                self.rows
+                    .capacity()
+                    .saturating_mul(std::mem::size_of::<(usize, Option<Row>)>()),
+            )
+            .saturating_add(
+                self.functions
+                    .capacity()
+                    .saturating_mul(std::mem::size_of::<(Range<usize>, String)>()),
+            )
+    }
+}
+
+/// Sizes which the encoding of a unit depends on
+#[derive(Clone, Copy)]
+struct Encoding {
+    version: u16,
+    address_size: u8,
+    offset_size: u8,
+    /// Offset of the unit in its section, to which most references are relative
+    unit_offset: u64,
+}
+
+#[derive(Clone, Copy)]
+enum Value<'a> {
+    Unsigned(u64),
+    Address(u64),
+    AddressIndex(u64),
+    String(&'a [u8]),
+    StringOffset(u64),
+    LineStringOffset(u64),
+    StringIndex(u64),
+    Reference(u64),
+    Other,
+}
+
+impl Value<'_> {
+    fn as_u64(self) -> Option<u64> {
+        match self {
+            Self::Unsigned(value) | Self::Reference(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+struct Abbreviation {
+    tag: u64,
+    /// Name, form and implicit constant of each attribute
+    attributes: Vec<(u64, u64, i64)>,
+}
+
+struct Parser<'a, 'b> {
+    sections: &'b DwarfSections<'a>,
+    text_address: Range<u64>,
+    files: HashMap<String, usize>,
+    debug_info: DebugInfo,
+}
+
+impl<'a> Parser<'a, '_> {
+    /// Converts an address into an instruction offset, the end of the text section is
+    /// only valid for the end of a sequence
+    fn pc_of_address(&self, address: u64, is_end: bool) -> Option<usize> {
+        let in_text = if is_end {
+            (self.text_address.start..=self.text_address.end).contains(&address)
+        } else {
+            self.text_address.contains(&address)
+        };
+        in_text.then(|| {
+            address
+                .saturating_sub(self.text_address.start)
+                .checked_div(crate::ebpf::INSN_SIZE as u64)
+                .unwrap_or(0) as usize
+        })
+    }
+
+    fn intern_file(&mut self, directory: &[u8], name: &[u8]) -> usize {
+        let name = String::from_utf8_lossy(name);
+        let path = if directory.is_empty() || name.starts_with('/') {
+            name.to_string()
+        } else {
+            format!("{}/{}", String::from_utf8_lossy(directory), name)
+        };
+        let files = &mut self.debug_info.files;
+        *self.files.entry(path).or_insert_with_key(|path| {
+            files.push(path.clone());
+            files.len().saturating_sub(1)
+        })
+    }
+
+    fn string(&self, value: Value<'a>, str_offsets_base: u64, offset_size: u8) -> Option<&'a [u8]> {
+        match value {
+            Value::String(string) => Some(string),
+            Value::StringOffset(offset) => {
+                Reader::at(self.sections.debug_str, offset).read_str().ok()
+            }
+            Value::LineStringOffset(offset) => Reader::at(self.sections.debug_line_str, offset)
+                .read_str()
+                .ok(),
+            Value::StringIndex(index) => {
+                let offset = index
+                    .checked_mul(offset_size as u64)?
+                    .checked_add(str_offsets_base)?;
+                let offset = Reader::at(self.sections.debug_str_offsets, offset)
+                    .read_uint(offset_size)
+                    .ok()?;
+                Reader::at(self.sections.debug_str, offset).read_str().ok()
+            }
+            _ => None,
+        }
+    }
+
+    fn address(&self, value: Value, addr_base: u64, address_size: u8) -> Option<u64> {
+        match value {
+            Value::Address(address) => Some(address),
+            Value::AddressIndex(index) => {
+                let offset = index
+                    .checked_mul(address_size as u64)?
+                    .checked_add(addr_base)?;
+                Reader::at(self.sections.debug_addr, offset)
+                    .read_uint(address_size)
+                    .ok()
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_line_programs(&mut self) -> Result<(), ElfParserError> {
+        let mut reader = Reader::new(self.sections.debug_line);
+        while !reader.is_empty() {
+            let (mut unit, offset_size) = reader.read_unit()?;
+            let version = unit.read_u16()?;
+            if !(2..=5).contains(&version) {
+                return Err(ElfParserError::InvalidDebugInfo);
+            }
+            let mut address_size = 8;
+            if version >= 5 {
+                address_size = unit.read_u8()?;
+                let _segment_selector_size = unit.read_u8()?;
+            }
+            let header_length = unit.read_uint(offset_size)?;
+            let program_offset = (unit.offset as u64)
+                .checked_add(header_length)
+                .ok_or(ElfParserError::OutOfBounds)?;
+            let minimum_instruction_length = unit.read_u8()? as u64;
+            if version >= 4 {
+                let _maximum_operations_per_instruction = unit.read_u8()?;
+            }
+            let _default_is_stmt = unit.read_u8()?;
+            let line_base = unit.read_u8()? as i8;
+            let line_range = unit.read_u8()?;
+            let opcode_base = unit.read_u8()?;
+            if line_range == 0 || opcode_base == 0 {
+                return Err(ElfParserError::InvalidDebugInfo);
+            }
+            let standard_opcode_lengths = unit.read_bytes(opcode_base.saturating_sub(1) as usize)?;
+            let encoding = Encoding {
+                version,
+                address_size,
+                offset_size,
+                unit_offset: 0,
+            };
+
+            // Before version 5 the file and directory indices start at 1
+            let mut directories = Vec::new();
+            let mut file_ids = Vec::new();
+            if version >= 5 {
+                let formats = unit.read_entry_formats()?;
+                for _ in 0..unit.read_uleb128()? {
+                    let (path, _directory) = self.read_entry(&mut unit, &formats, encoding)?;
+                    directories.push(path);
+                }
+                let formats = unit.read_entry_formats()?;
+                for _ in 0..unit.read_uleb128()? {
+                    let (path, directory) = self.read_entry(&mut unit, &formats, encoding)?;
+                    let directory = directories.get(directory as usize).copied().unwrap_or(b"");
+                    file_ids.push(self.intern_file(directory, path));
+                }
+            } else {
+                directories.push(&b""[..]);
+                loop {
+                    let directory = unit.read_str()?;
+                    if directory.is_empty() {
+                        break;
+                    }
+                    directories.push(directory);
+                }
+                file_ids.push(usize::MAX);
+                loop {
+                    let name = unit.read_str()?;
+                    if name.is_empty() {
+                        break;
+                    }
+                    let file_id = self.read_legacy_file_entry(&mut unit, &directories, name)?;
+                    file_ids.push(file_id);
+                }
+            }
+            unit.seek(program_offset)?;
+
+            let mut address = 0u64;
+            let mut file = 1u64;
+            let mut line = 1u64;
+            let mut column = 0u64;
+            while !unit.is_empty() {
+                let opcode = unit.read_u8()?;
+                let mut emit_row = false;
+                if opcode >= opcode_base {
+                    let adjusted_opcode = opcode.saturating_sub(opcode_base);
+                    let address_advance =
+                        adjusted_opcode.checked_div(line_range).unwrap_or(0) as u64;
+                    address = address
+                        .wrapping_add(address_advance.wrapping_mul(minimum_instruction_length));
+                    let line_advance =
+                        (line_base as i64).saturating_add(
+                            adjusted_opcode.checked_rem(line_range).unwrap_or(0) as i64,
+                        );
+                    line = line.wrapping_add_signed(line_advance);
+                    emit_row = true;
+                } else if opcode == 0 {
+                    let length = unit.read_uleb128()?;
+                    let end = (unit.offset as u64)
+                        .checked_add(length)
+                        .ok_or(ElfParserError::OutOfBounds)?;
+                    match unit.read_u8()? {
+                        DW_LNE_END_SEQUENCE => {
+                            if let Some(pc) = self.pc_of_address(address, true) {
+                                self.debug_info.rows.push((pc, None));
+                            }
+                            (address, file, line, column) = (0, 1, 1, 0);
+                        }
+                        DW_LNE_SET_ADDRESS => {
+                            address = unit.read_uint(
+                                length.saturating_sub(1).min(encoding.address_size as u64) as u8,
+                            )?;
+                        }
+                        DW_LNE_DEFINE_FILE if version < 5 => {
+                            let name = unit.read_str()?;
+                            let file_id =
+                                self.read_legacy_file_entry(&mut unit, &directories, name)?;
+                            file_ids.push(file_id);
+                        }
+                        _ => {}
+                    }
+                    unit.seek(end)?;
+                } else {
+                    match opcode {
+                        DW_LNS_COPY => emit_row = true,
+                        DW_LNS_ADVANCE_PC => {
+                            address = address.wrapping_add(
+                                unit.read_uleb128()?
+                                    .wrapping_mul(minimum_instruction_length),
+                            );
+                        }
+                        DW_LNS_ADVANCE_LINE => {
+                            line = line.wrapping_add_signed(unit.read_sleb128()?);
+                        }
+                        DW_LNS_SET_FILE => file = unit.read_uleb128()?,
+                        DW_LNS_SET_COLUMN => column = unit.read_uleb128()?,
+                        DW_LNS_CONST_ADD_PC => {
+                            let address_advance = 255u8
+                                .saturating_sub(opcode_base)
+                                .checked_div(line_range)
+                                .unwrap_or(0)
+                                as u64;
+                            address = address.wrapping_add(
+                                address_advance.wrapping_mul(minimum_instruction_length),
+                            );
+                        }
+                        DW_LNS_FIXED_ADVANCE_PC => {
+                            address = address.wrapping_add(unit.read_u16()? as u64);
+                        }
+                        _ => {
+                            // Skip the operands of opcodes which do not affect the rows
+                            let operands = standard_opcode_lengths
+                                .get(opcode.saturating_sub(1) as usize)
+                                .copied()
+                                .unwrap_or(0);
+                            for _ in 0..operands {
+                                unit.read_uleb128()?;
+                            }
+                        }
+                    }
+                }
+                if emit_row {
+                    let file_id = usize::try_from(file)
+                        .ok()
+                        .and_then(|file| file_ids.get(file).copied())
+                        .filter(|file_id| *file_id != usize::MAX);
+                    if let (Some(pc), Some(file)) = (self.pc_of_address(address, false), file_id) {
+                        self.debug_info.rows.push((
+                            pc,
+                            Some(Row {
+                                file,
+                                line: line.min(u32::MAX as u64) as u32,
+                                column: column.min(u32::MAX as u64) as u32,
+                            }),
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a file entry of the header before version 5 or of `DW_LNE_define_file`
+    fn read_legacy_file_entry(
+        &mut self,
+        reader: &mut Reader,
+        directories: &[&[u8]],
+        name: &[u8],
+    ) -> Result<usize, ElfParserError> {
+        let directory = reader.read_uleb128()?;
+        let _modification_time = reader.read_uleb128()?;
+        let _length = reader.read_uleb128()?;
+        let directory = directories.get(directory as usize).copied().unwrap_or(b"");
+        Ok(self.intern_file(directory, name))
+    }
+
+    /// Reads a directory or file entry of a version 5 header
+    fn read_entry(
+        &self,
+        reader: &mut Reader<'a>,
+        formats: &[(u64, u64)],
+        encoding: Encoding,
+    ) -> Result<(&'a [u8], u64), ElfParserError> {
+        let mut path = &b""[..];
+        let mut directory = 0;
+        for (content_type, form) in formats {
+            let value = reader.read_value(*form, 0, encoding)?;
+            match *content_type {
+                DW_LNCT_PATH => {
+                    path = self.string(value, 0, encoding.offset_size).unwrap_or(b"");
+                }
+                DW_LNCT_DIRECTORY_INDEX => directory = value.as_u64().unwrap_or(0),
+                _ => {}
+            }
+        }
+        Ok((path, directory))
+    }
+
+    fn parse_units(&mut self) -> Result<(), ElfParserError> {
+        // Subprograms which got their name from another entry, resolved at the end
+        let mut names = HashMap::new();
+        let mut unnamed_functions = Vec::new();
+        let mut reader = Reader::new(self.sections.debug_info);
+        while !reader.is_empty() {
+            let unit_offset = reader.offset as u64;
+            let (mut unit, offset_size) = reader.read_unit()?;
+            let version = unit.read_u16()?;
+            let (unit_type, address_size, abbreviations_offset) = match version {
+                2..=4 => {
+                    let abbreviations_offset = unit.read_uint(offset_size)?;
+                    (DW_UT_COMPILE, unit.read_u8()?, abbreviations_offset)
+                }
+                5 => {
+                    let unit_type = unit.read_u8()?;
+                    let address_size = unit.read_u8()?;
+                    (unit_type, address_size, unit.read_uint(offset_size)?)
+                }
+                _ => return Err(ElfParserError::InvalidDebugInfo),
+            };
+            match unit_type {
+                DW_UT_COMPILE | DW_UT_PARTIAL => {}
+                DW_UT_SKELETON | DW_UT_SPLIT_COMPILE => {
+                    let _dwo_id = unit.read_bytes(8)?;
+                }
+                _ => continue,
+            }
+            let encoding = Encoding {
+                version,
+                address_size,
+                offset_size,
+                unit_offset,
+            };
+            let abbreviations =
+                parse_abbreviations(self.sections.debug_abbrev, abbreviations_offset)?;
+            let mut str_offsets_base = 0;
+            let mut addr_base = 0;
+            while !unit.is_empty() {
+                let entry_offset = unit.offset as u64;
+                let code = unit.read_uleb128()?;
+                if code == 0 {
+                    continue;
+                }
+                let abbreviation = abbreviations
+                    .get(&code)
+                    .ok_or(ElfParserError::InvalidDebugInfo)?;
+                let mut name = None;
+                let mut linkage_name = None;
+                let mut low_pc = None;
+                let mut high_pc = None;
+                let mut origin = None;
+                for (attribute, form, implicit_const) in abbreviation.attributes.iter() {
+                    let value = unit.read_value(*form, *implicit_const, encoding)?;
+                    match *attribute {
+                        DW_AT_NAME => name = Some(value),
+                        DW_AT_LINKAGE_NAME | DW_AT_MIPS_LINKAGE_NAME => linkage_name = Some(value),
+                        DW_AT_LOW_PC => low_pc = Some(value),
+                        DW_AT_HIGH_PC => high_pc = Some(value),
+                        DW_AT_SPECIFICATION | DW_AT_ABSTRACT_ORIGIN => {
+                            origin = value.as_u64();
+                        }
+                        DW_AT_STR_OFFSETS_BASE => str_offsets_base = value.as_u64().unwrap_or(0),
+                        DW_AT_ADDR_BASE => addr_base = value.as_u64().unwrap_or(0),
+                        _ => {}
+                    }
+                }
+
+                // The demangled linkage name is more specific than the plain name
+                let name = linkage_name
+                    .and_then(|value| self.string(value, str_offsets_base, offset_size))
+                    .map(|name| {
+                        format!(
+                            "{:#}",
+                            rustc_demangle::demangle(&String::from_utf8_lossy(name))
+                        )
+                    })
+                    .or_else(|| {
+                        name.and_then(|value| self.string(value, str_offsets_base, offset_size))
+                            .map(|name| String::from_utf8_lossy(name).to_string())
+                    });
+                if abbreviation.tag != DW_TAG_SUBPROGRAM {
+                    continue;
+                }
+                if let Some(name) = &name {
+                    names.insert(entry_offset, name.clone());
+                }
+                let Some(low_pc) =
+                    low_pc.and_then(|value| self.address(value, addr_base, address_size))
+                else {
+                    continue;
+                };
+                let high_pc = match high_pc {
+                    Some(Value::Unsigned(length)) => low_pc.checked_add(length),
+                    Some(value) => self.address(value, addr_base, address_size),
+                    None => None,
+                };
+                let (Some(start), Some(end)) = (
+                    self.pc_of_address(low_pc, false),
+                    high_pc.and_then(|high_pc| self.pc_of_address(high_pc, true)),
+                ) else {
+                    continue;
+                };
+                match (name, origin) {
+                    (Some(name), _) => self.debug_info.functions.push((start..end, name)),
+                    (None, Some(origin)) => unnamed_functions.push((start..end, origin)),
+                    (None, None) => {}
+                }
+            }
+        }
+        for (pcs, origin) in unnamed_functions {
+            if let Some(name) = names.get(&origin) {
+                self.debug_info.functions.push((pcs, name.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_abbreviations(
+    debug_abbrev: &[u8],
+    offset: u64,
+) -> Result<HashMap<u64, Abbreviation>, ElfParserError> {
+    let mut abbreviations = HashMap::new();
+    let mut reader = Reader::at(debug_abbrev, offset);
+    loop {
+        let code = reader.read_uleb128()?;
+        if code == 0 {
+            return Ok(abbreviations);
+        }
+        let tag = reader.read_uleb128()?;
+        let _has_children = reader.read_u8()?;
+        let mut attributes = Vec::new();
+        loop {
+            let attribute = reader.read_uleb128()?;
+            let form = reader.read_uleb128()?;
+            if attribute == 0 && form == 0 {
+                break;
+            }
+            let implicit_const = if form == DW_FORM_IMPLICIT_CONST {
+                reader.read_sleb128()?
+            } else {
+                0
+            };
+            attributes.push((attribute, form, implicit_const));
+        }
+        abbreviations.insert(code, Abbreviation { tag, attributes });
+    }
+}
+
+/// Little endian cursor into a section
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    /// Starts at `offset`, which is checked by the first read
+    fn at(bytes: &'a [u8], offset: u64) -> Self {
+        Self {
+            bytes,
+            offset: usize::try_from(offset).unwrap_or(usize::MAX),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.offset >= self.bytes.len()
+    }
+
+    fn seek(&mut self, offset: u64) -> Result<(), ElfParserError> {
+        self.offset = usize::try_from(offset)
+            .ok()
+            .filter(|offset| *offset <= self.bytes.len())
+            .ok_or(ElfParserError::OutOfBounds)?;
+        Ok(())
+    }
+
+    fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], ElfParserError> {
+        let end = self
+            .offset
+            .checked_add(length)
+            .ok_or(ElfParserError::OutOfBounds)?;
+        let bytes = self
+            .bytes
+            .get(self.offset..end)
+            .ok_or(ElfParserError::OutOfBounds)?;
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, ElfParserError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, ElfParserError> {
+        Ok(u16::from_le_bytes(self.read_bytes(2)?.try_into().unwrap()))
+    }
+
+    fn read_uint(&mut self, size: u8) -> Result<u64, ElfParserError> {
+        if size > 8 {
+            return Err(ElfParserError::InvalidDebugInfo);
+        }
+        Ok(self
+            .read_bytes(size as usize)?
+            .iter()
+            .rev()
+            .fold(0, |value, byte| value << 8 | *byte as u64))
+    }
+
+    fn read_uleb128(&mut self) -> Result<u64, ElfParserError> {
+        let mut value = 0u64;
+        let mut shift = 0u32;
+        loop {
+            let byte = self.read_u8()?;
+            value |= ((byte & 0x7f) as u64).checked_shl(shift).unwrap_or(0);
+            shift = shift.saturating_add(7);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn read_sleb128(&mut self) -> Result<i64, ElfParserError> {
+        let mut value = 0i64;
+        let mut shift = 0u32;
+        loop {
+            let byte = self.read_u8()?;
+            value |= ((byte & 0x7f) as i64).checked_shl(shift).unwrap_or(0);
+            shift = shift.saturating_add(7);
+            if byte & 0x80 == 0 {
+                if byte & 0x40 != 0 {
+                    value |= (-1i64).checked_shl(shift).unwrap_or(0);
+                }
+                return Ok(value);
+            }
+        }
+    }
+
+    fn read_str(&mut self) -> Result<&'a [u8], ElfParserError> {
+        let remaining = self
+            .bytes
+            .get(self.offset..)
+            .ok_or(ElfParserError::OutOfBounds)?;
+        let length = remaining
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or(ElfParserError::OutOfBounds)?;
+        self.offset = self.offset.saturating_add(length).saturating_add(1);
+        Ok(&remaining[..length])
+    }
+
+    /// Reads the initial length and returns a reader which ends with the unit
+    fn read_unit(&mut self) -> Result<(Self, u8), ElfParserError> {
+        let (length, offset_size) = match self.read_uint(4)? {
+            0xffff_ffff => (self.read_uint(8)?, 8),
+            length if length >= 0xffff_fff0 => return Err(ElfParserError::InvalidDebugInfo),
+            length => (length, 4),
+        };
+        let start = self.offset;
+        let contents = self.read_bytes(usize::try_from(length).unwrap_or(usize::MAX))?;
+        let unit = Self {
+            bytes: &self.bytes[..start.saturating_add(contents.len())],
+            offset: start,
+        };
+        Ok((unit, offset_size))
+    }
+
+    fn read_entry_formats(&mut self) -> Result<Vec<(u64, u64)>, ElfParserError> {
+        (0..self.read_u8()?)
+            .map(|_| Ok((self.read_uleb128()?, self.read_uleb128()?)))
+            .collect()
+    }
+
+    fn read_value(
+        &mut self,
+        form: u64,
+        implicit_const: i64,
+        encoding: Encoding,
+    ) -> Result<Value<'a>, ElfParserError> {
+        let offset_size = encoding.offset_size;
+        let unit_reference =
+            |offset: u64| Value::Reference(encoding.unit_offset.wrapping_add(offset));
+        Ok(match form {
+            DW_FORM_ADDR => Value::Address(self.read_uint(encoding.address_size)?),
+            DW_FORM_DATA1 | DW_FORM_FLAG => Value::Unsigned(self.read_uint(1)?),
+            DW_FORM_DATA2 => Value::Unsigned(self.read_uint(2)?),
+            DW_FORM_DATA4 => Value::Unsigned(self.read_uint(4)?),
+            DW_FORM_DATA8 => Value::Unsigned(self.read_uint(8)?),
+            DW_FORM_UDATA => Value::Unsigned(self.read_uleb128()?),
+            DW_FORM_SDATA => Value::Unsigned(self.read_sleb128()? as u64),
+            DW_FORM_IMPLICIT_CONST => Value::Unsigned(implicit_const as u64),
+            DW_FORM_FLAG_PRESENT => Value::Unsigned(1),
+            DW_FORM_SEC_OFFSET => Value::Unsigned(self.read_uint(offset_size)?),
+            DW_FORM_STRING => Value::String(self.read_str()?),
+            DW_FORM_STRP => Value::StringOffset(self.read_uint(offset_size)?),
+            DW_FORM_LINE_STRP => Value::LineStringOffset(self.read_uint(offset_size)?),
+            DW_FORM_STRX => Value::StringIndex(self.read_uleb128()?),
+            DW_FORM_STRX1..=DW_FORM_STRX4 => Value::StringIndex(
+                self.read_uint((form.saturating_sub(DW_FORM_STRX1) as u8).saturating_add(1))?,
+            ),
+            DW_FORM_ADDRX => Value::AddressIndex(self.read_uleb128()?),
+            DW_FORM_ADDRX1..=DW_FORM_ADDRX4 => Value::AddressIndex(
+                self.read_uint((form.saturating_sub(DW_FORM_ADDRX1) as u8).saturating_add(1))?,
+            ),
+            DW_FORM_REF1 => unit_reference(self.read_uint(1)?),
+            DW_FORM_REF2 => unit_reference(self.read_uint(2)?),
+            DW_FORM_REF4 => unit_reference(self.read_uint(4)?),
+            DW_FORM_REF8 => unit_reference(self.read_uint(8)?),
+            DW_FORM_REF_UDATA => unit_reference(self.read_uleb128()?),
+            DW_FORM_REF_ADDR => Value::Reference(self.read_uint(if encoding.version <= 2 {
+                encoding.address_size
+            } else {
+                offset_size
+            })?),
+            DW_FORM_INDIRECT => {
+                let form = self.read_uleb128()?;
+                return self.read_value(form, implicit_const, encoding);
+            }
+            DW_FORM_STRP_SUP => {
+                self.read_uint(offset_size)?;
+                Value::Other
+            }
+            DW_FORM_REF_SUP4 => {
+                self.read_bytes(4)?;
+                Value::Other
+            }
+            DW_FORM_REF_SIG8 | DW_FORM_REF_SUP8 => {
+                self.read_bytes(8)?;
+                Value::Other
+            }
+            DW_FORM_DATA16 => {
+                self.read_bytes(16)?;
+                Value::Other
+            }
+            DW_FORM_LOCLISTX | DW_FORM_RNGLISTX => {
+                self.read_uleb128()?;
+                Value::Other
+            }
+            DW_FORM_BLOCK1 | DW_FORM_BLOCK2 | DW_FORM_BLOCK4 | DW_FORM_BLOCK | DW_FORM_EXPRLOC => {
+                let length = match form {
+                    DW_FORM_BLOCK1 => self.read_uint(1)?,
+                    DW_FORM_BLOCK2 => self.read_uint(2)?,
+                    DW_FORM_BLOCK4 => self.read_uint(4)?,
+                    _ => self.read_uleb128()?,
+                };
+                self.read_bytes(usize::try_from(length).unwrap_or(usize::MAX))?;
+                Value::Other
+            }
+            _ => return Err(ElfParserError::InvalidDebugInfo),
+        })
+    }
+}
@@ -1,6 +1,7 @@
 //! Dependency-less 64 bit ELF parser
 
 pub mod consts;
+pub mod dwarf;
 pub mod types;
 
 use std::{fmt, mem, ops::Range, slice};
@@ -10,7 +11,8 @@ use {consts::*, types::*};
 
 /// Maximum length of section name allowed.
 pub const SECTION_NAME_LENGTH_MAXIMUM: usize = 16;
-const SYMBOL_NAME_LENGTH_MAXIMUM: usize = 64;
+/// Maximum length of symbol name allowed.
+pub const SYMBOL_NAME_LENGTH_MAXIMUM: usize = 64;
 
 /// Error definitions
 #[derive(Debug, PartialEq, Eq, thiserror::Error)]
@@ -60,6 +62,9 @@ pub enum ElfParserError {
     /// No dynamic string table
     #[error("no dynamic string table")]
     NoDynamicStringTable,
+    /// DWARF debug information is inconsistent or unsupported
+    #[error("invalid debug info")]
+    InvalidDebugInfo,
 }
 
 impl Elf64Phdr {
@@ -141,20 +146,37 @@ pub struct Elf64<'a> {
     dynamic_relocations_table: Option<&'a [Elf64Rel]>,
     dynamic_symbol_table: Option<&'a [Elf64Sym]>,
     dynamic_symbol_names_section_header: Option<&'a Elf64Shdr>,
+    section_name_length_maximum: usize,
 }
 
 impl<'a> Elf64<'a> {
     /// Parse from the given byte slice
     pub fn parse(elf_bytes: &'a [u8]) -> Result<Self, ElfParserError> {
+        Self::parse_impl(elf_bytes, false)
+    }
+
+    /// Parse a relocatable object (`ET_REL`) from the given byte slice
+    ///
+    /// Unlike linked files, relocatable objects have no program headers, may place their
+    /// sections in any order and name them after program types and attach points, which
+    /// can be up to [SYMBOL_NAME_LENGTH_MAXIMUM] long.
+    pub fn parse_relocatable(elf_bytes: &'a [u8]) -> Result<Self, ElfParserError> {
+        Self::parse_impl(elf_bytes, true)
+    }
+
+    fn parse_impl(elf_bytes: &'a [u8], relocatable: bool) -> Result<Self, ElfParserError> {
         let (file_header_range, file_header) = Self::parse_file_header(elf_bytes)?;
 
+        let program_header_size_is_valid = file_header.e_phentsize
+            == mem::size_of::<Elf64Phdr>() as u16
+            || (relocatable && file_header.e_phnum == 0);
         if file_header.e_ident.ei_mag != ELFMAG
             || file_header.e_ident.ei_class != ELFCLASS64
             || file_header.e_ident.ei_data != ELFDATA2LSB
             || file_header.e_ident.ei_version != EV_CURRENT as u8
             || file_header.e_version != EV_CURRENT
             || file_header.e_ehsize != mem::size_of::<Elf64Ehdr>() as u16
-            || file_header.e_phentsize != mem::size_of::<Elf64Phdr>() as u16
+            || !program_header_size_is_valid
             || file_header.e_shentsize != mem::size_of::<Elf64Shdr>() as u16
             || file_header.e_shstrndx >= file_header.e_shnum
         {
@@ -206,7 +228,7 @@ impl<'a> Elf64<'a> {
             check_that_there_is_no_overlap(&section_range, &file_header_range)?;
             check_that_there_is_no_overlap(&section_range, &program_header_table_range)?;
             check_that_there_is_no_overlap(&section_range, &section_header_table_range)?;
-            if section_range.start < offset {
+            if !relocatable && section_range.start < offset {
                 return Err(ElfParserError::SectionNotInOrder);
             }
             offset = section_range.end;
@@ -235,6 +257,11 @@ impl<'a> Elf64<'a> {
             dynamic_relocations_table: None,
             dynamic_symbol_table: None,
             dynamic_symbol_names_section_header: None,
+            section_name_length_maximum: if relocatable {
+                SYMBOL_NAME_LENGTH_MAXIMUM
+            } else {
+                SECTION_NAME_LENGTH_MAXIMUM
+            },
         };
 
         parser.parse_sections()?;
@@ -268,6 +295,31 @@ impl<'a> Elf64<'a> {
         self.dynamic_relocations_table
     }
 
+    /// Returns the static relocation tables, like `.rel.text`, each with the section it applies to.
+    pub fn static_relocation_tables(
+        &self,
+    ) -> Result<Vec<(&'a Elf64Shdr, &'a [Elf64Rel])>, ElfParserError> {
+        self.section_header_table
+            .iter()
+            // The dynamic relocations table is loaded and does not apply to a specific section
+            .filter(|section_header| {
+                section_header.sh_type == SHT_REL
+                    && section_header.sh_flags & SHF_ALLOC == 0
+                    && section_header.sh_info != 0
+            })
+            .map(|section_header| {
+                let target_section_header = self
+                    .section_header_table
+                    .get(section_header.sh_info as usize)
+                    .ok_or(ElfParserError::InvalidSectionHeader)?;
+                Ok((
+                    target_section_header,
+                    Self::slice_from_section_header(self.elf_bytes, section_header)?,
+                ))
+            })
+            .collect()
+    }
+
     /// Parses the file header.
     pub fn parse_file_header(
         elf_bytes: &'a [u8],
@@ -345,7 +397,7 @@ impl<'a> Elf64<'a> {
                 self.elf_bytes,
                 section_names_section_header,
                 section_header.sh_name,
-                SECTION_NAME_LENGTH_MAXIMUM,
+                self.section_name_length_maximum,
             )?;
             section_header_by_name!(
                 self, section_header, section_name,
@@ -502,10 +554,23 @@ impl<'a> Elf64<'a> {
             self.section_names_section_header
                 .ok_or(ElfParserError::NoSectionNameStringTable)?,
             sh_name,
-            SECTION_NAME_LENGTH_MAXIMUM,
+            self.section_name_length_maximum,
         )
     }
 
+    /// Returns the DWARF sections
+    pub fn dwarf_sections(&self) -> dwarf::DwarfSections<'a> {
+        self.section_names_section_header
+            .map(|section_names_section_header| {
+                dwarf::DwarfSections::from_section_headers(
+                    self.elf_bytes,
+                    self.section_header_table,
+                    section_names_section_header,
+                )
+            })
+            .unwrap_or_default()
+    }
+
     /// Returns the name of the `st_name` symbol
     pub fn symbol_name(&self, st_name: Elf64Word) -> Result<&'a [u8], ElfParserError> {
         Self::get_string_in_section(
@@ -638,7 +703,7 @@ impl fmt::Debug for Elf64<'_> {
                 self.elf_bytes,
                 self.section_names_section_header.unwrap(),
                 section_header.sh_name,
-                SECTION_NAME_LENGTH_MAXIMUM,
+                self.section_name_length_maximum,
             )
             .and_then(|name| std::str::from_utf8(name).map_err(|_| ElfParserError::InvalidString))
             .unwrap();
@@ -0,0 +1,581 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! This module serializes an [Executable] into a BPF ELF
+
+// Note: The output is not meant to reproduce the file an executable was loaded
+// from. It only has to contain what the loader looks at, so that loading it again
+// results in an equivalent executable.
+
+use crate::{
+    ebpf::{self, INSN_SIZE},
+    elf::{ElfError, Executable},
+    elf_parser::{
+        consts::{
+            DT_NULL, DT_REL, DT_RELENT, DT_RELSZ, DT_STRSZ, DT_STRTAB, DT_SYMTAB, ELFCLASS64,
+            ELFDATA2LSB, ELFMAG, ELFOSABI_NONE, EM_BPF, ET_DYN, EV_CURRENT, PF_R, PF_X, PT_LOAD,
+            R_X86_64_32, SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE, SHT_DYNAMIC, SHT_DYNSYM, SHT_NOBITS,
+            SHT_NULL, SHT_PROGBITS, SHT_REL, SHT_STRTAB, SHT_SYMTAB, STB_GLOBAL, STT_FUNC,
+            STT_NOTYPE,
+        },
+        types::{
+            Elf64Dyn, Elf64Ehdr, Elf64Half, Elf64Phdr, Elf64Rel, Elf64Shdr, Elf64Sym, Elf64Word,
+            Elf64Xword, ElfIdent,
+        },
+        SECTION_NAME_LENGTH_MAXIMUM, SYMBOL_NAME_LENGTH_MAXIMUM,
+    },
+    program::SBPFVersion,
+    vm::ContextObject,
+};
+use byteorder::{ByteOrder, LittleEndian};
+use std::{collections::BTreeMap, mem, slice};
+
+/// Serializes an executable into an ELF which [Executable::load] accepts
+///
+/// For versions with `enable_stricter_elf_headers` the file consists of the
+/// program headers the strict parser expects, followed by the rodata and the
+/// bytecode. Older versions get the section based layout of the lenient parser:
+/// Calls are turned back into relative calls and syscalls into relocations
+/// against dynamic symbols, so that the loader can relocate them again.
+///
+/// In both cases the named functions of the function registry are emitted in
+/// `.symtab`, so that they are recovered with `enable_symbol_and_section_labels`.
+pub fn write_elf<C: ContextObject>(executable: &Executable<C>) -> Result<Vec<u8>, ElfError> {
+    let e_flags = match executable.get_sbpf_version() {
+        SBPFVersion::V0 => 0,
+        SBPFVersion::V1 => 1,
+        SBPFVersion::V2 => 2,
+        SBPFVersion::V3 => 3,
+        SBPFVersion::V4 => 4,
+        SBPFVersion::Linux | SBPFVersion::Reserved => return Err(ElfError::UnsupportedSBPFVersion),
+    };
+    if executable.get_sbpf_version().enable_stricter_elf_headers() {
+        write_strict(executable, e_flags)
+    } else {
+        write_lenient(executable, e_flags)
+    }
+}
+
+fn write_strict<C: ContextObject>(
+    executable: &Executable<C>,
+    e_flags: Elf64Word,
+) -> Result<Vec<u8>, ElfError> {
+    let (text_vaddr, text) = executable.get_text_bytes();
+    // The strict parser only accepts segment sizes which are a multiple of the instruction size
+    let mut rodata = executable.get_ro_section().to_vec();
+    rodata.resize(align_up(rodata.len(), INSN_SIZE), 0);
+
+    let mut segments: Vec<(&[u8], _, _, _, &[u8])> = Vec::new();
+    if !rodata.is_empty() {
+        segments.push((
+            b".rodata",
+            PF_R,
+            SHF_ALLOC,
+            ebpf::MM_RODATA_START,
+            rodata.as_slice(),
+        ));
+    }
+    segments.push((b".text", PF_X, SHF_ALLOC | SHF_EXECINSTR, text_vaddr, text));
+
+    let mut builder =
+        ElfBuilder::new(mem::size_of::<Elf64Ehdr>() + segments.len() * mem::size_of::<Elf64Phdr>());
+    let mut program_headers = Vec::new();
+    let mut text_section_index = 0;
+    for (name, p_flags, sh_flags, vm_addr, data) in segments {
+        let offset = builder.len();
+        let index = builder.push_section(name, SHT_PROGBITS, sh_flags, offset, data)?;
+        builder.section_headers[index].sh_addr = vm_addr;
+        builder.section_headers[index].sh_addralign = INSN_SIZE as Elf64Xword;
+        if p_flags == PF_X {
+            text_section_index = index;
+        }
+        program_headers.push(Elf64Phdr {
+            p_type: PT_LOAD,
+            p_flags,
+            p_offset: offset as u64,
+            p_vaddr: vm_addr,
+            p_paddr: vm_addr,
+            p_filesz: data.len() as u64,
+            p_memsz: data.len() as u64,
+            p_align: INSN_SIZE as u64,
+        });
+    }
+    builder.push_symbol_table(executable, text_section_index, text_vaddr)?;
+
+    let e_entry = text_vaddr + (executable.get_entrypoint_instruction_offset() * INSN_SIZE) as u64;
+    builder.finish(e_flags, e_entry, &program_headers)
+}
+
+fn write_lenient<C: ContextObject>(
+    executable: &Executable<C>,
+    e_flags: Elf64Word,
+) -> Result<Vec<u8>, ElfError> {
+    let (text_vaddr, text) = executable.get_text_bytes();
+    let header_size = mem::size_of::<Elf64Ehdr>();
+
+    // The lenient parser expects every section to be at the file offset which
+    // matches its virtual address (relative to MM_REGION_SIZE).
+    let mut sections = Vec::new();
+    for (vm_addr, data) in executable.get_ro_section_contents() {
+        let (name, sh_flags): (&[u8], _) = if vm_addr == text_vaddr {
+            (b".text", SHF_ALLOC | SHF_EXECINSTR)
+        } else {
+            (b".rodata", SHF_ALLOC)
+        };
+        sections.push((name, SHT_PROGBITS, sh_flags, vm_addr, data, data.len()));
+    }
+    for section in executable.get_writable_sections() {
+        let data = executable.get_writable_section_contents(section);
+        let (name, sh_type): (&[u8], _) = if data.is_empty() {
+            (b".bss", SHT_NOBITS)
+        } else {
+            (b".data", SHT_PROGBITS)
+        };
+        sections.push((
+            name,
+            sh_type,
+            SHF_ALLOC | SHF_WRITE,
+            section.vm_addr,
+            data,
+            section.len,
+        ));
+    }
+    sections.sort_by_key(|section| section.3);
+    if sections
+        .iter()
+        .filter(|section| section.0 == b".text")
+        .count()
+        != 1
+    {
+        return Err(ElfError::NotOneTextSection);
+    }
+
+    // Executables which were not loaded from an ELF (e.g. assembled ones) start
+    // at the beginning of the region, where the file header has to go. Their
+    // sections are stored further up in the file but keep their addresses, which
+    // makes the loader copy them to where they belong.
+    let lowest_offset = sections
+        .first()
+        .map(|section| section.3.saturating_sub(ebpf::MM_REGION_SIZE))
+        .unwrap_or_default();
+    let displacement = if lowest_offset < header_size as u64 {
+        align_up(header_size - lowest_offset as usize, 64) as u64
+    } else {
+        0
+    };
+    let section_addr = |vm_addr: u64| vm_addr.saturating_sub(ebpf::MM_REGION_SIZE);
+    let text_addr = section_addr(text_vaddr);
+    let text_offset = text_addr + displacement;
+
+    let mut text = text.to_vec();
+    let mut syscalls = Vec::new();
+    let mut pc = 0;
+    while (pc + 1) * INSN_SIZE <= text.len() {
+        let insn = ebpf::get_insn(&text, pc);
+        let imm_offset = pc * INSN_SIZE + 4;
+        if insn.opc == ebpf::LD_DW_IMM {
+            pc += 2;
+            continue;
+        }
+        if insn.opc == ebpf::CALL_IMM {
+            // The loader has replaced the immediates by function registry keys
+            let key = insn.imm as u32;
+            let imm = if let Some((_name, target_pc)) =
+                executable.get_function_registry().lookup_by_key(key)
+            {
+                target_pc as i64 - pc as i64 - 1
+            } else if let Some((name, _function)) = executable
+                .get_loader()
+                .get_function_registry()
+                .lookup_by_key(key)
+            {
+                syscalls.push((pc, name));
+                -1
+            } else {
+                return Err(ElfError::UnresolvedSymbol(
+                    format!("{key:#x}"),
+                    pc,
+                    text_offset as usize + pc * INSN_SIZE,
+                ));
+            };
+            LittleEndian::write_i32(&mut text[imm_offset..], imm as i32);
+        }
+        pc += 1;
+    }
+
+    let mut builder = ElfBuilder::new(header_size);
+    let mut text_section_index = 0;
+    for (name, sh_type, sh_flags, vm_addr, data, len) in sections {
+        let addr = section_addr(vm_addr);
+        let offset = addr + displacement;
+        let data = if name == b".text" {
+            text.as_slice()
+        } else {
+            data
+        };
+        let index = if sh_type == SHT_NOBITS {
+            builder.push_nobits_section(name, sh_flags, offset, len)?
+        } else {
+            builder.push_section(name, sh_type, sh_flags, offset as usize, data)?
+        };
+        builder.section_headers[index].sh_addr = addr;
+        if name == b".text" {
+            text_section_index = index;
+        }
+    }
+    if !syscalls.is_empty() {
+        builder.push_dynamic_relocations(&syscalls, text_offset)?;
+    }
+    builder.push_symbol_table(executable, text_section_index, text_addr)?;
+
+    let e_entry = text_addr + (executable.get_entrypoint_instruction_offset() * INSN_SIZE) as u64;
+    builder.finish(e_flags, e_entry, &[])
+}
+
+fn align_up(value: usize, alignment: usize) -> usize {
+    value.div_ceil(alignment) * alignment
+}
+
+/// Plain ELF structures which can be written as they are in memory
+///
+/// # Safety
+///
+/// Only for `#[repr(C)]` types without padding bytes.
+unsafe trait ElfStruct {}
+
+unsafe impl ElfStruct for u8 {}
+unsafe impl ElfStruct for Elf64Ehdr {}
+unsafe impl ElfStruct for Elf64Phdr {}
+unsafe impl ElfStruct for Elf64Shdr {}
+unsafe impl ElfStruct for Elf64Sym {}
+unsafe impl ElfStruct for Elf64Rel {}
+unsafe impl ElfStruct for Elf64Dyn {}
+
+/// Reinterprets a slice of ELF structures as bytes
+fn as_bytes<T: ElfStruct>(values: &[T]) -> &[u8] {
+    unsafe { slice::from_raw_parts(values.as_ptr().cast::<u8>(), mem::size_of_val(values)) }
+}
+
+/// A string table which starts with the empty string
+struct StringTable {
+    bytes: Vec<u8>,
+}
+
+impl StringTable {
+    fn new() -> Self {
+        Self { bytes: vec![0] }
+    }
+
+    fn insert(&mut self, string: &[u8], maximum_length: usize) -> Result<Elf64Word, ElfError> {
+        // The parser needs to find the terminator within the maximum length
+        if string.len() >= maximum_length {
+            return Err(ElfError::ValueOutOfBounds);
+        }
+        let offset = self.bytes.len() as Elf64Word;
+        self.bytes.extend_from_slice(string);
+        self.bytes.push(0);
+        Ok(offset)
+    }
+}
+
+/// Accumulates the file contents behind the file and program headers
+struct ElfBuilder {
+    bytes: Vec<u8>,
+    section_headers: Vec<Elf64Shdr>,
+    section_names: StringTable,
+}
+
+impl ElfBuilder {
+    fn new(header_size: usize) -> Self {
+        Self {
+            bytes: vec![0; header_size],
+            section_headers: vec![section_header(0, SHT_NULL, 0, 0, 0)],
+            section_names: StringTable::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    fn push_section(
+        &mut self,
+        name: &[u8],
+        sh_type: Elf64Word,
+        sh_flags: Elf64Xword,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<usize, ElfError> {
+        // Sections must be in ascending order and must not overlap
+        if offset < self.bytes.len() {
+            return Err(ElfError::ValueOutOfBounds);
+        }
+        self.bytes.resize(offset, 0);
+        self.bytes.extend_from_slice(data);
+        let sh_name = self
+            .section_names
+            .insert(name, SECTION_NAME_LENGTH_MAXIMUM)?;
+        self.section_headers.push(section_header(
+            sh_name,
+            sh_type,
+            sh_flags,
+            offset as u64,
+            data.len() as u64,
+        ));
+        Ok(self.section_headers.len() - 1)
+    }
+
+    fn push_nobits_section(
+        &mut self,
+        name: &[u8],
+        sh_flags: Elf64Xword,
+        offset: u64,
+        len: usize,
+    ) -> Result<usize, ElfError> {
+        let sh_name = self
+            .section_names
+            .insert(name, SECTION_NAME_LENGTH_MAXIMUM)?;
+        self.section_headers.push(section_header(
+            sh_name, SHT_NOBITS, sh_flags, offset, len as u64,
+        ));
+        Ok(self.section_headers.len() - 1)
+    }
+
+    /// Appends an aligned section, which is not loaded, at the end of the file
+    fn append_section<T: ElfStruct>(
+        &mut self,
+        name: &[u8],
+        sh_type: Elf64Word,
+        sh_flags: Elf64Xword,
+        entries: &[T],
+    ) -> Result<usize, ElfError> {
+        let offset = align_up(self.bytes.len(), mem::align_of::<T>());
+        let index = self.push_section(name, sh_type, sh_flags, offset, as_bytes(entries))?;
+        let section_header = &mut self.section_headers[index];
+        section_header.sh_addralign = mem::align_of::<T>() as Elf64Xword;
+        if mem::size_of::<T>() > 1 {
+            section_header.sh_entsize = mem::size_of::<T>() as Elf64Xword;
+        }
+        Ok(index)
+    }
+
+    /// Emits the syscalls as `R_BPF_64_32` relocations against undefined dynamic symbols
+    fn push_dynamic_relocations(
+        &mut self,
+        syscalls: &[(usize, &[u8])],
+        text_offset: u64,
+    ) -> Result<(), ElfError> {
+        let mut dynamic_symbol_names = StringTable::new();
+        let mut dynamic_symbols = vec![null_symbol()];
+        let mut symbol_indices = BTreeMap::new();
+        let mut relocations = Vec::with_capacity(syscalls.len());
+        for (pc, name) in syscalls {
+            let symbol_index = if let Some(symbol_index) = symbol_indices.get(name) {
+                *symbol_index
+            } else {
+                dynamic_symbols.push(Elf64Sym {
+                    st_name: dynamic_symbol_names.insert(name, SYMBOL_NAME_LENGTH_MAXIMUM)?,
+                    st_info: STB_GLOBAL << 4 | STT_NOTYPE,
+                    st_other: 0,
+                    st_shndx: 0,
+                    st_value: 0,
+                    st_size: 0,
+                });
+                let symbol_index = dynamic_symbols.len() as u64 - 1;
+                symbol_indices.insert(*name, symbol_index);
+                symbol_index
+            };
+            relocations.push(Elf64Rel {
+                r_offset: text_offset + (pc * INSN_SIZE) as u64,
+                r_info: symbol_index << 32 | R_X86_64_32 as u64,
+            });
+        }
+
+        // The entries are filled in once the addresses of the other sections are known
+        let dynamic_table_len = 7;
+        let mut dynamic_table = vec![
+            Elf64Dyn {
+                d_tag: DT_NULL,
+                d_val: 0,
+            };
+            dynamic_table_len
+        ];
+        let dynamic_index =
+            self.append_section(b".dynamic", SHT_DYNAMIC, SHF_ALLOC, &dynamic_table)?;
+        let dynamic_symbols_index =
+            self.append_section(b".dynsym", SHT_DYNSYM, SHF_ALLOC, &dynamic_symbols)?;
+        let dynamic_symbol_names_index = self.append_section(
+            b".dynstr",
+            SHT_STRTAB,
+            SHF_ALLOC,
+            &dynamic_symbol_names.bytes,
+        )?;
+        let relocations_index =
+            self.append_section(b".rel.dyn", SHT_REL, SHF_ALLOC, &relocations)?;
+        // The parser looks these sections up by their addresses
+        for index in [
+            dynamic_index,
+            dynamic_symbols_index,
+            dynamic_symbol_names_index,
+            relocations_index,
+        ] {
+            self.section_headers[index].sh_addr = self.section_headers[index].sh_offset;
+        }
+        self.section_headers[dynamic_index].sh_link = dynamic_symbol_names_index as Elf64Word;
+        self.section_headers[dynamic_symbols_index].sh_link =
+            dynamic_symbol_names_index as Elf64Word;
+        self.section_headers[dynamic_symbols_index].sh_info = 1;
+        self.section_headers[relocations_index].sh_link = dynamic_symbols_index as Elf64Word;
+
+        for (entry, (d_tag, d_val)) in dynamic_table.iter_mut().zip([
+            (DT_REL, self.section_headers[relocations_index].sh_addr),
+            (DT_RELSZ, self.section_headers[relocations_index].sh_size),
+            (DT_RELENT, mem::size_of::<Elf64Rel>() as u64),
+            (
+                DT_SYMTAB,
+                self.section_headers[dynamic_symbols_index].sh_addr,
+            ),
+            (
+                DT_STRTAB,
+                self.section_headers[dynamic_symbol_names_index].sh_addr,
+            ),
+            (
+                DT_STRSZ,
+                self.section_headers[dynamic_symbol_names_index].sh_size,
+            ),
+        ]) {
+            *entry = Elf64Dyn { d_tag, d_val };
+        }
+        let offset = self.section_headers[dynamic_index].sh_offset as usize;
+        self.bytes[offset..offset + mem::size_of_val(dynamic_table.as_slice())]
+            .copy_from_slice(as_bytes(&dynamic_table));
+        Ok(())
+    }
+
+    /// Emits the named functions of the function registry as `.symtab` and `.strtab`
+    fn push_symbol_table<C: ContextObject>(
+        &mut self,
+        executable: &Executable<C>,
+        text_section_index: usize,
+        text_address: u64,
+    ) -> Result<(), ElfError> {
+        let mut symbol_names = StringTable::new();
+        let mut symbols = vec![null_symbol()];
+        let mut functions = executable
+            .get_function_registry()
+            .iter()
+            .filter(|(_key, (name, _pc))| !name.is_empty())
+            .map(|(_key, (name, pc))| (pc, name))
+            .collect::<Vec<_>>();
+        functions.sort();
+        functions.dedup_by_key(|(_pc, name)| *name);
+        for (pc, name) in functions {
+            symbols.push(Elf64Sym {
+                st_name: symbol_names.insert(name, SYMBOL_NAME_LENGTH_MAXIMUM)?,
+                st_info: STB_GLOBAL << 4 | STT_FUNC,
+                st_other: 0,
+                st_shndx: text_section_index as u16,
+                st_value: text_address + (pc * INSN_SIZE) as u64,
+                st_size: 0,
+            });
+        }
+        let symbols_index = self.append_section(b".symtab", SHT_SYMTAB, 0, &symbols)?;
+        let symbol_names_index =
+            self.append_section(b".strtab", SHT_STRTAB, 0, &symbol_names.bytes)?;
+        self.section_headers[symbols_index].sh_link = symbol_names_index as Elf64Word;
+        self.section_headers[symbols_index].sh_info = 1;
+        Ok(())
+    }
+
+    /// Appends the section names and the section header table and writes the headers
+    fn finish(
+        mut self,
+        e_flags: Elf64Word,
+        e_entry: u64,
+        program_headers: &[Elf64Phdr],
+    ) -> Result<Vec<u8>, ElfError> {
+        let section_names_name = self
+            .section_names
+            .insert(b".shstrtab", SECTION_NAME_LENGTH_MAXIMUM)?;
+        let section_names_offset = self.bytes.len();
+        let section_names_len = self.section_names.bytes.len();
+        self.bytes.extend_from_slice(&self.section_names.bytes);
+        self.section_headers.push(section_header(
+            section_names_name,
+            SHT_STRTAB,
+            0,
+            section_names_offset as u64,
+            section_names_len as u64,
+        ));
+
+        let e_shoff = align_up(self.bytes.len(), mem::align_of::<Elf64Shdr>());
+        self.bytes.resize(e_shoff, 0);
+        self.bytes
+            .extend_from_slice(as_bytes(&self.section_headers));
+
+        let file_header = Elf64Ehdr {
+            e_ident: ElfIdent {
+                ei_mag: ELFMAG,
+                ei_class: ELFCLASS64,
+                ei_data: ELFDATA2LSB,
+                ei_version: EV_CURRENT as u8,
+                ei_osabi: ELFOSABI_NONE,
+                ei_abiversion: 0,
+                ei_pad: [0; 7],
+            },
+            e_type: ET_DYN,
+            e_machine: EM_BPF,
+            e_version: EV_CURRENT,
+            e_entry,
+            e_phoff: if program_headers.is_empty() {
+                0
+            } else {
+                mem::size_of::<Elf64Ehdr>() as u64
+            },
+            e_shoff: e_shoff as u64,
+            e_flags,
+            e_ehsize: mem::size_of::<Elf64Ehdr>() as Elf64Half,
+            e_phentsize: mem::size_of::<Elf64Phdr>() as Elf64Half,
+            e_phnum: program_headers.len() as Elf64Half,
+            e_shentsize: mem::size_of::<Elf64Shdr>() as Elf64Half,
+            e_shnum: self.section_headers.len() as Elf64Half,
+            e_shstrndx: (self.section_headers.len() - 1) as Elf64Half,
+        };
+        let file_header = as_bytes(slice::from_ref(&file_header));
+        self.bytes[..file_header.len()].copy_from_slice(file_header);
+        let program_headers = as_bytes(program_headers);
+        self.bytes[file_header.len()..file_header.len() + program_headers.len()]
+            .copy_from_slice(program_headers);
+        Ok(self.bytes)
+    }
+}
+
+fn section_header(
+    sh_name: Elf64Word,
+    sh_type: Elf64Word,
+    sh_flags: Elf64Xword,
+    sh_offset: u64,
+    sh_size: u64,
+) -> Elf64Shdr {
+    Elf64Shdr {
+        sh_name,
+        sh_type,
+        sh_flags,
+        sh_addr: 0,
+        sh_offset,
+        sh_size,
+        sh_link: 0,
+        sh_info: 0,
+        sh_addralign: if sh_type == SHT_NULL { 0 } else { 1 },
+        sh_entsize: 0,
+    }
+}
+
+fn null_symbol() -> Elf64Sym {
+    Elf64Sym {
+        st_name: 0,
+        st_info: 0,
+        st_other: 0,
+        st_shndx: 0,
+        st_value: 0,
+        st_size: 0,
+    }
+}
@@ -72,6 +72,12 @@ pub enum EbpfError {
     /// Syscall error
     #[error("Syscall error: {0}")]
     SyscallError(Box<dyn Error>),
+    /// A fact in the [crate::verifier::VerifiedFacts] the JIT was given did not hold
+    #[error("verified fact violated at BPF instruction")]
+    VerifiedFactViolated,
+    /// Atomic memory access which is not naturally aligned
+    #[error("unaligned atomic access at address {0:#x}")]
+    UnalignedAtomicAccess(u64),
 }
 
 /// Same as `Result` but provides a stable memory layout
@@ -250,6 +250,18 @@ impl BpfCode {
         }
     }
 
+    /// creates ATOMIC read-modify-write instruction, MEMORY is the destination
+    pub fn atomic(&mut self, mem_size: MemSize, op: AtomicOp) -> Atomic<'_> {
+        Atomic {
+            bpf_code: self,
+            mem_size,
+            insn: Insn {
+                imm: op as i64,
+                ..Insn::default()
+            },
+        }
+    }
+
     /// create unconditional JMP instruction
     pub fn jump_unconditional(&mut self) -> Jump<'_> {
         self.jump_conditional(Cond::Abs, Source::Imm)
@@ -496,6 +508,54 @@ pub enum MemSize {
     DoubleWord = BPF_DW as isize,
 }
 
+/// struct representation of ATOMIC instructions
+pub struct Atomic<'i> {
+    bpf_code: &'i mut BpfCode,
+    mem_size: MemSize,
+    insn: Insn,
+}
+
+impl<'i> Atomic<'i> {
+    /// push ATOMIC instruction into BpfCode instruction stack
+    pub fn push(self) -> &'i mut BpfCode {
+        let mut asm = self.into_bytes();
+        self.bpf_code.instructions.append(&mut asm);
+        self.bpf_code
+    }
+}
+
+impl Instruction for Atomic<'_> {
+    fn opt_code_byte(&self) -> u8 {
+        let size = self.mem_size as u8;
+        BPF_ATOMIC | BPF_STX | size
+    }
+
+    fn get_insn_mut(&mut self) -> &mut Insn {
+        &mut self.insn
+    }
+
+    fn get_insn(&self) -> &Insn {
+        &self.insn
+    }
+}
+
+#[derive(Copy, Clone)]
+#[cfg_attr(
+    feature = "fuzzer-not-safe-for-production",
+    derive(arbitrary::Arbitrary, Debug, PartialEq, Eq)
+)]
+/// Read-modify-write operation of ATOMIC instructions
+pub enum AtomicOp {
+    /// `*(dst + off) += src`
+    Add = ATOMIC_ADD as isize,
+    /// `*(dst + off) += src`, src receives the previous value
+    FetchAdd = ATOMIC_FETCH_ADD as isize,
+    /// `*(dst + off) = src`, src receives the previous value
+    Exchange = ATOMIC_XCHG as isize,
+    /// `*(dst + off) = src` if it equals r0, r0 receives the previous value
+    CompareExchange = ATOMIC_CMPXCHG as isize,
+}
+
 #[derive(Copy, Clone)]
 enum Addressing {
     Imm = BPF_IMM as isize,
@@ -1112,6 +1172,74 @@ mod tests {
     }
 
     #[cfg(test)]
+    mod atomic_instructions {
+        use super::super::*;
+
+        #[test]
+        fn atomic_add_word_from_src_into_dst_address() {
+            let mut program = BpfCode::new();
+            program
+                .atomic(MemSize::Word, AtomicOp::Add)
+                .set_dst(0x01)
+                .set_src(0x02)
+                .set_off(0x00_11)
+                .push();
+
+            assert_eq!(
+                program.into_bytes(),
+                &[0xc3, 0x21, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00]
+            );
+        }
+
+        #[test]
+        fn atomic_fetch_add_double_word_from_src_into_dst_address() {
+            let mut program = BpfCode::new();
+            program
+                .atomic(MemSize::DoubleWord, AtomicOp::FetchAdd)
+                .set_dst(0x01)
+                .set_src(0x02)
+                .set_off(0x00_11)
+                .push();
+
+            assert_eq!(
+                program.into_bytes(),
+                &[0xdb, 0x21, 0x11, 0x00, 0x01, 0x00, 0x00, 0x00]
+            );
+        }
+
+        #[test]
+        fn atomic_exchange_double_word_from_src_into_dst_address() {
+            let mut program = BpfCode::new();
+            program
+                .atomic(MemSize::DoubleWord, AtomicOp::Exchange)
+                .set_dst(0x01)
+                .set_src(0x02)
+                .set_off(0x00_11)
+                .push();
+
+            assert_eq!(
+                program.into_bytes(),
+                &[0xdb, 0x21, 0x11, 0x00, 0xe1, 0x00, 0x00, 0x00]
+            );
+        }
+
+        #[test]
+        fn atomic_compare_exchange_word_from_src_into_dst_address() {
+            let mut program = BpfCode::new();
+            program
+                .atomic(MemSize::Word, AtomicOp::CompareExchange)
+                .set_dst(0x01)
+                .set_src(0x02)
+                .set_off(0x00_11)
+                .push();
+
+            assert_eq!(
+                program.into_bytes(),
+                &[0xc3, 0x21, 0x11, 0x00, 0xf1, 0x00, 0x00, 0x00]
+            );
+        }
+    }
+
     mod load_instructions {
         #[cfg(test)]
         mod register {
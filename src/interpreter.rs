@@ -16,9 +16,11 @@ use crate::{
     ebpf,
     elf::Executable,
     error::{EbpfError, ProgramResult},
+    memory_region::ShadowBitmap,
     program::BuiltinFunction,
     vm::{Config, ContextObject, EbpfVm},
 };
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 
 /// Virtual memory operation helper.
 macro_rules! translate_memory_access {
@@ -35,13 +37,16 @@ macro_rules! translate_memory_access {
     };
 
     // MemoryMapping::load()
-    ($self:ident, load, $vm_addr:ident, $T:ty) => {
-        translate_memory_access!(_impl, $self, load, $vm_addr, $T,)
-    };
+    ($self:ident, load, $vm_addr:ident, $T:ty) => {{
+        let value = translate_memory_access!(_impl, $self, load, $vm_addr, $T,);
+        $self.check_stack_shadow($vm_addr, std::mem::size_of::<$T>() as u64);
+        value
+    }};
 
     // MemoryMapping::store()
     ($self:ident, store, $value:expr, $vm_addr:ident, $T:ty) => {
         translate_memory_access!(_impl, $self, store, $vm_addr, $T, ($value) as $T);
+        $self.mark_stack_shadow($vm_addr, std::mem::size_of::<$T>() as u64);
     };
 }
 
@@ -63,6 +68,32 @@ macro_rules! throw_error {
     };
 }
 
+/// Linux eBPF `div` / `mod`, which yield a result for any divisor instead of trapping
+fn non_trapping_division(insn: &ebpf::Insn, dst: u64, src: u64) -> u64 {
+    let is_mod = insn.opc & ebpf::BPF_ALU_OP_MASK == ebpf::BPF_MOD;
+    let signed = insn.off == 1;
+    if insn.opc & ebpf::BPF_CLS_MASK == ebpf::BPF_ALU32_LOAD {
+        let (dst, src) = (dst as u32, src as u32);
+        (match (is_mod, src == 0, signed) {
+            (false, true, _) => 0,
+            (true, true, _) => dst,
+            (false, false, false) => dst / src,
+            (true, false, false) => dst % src,
+            (false, false, true) => (dst as i32).wrapping_div(src as i32) as u32,
+            (true, false, true) => (dst as i32).wrapping_rem(src as i32) as u32,
+        }) as u64
+    } else {
+        match (is_mod, src == 0, signed) {
+            (false, true, _) => 0,
+            (true, true, _) => dst,
+            (false, false, false) => dst / src,
+            (true, false, false) => dst % src,
+            (false, false, true) => (dst as i64).wrapping_div(src as i64) as u64,
+            (true, false, true) => (dst as i64).wrapping_rem(src as i64) as u64,
+        }
+    }
+}
+
 macro_rules! check_pc {
     ($self:expr, $next_pc:ident, $target_pc:expr) => {
         if ebpf::is_pc_in_program($self.program, $target_pc as usize) {
@@ -92,6 +123,9 @@ pub struct Interpreter<'a, 'b, C: ContextObject> {
     /// General purpose registers and pc
     pub reg: [u64; 12],
 
+    /// Written bytes of the stack, if [Config::enable_stack_shadow]
+    pub(crate) stack_shadow: Option<ShadowBitmap>,
+
     #[cfg(feature = "debugger")]
     pub(crate) debug_state: DebugState,
     #[cfg(feature = "debugger")]
@@ -106,12 +140,21 @@ impl<'a, 'b, C: ContextObject> Interpreter<'a, 'b, C> {
         registers: [u64; 12],
     ) -> Self {
         let (program_vm_addr, program) = executable.get_text_bytes();
+        let config = executable.get_config();
+        let stack_shadow = if config.enable_stack_shadow {
+            vm.memory_mapping
+                .find_region(config.address_space_layout.stack_start())
+                .map(|(_index, region)| ShadowBitmap::new(region))
+        } else {
+            None
+        };
         Self {
             vm,
             executable,
             program,
             program_vm_addr,
             reg: registers,
+            stack_shadow,
             #[cfg(feature = "debugger")]
             debug_state: DebugState::Continue,
             #[cfg(feature = "debugger")]
@@ -152,18 +195,53 @@ impl<'a, 'b, C: ContextObject> Interpreter<'a, 'b, C> {
                 self.reg[ebpf::FRAME_PTR_REG].wrapping_add(stack_frame_size as u64);
         }
 
+        if let Some(stack_shadow) = &mut self.stack_shadow {
+            // Every call starts with an uninitialized frame, like the static analysis assumes
+            let frame_pointer = self.reg[ebpf::FRAME_PTR_REG];
+            if self.executable.get_sbpf_version().manual_stack_frame_bump() {
+                // The callee bumps its frame below the one of the caller
+                let stack_start = config.address_space_layout.stack_start();
+                stack_shadow.clear(stack_start, frame_pointer.saturating_sub(stack_start));
+            } else {
+                let stack_frame_size = config.stack_frame_size as u64;
+                stack_shadow.clear(
+                    frame_pointer.saturating_sub(stack_frame_size),
+                    stack_frame_size,
+                );
+            }
+        }
+
         true
     }
 
+    #[inline(always)]
+    fn mark_stack_shadow(&mut self, vm_addr: u64, len: u64) {
+        if let Some(stack_shadow) = &mut self.stack_shadow {
+            stack_shadow.mark(vm_addr, len);
+        }
+    }
+
+    #[inline(always)]
+    fn check_stack_shadow(&mut self, vm_addr: u64, len: u64) {
+        if let Some(stack_shadow) = &self.stack_shadow {
+            if stack_shadow.first_unwritten(vm_addr, len).is_some() {
+                let offset = vm_addr.wrapping_sub(self.reg[ebpf::FRAME_PTR_REG]) as i64;
+                self.vm
+                    .uninitialized_stack_reads
+                    .insert((self.reg[11] as usize, offset));
+            }
+        }
+    }
+
     fn sign_extension(&self, value: i32) -> u64 {
         if self
             .executable
             .get_sbpf_version()
-            .explicit_sign_extension_of_results()
+            .implicit_sign_extension_of_results()
         {
-            value as u32 as u64
-        } else {
             value as i64 as u64
+        } else {
+            value as u32 as u64
         }
     }
 
@@ -253,6 +331,81 @@ impl<'a, 'b, C: ContextObject> Interpreter<'a, 'b, C> {
                 translate_memory_access!(self, store, self.reg[src], vm_addr, u64);
             },
 
+            // Linux eBPF
+            ebpf::LD_B_SX_REG if self.executable.get_sbpf_version().enable_ldsx() => {
+                let vm_addr = (self.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
+                self.reg[dst] = translate_memory_access!(self, load, vm_addr, u8) as i8 as i64 as u64;
+            },
+            ebpf::LD_H_SX_REG if self.executable.get_sbpf_version().enable_ldsx() => {
+                let vm_addr = (self.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
+                self.reg[dst] = translate_memory_access!(self, load, vm_addr, u16) as i16 as i64 as u64;
+            },
+            ebpf::LD_W_SX_REG if self.executable.get_sbpf_version().enable_ldsx() => {
+                let vm_addr = (self.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
+                self.reg[dst] = translate_memory_access!(self, load, vm_addr, u32) as i32 as i64 as u64;
+            },
+            ebpf::DIV32_IMM | ebpf::MOD32_IMM | ebpf::DIV64_IMM | ebpf::MOD64_IMM if self.executable.get_sbpf_version().non_trapping_division() => {
+                self.reg[dst] = non_trapping_division(&insn, self.reg[dst], insn.imm as u64);
+            },
+            ebpf::DIV32_REG | ebpf::MOD32_REG | ebpf::DIV64_REG | ebpf::MOD64_REG if self.executable.get_sbpf_version().non_trapping_division() => {
+                self.reg[dst] = non_trapping_division(&insn, self.reg[dst], self.reg[src]);
+            },
+            ebpf::MOV32_REG if self.executable.get_sbpf_version().enable_movsx() && insn.off != 0 => self.reg[dst] = match insn.off {
+                8 => self.reg[src] as i8  as i32 as u32 as u64,
+                _ => self.reg[src] as i16 as i32 as u32 as u64,
+            },
+            ebpf::MOV64_REG if self.executable.get_sbpf_version().enable_movsx() && insn.off != 0 => self.reg[dst] = match insn.off {
+                8  => self.reg[src] as i8  as i64 as u64,
+                16 => self.reg[src] as i16 as i64 as u64,
+                _  => self.reg[src] as i32 as i64 as u64,
+            },
+            ebpf::ST_W_ATOMIC | ebpf::ST_DW_ATOMIC if self.executable.get_sbpf_version().enable_atomics() && self.executable.get_config().enable_atomic_instructions => {
+                let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
+                let len = if insn.opc == ebpf::ST_W_ATOMIC { 4 } else { 8 };
+                let host_addr = match self.vm.memory_mapping.map_atomic(vm_addr, len) {
+                    ProgramResult::Ok(host_addr) => host_addr,
+                    ProgramResult::Err(err) => {
+                        throw_error!(self, err);
+                    },
+                };
+                self.check_stack_shadow(vm_addr, len);
+                self.mark_stack_shadow(vm_addr, len);
+                // map_atomic() only returns naturally aligned host addresses
+                macro_rules! read_modify_write {
+                    ($atomic:ty, $value:ty) => {{
+                        let atomic = unsafe { &*(host_addr as *const $atomic) };
+                        let value = self.reg[src] as $value;
+                        let previous = match insn.imm {
+                            ebpf::ATOMIC_ADD | ebpf::ATOMIC_FETCH_ADD => atomic.fetch_add(value, Ordering::SeqCst),
+                            ebpf::ATOMIC_XCHG => atomic.swap(value, Ordering::SeqCst),
+                            ebpf::ATOMIC_CMPXCHG => atomic
+                                .compare_exchange(self.reg[0] as $value, value, Ordering::SeqCst, Ordering::SeqCst)
+                                .unwrap_or_else(|previous| previous),
+                            _ => {
+                                throw_error!(self, EbpfError::InvalidInstruction);
+                            }
+                        };
+                        previous as u64
+                    }};
+                }
+                let previous = if len == 4 { read_modify_write!(AtomicU32, u32) } else { read_modify_write!(AtomicU64, u64) };
+                match insn.imm {
+                    ebpf::ATOMIC_FETCH_ADD | ebpf::ATOMIC_XCHG => self.reg[src] = previous,
+                    ebpf::ATOMIC_CMPXCHG => self.reg[0] = previous,
+                    _ => {}
+                }
+            },
+            ebpf::BSWAP if self.executable.get_sbpf_version().enable_bswap() => {
+                self.reg[dst] = match insn.imm {
+                    16 => (self.reg[dst] as u16).swap_bytes() as u64,
+                    32 => (self.reg[dst] as u32).swap_bytes() as u64,
+                    64 =>  self.reg[dst].swap_bytes(),
+                    _  => {
+                        throw_error!(self, EbpfError::InvalidInstruction);
+                    }
+                };
+            },
+
             // BPF_ALU32_LOAD class
             ebpf::ADD32_IMM  => self.reg[dst] = self.sign_extension((self.reg[dst] as i32).wrapping_add(insn.imm as i32)),
             ebpf::ADD32_REG  => self.reg[dst] = self.sign_extension((self.reg[dst] as i32).wrapping_add(self.reg[src] as i32)),
@@ -544,7 +697,7 @@ impl<'a, 'b, C: ContextObject> Interpreter<'a, 'b, C> {
                 // External syscall
                 if !self.executable.get_sbpf_version().static_syscalls() || insn.src == 0 {
                     if let Some((_, function)) = self.executable.get_loader().get_function_registry().lookup_by_key(insn.imm as u32) {
-                        self.reg[0] = match self.dispatch_syscall(function) {
+                        self.reg[0] = match self.dispatch_syscall(insn.imm as u32, function) {
                             ProgramResult::Ok(value) => *value,
                             ProgramResult::Err(_err) => return false,
                         };
@@ -599,7 +752,22 @@ impl<'a, 'b, C: ContextObject> Interpreter<'a, 'b, C> {
         true
     }
 
-    fn dispatch_syscall(&mut self, function: BuiltinFunction<C>) -> &ProgramResult {
+    fn dispatch_syscall(&mut self, key: u32, function: BuiltinFunction<C>) -> &ProgramResult {
+        if self.stack_shadow.is_some() && self.executable.get_loader().is_writing_function(key) {
+            // The syscall may write through pointers into the stack, assume it fills up to the frame
+            let stack_start = self
+                .executable
+                .get_config()
+                .address_space_layout
+                .stack_start();
+            let frame_pointer = self.reg[ebpf::FRAME_PTR_REG];
+            for reg in 1..=5 {
+                let vm_addr = self.reg[reg];
+                if (stack_start..frame_pointer).contains(&vm_addr) {
+                    self.mark_stack_shadow(vm_addr, frame_pointer - vm_addr);
+                }
+            }
+        }
         self.vm.due_insn_count = self.vm.previous_instruction_meter - self.vm.due_insn_count;
         self.vm.registers[0..6].copy_from_slice(&self.reg[0..6]);
         self.vm.invoke_function(function);
@@ -33,7 +33,11 @@ use crate::{
         allocate_pages, free_pages, get_system_page_size, protect_pages, round_to_page_size,
     },
     memory_region::MemoryMapping,
-    vm::{get_runtime_environment_key, Config, ContextObject, EbpfVm, RuntimeEnvironmentSlot},
+    verifier::VerifiedFacts,
+    vm::{
+        get_runtime_environment_key, CallFrame, Config, ContextObject, EbpfVm,
+        RuntimeEnvironmentSlot,
+    },
     x86::{
         FenceType, X86IndirectAccess, X86Instruction,
         X86Register::{self, *},
@@ -58,6 +62,8 @@ pub struct JitProgram {
     pc_section: &'static mut [u32],
     /// The x86 machinecode
     text_section: &'static mut [u8],
+    /// Some stack accesses skip the MemoryMapping and use [EbpfVm::stack_translation_offset]
+    inline_stack_translation: bool,
 }
 
 impl JitProgram {
@@ -74,6 +80,7 @@ impl JitProgram {
                     raw.add(pc_loc_table_size),
                     over_allocated_code_size,
                 ),
+                inline_stack_translation: false,
             })
         }
     }
@@ -112,6 +119,10 @@ impl JitProgram {
         Ok(())
     }
 
+    pub(crate) fn translates_stack_accesses_inline(&self) -> bool {
+        self.inline_stack_translation
+    }
+
     pub(crate) fn invoke<C: ContextObject>(
         &self,
         _config: &Config,
@@ -231,8 +242,10 @@ const ANCHOR_CALL_UNSUPPORTED_INSTRUCTION: usize = 11;
 const ANCHOR_EXTERNAL_FUNCTION_CALL: usize = 12;
 const ANCHOR_INTERNAL_FUNCTION_CALL_PROLOGUE: usize = 13;
 const ANCHOR_INTERNAL_FUNCTION_CALL_REG: usize = 14;
+const ANCHOR_VERIFIED_FACT_VIOLATED: usize = 15;
 const ANCHOR_TRANSLATE_MEMORY_ADDRESS: usize = 21;
-const ANCHOR_COUNT: usize = 34; // Update me when adding or removing anchors
+const ANCHOR_TRANSLATE_ATOMIC_ADDRESS: usize = 34;
+const ANCHOR_COUNT: usize = 36; // Update me when adding or removing anchors
 
 const REGISTER_MAP: [X86Register; 11] = [
     CALLER_SAVED_REGISTERS[0], // RAX
@@ -348,6 +361,7 @@ pub struct JitCompiler<'a, C: ContextObject> {
     program: &'a [u8],
     program_vm_addr: u64,
     config: &'a Config,
+    verified_facts: Option<&'a VerifiedFacts>,
     pc: usize,
     last_instruction_meter_validation_pc: usize,
     next_noop_insertion: u32,
@@ -383,8 +397,8 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
         if config.noop_instruction_rate != 0 {
             code_length_estimate += code_length_estimate / config.noop_instruction_rate as usize;
         }
-        if config.instruction_meter_checkpoint_distance != 0 {
-            code_length_estimate += pc / config.instruction_meter_checkpoint_distance * MACHINE_CODE_PER_INSTRUCTION_METER_CHECKPOINT;
+        if let Some(checkpoints) = pc.checked_div(config.instruction_meter_checkpoint_distance) {
+            code_length_estimate += checkpoints * MACHINE_CODE_PER_INSTRUCTION_METER_CHECKPOINT;
         }
         // Relative jump destinations limit the maximum output size
         debug_assert!(code_length_estimate < (i32::MAX as usize));
@@ -402,6 +416,7 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
             program_vm_addr,
             program,
             config,
+            verified_facts: None,
             pc: 0,
             last_instruction_meter_validation_pc: 0,
             next_noop_insertion: if config.noop_instruction_rate == 0 { u32::MAX } else { diversification_rng.gen_range(0..config.noop_instruction_rate * 2) },
@@ -413,6 +428,14 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
         })
     }
 
+    /// Leaves out the runtime checks which the given facts make redundant
+    ///
+    /// The facts have to be about the executable being compiled.
+    pub fn with_verified_facts(mut self, verified_facts: &'a VerifiedFacts) -> Self {
+        self.verified_facts = Some(verified_facts);
+        self
+    }
+
     /// Compiles the given executable, consuming the compiler
     pub fn compile(mut self) -> Result<JitProgram, EbpfError> {
         // Randomized padding at the start before random intervals begin
@@ -474,6 +497,19 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
                     self.emit_address_translation(Some(dst), Value::RegisterPlusConstant64(src, insn.off as i64, true), 8, None);
                 },
 
+                ebpf::LD_B_SX_REG if self.executable.get_sbpf_version().enable_ldsx() => {
+                    self.emit_address_translation(Some(dst), Value::RegisterPlusConstant64(src, insn.off as i64, true), 1, None);
+                    self.emit_ins(X86Instruction::sign_extend(OperandSize::S8, dst, dst));
+                },
+                ebpf::LD_H_SX_REG if self.executable.get_sbpf_version().enable_ldsx() => {
+                    self.emit_address_translation(Some(dst), Value::RegisterPlusConstant64(src, insn.off as i64, true), 2, None);
+                    self.emit_ins(X86Instruction::sign_extend(OperandSize::S16, dst, dst));
+                },
+                ebpf::LD_W_SX_REG if self.executable.get_sbpf_version().enable_ldsx() => {
+                    self.emit_address_translation(Some(dst), Value::RegisterPlusConstant64(src, insn.off as i64, true), 4, None);
+                    self.emit_ins(X86Instruction::sign_extend(OperandSize::S32, dst, dst));
+                },
+
                 // BPF_ST class
                 ebpf::ST_B_IMM  if !self.executable.get_sbpf_version().move_memory_instruction_classes() => {
                     self.emit_address_translation(None, Value::RegisterPlusConstant64(dst, insn.off as i64, true), 1, Some(Value::Constant64(insn.imm, true)));
@@ -502,16 +538,47 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
                     self.emit_address_translation(None, Value::RegisterPlusConstant64(dst, insn.off as i64, true), 8, Some(Value::Register(src)));
                 },
 
+                ebpf::ST_W_ATOMIC | ebpf::ST_DW_ATOMIC if self.executable.get_sbpf_version().enable_atomics() && self.config.enable_atomic_instructions => {
+                    let size = if insn.opc == ebpf::ST_W_ATOMIC { OperandSize::S32 } else { OperandSize::S64 };
+                    self.emit_atomic_address_translation(Value::RegisterPlusConstant64(dst, insn.off as i64, true), size as u64 / 8);
+                    // The host address is in REGISTER_SCRATCH now
+                    match insn.imm {
+                        ebpf::ATOMIC_ADD => {
+                            self.emit::<u8>(0xf0); // lock prefix
+                            self.emit_ins(X86Instruction::alu(size, 0x01, src, REGISTER_SCRATCH, Some(X86IndirectAccess::Offset(0))));
+                        }
+                        ebpf::ATOMIC_FETCH_ADD => {
+                            self.emit::<u8>(0xf0); // lock prefix
+                            self.emit_ins(X86Instruction::xadd(size, src, REGISTER_SCRATCH, Some(X86IndirectAccess::Offset(0))));
+                        }
+                        ebpf::ATOMIC_XCHG => {
+                            // xchg with a memory operand is always locked
+                            self.emit_ins(X86Instruction::xchg(size, src, REGISTER_SCRATCH, Some(X86IndirectAccess::Offset(0))));
+                        }
+                        ebpf::ATOMIC_CMPXCHG => {
+                            self.emit::<u8>(0xf0); // lock prefix
+                            self.emit_ins(X86Instruction::cmpxchg(size, src, REGISTER_SCRATCH, Some(X86IndirectAccess::Offset(0))));
+                            if insn.opc == ebpf::ST_W_ATOMIC {
+                                // cmpxchg leaves RAX untouched on success, so zero extend it explicitly
+                                self.emit_ins(X86Instruction::mov(OperandSize::S32, REGISTER_MAP[0], REGISTER_MAP[0]));
+                            }
+                        }
+                        _ => {
+                            return Err(EbpfError::InvalidInstruction);
+                        }
+                    }
+                },
+
                 // BPF_ALU32_LOAD class
                 ebpf::ADD32_IMM  => {
                     self.emit_sanitized_alu(OperandSize::S32, 0x01, 0, dst, insn.imm);
-                    if !self.executable.get_sbpf_version().explicit_sign_extension_of_results() {
+                    if self.executable.get_sbpf_version().implicit_sign_extension_of_results() {
                         self.emit_ins(X86Instruction::alu(OperandSize::S64, 0x63, dst, dst, None)); // sign extend i32 to i64
                     }
                 },
                 ebpf::ADD32_REG  => {
                     self.emit_ins(X86Instruction::alu(OperandSize::S32, 0x01, src, dst, None));
-                    if !self.executable.get_sbpf_version().explicit_sign_extension_of_results() {
+                    if self.executable.get_sbpf_version().implicit_sign_extension_of_results() {
                         self.emit_ins(X86Instruction::alu(OperandSize::S64, 0x63, dst, dst, None)); // sign extend i32 to i64
                     }
                 },
@@ -524,13 +591,13 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
                     } else {
                         self.emit_sanitized_alu(OperandSize::S32, 0x29, 5, dst, insn.imm);
                     }
-                    if !self.executable.get_sbpf_version().explicit_sign_extension_of_results() {
+                    if self.executable.get_sbpf_version().implicit_sign_extension_of_results() {
                         self.emit_ins(X86Instruction::alu(OperandSize::S64, 0x63, dst, dst, None)); // sign extend i32 to i64
                     }
                 },
                 ebpf::SUB32_REG  => {
                     self.emit_ins(X86Instruction::alu(OperandSize::S32, 0x29, src, dst, None));
-                    if !self.executable.get_sbpf_version().explicit_sign_extension_of_results() {
+                    if self.executable.get_sbpf_version().implicit_sign_extension_of_results() {
                         self.emit_ins(X86Instruction::alu(OperandSize::S64, 0x63, dst, dst, None)); // sign extend i32 to i64
                     }
                 },
@@ -541,7 +608,7 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
                         self.emit_ins(X86Instruction::load_immediate(REGISTER_SCRATCH, insn.imm));
                     }
                     self.emit_ins(X86Instruction::alu_escaped(OperandSize::S32, 1, 0xaf, dst, REGISTER_SCRATCH, None));
-                    if !self.executable.get_sbpf_version().explicit_sign_extension_of_results() {
+                    if self.executable.get_sbpf_version().implicit_sign_extension_of_results() {
                         self.emit_ins(X86Instruction::alu(OperandSize::S64, 0x63, dst, dst, None)); // sign extend i32 to i64
                     }
                 },
@@ -550,7 +617,7 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
                         OperandSize::S32,
                         (insn.opc & ebpf::BPF_ALU_OP_MASK) == ebpf::BPF_MOD,
                         (insn.opc & ebpf::BPF_ALU_OP_MASK) != ebpf::BPF_MUL,
-                        (insn.opc & ebpf::BPF_ALU_OP_MASK) == ebpf::BPF_MUL,
+                        insn.off == 1 && self.executable.get_sbpf_version().enable_signed_division(),
                         dst, dst, Some(insn.imm),
                     ),
                 ebpf::LD_1B_REG  if self.executable.get_sbpf_version().move_memory_instruction_classes() => {
@@ -558,7 +625,7 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
                 },
                 ebpf::MUL32_REG if !self.executable.get_sbpf_version().enable_pqr() => {
                     self.emit_ins(X86Instruction::alu_escaped(OperandSize::S32, 1, 0xaf, dst, src, None));
-                    if !self.executable.get_sbpf_version().explicit_sign_extension_of_results() {
+                    if self.executable.get_sbpf_version().implicit_sign_extension_of_results() {
                         self.emit_ins(X86Instruction::alu(OperandSize::S64, 0x63, dst, dst, None)); // sign extend i32 to i64
                     }
                 },
@@ -567,7 +634,7 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
                         OperandSize::S32,
                         (insn.opc & ebpf::BPF_ALU_OP_MASK) == ebpf::BPF_MOD,
                         (insn.opc & ebpf::BPF_ALU_OP_MASK) != ebpf::BPF_MUL,
-                        (insn.opc & ebpf::BPF_ALU_OP_MASK) == ebpf::BPF_MUL,
+                        insn.off == 1 && self.executable.get_sbpf_version().enable_signed_division(),
                         src, dst, None,
                     ),
                 ebpf::LD_2B_REG  if self.executable.get_sbpf_version().move_memory_instruction_classes() => {
@@ -598,7 +665,10 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
                     }
                 }
                 ebpf::MOV32_REG  => {
-                    if self.executable.get_sbpf_version().explicit_sign_extension_of_results() {
+                    if self.executable.get_sbpf_version().enable_movsx() && insn.off != 0 {
+                        self.emit_ins(X86Instruction::sign_extend(if insn.off == 8 { OperandSize::S8 } else { OperandSize::S16 }, src, dst));
+                        self.emit_ins(X86Instruction::mov(OperandSize::S32, dst, dst)); // zero extend u32 to u64
+                    } else if self.executable.get_sbpf_version().explicit_sign_extension_of_results() {
                         self.emit_ins(X86Instruction::mov_with_sign_extension(OperandSize::S64, src, dst));
                     } else {
                         self.emit_ins(X86Instruction::mov(OperandSize::S32, src, dst));
@@ -661,7 +731,7 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
                         OperandSize::S64,
                         (insn.opc & ebpf::BPF_ALU_OP_MASK) == ebpf::BPF_MOD,
                         (insn.opc & ebpf::BPF_ALU_OP_MASK) != ebpf::BPF_MUL,
-                        (insn.opc & ebpf::BPF_ALU_OP_MASK) == ebpf::BPF_MUL,
+                        insn.off == 1 && self.executable.get_sbpf_version().enable_signed_division(),
                         dst, dst, Some(insn.imm),
                     ),
                 ebpf::ST_1B_IMM  if self.executable.get_sbpf_version().move_memory_instruction_classes() => {
@@ -678,7 +748,7 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
                         OperandSize::S64,
                         (insn.opc & ebpf::BPF_ALU_OP_MASK) == ebpf::BPF_MOD,
                         (insn.opc & ebpf::BPF_ALU_OP_MASK) != ebpf::BPF_MUL,
-                        (insn.opc & ebpf::BPF_ALU_OP_MASK) == ebpf::BPF_MUL,
+                        insn.off == 1 && self.executable.get_sbpf_version().enable_signed_division(),
                         src, dst, None,
                     ),
                 ebpf::ST_1B_REG  if self.executable.get_sbpf_version().move_memory_instruction_classes() => {
@@ -717,12 +787,36 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
                         self.emit_ins(X86Instruction::load_immediate(dst, insn.imm));
                     }
                 }
-                ebpf::MOV64_REG  => self.emit_ins(X86Instruction::mov(OperandSize::S64, src, dst)),
+                ebpf::MOV64_REG  => {
+                    if self.executable.get_sbpf_version().enable_movsx() && insn.off != 0 {
+                        let source_size = match insn.off {
+                            8 => OperandSize::S8,
+                            16 => OperandSize::S16,
+                            _ => OperandSize::S32,
+                        };
+                        self.emit_ins(X86Instruction::sign_extend(source_size, src, dst));
+                    } else {
+                        self.emit_ins(X86Instruction::mov(OperandSize::S64, src, dst));
+                    }
+                }
                 ebpf::ARSH64_IMM => self.emit_shift(OperandSize::S64, 7, REGISTER_SCRATCH, dst, Some(insn.imm)),
                 ebpf::ARSH64_REG => self.emit_shift(OperandSize::S64, 7, src, dst, None),
                 ebpf::HOR64_IMM if self.executable.get_sbpf_version().disable_lddw() => {
                     self.emit_sanitized_alu(OperandSize::S64, 0x09, 1, dst, (insn.imm as u64).wrapping_shl(32) as i64);
                 }
+                ebpf::BSWAP if self.executable.get_sbpf_version().enable_bswap() => {
+                    match insn.imm {
+                        16 => {
+                            self.emit_ins(X86Instruction::bswap(OperandSize::S16, dst));
+                            self.emit_ins(X86Instruction::alu_immediate(OperandSize::S32, 0x81, 4, dst, 0xffff, None)); // Mask to 16 bit
+                        }
+                        32 => self.emit_ins(X86Instruction::bswap(OperandSize::S32, dst)),
+                        64 => self.emit_ins(X86Instruction::bswap(OperandSize::S64, dst)),
+                        _ => {
+                            return Err(EbpfError::InvalidInstruction);
+                        }
+                    }
+                }
 
                 // BPF_PQR class
                 ebpf::LMUL32_IMM | ebpf::LMUL64_IMM | ebpf::UHMUL64_IMM | ebpf::SHMUL64_IMM |
@@ -823,7 +917,7 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
                     if self.executable.get_sbpf_version().static_syscalls() {
                         let target_pc = (self.pc as i64).saturating_add(insn.imm).saturating_add(1);
                         if ebpf::is_pc_in_program(self.program, target_pc as usize) && insn.src == 1 {
-                            self.emit_internal_call(Value::Constant64(target_pc as i64, true));
+                            self.emit_internal_call(Value::Constant64(target_pc, true));
                             resolved = true;
                         }
                     } else if let Some((_function_name, target_pc)) =
@@ -846,7 +940,16 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
                     } else {
                         REGISTER_MAP[insn.imm as usize]
                     };
-                    self.emit_internal_call(Value::Register(target_pc));
+                    if let Some(function_pc) = self.verified_facts.and_then(|facts| facts.callx_targets.get(&self.pc).copied()) {
+                        if self.config.assert_verified_facts {
+                            self.emit_ins(X86Instruction::load_immediate(REGISTER_SCRATCH, self.program_vm_addr as i64 + (function_pc * INSN_SIZE) as i64));
+                            self.emit_ins(X86Instruction::cmp(OperandSize::S64, REGISTER_SCRATCH, target_pc, None)); // target_address == function_address
+                            self.emit_verified_fact_assertion(0x84);
+                        }
+                        self.emit_internal_call(Value::Constant64(function_pc as i64, true));
+                    } else {
+                        self.emit_internal_call(Value::Register(target_pc));
+                    }
                 },
                 ebpf::EXIT      => {
                     self.emit_validate_and_profile_instruction_count(Some(0));
@@ -1178,6 +1281,31 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
         }
     }
 
+    fn emit_atomic_address_translation(&mut self, vm_addr: Value, len: u64) {
+        self.emit_vm_address(vm_addr);
+        // Even without address translation the alignment has to be checked
+        let anchor = ANCHOR_TRANSLATE_ATOMIC_ADDRESS + (len.trailing_zeros() as usize - 2);
+        self.emit_ins(X86Instruction::push_immediate(OperandSize::S64, self.pc as i32));
+        self.emit_ins(X86Instruction::call_immediate(self.relative_to_anchor(anchor, 5)));
+    }
+
+    fn emit_vm_address(&mut self, vm_addr: Value) {
+        match vm_addr {
+            Value::RegisterPlusConstant64(reg, constant, user_provided) => {
+                if user_provided && self.should_sanitize_constant(constant) {
+                    self.emit_sanitized_load_immediate(REGISTER_SCRATCH, constant);
+                } else {
+                    self.emit_ins(X86Instruction::load_immediate(REGISTER_SCRATCH, constant));
+                }
+                self.emit_ins(X86Instruction::alu(OperandSize::S64, 0x01, reg, REGISTER_SCRATCH, None));
+            },
+            _ => {
+                #[cfg(debug_assertions)]
+                unreachable!();
+            },
+        }
+    }
+
     fn emit_address_translation(&mut self, dst: Option<X86Register>, vm_addr: Value, len: u64, value: Option<Value>) {
         debug_assert_ne!(dst.is_some(), value.is_some());
 
@@ -1196,22 +1324,13 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
             _ => {}
         }
 
-        match vm_addr {
-            Value::RegisterPlusConstant64(reg, constant, user_provided) => {
-                if user_provided && self.should_sanitize_constant(constant) {
-                    self.emit_sanitized_load_immediate(REGISTER_SCRATCH, constant);
-                } else {
-                    self.emit_ins(X86Instruction::load_immediate(REGISTER_SCRATCH, constant));
-                }
-                self.emit_ins(X86Instruction::alu(OperandSize::S64, 0x01, reg, REGISTER_SCRATCH, None));
-            },
-            _ => {
-                #[cfg(debug_assertions)]
-                unreachable!();
-            },
-        }
+        self.emit_vm_address(vm_addr);
 
-        if self.config.enable_address_translation {
+        let in_frame_stack_access = self.is_in_frame_stack_access();
+        if in_frame_stack_access {
+            self.emit_stack_address_translation(len);
+        }
+        if self.config.enable_address_translation && !in_frame_stack_access {
             let anchor_base = match value {
                 Some(Value::Register(_reg)) => 4,
                 Some(Value::Constant64(_constant, _user_provided)) => 8,
@@ -1232,7 +1351,12 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
                 _ => unreachable!(),
             }
         } else {
-            self.emit_ins(X86Instruction::xchg(OperandSize::S64, RSP, REGISTER_MAP[0], Some(stack_slot_of_value_to_store))); // Save REGISTER_MAP[0] and retrieve value to store
+            if let Some(Value::Constant64(_constant, _user_provided)) = value {
+                // Second half of emit_sanitized_load_immediate(stack_slot_of_value_to_store, constant)
+                let lower_key = self.immediate_value_key as i32 as i64;
+                self.emit_ins(X86Instruction::alu_immediate(OperandSize::S64, 0x81, 0, RSP, lower_key, Some(stack_slot_of_value_to_store)));
+            }
+            self.emit_ins(X86Instruction::xchg(OperandSize::S64, REGISTER_MAP[0], RSP, Some(stack_slot_of_value_to_store))); // Save REGISTER_MAP[0] and retrieve value to store
             match len {
                 1 => self.emit_ins(X86Instruction::store(OperandSize::S8, REGISTER_MAP[0], REGISTER_SCRATCH, X86IndirectAccess::Offset(0))),
                 2 => self.emit_ins(X86Instruction::store(OperandSize::S16, REGISTER_MAP[0], REGISTER_SCRATCH, X86IndirectAccess::Offset(0))),
@@ -1240,8 +1364,42 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
                 8 => self.emit_ins(X86Instruction::store(OperandSize::S64, REGISTER_MAP[0], REGISTER_SCRATCH, X86IndirectAccess::Offset(0))),
                 _ => unreachable!(),
             }
-            self.emit_ins(X86Instruction::xchg(OperandSize::S64, RSP, REGISTER_MAP[0], Some(stack_slot_of_value_to_store))); // Restore REGISTER_MAP[0]
+            self.emit_ins(X86Instruction::xchg(OperandSize::S64, REGISTER_MAP[0], RSP, Some(stack_slot_of_value_to_store))); // Restore REGISTER_MAP[0]
+        }
+    }
+
+    fn is_in_frame_stack_access(&self) -> bool {
+        // The gaps between the stack frames make the translation non-linear
+        self.config.enable_address_translation
+            && !(self.executable.get_sbpf_version().stack_frame_gaps() && self.config.enable_stack_frame_gaps)
+            && self.verified_facts.is_some_and(|facts| facts.in_frame_stack_accesses.contains(&self.pc))
+    }
+
+    /// Translates the vm address in REGISTER_SCRATCH, which the verifier proved to be in the current stack frame
+    fn emit_stack_address_translation(&mut self, len: u64) {
+        self.result.inline_stack_translation = true;
+        if self.config.assert_verified_facts {
+            // With fixed frames the frame pointer does not move inside of a function
+            self.emit_ins(X86Instruction::alu(OperandSize::S64, 0x29, REGISTER_MAP[FRAME_PTR_REG], REGISTER_SCRATCH, None)); // vm_addr -= frame_pointer;
+            self.emit_ins(X86Instruction::cmp_immediate(OperandSize::S64, REGISTER_SCRATCH, -(self.config.stack_frame_size as i64), None));
+            let below_frame = self.emit_local_jump(Some(0x8c));
+            self.emit_ins(X86Instruction::cmp_immediate(OperandSize::S64, REGISTER_SCRATCH, -(len as i64), None));
+            let in_frame = self.emit_local_jump(Some(0x8e));
+            self.resolve_local_jump(below_frame);
+            self.emit_ins(X86Instruction::load_immediate(REGISTER_SCRATCH, self.pc as i64));
+            self.emit_ins(X86Instruction::jump_immediate(self.relative_to_anchor(ANCHOR_VERIFIED_FACT_VIOLATED, 5)));
+            self.resolve_local_jump(in_frame);
+            self.emit_ins(X86Instruction::alu(OperandSize::S64, 0x01, REGISTER_MAP[FRAME_PTR_REG], REGISTER_SCRATCH, None)); // vm_addr += frame_pointer;
         }
+        self.emit_ins(X86Instruction::alu(OperandSize::S64, 0x03, REGISTER_SCRATCH, REGISTER_PTR_TO_VM, Some(X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::StackTranslationOffset))))); // host_addr = vm_addr + stack_translation_offset;
+    }
+
+    /// Throws unless the condition holds for the status flags
+    fn emit_verified_fact_assertion(&mut self, condition: u8) {
+        let holds = self.emit_local_jump(Some(condition));
+        self.emit_ins(X86Instruction::load_immediate(REGISTER_SCRATCH, self.pc as i64));
+        self.emit_ins(X86Instruction::jump_immediate(self.relative_to_anchor(ANCHOR_VERIFIED_FACT_VIOLATED, 5)));
+        self.resolve_local_jump(holds);
     }
 
     fn emit_conditional_branch_reg(&mut self, size: OperandSize, op: u8, bitwise: bool, first_operand: X86Register, second_operand: X86Register, target_pc: usize) {
@@ -1316,9 +1474,52 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
         // dst-in  RAX  RAX   RAX   RAX  RAX  RAX  RAX
         // dst-out RAX  RDX   RDX   RAX  RAX  RDX  RDX
 
-        if division {
+        let divisor_is_nonzero = imm.is_none()
+            && self.verified_facts.is_some_and(|facts| facts.nonzero_divisors.contains(&self.pc));
+        if divisor_is_nonzero && self.config.assert_verified_facts {
+            self.emit_ins(X86Instruction::test(size, src, src, None)); // src != 0
+            self.emit_verified_fact_assertion(0x85);
+        }
+        let mut jumps_to_end = Vec::new();
+        if division && self.executable.get_sbpf_version().non_trapping_division() {
+            // Linux eBPF: x / 0 = 0, x % 0 = x, MIN / -1 = MIN and x % -1 = 0
+            // (the verifier rejects a zero immediate, so only -1 can be known upfront)
+            if imm.is_none() && !divisor_is_nonzero {
+                self.emit_ins(X86Instruction::test(size, src, src, None)); // src == 0
+                let skip = self.emit_local_jump(Some(0x85));
+                if alt_dst {
+                    if let OperandSize::S32 = size {
+                        self.emit_ins(X86Instruction::mov(OperandSize::S32, dst, dst)); // zero extend u32 to u64
+                    }
+                } else {
+                    self.emit_ins(X86Instruction::alu(OperandSize::S32, 0x31, dst, dst, None)); // dst = 0
+                }
+                jumps_to_end.push(self.emit_local_jump(None));
+                self.resolve_local_jump(skip);
+            }
+            if signed && imm.unwrap_or(-1) == -1 {
+                let skip = if imm.is_none() {
+                    self.emit_ins(X86Instruction::cmp_immediate(size, src, -1, None)); // src == -1
+                    Some(self.emit_local_jump(Some(0x85)))
+                } else {
+                    None
+                };
+                if alt_dst {
+                    self.emit_ins(X86Instruction::alu(OperandSize::S32, 0x31, dst, dst, None)); // dst = 0
+                } else {
+                    self.emit_ins(X86Instruction::alu_immediate(size, 0xf7, 3, dst, 0, None)); // dst = -dst
+                }
+                match skip {
+                    Some(skip) => {
+                        jumps_to_end.push(self.emit_local_jump(None));
+                        self.resolve_local_jump(skip);
+                    }
+                    None => return,
+                }
+            }
+        } else if division {
             // Prevent division by zero
-            if imm.is_none() {
+            if imm.is_none() && !divisor_is_nonzero {
                 self.emit_ins(X86Instruction::load_immediate(REGISTER_SCRATCH, self.pc as i64)); // Save pc
                 self.emit_ins(X86Instruction::test(size, src, src, None)); // src == 0
                 self.emit_ins(X86Instruction::conditional_jump_immediate(0x84, self.relative_to_anchor(ANCHOR_DIV_BY_ZERO, 6)));
@@ -1386,10 +1587,35 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
             self.emit_ins(X86Instruction::pop(RAX));
         }
         if let OperandSize::S32 = size {
-            if signed && !self.executable.get_sbpf_version().explicit_sign_extension_of_results() {
+            if signed && self.executable.get_sbpf_version().implicit_sign_extension_of_results() {
                 self.emit_ins(X86Instruction::alu(OperandSize::S64, 0x63, dst, dst, None)); // sign extend i32 to i64
             }
         }
+        for jump in jumps_to_end {
+            self.resolve_local_jump(jump);
+        }
+    }
+
+    /// Emits a forward jump inside the machine code of the current instruction
+    ///
+    /// Returns the end of the jump instruction, which is handed to `resolve_local_jump` once the destination is emitted.
+    fn emit_local_jump(&mut self, condition: Option<u8>) -> usize {
+        let (instruction, instruction_length) = match condition {
+            Some(opcode) => (X86Instruction::conditional_jump_immediate(opcode, 0), 6),
+            None => (X86Instruction::jump_immediate(0), 5),
+        };
+        let instruction_end = self.offset_in_text_section + instruction_length;
+        self.emit_ins(instruction);
+        instruction_end
+    }
+
+    /// Points a jump emitted by `emit_local_jump` at the current position
+    fn resolve_local_jump(&mut self, instruction_end: usize) {
+        let offset_value = (self.offset_in_text_section - instruction_end) as i32;
+        unsafe {
+            let location = self.result.text_section.as_ptr().add(instruction_end - mem::size_of::<i32>());
+            ptr::write_unaligned(location as *mut i32, offset_value);
+        }
     }
 
     fn emit_set_exception_kind(&mut self, err: EbpfError) {
@@ -1497,6 +1723,11 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
         self.emit_set_exception_kind(EbpfError::DivideByZero);
         self.emit_ins(X86Instruction::jump_immediate(self.relative_to_anchor(ANCHOR_THROW_EXCEPTION, 5)));
 
+        // Handler for EbpfError::VerifiedFactViolated
+        self.set_anchor(ANCHOR_VERIFIED_FACT_VIOLATED);
+        self.emit_set_exception_kind(EbpfError::VerifiedFactViolated);
+        self.emit_ins(X86Instruction::jump_immediate(self.relative_to_anchor(ANCHOR_THROW_EXCEPTION, 5)));
+
         // Handler for EbpfError::DivideOverflow
         self.set_anchor(ANCHOR_DIV_OVERFLOW);
         self.emit_set_exception_kind(EbpfError::DivideOverflow);
@@ -1533,6 +1764,12 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
         if self.config.enable_instruction_meter {
             self.emit_ins(X86Instruction::load(OperandSize::S64, REGISTER_PTR_TO_VM, REGISTER_INSTRUCTION_METER, X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::PreviousInstructionMeter)))); // REGISTER_INSTRUCTION_METER = *PreviousInstructionMeter;
         }
+        if self.verified_facts.is_some_and(|facts| !facts.in_frame_stack_accesses.is_empty()) {
+            // The builtin might have changed the stack region, so it has to be mapped again
+            self.emit_rust_call(Value::Constant64(EbpfVm::<C>::update_stack_translation_offset as *const u8 as i64, false), &[
+                Argument { index: 0, value: Value::RegisterPlusConstant32(REGISTER_PTR_TO_VM, self.slot_in_vm(RuntimeEnvironmentSlot::HostStackPointer), false) }, // The first field is at the start of the EbpfVm
+            ], None);
+        }
 
         // Test if result indicates that an error occured
         self.emit_result_is_err(REGISTER_SCRATCH);
@@ -1555,6 +1792,22 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
         // Push the caller's frame pointer. The code to restore it is emitted at the end of emit_internal_call().
         self.emit_ins(X86Instruction::store(OperandSize::S64, REGISTER_MAP[FRAME_PTR_REG], RSP, X86IndirectAccess::OffsetIndexShift(8, RSP, 0)));
         self.emit_ins(X86Instruction::xchg(OperandSize::S64, REGISTER_SCRATCH, RSP, Some(X86IndirectAccess::OffsetIndexShift(0, RSP, 0)))); // Push return address and restore original REGISTER_SCRATCH
+        // Record the call frame like the interpreter does, so that it can be found in a backtrace
+        #[allow(clippy::ptr_arg)] // The JIT passes a pointer to the Vec inside EbpfVm
+        fn record_call_frame(call_frames: &mut Vec<CallFrame>, call_depth: u64, pc: u64, frame_pointer: u64) {
+            if let Some(frame) = call_frames.get_mut(call_depth as usize) {
+                frame.frame_pointer = frame_pointer;
+                frame.target_pc = pc + 1;
+            }
+        }
+        if self.config.enable_backtrace {
+            self.emit_rust_call(Value::Constant64(record_call_frame as *const u8 as i64, false), &[
+                Argument { index: 3, value: Value::Register(REGISTER_MAP[FRAME_PTR_REG]) },
+                Argument { index: 2, value: Value::Register(REGISTER_SCRATCH) },
+                Argument { index: 1, value: Value::RegisterIndirect(REGISTER_PTR_TO_VM, self.slot_in_vm(RuntimeEnvironmentSlot::CallDepth), false) },
+                Argument { index: 0, value: Value::RegisterPlusConstant32(REGISTER_PTR_TO_VM, self.slot_in_vm(RuntimeEnvironmentSlot::CallFrames), false) },
+            ], None);
+        }
         // Increase env.call_depth
         let call_depth_access = X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::CallDepth));
         self.emit_ins(X86Instruction::alu_immediate(OperandSize::S64, 0x81, 0, REGISTER_PTR_TO_VM, 1, Some(call_depth_access))); // env.call_depth += 1;
@@ -1673,6 +1926,28 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
 
             self.emit_ins(X86Instruction::return_near());
         }
+
+        // Translates a vm memory address to a host memory address for a read-modify-write access
+        for len in &[4u64, 8u64] {
+            self.set_anchor(ANCHOR_TRANSLATE_ATOMIC_ADDRESS + (len.trailing_zeros() as usize - 2));
+            // call MemoryMapping::map_atomic storing the result in RuntimeEnvironmentSlot::ProgramResult
+            self.emit_rust_call(Value::Constant64(MemoryMapping::map_atomic as *const u8 as i64, false), &[
+                Argument { index: 2, value: Value::Register(REGISTER_SCRATCH) }, // Specify first as the src register could be overwritten by other arguments
+                Argument { index: 3, value: Value::Constant64(*len as i64, false) },
+                Argument { index: 1, value: Value::RegisterPlusConstant32(REGISTER_PTR_TO_VM, self.slot_in_vm(RuntimeEnvironmentSlot::MemoryMapping), false) },
+                Argument { index: 0, value: Value::RegisterPlusConstant32(REGISTER_PTR_TO_VM, self.slot_in_vm(RuntimeEnvironmentSlot::ProgramResult), false) },
+            ], None);
+
+            // Throw error if the result indicates one
+            self.emit_result_is_err(REGISTER_SCRATCH);
+            self.emit_ins(X86Instruction::pop(REGISTER_SCRATCH)); // REGISTER_SCRATCH = self.pc
+            self.emit_ins(X86Instruction::xchg(OperandSize::S64, REGISTER_SCRATCH, RSP, Some(X86IndirectAccess::OffsetIndexShift(0, RSP, 0)))); // Swap return address and self.pc
+            self.emit_ins(X86Instruction::conditional_jump_immediate(0x85, self.relative_to_anchor(ANCHOR_THROW_EXCEPTION, 6)));
+
+            // unwrap() the result into REGISTER_SCRATCH
+            self.emit_ins(X86Instruction::load(OperandSize::S64, REGISTER_PTR_TO_VM, REGISTER_SCRATCH, X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::ProgramResult) + std::mem::size_of::<u64>() as i32)));
+            self.emit_ins(X86Instruction::return_near());
+        }
     }
 
     fn set_anchor(&mut self, anchor: usize) {
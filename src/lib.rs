@@ -23,24 +23,34 @@ extern crate log;
 extern crate rand;
 extern crate thiserror;
 
+pub mod abstract_interpretation;
 pub mod aligned_memory;
 mod asm_parser;
 pub mod assembler;
+pub mod cost_analysis;
+pub mod dead_code_analysis;
 #[cfg(feature = "debugger")]
 pub mod debugger;
 pub mod disassembler;
 pub mod ebpf;
 pub mod elf;
+pub mod elf_linter;
 pub mod elf_parser;
+pub mod elf_writer;
 pub mod error;
 pub mod insn_builder;
 pub mod interpreter;
 #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
 pub mod jit;
+pub mod linux_object;
+pub mod loop_analysis;
 #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
 mod memory_management;
 pub mod memory_region;
+pub mod migration;
 pub mod program;
+pub mod stack_analysis;
+pub mod stack_initialization;
 pub mod static_analysis;
 pub mod verifier;
 pub mod vm;
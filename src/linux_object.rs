@@ -0,0 +1,296 @@
+//! Loader for relocatable Linux eBPF object files
+//!
+//! Object files compiled for the Linux kernel keep every program in a section named after its
+//! program type and attach point, e.g. `socket` or `xdp`, share subprograms through `.text` and
+//! leave the helper calls and map references to be resolved when loading. [load_linux_object]
+//! links one program and its subprograms into an SBPFv0 [Executable], which calls builtin
+//! functions in place of the helpers and embeds the addresses of the maps provided by the host.
+
+use crate::{
+    aligned_memory::AlignedMemory,
+    ebpf::{self, HOST_ALIGN, INSN_SIZE},
+    elf::{ElfError, Executable},
+    elf_parser::{
+        consts::{EM_BPF, ET_REL, R_BPF_64_32, R_BPF_64_64, SHF_EXECINSTR},
+        types::Elf64Sym,
+        Elf64,
+    },
+    program::{BuiltinProgram, FunctionRegistry, SBPFVersion},
+    vm::ContextObject,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    ops::Range,
+};
+
+#[cfg(not(feature = "shuttle-test"))]
+use std::sync::Arc;
+
+#[cfg(feature = "shuttle-test")]
+use shuttle::sync::Arc;
+
+/// `src` of a `lddw` which loads a map instead of an immediate
+const BPF_PSEUDO_MAP_FD: u8 = 1;
+/// `src` of a `call` of a subprogram instead of a helper
+const BPF_PSEUDO_CALL: u8 = 1;
+
+/// What the helpers and maps an object file refers to are resolved to
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LinuxImports {
+    /// Name of the builtin function registered in the loader which implements each helper id
+    pub helpers: BTreeMap<u32, Vec<u8>>,
+    /// Virtual address of each map, by the name of its symbol
+    pub maps: BTreeMap<Vec<u8>, u64>,
+}
+
+/// A section of the object file which is part of the linked program
+struct CodeSection {
+    /// Index in the section header table
+    index: usize,
+    /// File offset of the section
+    offset: usize,
+    /// Instructions of the section in the linked program
+    pcs: Range<usize>,
+}
+
+/// Loads the program in `program_section` of a relocatable Linux eBPF object file
+///
+/// The subprograms in `.text` are appended to the program, which starts at pc 0. BTF and all
+/// other sections which are not code are ignored.
+pub fn load_linux_object<C: ContextObject>(
+    bytes: &[u8],
+    program_section: &[u8],
+    loader: Arc<BuiltinProgram<C>>,
+    imports: &LinuxImports,
+) -> Result<Executable<C>, ElfError> {
+    if !loader
+        .get_config()
+        .enabled_sbpf_versions
+        .contains(&SBPFVersion::V0)
+    {
+        return Err(ElfError::UnsupportedSBPFVersion);
+    }
+    let aligned_memory = AlignedMemory::<{ HOST_ALIGN }>::from_slice(bytes);
+    let elf_bytes = aligned_memory.as_slice();
+    let elf = Elf64::parse_relocatable(elf_bytes)?;
+    let header = elf.file_header();
+    if header.e_type != ET_REL {
+        return Err(ElfError::WrongType);
+    }
+    if header.e_machine != EM_BPF {
+        return Err(ElfError::WrongMachine);
+    }
+
+    let section_headers = elf.section_header_table();
+    let find_code_section = |name: &[u8]| {
+        section_headers.iter().position(|section_header| {
+            section_header.sh_flags & SHF_EXECINSTR != 0
+                && elf.section_name(section_header.sh_name).ok() == Some(name)
+        })
+    };
+    let program_index = find_code_section(program_section).ok_or_else(|| {
+        ElfError::SectionNotFound(String::from_utf8_lossy(program_section).to_string())
+    })?;
+    let mut text = Vec::new();
+    let mut code_sections = Vec::new();
+    for index in std::iter::once(program_index)
+        .chain(find_code_section(b".text").filter(|text_index| *text_index != program_index))
+    {
+        let section_header = &section_headers[index];
+        let section_bytes = elf_bytes
+            .get(section_header.file_range().unwrap_or_default())
+            .ok_or(ElfError::ValueOutOfBounds)?;
+        if section_bytes.len().checked_rem(INSN_SIZE) != Some(0) {
+            return Err(ElfError::ValueOutOfBounds);
+        }
+        let first_pc = text.len().checked_div(INSN_SIZE).unwrap_or(0);
+        text.extend_from_slice(section_bytes);
+        code_sections.push(CodeSection {
+            index,
+            offset: section_header.sh_offset as usize,
+            pcs: first_pc..text.len().checked_div(INSN_SIZE).unwrap_or(0),
+        });
+    }
+
+    let symbol_table = elf.symbol_table()?.unwrap_or_default();
+    // Subprograms are labeled by the symbol at their first instruction if there is one
+    let function_name = |target_pc: usize| {
+        code_sections
+            .iter()
+            .find(|code_section| code_section.pcs.contains(&target_pc))
+            .and_then(|code_section| {
+                let offset = target_pc
+                    .saturating_sub(code_section.pcs.start)
+                    .saturating_mul(INSN_SIZE) as u64;
+                symbol_table.iter().find(|symbol| {
+                    symbol.st_shndx as usize == code_section.index
+                        && symbol.st_value == offset
+                        && symbol.st_name != 0
+                })
+            })
+            .and_then(|symbol| elf.symbol_name(symbol.st_name).ok())
+            .map(|name| name.to_vec())
+            .unwrap_or_else(|| format!("function_{target_pc}").into_bytes())
+    };
+    let mut function_registry = FunctionRegistry::default();
+    let mut register_call = |text: &mut [u8], pc: usize, target_pc: i64| {
+        let target_pc = usize::try_from(target_pc)
+            .ok()
+            .filter(|target_pc| {
+                code_sections
+                    .iter()
+                    .any(|code_section| code_section.pcs.contains(target_pc))
+            })
+            .ok_or(ElfError::RelativeJumpOutOfBounds(pc))?;
+        let key = function_registry.register_function_hashed_legacy(
+            &loader,
+            true,
+            function_name(target_pc),
+            target_pc,
+        )?;
+        write_call(text, pc, key);
+        Ok::<(), ElfError>(())
+    };
+
+    // Calls of subprograms in other sections and map references have relocations
+    let mut relocated_pcs = BTreeSet::new();
+    for (section_header, relocations) in elf.static_relocation_tables()? {
+        let Some(code_section) = code_sections.iter().find(|code_section| {
+            std::ptr::eq(&section_headers[code_section.index], section_header)
+        }) else {
+            continue;
+        };
+        for relocation in relocations {
+            let r_offset = relocation.r_offset as usize;
+            let pc = r_offset
+                .checked_div(INSN_SIZE)
+                .filter(|_| r_offset.checked_rem(INSN_SIZE) == Some(0))
+                .map(|pc| code_section.pcs.start.saturating_add(pc))
+                .filter(|pc| code_section.pcs.contains(pc))
+                .ok_or(ElfError::ValueOutOfBounds)?;
+            let symbol = symbol_table
+                .get(relocation.r_sym() as usize)
+                .ok_or(ElfError::UnknownSymbol(relocation.r_sym() as usize))?;
+            let name = elf
+                .symbol_name(symbol.st_name)
+                .map_err(|_| ElfError::UnknownSymbol(symbol.st_name as usize))?;
+            let insn = ebpf::get_insn(&text, pc);
+            match (relocation.r_type(), insn.opc) {
+                (R_BPF_64_64, ebpf::LD_DW_IMM)
+                    if code_section.pcs.contains(&pc.saturating_add(1)) =>
+                {
+                    let address = imports.maps.get(name).ok_or_else(|| {
+                        ElfError::UnresolvedSymbol(
+                            String::from_utf8_lossy(name).to_string(),
+                            pc,
+                            code_section.offset.saturating_add(r_offset),
+                        )
+                    })?;
+                    write_lddw(&mut text, pc, *address);
+                }
+                (R_BPF_64_32, ebpf::CALL_IMM) if insn.src == BPF_PSEUDO_CALL => {
+                    let target_pc =
+                        subprogram_pc(&code_sections, symbol, insn.imm).ok_or_else(|| {
+                            ElfError::UnresolvedSymbol(
+                                String::from_utf8_lossy(name).to_string(),
+                                pc,
+                                code_section.offset.saturating_add(r_offset),
+                            )
+                        })?;
+                    register_call(&mut text, pc, target_pc)?;
+                }
+                _ => return Err(ElfError::UnknownRelocation(relocation.r_type())),
+            }
+            relocated_pcs.insert(pc);
+        }
+    }
+
+    // Calls within a section are relative and helpers are called by their id
+    for code_section in code_sections.iter() {
+        let mut pc = code_section.pcs.start;
+        while pc < code_section.pcs.end {
+            let insn = ebpf::get_insn(&text, pc);
+            let offset = code_section.offset.saturating_add(
+                pc.saturating_sub(code_section.pcs.start)
+                    .saturating_mul(INSN_SIZE),
+            );
+            if relocated_pcs.contains(&pc) {
+                // Already resolved
+            } else if insn.opc == ebpf::CALL_IMM && insn.src == BPF_PSEUDO_CALL {
+                let target_pc = (pc as i64).saturating_add(1).saturating_add(insn.imm);
+                if !code_section
+                    .pcs
+                    .contains(&usize::try_from(target_pc).unwrap_or(usize::MAX))
+                {
+                    return Err(ElfError::RelativeJumpOutOfBounds(pc));
+                }
+                register_call(&mut text, pc, target_pc)?;
+            } else if insn.opc == ebpf::CALL_IMM {
+                let unresolved =
+                    || ElfError::UnresolvedSymbol(format!("helper #{}", insn.imm), pc, offset);
+                let name = imports
+                    .helpers
+                    .get(&(insn.imm as u32))
+                    .ok_or_else(unresolved)?;
+                let key = ebpf::hash_symbol_name(name);
+                if loader.get_function_registry().lookup_by_key(key).is_none() {
+                    return Err(ElfError::UnresolvedSymbol(
+                        String::from_utf8_lossy(name).to_string(),
+                        pc,
+                        offset,
+                    ));
+                }
+                write_call(&mut text, pc, key);
+            } else if insn.opc == ebpf::LD_DW_IMM && insn.src == BPF_PSEUDO_MAP_FD {
+                return Err(ElfError::UnresolvedSymbol(
+                    format!("map fd {}", insn.imm),
+                    pc,
+                    offset,
+                ));
+            }
+            pc = pc.saturating_add(if insn.opc == ebpf::LD_DW_IMM { 2 } else { 1 });
+        }
+    }
+
+    Executable::new_from_text_bytes(&text, loader, SBPFVersion::V0, function_registry)
+}
+
+/// The pc of a subprogram called through a relocation
+///
+/// The symbol is either the subprogram itself, with an `imm` of -1, or the section, with the
+/// offset of the subprogram encoded in `imm` like a relative call from the start of the section.
+fn subprogram_pc(code_sections: &[CodeSection], symbol: &Elf64Sym, imm: i64) -> Option<i64> {
+    let code_section = code_sections
+        .iter()
+        .find(|code_section| code_section.index == symbol.st_shndx as usize)?;
+    let symbol_pc = symbol.st_value.checked_div(INSN_SIZE as u64)? as i64;
+    (code_section.pcs.start as i64)
+        .checked_add(symbol_pc)?
+        .checked_add(imm)?
+        .checked_add(1)
+}
+
+/// Replaces the call at `pc` by a call of `key`
+fn write_call(text: &mut [u8], pc: usize, key: u32) {
+    let mut insn = ebpf::get_insn(text, pc);
+    insn.src = 0;
+    insn.imm = key as i64;
+    write_insn(text, pc, &insn);
+}
+
+/// Replaces the immediate of the `lddw` at `pc` by `value`
+fn write_lddw(text: &mut [u8], pc: usize, value: u64) {
+    let mut insn = ebpf::get_insn(text, pc);
+    insn.src = 0;
+    insn.imm = value as u32 as i64;
+    write_insn(text, pc, &insn);
+    let mut insn = ebpf::get_insn(text, pc.saturating_add(1));
+    insn.imm = value.checked_shr(32).unwrap_or(0) as i64;
+    write_insn(text, pc.saturating_add(1), &insn);
+}
+
+fn write_insn(text: &mut [u8], pc: usize, insn: &ebpf::Insn) {
+    let start = pc.saturating_mul(INSN_SIZE);
+    text[start..start.saturating_add(INSN_SIZE)].copy_from_slice(&insn.to_array());
+}
@@ -0,0 +1,668 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Termination of the loops in the control-flow graph
+//!
+//! Every strongly connected component of the control-flow graph of [Analysis] which contains a
+//! cycle is a loop. If it is only entered through one basic block, its header, the cycles which
+//! avoid the header form the loops nested in it, which are found the same way in the component
+//! without the header. A loop is bounded by a constant if one of its conditional exits compares
+//! an induction variable against a constant, so that the variable, which is stepped by a constant
+//! exactly once per iteration, must eventually leave the loop without wrapping around. Both, the
+//! exit and the step, have to be on every path from the header back to it and outside of the
+//! nested loops. Loops with multiple entries are not split up, so the exit and the step have to
+//! be on all of their cycles instead. Loops whose exit condition depends on the return value of a
+//! syscall, directly or as the bound of an induction variable, are reported as bounded by it.
+//! Everything else is only stopped by the instruction meter.
+
+use crate::{
+    abstract_interpretation::{AbstractInterpretation, MemoryAccess, RegisterType},
+    ebpf,
+    static_analysis::Analysis,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+/// What ends a loop
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopBound {
+    /// An induction variable reaches a constant
+    Constant {
+        /// Conditional jump which leaves the loop
+        exit: usize,
+        /// Register holding the induction variable
+        register: u8,
+        /// Added to the induction variable in every iteration
+        step: i64,
+        /// Value the induction variable is compared against
+        bound: u64,
+    },
+    /// The exit condition depends on the return value of a syscall
+    Syscall {
+        /// Conditional jump which leaves the loop
+        exit: usize,
+        /// The call of the syscall
+        syscall: usize,
+    },
+    /// Only the instruction meter ends the loop
+    Unknown,
+}
+
+/// A strongly connected component of the control-flow graph, or of a loop without its header
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Loop {
+    /// Name of the function containing the loop
+    pub function: String,
+    /// First pcs of the basic blocks of the loop, including those of the nested loops
+    pub blocks: BTreeSet<usize>,
+    /// What ends the loop
+    pub bound: LoopBound,
+}
+
+/// Classification of all loops of an executable
+#[derive(Debug)]
+pub struct LoopAnalysis {
+    /// Loops by the first pc of the basic block through which they are entered
+    pub loops: BTreeMap<usize, Loop>,
+}
+
+/// Relation between the induction variable and its bound which keeps the loop going
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Relation {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Relation {
+    fn negate(self) -> Self {
+        match self {
+            Self::Equal => Self::NotEqual,
+            Self::NotEqual => Self::Equal,
+            Self::Less => Self::GreaterOrEqual,
+            Self::LessOrEqual => Self::Greater,
+            Self::Greater => Self::LessOrEqual,
+            Self::GreaterOrEqual => Self::Less,
+        }
+    }
+
+    fn mirror(self) -> Self {
+        match self {
+            Self::Less => Self::Greater,
+            Self::LessOrEqual => Self::GreaterOrEqual,
+            Self::Greater => Self::Less,
+            Self::GreaterOrEqual => Self::LessOrEqual,
+            relation => relation,
+        }
+    }
+}
+
+/// Operand of a comparison
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operand {
+    Register(u8),
+    Constant(u64),
+}
+
+/// Condition under which a conditional jump stays inside of the loop
+struct Condition {
+    exit: usize,
+    relation: Relation,
+    signed: bool,
+    bits: u32,
+    left: u8,
+    right: Operand,
+}
+
+impl LoopAnalysis {
+    /// Finds and classifies the loops
+    pub fn new(analysis: &Analysis) -> Self {
+        let all_blocks = analysis
+            .cfg_nodes
+            .keys()
+            .filter(|start| **start != analysis.super_root)
+            .copied()
+            .collect();
+        let mut worklist = cyclic_components(analysis, &all_blocks);
+        let mut abstract_interpretation = None;
+        let mut loops = BTreeMap::new();
+        while let Some(blocks) = worklist.pop() {
+            let entries = blocks
+                .iter()
+                .filter(|start| {
+                    analysis.cfg_nodes[*start]
+                        .sources
+                        .iter()
+                        .any(|source| !blocks.contains(source))
+                })
+                .copied()
+                .collect::<Vec<_>>();
+            let header = *entries.first().or(blocks.first()).unwrap();
+            // The header of a loop with a single entry dominates all of its blocks
+            let nested_loops = (entries.len() <= 1).then(|| {
+                let mut body = blocks.clone();
+                body.remove(&header);
+                cyclic_components(analysis, &body)
+            });
+            let nested_blocks = nested_loops
+                .as_ref()
+                .map(|nested_loops| nested_loops.iter().flatten().copied().collect());
+            let bound = Classifier {
+                analysis,
+                blocks: &blocks,
+                header,
+                nested_blocks: nested_blocks.as_ref(),
+                abstract_interpretation: &mut abstract_interpretation,
+            }
+            .classify();
+            worklist.extend(nested_loops.into_iter().flatten());
+            let function = analysis
+                .functions
+                .range(..=header)
+                .next_back()
+                .map(|(_pc, (_key, name))| name.clone())
+                .unwrap_or_default();
+            loops.insert(
+                header,
+                Loop {
+                    function,
+                    blocks,
+                    bound,
+                },
+            );
+        }
+        Self { loops }
+    }
+
+    /// Loops which only the instruction meter ends, by their header
+    pub fn unbounded_loops(&self) -> impl Iterator<Item = (&usize, &Loop)> {
+        self.loops
+            .iter()
+            .filter(|(_header, r#loop)| r#loop.bound == LoopBound::Unknown)
+    }
+}
+
+impl fmt::Display for LoopAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = |predicate: fn(&LoopBound) -> bool| {
+            self.loops
+                .values()
+                .filter(|r#loop| predicate(&r#loop.bound))
+                .count()
+        };
+        writeln!(
+            f,
+            "{} loops: {} bounded by a constant, {} by a syscall, {} unknown",
+            self.loops.len(),
+            count(|bound| matches!(bound, LoopBound::Constant { .. })),
+            count(|bound| matches!(bound, LoopBound::Syscall { .. })),
+            count(|bound| *bound == LoopBound::Unknown),
+        )?;
+        for (header, r#loop) in self.loops.iter() {
+            write!(f, "{}: loop at insn #{} ", r#loop.function, header)?;
+            match r#loop.bound {
+                LoopBound::Constant {
+                    exit,
+                    register,
+                    step,
+                    bound,
+                } => writeln!(
+                    f,
+                    "bounded by r{register} stepping {step} towards {bound:#x} (insn #{exit})"
+                )?,
+                LoopBound::Syscall { exit, syscall } => writeln!(
+                    f,
+                    "bounded by the syscall at insn #{syscall} (insn #{exit})"
+                )?,
+                LoopBound::Unknown => writeln!(f, "unknown")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Strongly connected components of the control-flow graph between the given basic blocks,
+/// which contain a cycle
+fn cyclic_components(analysis: &Analysis, blocks: &BTreeSet<usize>) -> Vec<BTreeSet<usize>> {
+    // Tarjan's algorithm with an explicit call stack
+    let mut indices = BTreeMap::<usize, (usize, usize)>::new();
+    let mut stack = Vec::new();
+    let mut on_stack = BTreeSet::new();
+    let mut components = Vec::new();
+    for root in blocks.iter() {
+        if indices.contains_key(root) {
+            continue;
+        }
+        let mut call_stack = vec![(*root, 0)];
+        while let Some((node, edge)) = call_stack.pop() {
+            if edge == 0 {
+                indices.insert(node, (indices.len(), indices.len()));
+                stack.push(node);
+                on_stack.insert(node);
+            }
+            let destinations = &analysis.cfg_nodes[&node].destinations;
+            let unvisited =
+                destinations
+                    .iter()
+                    .enumerate()
+                    .skip(edge)
+                    .find(|(_position, destination)| {
+                        blocks.contains(destination) && !indices.contains_key(destination)
+                    });
+            if let Some((position, destination)) = unvisited {
+                call_stack.push((node, position + 1));
+                call_stack.push((*destination, 0));
+                continue;
+            }
+            let (index, mut lowlink) = indices[&node];
+            for destination in destinations.iter() {
+                if on_stack.contains(destination) {
+                    lowlink = lowlink.min(indices[destination].1);
+                }
+            }
+            indices.insert(node, (index, lowlink));
+            if lowlink == index {
+                let mut component = BTreeSet::new();
+                while let Some(member) = stack.pop() {
+                    on_stack.remove(&member);
+                    component.insert(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                if component.len() > 1 || destinations.contains(&node) {
+                    components.push(component);
+                }
+            }
+        }
+    }
+    components
+}
+
+struct Classifier<'a, 'b> {
+    analysis: &'a Analysis<'a>,
+    blocks: &'b BTreeSet<usize>,
+    header: usize,
+    /// Blocks of the nested loops, unless the loop has multiple entries
+    nested_blocks: Option<&'b BTreeSet<usize>>,
+    abstract_interpretation: &'b mut Option<AbstractInterpretation>,
+}
+
+impl Classifier<'_, '_> {
+    fn classify(&mut self) -> LoopBound {
+        let mut result = LoopBound::Unknown;
+        for start in self.blocks.iter() {
+            let Some(condition) = self.exit_condition(*start) else {
+                continue;
+            };
+            if !self.is_on_every_iteration(*start) {
+                continue;
+            }
+            let mut operands = vec![(condition.relation, condition.left, condition.right)];
+            if let Operand::Register(right) = condition.right {
+                operands.push((
+                    condition.relation.mirror(),
+                    right,
+                    Operand::Register(condition.left),
+                ));
+            }
+            for (relation, register, bound) in operands {
+                let Some(step) = self.induction_step(register, condition.bits) else {
+                    continue;
+                };
+                let bound = match bound {
+                    Operand::Constant(bound) => Some(bound),
+                    Operand::Register(bound) if self.is_invariant(bound) => {
+                        match self.constant_at_header(bound) {
+                            Some(bound) => Some(bound),
+                            None => {
+                                if let (LoopBound::Unknown, Some(syscall)) =
+                                    (result, self.syscall_origin(self.header, None, bound))
+                                {
+                                    result = LoopBound::Syscall {
+                                        exit: condition.exit,
+                                        syscall,
+                                    };
+                                }
+                                None
+                            }
+                        }
+                    }
+                    Operand::Register(_) => None,
+                };
+                if let Some(bound) = bound {
+                    let bound = if condition.bits == 32 {
+                        bound as u32 as u64
+                    } else {
+                        bound
+                    };
+                    if terminates(relation, condition.signed, condition.bits, step, bound) {
+                        return LoopBound::Constant {
+                            exit: condition.exit,
+                            register,
+                            step,
+                            bound,
+                        };
+                    }
+                }
+            }
+            if result == LoopBound::Unknown {
+                let jump = self.analysis.cfg_nodes[start].instructions.end - 1;
+                let mut registers = vec![condition.left];
+                if let Operand::Register(right) = condition.right {
+                    registers.push(right);
+                }
+                for register in registers {
+                    if let Some(syscall) = self.syscall_origin(*start, Some(jump), register) {
+                        result = LoopBound::Syscall {
+                            exit: condition.exit,
+                            syscall,
+                        };
+                        break;
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// The condition of a conditional jump at the end of the basic block which leaves the loop
+    fn exit_condition(&self, start: usize) -> Option<Condition> {
+        let cfg_node = &self.analysis.cfg_nodes[&start];
+        let insn = &self.analysis.instructions[cfg_node.instructions.end - 1];
+        let sbpf_version = self.analysis.executable.get_sbpf_version();
+        let bits = match insn.opc & ebpf::BPF_CLS_MASK {
+            ebpf::BPF_JMP64 => 64,
+            ebpf::BPF_JMP32 if sbpf_version.enable_jmp32() => 32,
+            _ => return None,
+        };
+        let (relation, signed) = match insn.opc & ebpf::BPF_ALU_OP_MASK {
+            ebpf::BPF_JEQ => (Relation::Equal, false),
+            ebpf::BPF_JNE => (Relation::NotEqual, false),
+            ebpf::BPF_JLT => (Relation::Less, false),
+            ebpf::BPF_JLE => (Relation::LessOrEqual, false),
+            ebpf::BPF_JGT => (Relation::Greater, false),
+            ebpf::BPF_JGE => (Relation::GreaterOrEqual, false),
+            ebpf::BPF_JSLT => (Relation::Less, true),
+            ebpf::BPF_JSLE => (Relation::LessOrEqual, true),
+            ebpf::BPF_JSGT => (Relation::Greater, true),
+            ebpf::BPF_JSGE => (Relation::GreaterOrEqual, true),
+            _ => return None,
+        };
+        let target_pc = (insn.ptr as isize + insn.off as isize + 1) as usize;
+        let relation = match (
+            self.blocks.contains(&target_pc),
+            self.blocks.contains(&(insn.ptr + 1)),
+        ) {
+            (true, false) => relation,
+            (false, true) => relation.negate(),
+            _ => return None,
+        };
+        let right = if insn.opc & ebpf::BPF_X != 0 {
+            Operand::Register(insn.src)
+        } else if bits == 32 {
+            Operand::Constant(insn.imm as u32 as u64)
+        } else {
+            Operand::Constant(insn.imm as u64)
+        };
+        Some(Condition {
+            exit: insn.ptr,
+            relation,
+            signed,
+            bits,
+            left: insn.dst,
+            right,
+        })
+    }
+
+    /// The constant step of an induction variable which is written exactly once, on every cycle
+    fn induction_step(&self, register: u8, bits: u32) -> Option<i64> {
+        let sbpf_version = self.analysis.executable.get_sbpf_version();
+        let mut step = None;
+        for start in self.blocks.iter() {
+            for insn in self.instructions(*start) {
+                if self.written_registers(insn) & (1 << register) == 0 {
+                    continue;
+                }
+                if step.is_some() {
+                    return None;
+                }
+                let imm = if bits == 32 {
+                    insn.imm as i32 as i64
+                } else {
+                    insn.imm
+                };
+                step = Some((
+                    *start,
+                    match insn.opc {
+                        ebpf::ADD32_IMM if bits == 32 => imm,
+                        ebpf::ADD64_IMM if bits == 64 => imm,
+                        ebpf::SUB32_IMM if bits == 32 => imm.wrapping_neg(),
+                        ebpf::SUB64_IMM
+                            if bits == 64 && !sbpf_version.swap_sub_reg_imm_operands() =>
+                        {
+                            imm.wrapping_neg()
+                        }
+                        _ => return None,
+                    },
+                ));
+            }
+        }
+        let (start, step) = step?;
+        (step != 0 && self.is_on_every_iteration(start)).then_some(step)
+    }
+
+    fn is_invariant(&self, register: u8) -> bool {
+        self.blocks.iter().all(|start| {
+            self.instructions(*start)
+                .iter()
+                .all(|insn| self.written_registers(insn) & (1 << register) == 0)
+        })
+    }
+
+    fn constant_at_header(&mut self, register: u8) -> Option<u64> {
+        let analysis = self.analysis;
+        let state = self
+            .abstract_interpretation
+            .get_or_insert_with(|| AbstractInterpretation::run(analysis))
+            .block_states
+            .get(&self.header)?;
+        match state.registers.get(register as usize)? {
+            RegisterType::Scalar(range) => range.as_constant().map(|value| value as u64),
+            _ => None,
+        }
+    }
+
+    /// Searches the definitions of a register backwards for the result of a syscall
+    ///
+    /// Follows moves between registers. All definitions, which reach the instruction at `end` or
+    /// the end of the basic block, have to stem from the same syscall.
+    fn syscall_origin(&self, start: usize, end: Option<usize>, register: u8) -> Option<usize> {
+        let mut visited = BTreeSet::new();
+        let mut worklist = vec![(start, end, register)];
+        let mut result = None;
+        while let Some((start, end, mut register)) = worklist.pop() {
+            if !visited.insert((start, end, register)) {
+                continue;
+            }
+            let cfg_node = &self.analysis.cfg_nodes[&start];
+            let end = end.unwrap_or(cfg_node.instructions.end);
+            let mut definition = None;
+            for insn in self.analysis.instructions[cfg_node.instructions.start..end]
+                .iter()
+                .rev()
+            {
+                if self.written_registers(insn) & (1 << register) == 0 {
+                    continue;
+                }
+                if insn.opc == ebpf::MOV64_REG {
+                    register = insn.src;
+                    continue;
+                }
+                definition = Some(insn);
+                break;
+            }
+            match definition {
+                Some(insn) if register == 0 && self.is_syscall(insn) => {
+                    if result.is_some_and(|syscall| syscall != insn.ptr) {
+                        return None;
+                    }
+                    result = Some(insn.ptr);
+                }
+                Some(_) => return None,
+                None => {
+                    if cfg_node.sources.is_empty() {
+                        return None;
+                    }
+                    for source in cfg_node.sources.iter() {
+                        if *source == self.analysis.super_root {
+                            return None;
+                        }
+                        worklist.push((*source, None, register));
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    fn is_syscall(&self, insn: &ebpf::Insn) -> bool {
+        let executable = self.analysis.executable;
+        insn.opc == ebpf::CALL_IMM
+            && if executable.get_sbpf_version().static_syscalls() {
+                insn.src == 0
+            } else {
+                executable
+                    .get_function_registry()
+                    .lookup_by_key(insn.imm as u32)
+                    .is_none()
+            }
+    }
+
+    /// Whether the basic block is passed exactly once per iteration
+    fn is_on_every_iteration(&self, cut: usize) -> bool {
+        let Some(nested_blocks) = self.nested_blocks else {
+            return self.is_on_every_cycle(cut);
+        };
+        if cut == self.header {
+            return true;
+        }
+        if nested_blocks.contains(&cut) {
+            return false;
+        }
+        // Search for a path from the header back to it which avoids the basic block
+        let mut visited = BTreeSet::from([self.header]);
+        let mut worklist = vec![self.header];
+        while let Some(node) = worklist.pop() {
+            for destination in self.analysis.cfg_nodes[&node].destinations.iter() {
+                if *destination == self.header {
+                    return false;
+                }
+                if *destination != cut
+                    && self.blocks.contains(destination)
+                    && visited.insert(*destination)
+                {
+                    worklist.push(*destination);
+                }
+            }
+        }
+        true
+    }
+
+    /// Whether removing the basic block leaves the loop without cycles
+    fn is_on_every_cycle(&self, cut: usize) -> bool {
+        // Depth first search for a back edge, 1 means on the stack and 2 finished
+        let mut colors = BTreeMap::<usize, u8>::new();
+        for root in self.blocks.iter() {
+            if *root == cut || colors.contains_key(root) {
+                continue;
+            }
+            colors.insert(*root, 1);
+            let mut stack = vec![(*root, 0)];
+            while let Some((node, index)) = stack.pop() {
+                let destinations = &self.analysis.cfg_nodes[&node].destinations;
+                if let Some(destination) = destinations.get(index) {
+                    stack.push((node, index + 1));
+                    if *destination == cut || !self.blocks.contains(destination) {
+                        continue;
+                    }
+                    match colors.get(destination) {
+                        Some(1) => return false,
+                        Some(_) => {}
+                        None => {
+                            colors.insert(*destination, 1);
+                            stack.push((*destination, 0));
+                        }
+                    }
+                } else {
+                    colors.insert(node, 2);
+                }
+            }
+        }
+        true
+    }
+
+    fn instructions(&self, start: usize) -> &[ebpf::Insn] {
+        &self.analysis.instructions[self.analysis.cfg_nodes[&start].instructions.clone()]
+    }
+
+    /// Bit mask of the registers an instruction may change
+    fn written_registers(&self, insn: &ebpf::Insn) -> u16 {
+        let sbpf_version = self.analysis.executable.get_sbpf_version();
+        if let Some(access) = MemoryAccess::decode(insn, sbpf_version) {
+            return if access.is_load {
+                1 << insn.dst
+            } else if insn.opc == ebpf::ST_W_ATOMIC || insn.opc == ebpf::ST_DW_ATOMIC {
+                match insn.imm {
+                    ebpf::ATOMIC_FETCH_ADD | ebpf::ATOMIC_XCHG => 1 << insn.src,
+                    ebpf::ATOMIC_CMPXCHG => 1,
+                    _ => 0,
+                }
+            } else {
+                0
+            };
+        }
+        match insn.opc {
+            ebpf::LD_DW_IMM if !sbpf_version.disable_lddw() => return 1 << insn.dst,
+            // Syscalls only write r0, but are treated like functions for simplicity
+            ebpf::CALL_IMM | ebpf::CALL_REG => return (1 << ebpf::FIRST_SCRATCH_REG) - 1,
+            _ => {}
+        }
+        match insn.opc & ebpf::BPF_CLS_MASK {
+            ebpf::BPF_ALU32_LOAD | ebpf::BPF_ALU64_STORE => 1 << insn.dst,
+            ebpf::BPF_PQR if sbpf_version.enable_pqr() => 1 << insn.dst,
+            _ => 0,
+        }
+    }
+}
+
+/// Whether an induction variable which is stepped while the relation holds leaves the loop
+fn terminates(relation: Relation, signed: bool, bits: u32, step: i64, bound: u64) -> bool {
+    let modulus = 1i128 << bits;
+    let step = step as i128;
+    // The bound and the values of the induction variable as integers without wrap around
+    let (bound, min, max) = if signed {
+        let bound = bound as i128;
+        let bound = if bound >= modulus / 2 {
+            bound - modulus
+        } else {
+            bound
+        };
+        (bound, -modulus / 2, modulus / 2 - 1)
+    } else {
+        (bound as i128, 0, modulus - 1)
+    };
+    match relation {
+        // Any change of the variable leaves the loop
+        Relation::Equal => step.rem_euclid(modulus) != 0,
+        // Steps of one can not skip the bound
+        Relation::NotEqual => step == 1 || step == -1,
+        Relation::Less => step > 0 && bound - 1 + step <= max,
+        Relation::LessOrEqual => step > 0 && bound + step <= max,
+        Relation::Greater => step < 0 && bound + 1 + step >= min,
+        Relation::GreaterOrEqual => step < 0 && bound + step >= min,
+    }
+}
@@ -7,7 +7,8 @@ use crate::{
     program::SBPFVersion,
     vm::Config,
 };
-use std::{array, cell::UnsafeCell, fmt, mem, ops::Range, ptr};
+use byteorder::{ByteOrder, LittleEndian};
+use std::{array, cell::UnsafeCell, fmt, marker::PhantomData, mem, ops::Range, ptr};
 
 /* Explanation of the Gapped Memory
 
@@ -26,6 +27,111 @@ use std::{array, cell::UnsafeCell, fmt, mem, ops::Range, ptr};
     Host:  frame 0 | frame 1 | frame 2 | ...
 */
 
+/// Placement of the memory regions in the virtual address space
+///
+/// Every region occupies a slot of `MM_REGION_SIZE` bytes and is identified by the upper
+/// bits of its virtual addresses. The slots of the rodata (0) and bytecode (1) regions
+/// are fixed, as their addresses are baked into the programs by the linker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressSpaceLayout {
+    /// Total number of region slots, addresses beyond them are never mapped
+    pub number_of_regions: usize,
+    /// Slot of the stack region
+    pub stack_region_index: usize,
+    /// Slot of the heap region
+    pub heap_region_index: usize,
+    /// Slot of the first input region, the others follow consecutively
+    pub first_input_region_index: usize,
+    /// Number of input regions
+    pub number_of_input_regions: usize,
+}
+
+impl Default for AddressSpaceLayout {
+    fn default() -> Self {
+        Self {
+            number_of_regions: 5,
+            stack_region_index: 2,
+            heap_region_index: 3,
+            first_input_region_index: 4,
+            number_of_input_regions: 1,
+        }
+    }
+}
+
+impl AddressSpaceLayout {
+    fn region_start(index: usize) -> u64 {
+        (index as u64).saturating_mul(ebpf::MM_REGION_SIZE)
+    }
+
+    /// Virtual address of the stack region
+    pub fn stack_start(&self) -> u64 {
+        Self::region_start(self.stack_region_index)
+    }
+
+    /// Virtual address of the heap region
+    pub fn heap_start(&self) -> u64 {
+        Self::region_start(self.heap_region_index)
+    }
+
+    /// Virtual address of the input region with the given index
+    pub fn input_start(&self, input_index: usize) -> Option<u64> {
+        (input_index < self.number_of_input_regions)
+            .then(|| Self::region_start(self.first_input_region_index.saturating_add(input_index)))
+    }
+
+    /// Exclusive upper bound of all mappable virtual addresses
+    pub fn end(&self) -> u64 {
+        Self::region_start(self.number_of_regions)
+    }
+
+    /// Name of the region slot which contains the given address
+    pub fn region_name(&self, vm_addr: u64) -> &'static str {
+        let index = vm_addr
+            .checked_shr(ebpf::VIRTUAL_ADDRESS_BITS as u32)
+            .unwrap_or(0) as usize;
+        let input_regions = self.first_input_region_index
+            ..self
+                .first_input_region_index
+                .saturating_add(self.number_of_input_regions);
+        if index >= self.number_of_regions {
+            "unknown"
+        } else if index == 1 {
+            "program"
+        } else if index == self.stack_region_index {
+            "stack"
+        } else if index == self.heap_region_index {
+            "heap"
+        } else if input_regions.contains(&index) {
+            "input"
+        } else {
+            "unknown"
+        }
+    }
+
+    /// Checks that all slots are distinct and fit into the virtual address space
+    ///
+    /// On failure the index of the offending slot is reported.
+    pub fn validate(&self) -> Result<(), EbpfError> {
+        let max_number_of_regions = 1usize
+            .checked_shl(64u32.saturating_sub(ebpf::VIRTUAL_ADDRESS_BITS as u32))
+            .unwrap_or(usize::MAX);
+        if self.number_of_regions > max_number_of_regions {
+            return Err(EbpfError::InvalidMemoryRegion(self.number_of_regions));
+        }
+        let mut used = vec![false; self.number_of_regions];
+        let fixed_regions = [0, 1, self.stack_region_index, self.heap_region_index];
+        let input_regions = (0..self.number_of_input_regions)
+            .map(|input_index| self.first_input_region_index.saturating_add(input_index));
+        for index in fixed_regions.iter().copied().chain(input_regions) {
+            match used.get_mut(index) {
+                Some(slot) if !*slot => *slot = true,
+                _ => return Err(EbpfError::InvalidMemoryRegion(index)),
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Callback executed before generate_access_violation()
 pub type AccessViolationHandler = Box<dyn Fn(&mut MemoryRegion, u64, AccessType, u64, u64)>;
 /// Fail always
@@ -162,6 +268,67 @@ impl std::cmp::Ord for MemoryRegion {
     }
 }
 
+/// Remembers which bytes in the vm address space of a [MemoryRegion] have been written
+///
+/// Used on the stack to find reads of bytes whose value depends on how the stack was filled.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ShadowBitmap {
+    vm_addr_range: Range<u64>,
+    bits: Vec<u64>,
+}
+
+impl ShadowBitmap {
+    /// Creates a bitmap in which no byte of the region has been written yet
+    pub fn new(region: &MemoryRegion) -> Self {
+        let vm_addr_range = region.vm_addr_range();
+        let len = vm_addr_range.end.saturating_sub(vm_addr_range.start);
+        Self {
+            vm_addr_range,
+            bits: vec![0; len.div_ceil(64) as usize],
+        }
+    }
+
+    /// Marks bytes as written, ignores those outside of the region
+    pub fn mark(&mut self, vm_addr: u64, len: u64) {
+        let start = vm_addr.max(self.vm_addr_range.start);
+        let end = vm_addr.saturating_add(len).min(self.vm_addr_range.end);
+        for vm_addr in start..end {
+            if let Some((word, bit)) = self.position(vm_addr) {
+                self.bits[word] |= bit;
+            }
+        }
+    }
+
+    /// Marks bytes as not written again, ignores those outside of the region
+    pub fn clear(&mut self, vm_addr: u64, len: u64) {
+        let start = vm_addr.max(self.vm_addr_range.start);
+        let end = vm_addr.saturating_add(len).min(self.vm_addr_range.end);
+        for vm_addr in start..end {
+            if let Some((word, bit)) = self.position(vm_addr) {
+                self.bits[word] &= !bit;
+            }
+        }
+    }
+
+    /// Returns the first byte which has not been written yet
+    ///
+    /// Bytes outside of the region count as written.
+    pub fn first_unwritten(&self, vm_addr: u64, len: u64) -> Option<u64> {
+        (vm_addr..vm_addr.saturating_add(len)).find(|vm_addr| {
+            self.position(*vm_addr)
+                .is_some_and(|(word, bit)| self.bits[word] & bit == 0)
+        })
+    }
+
+    fn position(&self, vm_addr: u64) -> Option<(usize, u64)> {
+        if !self.vm_addr_range.contains(&vm_addr) {
+            return None;
+        }
+        let offset = vm_addr.saturating_sub(self.vm_addr_range.start);
+        Some(((offset / 64) as usize, 1 << (offset % 64)))
+    }
+}
+
 /// Type of memory access
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum AccessType {
@@ -171,6 +338,238 @@ pub enum AccessType {
     Store,
 }
 
+/* Explanation of the Scattered Input
+
+    Instead of copying all buffers into one contiguous host allocation, the
+    InputRegionBuilder maps every host buffer as its own MemoryRegion, placed
+    back to back in the virtual address space. Each buffer starts at a multiple
+    of the alignment, the space in between is mapped read-only to zeros.
+
+    Guest: buffer 0 | pad | buffer 1 | buffer 2 | pad | ...
+              |        |       |          |        |
+    Host:  buffer 0   ZEROS  buffer 1   buffer 2  ZEROS
+*/
+
+/// Largest alignment supported by the [InputRegionBuilder]
+pub const MAX_INPUT_ALIGNMENT: u64 = 4096;
+
+/// Backing memory of the alignment padding between buffers of the [InputRegionBuilder]
+static INPUT_PADDING: [u8; MAX_INPUT_ALIGNMENT as usize] = [0; MAX_INPUT_ALIGNMENT as usize];
+
+/// Placement of one host buffer in an [InputLayout]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputLayoutEntry {
+    /// Start virtual address
+    pub vm_addr: u64,
+    /// Length in bytes
+    pub len: u64,
+    /// Is `AccessType::Store` allowed
+    pub writable: bool,
+}
+
+/// Describes how the buffers of an [InputRegionBuilder] are placed in the virtual address space
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputLayout {
+    /// Start virtual address of the first buffer
+    pub vm_addr: u64,
+    /// Alignment of every buffer in the virtual address space
+    pub alignment: u64,
+    /// Placement of every buffer in insertion order
+    pub entries: Vec<InputLayoutEntry>,
+}
+
+impl InputLayout {
+    /// Size of a serialized [InputLayoutEntry] in bytes
+    const ENTRY_SIZE: usize = 17;
+    /// Size of the serialized header in bytes
+    const HEADER_SIZE: usize = 24;
+
+    /// Virtual address right after the padding of the last buffer
+    pub fn end(&self) -> u64 {
+        self.entries.last().map_or(self.vm_addr, |entry| {
+            align_up(entry.vm_addr.saturating_add(entry.len), self.alignment)
+        })
+    }
+
+    /// Serializes the layout in little endian:
+    /// vm_addr, alignment and number of entries as u64 followed by
+    /// vm_addr (u64), len (u64) and writable (u8) of every entry.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(
+            Self::HEADER_SIZE.saturating_add(self.entries.len().saturating_mul(Self::ENTRY_SIZE)),
+        );
+        result.extend_from_slice(&self.vm_addr.to_le_bytes());
+        result.extend_from_slice(&self.alignment.to_le_bytes());
+        result.extend_from_slice(&(self.entries.len() as u64).to_le_bytes());
+        for entry in self.entries.iter() {
+            result.extend_from_slice(&entry.vm_addr.to_le_bytes());
+            result.extend_from_slice(&entry.len.to_le_bytes());
+            result.push(entry.writable as u8);
+        }
+        result
+    }
+
+    /// Parses the output of [InputLayout::serialize]
+    ///
+    /// Returns `None` if the bytes are truncated or describe overlapping or misaligned buffers.
+    pub fn deserialize(bytes: &[u8]) -> Option<Self> {
+        let read_u64 = |offset: usize| -> Option<u64> {
+            Some(LittleEndian::read_u64(
+                bytes.get(offset..offset.checked_add(8)?)?,
+            ))
+        };
+        let vm_addr = read_u64(0)?;
+        let alignment = read_u64(8)?;
+        let number_of_entries = read_u64(16)? as usize;
+        if bytes.len()
+            != number_of_entries
+                .checked_mul(Self::ENTRY_SIZE)?
+                .checked_add(Self::HEADER_SIZE)?
+        {
+            return None;
+        }
+        let mut builder = InputRegionBuilder::new(vm_addr, alignment).ok()?;
+        for index in 0..number_of_entries {
+            let offset = Self::HEADER_SIZE.saturating_add(index.saturating_mul(Self::ENTRY_SIZE));
+            let entry = InputLayoutEntry {
+                vm_addr: read_u64(offset)?,
+                len: read_u64(offset.saturating_add(8))?,
+                writable: match bytes.get(offset.saturating_add(16))? {
+                    0 => false,
+                    1 => true,
+                    _ => return None,
+                },
+            };
+            if builder.next_vm_addr() != entry.vm_addr {
+                return None;
+            }
+            builder.push_entry(entry.len, entry.writable)?;
+        }
+        Some(builder.layout)
+    }
+
+    /// Maps the given host buffers according to this layout
+    ///
+    /// The buffers must have exactly the lengths recorded in the layout.
+    /// This allows serializing the layout once and mapping the account data in place later.
+    pub fn to_regions(&self, buffers: Vec<&mut [u8]>) -> Result<Vec<MemoryRegion>, EbpfError> {
+        if buffers.len() != self.entries.len() {
+            return Err(EbpfError::InvalidMemoryRegion(buffers.len()));
+        }
+        let mut builder = InputRegionBuilder::new(self.vm_addr, self.alignment)?;
+        for (index, (entry, buffer)) in self.entries.iter().zip(buffers).enumerate() {
+            if entry.len != buffer.len() as u64 {
+                return Err(EbpfError::InvalidMemoryRegion(index));
+            }
+            if entry.writable {
+                builder.push_writable(buffer)?;
+            } else {
+                builder.push_readonly(buffer)?;
+            }
+        }
+        Ok(builder.build().0)
+    }
+}
+
+fn align_up(value: u64, alignment: u64) -> u64 {
+    value.saturating_add(alignment.saturating_sub(1)) & !alignment.saturating_sub(1)
+}
+
+/// Builds a contiguous virtual address range out of multiple host buffers without copying them
+pub struct InputRegionBuilder<'a> {
+    /// Placement of the buffers pushed so far
+    layout: InputLayout,
+    /// Regions of the buffers and of the padding between them
+    regions: Vec<MemoryRegion>,
+    /// Ties the host addresses in `regions` to the pushed buffers
+    _buffers: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> InputRegionBuilder<'a> {
+    /// Starts an empty range at `vm_addr`
+    ///
+    /// `alignment` must be a power of two no larger than [MAX_INPUT_ALIGNMENT]
+    /// and `vm_addr` must be a multiple of it.
+    pub fn new(vm_addr: u64, alignment: u64) -> Result<Self, EbpfError> {
+        if !alignment.is_power_of_two()
+            || alignment > MAX_INPUT_ALIGNMENT
+            || align_up(vm_addr, alignment) != vm_addr
+        {
+            return Err(EbpfError::InvalidMemoryRegion(0));
+        }
+        Ok(Self {
+            layout: InputLayout {
+                vm_addr,
+                alignment,
+                entries: Vec::new(),
+            },
+            regions: Vec::new(),
+            _buffers: PhantomData,
+        })
+    }
+
+    /// Virtual address at which the next buffer will be placed
+    pub fn next_vm_addr(&self) -> u64 {
+        self.layout.end()
+    }
+
+    /// Records the next entry and its padding, returns its virtual address
+    fn push_entry(&mut self, len: u64, writable: bool) -> Option<u64> {
+        let vm_addr = self.next_vm_addr();
+        let end = vm_addr.checked_add(len)?;
+        let alignment_mask = self.layout.alignment.saturating_sub(1);
+        let aligned_end = end.checked_add(alignment_mask)? & !alignment_mask;
+        self.layout.entries.push(InputLayoutEntry {
+            vm_addr,
+            len,
+            writable,
+        });
+        let padding = aligned_end.saturating_sub(end) as usize;
+        if padding > 0 {
+            self.regions
+                .push(MemoryRegion::new_readonly(&INPUT_PADDING[..padding], end));
+        }
+        Some(vm_addr)
+    }
+
+    fn push_region(&mut self, region: MemoryRegion) -> Result<u64, EbpfError> {
+        let index = self.layout.entries.len();
+        let vm_addr = self
+            .push_entry(region.len, region.writable)
+            .ok_or(EbpfError::InvalidMemoryRegion(index))?;
+        if region.len > 0 {
+            self.regions.push(MemoryRegion { vm_addr, ..region });
+        }
+        Ok(vm_addr)
+    }
+
+    /// Appends a readonly buffer and returns its virtual address
+    ///
+    /// Fails if the buffer does not fit into the rest of the virtual address space.
+    pub fn push_readonly(&mut self, buffer: &'a [u8]) -> Result<u64, EbpfError> {
+        self.push_region(MemoryRegion::new_readonly(buffer, 0))
+    }
+
+    /// Appends a writable buffer and returns its virtual address
+    ///
+    /// Fails if the buffer does not fit into the rest of the virtual address space.
+    pub fn push_writable(&mut self, buffer: &'a mut [u8]) -> Result<u64, EbpfError> {
+        self.push_region(MemoryRegion::new_writable(buffer, 0))
+    }
+
+    /// Placement of the buffers pushed so far
+    pub fn layout(&self) -> &InputLayout {
+        &self.layout
+    }
+
+    /// Returns the regions to be passed to [MemoryMapping::new] and the layout
+    pub fn build(self) -> (Vec<MemoryRegion>, InputLayout) {
+        let mut regions = self.regions;
+        regions.sort();
+        (regions, self.layout)
+    }
+}
+
 /// Common parts of [UnalignedMemoryMapping] and [AlignedMemoryMapping]
 pub struct CommonMemoryMapping {
     /// Mapped memory regions
@@ -183,6 +582,10 @@ pub struct CommonMemoryMapping {
     stack_frame_size: i64,
     /// Executable sbpf_version
     sbpf_version: SBPFVersion,
+    /// Placement of the regions in the virtual address space
+    layout: AddressSpaceLayout,
+    /// Incremented every time the set of regions changes
+    generation: u64,
 }
 
 impl CommonMemoryMapping {
@@ -193,7 +596,7 @@ impl CommonMemoryMapping {
         len: u64,
     ) -> ProgramResult {
         let stack_frame = (vm_addr as i64)
-            .saturating_sub(ebpf::MM_STACK_START as i64)
+            .saturating_sub(self.layout.stack_start() as i64)
             .checked_div(self.stack_frame_size)
             .unwrap_or(0);
         if !self.sbpf_version.manual_stack_frame_bump()
@@ -206,18 +609,11 @@ impl CommonMemoryMapping {
                 stack_frame,
             ))
         } else {
-            let region_name = match vm_addr & (!ebpf::MM_BYTECODE_START.saturating_sub(1)) {
-                ebpf::MM_BYTECODE_START => "program",
-                ebpf::MM_STACK_START => "stack",
-                ebpf::MM_HEAP_START => "heap",
-                ebpf::MM_INPUT_START => "input",
-                _ => "unknown",
-            };
             ProgramResult::Err(EbpfError::AccessViolation(
                 access_type,
                 vm_addr,
                 len,
-                region_name,
+                self.layout.region_name(vm_addr),
             ))
         }
     }
@@ -270,15 +666,10 @@ impl<'a> UnalignedMemoryMapping {
             sbpf_version <= SBPFVersion::V3,
             "SBPFv4 and later versions do not support unaligned memory"
         );
+        config.address_space_layout.validate()?;
         regions.sort();
+        Self::validate_ordering(&regions, &config.address_space_layout)?;
         let number_of_regions = regions.len();
-        for index in 1..number_of_regions {
-            let first = &regions[index.saturating_sub(1)];
-            let second = &regions[index];
-            if first.vm_addr_range().end > second.vm_addr {
-                return Err(EbpfError::InvalidMemoryRegion(index));
-            }
-        }
         let mut result = Self {
             common: CommonMemoryMapping {
                 regions: regions.into_boxed_slice(),
@@ -287,6 +678,8 @@ impl<'a> UnalignedMemoryMapping {
                 max_call_depth: config.max_call_depth as i64,
                 stack_frame_size: config.stack_frame_size as i64,
                 sbpf_version,
+                layout: config.address_space_layout,
+                generation: 0,
             },
             region_addresses: vec![0; number_of_regions].into_boxed_slice(),
             region_index_lookup: vec![0; number_of_regions].into_boxed_slice(),
@@ -296,6 +689,39 @@ impl<'a> UnalignedMemoryMapping {
         Ok(result)
     }
 
+    /// Checks that sorted regions do not overlap and end inside the address space
+    fn validate_ordering(
+        regions: &[MemoryRegion],
+        layout: &AddressSpaceLayout,
+    ) -> Result<(), EbpfError> {
+        for index in 1..regions.len() {
+            let first = &regions[index.saturating_sub(1)];
+            let second = &regions[index];
+            if first.vm_addr_range().end > second.vm_addr {
+                return Err(EbpfError::InvalidMemoryRegion(index));
+            }
+        }
+        if let Some(last) = regions.last() {
+            if last.vm_addr_range().end > layout.end() {
+                return Err(EbpfError::InvalidMemoryRegion(
+                    regions.len().saturating_sub(1),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces all regions, rebuilds the search tree and flushes the cache
+    fn set_regions(&mut self, regions: Vec<MemoryRegion>) {
+        let number_of_regions = regions.len();
+        self.common.regions = regions.into_boxed_slice();
+        self.region_addresses = vec![0; number_of_regions].into_boxed_slice();
+        self.region_index_lookup = vec![0; number_of_regions].into_boxed_slice();
+        self.construct_eytzinger_order(0, 0);
+        self.cache.get_mut().flush();
+        self.common.generation = self.common.generation.wrapping_add(1);
+    }
+
     /// Returns the `MemoryRegion` which may contain the given address.
     #[allow(clippy::arithmetic_side_effects)]
     #[inline(always)]
@@ -339,8 +765,35 @@ impl<'a> UnalignedMemoryMapping {
     pub fn replace_region(&mut self, index: usize, region: MemoryRegion) -> Result<(), EbpfError> {
         self.common.regions[index] = region;
         self.cache.get_mut().flush();
+        self.common.generation = self.common.generation.wrapping_add(1);
         Ok(())
     }
+
+    /// Inserts a new `MemoryRegion` and returns its index
+    ///
+    /// Fails if the region overlaps any existing region.
+    pub fn insert_region(&mut self, region: MemoryRegion) -> Result<usize, EbpfError> {
+        let index = self
+            .common
+            .regions
+            .partition_point(|other| other.vm_addr <= region.vm_addr);
+        let mut regions = self.common.regions.to_vec();
+        regions.insert(index, region);
+        Self::validate_ordering(&regions, &self.common.layout)?;
+        self.set_regions(regions);
+        Ok(index)
+    }
+
+    /// Removes the `MemoryRegion` at the given index and returns it
+    pub fn remove_region(&mut self, index: usize) -> Result<MemoryRegion, EbpfError> {
+        if index >= self.common.regions.len() {
+            return Err(EbpfError::InvalidMemoryRegion(index));
+        }
+        let mut regions = self.common.regions.to_vec();
+        let region = regions.remove(index);
+        self.set_regions(regions);
+        Ok(region)
+    }
 }
 
 /// Memory mapping that uses the upper half of an address to identify the
@@ -366,6 +819,18 @@ impl AlignedMemoryMapping {
         sbpf_version: SBPFVersion,
         access_violation_handler: AccessViolationHandler,
     ) -> Result<Self, EbpfError> {
+        config.address_space_layout.validate()?;
+        if let Some(region) = regions
+            .iter()
+            .find(|region| region.vm_addr >= config.address_space_layout.end())
+        {
+            return Err(EbpfError::InvalidMemoryRegion(
+                region
+                    .vm_addr
+                    .checked_shr(ebpf::VIRTUAL_ADDRESS_BITS as u32)
+                    .unwrap_or(0) as usize,
+            ));
+        }
         if config.allow_memory_region_zero {
             regions.sort();
             let mut expected_region_index = 0;
@@ -411,6 +876,8 @@ impl AlignedMemoryMapping {
                 max_call_depth: config.max_call_depth as i64,
                 stack_frame_size: config.stack_frame_size as i64,
                 sbpf_version,
+                layout: config.address_space_layout,
+                generation: 0,
             },
         })
     }
@@ -444,8 +911,53 @@ impl AlignedMemoryMapping {
             return Err(EbpfError::InvalidMemoryRegion(index));
         }
         self.common.regions[index] = region;
+        self.common.generation = self.common.generation.wrapping_add(1);
         Ok(())
     }
+
+    /// Inserts a new `MemoryRegion` into the empty slot at `vm_addr >> VIRTUAL_ADDRESS_BITS`
+    /// and returns its index
+    pub fn insert_region(&mut self, region: MemoryRegion) -> Result<usize, EbpfError> {
+        let index = region
+            .vm_addr
+            .checked_shr(ebpf::VIRTUAL_ADDRESS_BITS as u32)
+            .unwrap_or(0) as usize;
+        if (index == 0 && !self.common.allow_memory_region_zero)
+            || index >= self.common.layout.number_of_regions
+        {
+            return Err(EbpfError::InvalidMemoryRegion(index));
+        }
+        if let Some(slot) = self.common.regions.get(index) {
+            if slot.len != 0 {
+                return Err(EbpfError::InvalidMemoryRegion(index));
+            }
+        } else {
+            let mut regions = self.common.regions.to_vec();
+            for padding_index in regions.len()..=index {
+                regions.push(MemoryRegion::new_readonly(
+                    &[],
+                    (padding_index as u64).saturating_mul(ebpf::MM_REGION_SIZE),
+                ));
+            }
+            self.common.regions = regions.into_boxed_slice();
+        }
+        self.replace_region(index, region)?;
+        Ok(index)
+    }
+
+    /// Removes the `MemoryRegion` at the given index and returns it
+    ///
+    /// The slot is kept as an empty region so that the indices of the others stay the same.
+    pub fn remove_region(&mut self, index: usize) -> Result<MemoryRegion, EbpfError> {
+        if index >= self.common.regions.len() {
+            return Err(EbpfError::InvalidMemoryRegion(index));
+        }
+        let empty_region =
+            MemoryRegion::new_readonly(&[], (index as u64).saturating_mul(ebpf::MM_REGION_SIZE));
+        let region = mem::replace(&mut self.common.regions[index], empty_region);
+        self.common.generation = self.common.generation.wrapping_add(1);
+        Ok(region)
+    }
 }
 
 /// Maps virtual memory to host memory.
@@ -525,6 +1037,23 @@ impl MemoryMapping {
         common.generate_access_violation(access_type, vm_addr, len)
     }
 
+    /// Map the virtual memory of an atomic read-modify-write to host memory
+    ///
+    /// Both addresses have to be a multiple of `len`, so that the host can access them atomically
+    /// without a split lock.
+    pub fn map_atomic(&mut self, vm_addr: u64, len: u64) -> ProgramResult {
+        let alignment_mask = len.saturating_sub(1);
+        if vm_addr & alignment_mask != 0 {
+            return ProgramResult::Err(EbpfError::UnalignedAtomicAccess(vm_addr));
+        }
+        match self.map_with_access_violation_handler(AccessType::Store, vm_addr, len) {
+            ProgramResult::Ok(host_addr) if host_addr & alignment_mask != 0 => {
+                ProgramResult::Err(EbpfError::UnalignedAtomicAccess(vm_addr))
+            }
+            result => result,
+        }
+    }
+
     /// Map virtual memory to host memory and potentially call the [AccessViolationHandler].
     ///
     /// This requires the [MemoryMapping] to be mutable and
@@ -632,6 +1161,54 @@ impl MemoryMapping {
             MemoryMapping::Unaligned(m) => m.replace_region(index, region),
         }
     }
+
+    /// Changes the length of the `MemoryRegion` at the given index
+    ///
+    /// This can be called from a builtin function while the program is running, both the
+    /// interpreter and the JIT translate every access through the current [MemoryMapping].
+    /// The caller must ensure that `new_len` bytes are valid at the host address of the region.
+    pub fn resize_region(&mut self, index: usize, new_len: u64) -> Result<(), EbpfError> {
+        let mut region = self
+            .get_regions()
+            .get(index)
+            .ok_or(EbpfError::InvalidMemoryRegion(index))?
+            .clone();
+        region.len = new_len;
+        self.replace_region(index, region)
+    }
+
+    /// Adds a `MemoryRegion` while keeping the regions ordered and returns its index
+    ///
+    /// Indices of regions with higher addresses shift by one in the unaligned mapping.
+    pub fn insert_region(&mut self, region: MemoryRegion) -> Result<usize, EbpfError> {
+        match self {
+            MemoryMapping::Identity => Err(EbpfError::InvalidMemoryRegion(0)),
+            MemoryMapping::Aligned(m) => m.insert_region(region),
+            MemoryMapping::Unaligned(m) => m.insert_region(region),
+        }
+    }
+
+    /// Removes the `MemoryRegion` at the given index and returns it
+    ///
+    /// The mapping never owns the host memory of a region, so host addresses which were
+    /// translated before stay dereferenceable as long as the caller keeps the memory alive.
+    /// Use [MemoryMapping::generation] to detect that they might no longer be mapped.
+    pub fn remove_region(&mut self, index: usize) -> Result<MemoryRegion, EbpfError> {
+        match self {
+            MemoryMapping::Identity => Err(EbpfError::InvalidMemoryRegion(index)),
+            MemoryMapping::Aligned(m) => m.remove_region(index),
+            MemoryMapping::Unaligned(m) => m.remove_region(index),
+        }
+    }
+
+    /// Counter which changes whenever a region is replaced, resized, inserted or removed
+    pub fn generation(&self) -> u64 {
+        match self {
+            MemoryMapping::Identity => 0,
+            MemoryMapping::Aligned(m) => m.common.generation,
+            MemoryMapping::Unaligned(m) => m.common.generation,
+        }
+    }
 }
 
 /// Fast, small linear cache used to speed up unaligned memory mapping.
@@ -798,6 +1375,27 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_shadow_bitmap() {
+        let mut mem = vec![0; 64];
+        let region = MemoryRegion::new_writable_gapped(&mut mem, ebpf::MM_STACK_START, 32);
+        let mut shadow = ShadowBitmap::new(&region);
+        let start = ebpf::MM_STACK_START;
+        assert_eq!(shadow.first_unwritten(start, 8), Some(start));
+        shadow.mark(start + 60, 8);
+        assert_eq!(shadow.first_unwritten(start + 60, 8), None);
+        assert_eq!(shadow.first_unwritten(start + 56, 8), Some(start + 56));
+        shadow.mark(start + 56, 2);
+        assert_eq!(shadow.first_unwritten(start + 56, 8), Some(start + 58));
+        shadow.clear(start + 62, 4);
+        assert_eq!(shadow.first_unwritten(start + 60, 8), Some(start + 62));
+        shadow.mark(start + 62, 4);
+        // The bitmap covers the gaps, bytes past the region count as written
+        shadow.mark(start + 120, 16);
+        assert_eq!(shadow.first_unwritten(start + 120, 16), None);
+        assert_eq!(shadow.first_unwritten(start - 8, 8), None);
+    }
+
     #[test]
     fn test_unaligned_map_overlap() {
         let config = Config {
@@ -1273,6 +1871,172 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_insert_remove_resize_region() {
+        for aligned_memory_mapping in [false, true] {
+            let config = Config {
+                aligned_memory_mapping,
+                ..Config::default()
+            };
+            let mem1 = [11, 11];
+            let mem2 = [22, 22, 22, 22];
+            let mem3 = [33];
+            let mut m = MemoryMapping::new(
+                vec![MemoryRegion::new_readonly(&mem1, ebpf::MM_REGION_SIZE)],
+                &config,
+                SBPFVersion::V3,
+            )
+            .unwrap();
+            let generation = m.generation();
+
+            // populate the mapping cache before changing the regions
+            assert_eq!(m.load::<u8>(ebpf::MM_REGION_SIZE).unwrap(), 11);
+            m.map(AccessType::Load, ebpf::MM_REGION_SIZE * 2, 1)
+                .unwrap_err();
+
+            let index = m
+                .insert_region(MemoryRegion::new_readonly(&mem2, ebpf::MM_REGION_SIZE * 2))
+                .unwrap();
+            assert_ne!(m.generation(), generation);
+            assert_eq!(m.get_regions()[index].vm_addr, ebpf::MM_REGION_SIZE * 2);
+            assert_eq!(m.load::<u8>(ebpf::MM_REGION_SIZE * 2).unwrap(), 22);
+
+            // overlapping or occupied
+            assert!(m
+                .insert_region(MemoryRegion::new_readonly(
+                    &mem3,
+                    ebpf::MM_REGION_SIZE * 2 + 1
+                ))
+                .is_err());
+
+            // grow and shrink
+            m.resize_region(index, 1).unwrap();
+            m.map(AccessType::Load, ebpf::MM_REGION_SIZE * 2 + 1, 1)
+                .unwrap_err();
+            m.resize_region(index, mem2.len() as u64).unwrap();
+            assert_eq!(m.load::<u8>(ebpf::MM_REGION_SIZE * 2 + 3).unwrap(), 22);
+            assert!(m.resize_region(m.get_regions().len(), 1).is_err());
+
+            let index = m
+                .get_regions()
+                .iter()
+                .position(|region| region.vm_addr == ebpf::MM_REGION_SIZE)
+                .unwrap();
+            let removed = m.remove_region(index).unwrap();
+            assert_eq!(removed.host_addr, mem1.as_ptr() as u64);
+            m.map(AccessType::Load, ebpf::MM_REGION_SIZE, 1)
+                .unwrap_err();
+            assert_eq!(m.load::<u8>(ebpf::MM_REGION_SIZE * 2).unwrap(), 22);
+
+            // the freed address range can be reused
+            m.insert_region(MemoryRegion::new_readonly(&mem3, ebpf::MM_REGION_SIZE))
+                .unwrap();
+            assert_eq!(m.load::<u8>(ebpf::MM_REGION_SIZE).unwrap(), 33);
+        }
+    }
+
+    #[test]
+    fn test_unaligned_resize_region_overlap() {
+        let config = Config::default();
+        let mem1 = [11, 11];
+        let mem2 = [22];
+        let mut m = MemoryMapping::new(
+            vec![
+                MemoryRegion::new_readonly(&mem1, ebpf::MM_REGION_SIZE),
+                MemoryRegion::new_readonly(&mem2, ebpf::MM_REGION_SIZE + 2),
+            ],
+            &config,
+            SBPFVersion::V3,
+        )
+        .unwrap();
+        assert_error!(m.resize_region(0, 3), "InvalidMemoryRegion(0)");
+        m.remove_region(1).unwrap();
+        m.resize_region(0, 3).unwrap();
+        assert_eq!(m.get_regions().len(), 1);
+    }
+
+    #[test]
+    fn test_input_region_builder() {
+        let config = Config::default();
+        let mut buffer_a = [1u8, 2, 3];
+        let buffer_b = [4u8; 8];
+        let mut buffer_c = [5u8; 5];
+        let mut builder = InputRegionBuilder::new(ebpf::MM_INPUT_START, 8).unwrap();
+        assert_eq!(
+            builder.push_writable(&mut buffer_a).unwrap(),
+            ebpf::MM_INPUT_START
+        );
+        assert_eq!(
+            builder.push_readonly(&buffer_b).unwrap(),
+            ebpf::MM_INPUT_START + 8
+        );
+        assert_eq!(
+            builder.push_readonly(&[]).unwrap(),
+            ebpf::MM_INPUT_START + 16
+        );
+        assert_eq!(
+            builder.push_writable(&mut buffer_c).unwrap(),
+            ebpf::MM_INPUT_START + 16
+        );
+        let (regions, layout) = builder.build();
+        assert_eq!(layout.end(), ebpf::MM_INPUT_START + 24);
+        // 3 buffers and 2 paddings, the empty buffer is not mapped
+        assert_eq!(regions.len(), 5);
+
+        let mut m = MemoryMapping::new(regions, &config, SBPFVersion::V3).unwrap();
+        assert_eq!(m.load::<u8>(ebpf::MM_INPUT_START + 2).unwrap(), 3);
+        assert_eq!(m.load::<u8>(ebpf::MM_INPUT_START + 3).unwrap(), 0);
+        assert_eq!(
+            m.load::<u64>(ebpf::MM_INPUT_START + 8).unwrap(),
+            0x0404040404040404
+        );
+        m.store(0x99u8, ebpf::MM_INPUT_START + 16).unwrap();
+        assert_error!(m.store(0u8, ebpf::MM_INPUT_START + 3), "AccessViolation");
+        assert_error!(m.store(0u8, ebpf::MM_INPUT_START + 8), "AccessViolation");
+        assert_error!(m.load::<u8>(ebpf::MM_INPUT_START + 24), "AccessViolation");
+        assert_eq!(buffer_c[0], 0x99);
+
+        // The layout can be transported and applied to other buffers of the same lengths
+        let serialized = layout.serialize();
+        let deserialized = InputLayout::deserialize(&serialized).unwrap();
+        assert_eq!(deserialized, layout);
+        let mut other_a = [6u8; 3];
+        let mut other_b = [7u8; 8];
+        let mut other_c = [8u8; 5];
+        let regions = layout
+            .to_regions(vec![&mut other_a, &mut other_b, &mut [], &mut other_c])
+            .unwrap();
+        let m = MemoryMapping::new(regions, &config, SBPFVersion::V3).unwrap();
+        assert_eq!(
+            m.map(AccessType::Load, ebpf::MM_INPUT_START + 16, 5)
+                .unwrap(),
+            other_c.as_ptr() as u64
+        );
+        assert_error!(
+            layout.to_regions(vec![&mut other_a, &mut other_c, &mut [], &mut other_b]),
+            "InvalidMemoryRegion(1)"
+        );
+
+        // Truncated, trailing bytes and misplaced entries are rejected
+        assert!(InputLayout::deserialize(&serialized[..serialized.len() - 1]).is_none());
+        let mut invalid = serialized.clone();
+        invalid.push(0);
+        assert!(InputLayout::deserialize(&invalid).is_none());
+        let mut invalid = serialized;
+        invalid[24] = 1;
+        assert!(InputLayout::deserialize(&invalid).is_none());
+
+        assert!(InputRegionBuilder::new(ebpf::MM_INPUT_START, 3).is_err());
+        assert!(InputRegionBuilder::new(ebpf::MM_INPUT_START + 4, 8).is_err());
+        assert!(InputRegionBuilder::new(ebpf::MM_INPUT_START, MAX_INPUT_ALIGNMENT * 2).is_err());
+
+        // Buffers which exceed the virtual address space are rejected without being recorded
+        let mut builder = InputRegionBuilder::new(u64::MAX - 15, 8).unwrap();
+        assert_eq!(builder.push_readonly(&[0; 4]).unwrap(), u64::MAX - 15);
+        assert_error!(builder.push_readonly(&[0; 8]), "InvalidMemoryRegion(1)");
+        assert_eq!(builder.layout().entries.len(), 1);
+    }
+
     #[test]
     fn test_access_violation_handler_map() {
         for aligned_memory_mapping in [true, false] {
@@ -0,0 +1,846 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Reports what has to change for an executable to run under another SBPF version
+//!
+//! The analysis compares how the source and the target [SBPFVersion] decode, verify and execute
+//! each instruction of a loaded executable. Instructions which depend on the relocations of the
+//! legacy ELF format are listed as well, since the stricter ELF headers do not support them.
+
+use crate::{
+    disassembler::disassemble_instruction,
+    ebpf,
+    elf::{BpfRelocationType, ElfError, Executable},
+    error::EbpfError,
+    program::{BuiltinProgram, FunctionRegistry, SBPFVersion},
+    static_analysis::Analysis,
+    verifier::{verify_insn, RequisiteVerifier},
+    vm::ContextObject,
+};
+use byteorder::{ByteOrder, LittleEndian};
+use std::{convert::TryFrom, fmt, ops::Range};
+
+#[cfg(not(feature = "shuttle-test"))]
+use std::sync::Arc;
+
+#[cfg(feature = "shuttle-test")]
+use shuttle::sync::Arc;
+
+/// Legacy multiplication, division and remainder opcodes and their SIMD-0174 counterparts
+const PQR_OPCODES: [(u8, u8); 12] = [
+    (ebpf::MUL32_IMM, ebpf::LMUL32_IMM),
+    (ebpf::MUL32_REG, ebpf::LMUL32_REG),
+    (ebpf::MUL64_IMM, ebpf::LMUL64_IMM),
+    (ebpf::MUL64_REG, ebpf::LMUL64_REG),
+    (ebpf::DIV32_IMM, ebpf::UDIV32_IMM),
+    (ebpf::DIV32_REG, ebpf::UDIV32_REG),
+    (ebpf::DIV64_IMM, ebpf::UDIV64_IMM),
+    (ebpf::DIV64_REG, ebpf::UDIV64_REG),
+    (ebpf::MOD32_IMM, ebpf::UREM32_IMM),
+    (ebpf::MOD32_REG, ebpf::UREM32_REG),
+    (ebpf::MOD64_IMM, ebpf::UREM64_IMM),
+    (ebpf::MOD64_REG, ebpf::UREM64_REG),
+];
+
+/// Legacy memory access opcodes and their SIMD-0173 counterparts
+const MEMORY_OPCODES: [(u8, u8); 12] = [
+    (ebpf::LD_B_REG, ebpf::LD_1B_REG),
+    (ebpf::LD_H_REG, ebpf::LD_2B_REG),
+    (ebpf::LD_W_REG, ebpf::LD_4B_REG),
+    (ebpf::LD_DW_REG, ebpf::LD_8B_REG),
+    (ebpf::ST_B_IMM, ebpf::ST_1B_IMM),
+    (ebpf::ST_H_IMM, ebpf::ST_2B_IMM),
+    (ebpf::ST_W_IMM, ebpf::ST_4B_IMM),
+    (ebpf::ST_DW_IMM, ebpf::ST_8B_IMM),
+    (ebpf::ST_B_REG, ebpf::ST_1B_REG),
+    (ebpf::ST_H_REG, ebpf::ST_2B_REG),
+    (ebpf::ST_W_REG, ebpf::ST_4B_REG),
+    (ebpf::ST_DW_REG, ebpf::ST_8B_REG),
+];
+
+/// How an instruction is affected by the migration
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MigrationIssueKind {
+    /// The target version rejects the instruction
+    Rejected,
+    /// The target version accepts the instruction, but it behaves differently
+    ChangedSemantics,
+    /// The instruction depends on a relocation which the target version does not support
+    Relocation,
+}
+
+impl fmt::Display for MigrationIssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Rejected => write!(f, "rejected"),
+            Self::ChangedSemantics => write!(f, "changed"),
+            Self::Relocation => write!(f, "relocation"),
+        }
+    }
+}
+
+/// Instructions which behave under the target version like the original ones did before
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Replacement {
+    /// Instructions of the source version to be replaced
+    pub pcs: Range<usize>,
+    /// Encoded for the target version, `lddw` takes two slots
+    pub instructions: Vec<ebpf::Insn>,
+    /// Disassembly of the instructions, separated by `; `
+    pub assembly: String,
+}
+
+/// A single instruction which needs attention
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MigrationIssue {
+    /// Instruction offset into the text section
+    pub pc: usize,
+    /// How the instruction is affected
+    pub kind: MigrationIssueKind,
+    /// Disassembly of the instruction under the source version
+    pub instruction: String,
+    /// What changes under the target version
+    pub reason: String,
+    /// Suggested replacement, if there is a mechanical one
+    pub replacement: Option<Replacement>,
+}
+
+impl fmt::Display for MigrationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at pc {}: `{}`: {}",
+            self.kind, self.pc, self.instruction, self.reason
+        )?;
+        if let Some(replacement) = &self.replacement {
+            write!(f, ", use `{}`", replacement.assembly)?;
+        }
+        Ok(())
+    }
+}
+
+/// Result of [analyze_migration]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MigrationReport {
+    /// Version of the analyzed executable
+    pub source_version: SBPFVersion,
+    /// Version the executable is meant to be migrated to
+    pub target_version: SBPFVersion,
+    /// Affected instructions ordered by pc
+    pub issues: Vec<MigrationIssue>,
+}
+
+impl MigrationReport {
+    /// Number of issues of the given kind
+    pub fn count(&self, kind: MigrationIssueKind) -> usize {
+        self.issues
+            .iter()
+            .filter(|issue| issue.kind == kind)
+            .count()
+    }
+}
+
+impl fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:?} -> {:?}: {} rejected, {} changed, {} relocations",
+            self.source_version,
+            self.target_version,
+            self.count(MigrationIssueKind::Rejected),
+            self.count(MigrationIssueKind::ChangedSemantics),
+            self.count(MigrationIssueKind::Relocation),
+        )?;
+        for issue in self.issues.iter() {
+            writeln!(f, "{issue}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Lists every instruction of an executable which is affected by migrating it to `target_version`
+pub fn analyze_migration<C: ContextObject>(
+    executable: &Executable<C>,
+    target_version: SBPFVersion,
+) -> Result<MigrationReport, EbpfError> {
+    let analysis = Analysis::from_executable(executable)?;
+    let mut migration = Migration {
+        executable,
+        analysis: &analysis,
+        source: executable.get_sbpf_version(),
+        target: target_version,
+        issues: Vec::new(),
+    };
+    for insn in analysis.instructions.iter() {
+        migration.analyze_insn(insn);
+    }
+    Ok(MigrationReport {
+        source_version: migration.source,
+        target_version,
+        issues: migration.issues,
+    })
+}
+
+/// Error of [migrate_to_v3]
+#[derive(Debug, thiserror::Error)]
+pub enum MigrationError {
+    /// The executable does not use a legacy version
+    #[error("{0:?} executables can not be migrated to V3")]
+    UnsupportedVersion(SBPFVersion),
+    /// V3 has no place for writable sections
+    #[error("writable sections are not supported by V3")]
+    WritableSections,
+    /// An affected instruction has no mechanical replacement
+    #[error("instruction at pc {0} can not be translated, {1}")]
+    Untranslatable(usize, String),
+    /// A jump, call or function starts inside a group of replaced instructions
+    #[error("jump at pc {0} targets the middle of a replaced instruction")]
+    InvalidJumpTarget(usize),
+    /// The jump offset does not fit into 16 bits anymore
+    #[error("jump at pc {0} is out of range after the translation")]
+    JumpOutOfRange(usize),
+    /// A loaded value is not known to be a number, but can not be relocated as an address
+    #[error("address {1:#x} loaded at pc {0} can not be relocated")]
+    UnrelocatableAddress(usize, u64),
+    /// A relocated word of the rodata can not be relocated again
+    #[error("relocated rodata at {0:#x} can not be relocated")]
+    UnrelocatableData(u64),
+    /// Loading or verifying the translated executable failed
+    #[error("{0}")]
+    EbpfError(#[from] EbpfError),
+}
+
+/// Translates a V0, V1 or V2 executable into an equivalent V3 executable
+///
+/// Every instruction reported by [analyze_migration] is substituted by its [Replacement],
+/// after which jumps and calls are retargeted to the new layout. The addresses which the ELF
+/// relocations patched into `lddw` instructions and the rodata are redirected to the lower
+/// rodata address or, if they point into the text section, to the translated instruction.
+/// Executables which were not loaded from an ELF have no relocations, so loading a value which
+/// points into the program image is reported instead of guessing whether it is an address.
+/// Code addresses which are computed at runtime can not be translated.
+pub fn migrate_to_v3<C: ContextObject>(
+    executable: &Executable<C>,
+    loader: Arc<BuiltinProgram<C>>,
+) -> Result<Executable<C>, MigrationError> {
+    let source = executable.get_sbpf_version();
+    if source >= SBPFVersion::V3 {
+        return Err(MigrationError::UnsupportedVersion(source));
+    }
+    if !executable.get_writable_sections().is_empty() {
+        return Err(MigrationError::WritableSections);
+    }
+    if !loader
+        .get_config()
+        .enabled_sbpf_versions
+        .contains(&SBPFVersion::V3)
+    {
+        return Err(EbpfError::ElfError(ElfError::UnsupportedSBPFVersion).into());
+    }
+    let report = analyze_migration(executable, SBPFVersion::V3)?;
+    if let Some(issue) = report
+        .issues
+        .iter()
+        .find(|issue| issue.replacement.is_none() && issue.kind != MigrationIssueKind::Relocation)
+    {
+        return Err(MigrationError::Untranslatable(
+            issue.pc,
+            issue.reason.clone(),
+        ));
+    }
+
+    // Lay out the groups of instructions and remember where each of them starts
+    let (text_vaddr, text) = executable.get_text_bytes();
+    let slots = text.len() / ebpf::INSN_SIZE;
+    let mut replacements = report
+        .issues
+        .iter()
+        .filter_map(|issue| issue.replacement.as_ref())
+        .peekable();
+    let mut groups = Vec::new();
+    let mut new_pc_of = vec![None; slots + 1];
+    let mut new_pc = 0;
+    let mut pc = 0;
+    while pc < slots {
+        if let Some(replacement) = replacements.next_if(|replacement| replacement.pcs.start < pc) {
+            return Err(MigrationError::Untranslatable(
+                replacement.pcs.start,
+                "the replacements overlap".to_string(),
+            ));
+        }
+        let (pcs, instructions, replaced) =
+            match replacements.next_if(|replacement| replacement.pcs.start == pc) {
+                Some(replacement) => (
+                    replacement.pcs.clone(),
+                    replacement.instructions.clone(),
+                    true,
+                ),
+                None => {
+                    let mut instructions = vec![ebpf::get_insn_unchecked(text, pc)];
+                    if instructions[0].opc == ebpf::LD_DW_IMM && pc + 1 < slots {
+                        instructions.push(ebpf::get_insn_unchecked(text, pc + 1));
+                    }
+                    (pc..pc + instructions.len(), instructions, false)
+                }
+            };
+        new_pc_of[pc] = Some(new_pc);
+        new_pc += instructions.len();
+        pc = pcs.end;
+        groups.push((pcs, instructions, replaced));
+    }
+    new_pc_of[slots] = Some(new_pc);
+    let map_pc = |from_pc: usize, target_pc: i64| {
+        usize::try_from(target_pc)
+            .ok()
+            .and_then(|target_pc| new_pc_of.get(target_pc).copied().flatten())
+            .ok_or(MigrationError::InvalidJumpTarget(from_pc))
+    };
+
+    // Addresses into the text section become function pointers, all others point to rodata
+    let text_range = text_vaddr..text_vaddr + text.len() as u64;
+    let ro_region = executable.get_ro_region();
+    let ro_range = ro_region.vm_addr..ro_region.vm_addr + ro_region.len;
+    let relocations = executable.get_relocations();
+    let relocate = |address: u64| {
+        if text_range.contains(&address) {
+            let offset = address - text_range.start;
+            if offset.checked_rem(ebpf::INSN_SIZE as u64) != Some(0) {
+                return None;
+            }
+            new_pc_of[(offset / ebpf::INSN_SIZE as u64) as usize]
+                .map(|pc| ebpf::MM_BYTECODE_START + (pc * ebpf::INSN_SIZE) as u64)
+        } else {
+            Some(address - ebpf::MM_BYTECODE_START + ebpf::MM_RODATA_START)
+        }
+    };
+
+    let mut migrated_text = Vec::with_capacity(new_pc * ebpf::INSN_SIZE);
+    for (pcs, mut instructions, replaced) in groups {
+        let group_pc = new_pc_of[pcs.start].unwrap();
+        let len = instructions.len();
+        for index in 0..len {
+            let (pc, new_pc) = (pcs.start + index, group_pc + index);
+            let insn = &mut instructions[index];
+            if insn.opc == ebpf::CALL_IMM && replaced && insn.src == 1 {
+                let target_pc = map_pc(pc, pcs.start as i64 + 1 + insn.imm)?;
+                insn.imm = target_pc as i64 - new_pc as i64 - 1;
+            } else if insn.opc & ebpf::BPF_CLS_MASK == ebpf::BPF_JMP64
+                && !matches!(insn.opc, ebpf::CALL_IMM | ebpf::CALL_REG | ebpf::EXIT)
+                && !replaced
+            {
+                let target_pc = map_pc(pc, pc as i64 + 1 + insn.off as i64)?;
+                insn.off = i16::try_from(target_pc as i64 - new_pc as i64 - 1)
+                    .map_err(|_| MigrationError::JumpOutOfRange(pc))?;
+            } else if insn.opc == ebpf::LD_DW_IMM && index + 1 < len {
+                let address =
+                    insn.imm as u32 as u64 | (instructions[index + 1].imm as u64).wrapping_shl(32);
+                // Replaced instructions are relocated where their group starts
+                let source_pc = if replaced { pcs.start } else { pc };
+                let is_relocated = match &relocations {
+                    Some(relocations) => matches!(
+                        relocations.get(&(text_vaddr + (source_pc * ebpf::INSN_SIZE) as u64)),
+                        Some(BpfRelocationType::R_Bpf_64_64 | BpfRelocationType::R_Bpf_64_Relative)
+                    ),
+                    None => ro_range.contains(&address),
+                };
+                if is_relocated {
+                    let address = relocate(address)
+                        .ok_or(MigrationError::UnrelocatableAddress(pcs.start, address))?;
+                    instructions[index].imm = address as u32 as i32 as i64;
+                    instructions[index + 1].imm = (address >> 32) as u32 as i32 as i64;
+                }
+            }
+        }
+        for insn in instructions.iter() {
+            migrated_text.extend_from_slice(&insn.to_array());
+        }
+    }
+
+    // The rodata keeps its offsets, so it is prefixed by as many zeros as the text section
+    // used to be in front of it
+    let mut rodata = Vec::new();
+    if ro_range != text_range {
+        rodata.resize((ro_range.start - ebpf::MM_BYTECODE_START) as usize, 0);
+        rodata.extend_from_slice(executable.get_ro_section());
+        rodata.resize(rodata.len().next_multiple_of(ebpf::INSN_SIZE), 0);
+        for (address, relocation_type) in relocations.iter().flatten() {
+            if !ro_range.contains(address) || text_range.contains(address) {
+                continue;
+            }
+            let offset = (address - ebpf::MM_BYTECODE_START) as usize;
+            match relocation_type {
+                BpfRelocationType::R_Bpf_64_Relative | BpfRelocationType::R_Bpf_64_Abs64 => {
+                    let word = rodata
+                        .get(offset..offset + 8)
+                        .map(LittleEndian::read_u64)
+                        .ok_or(MigrationError::UnrelocatableData(*address))?;
+                    // Absolute symbols are numbers, not addresses
+                    if ro_range.contains(&word) {
+                        let word =
+                            relocate(word).ok_or(MigrationError::UnrelocatableData(*address))?;
+                        LittleEndian::write_u64(&mut rodata[offset..], word);
+                    }
+                }
+                BpfRelocationType::R_Bpf_64_Abs32 => {
+                    let word = rodata
+                        .get(offset..offset + 4)
+                        .map(LittleEndian::read_u32)
+                        .ok_or(MigrationError::UnrelocatableData(*address))?;
+                    if ro_range.contains(&(word as u64)) {
+                        return Err(MigrationError::UnrelocatableData(*address));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut function_registry = FunctionRegistry::default();
+    for (_key, (name, pc)) in executable.get_function_registry().iter() {
+        let new_pc = map_pc(pc, pc as i64)?;
+        function_registry
+            .register_function(new_pc as u32, name, new_pc)
+            .map_err(EbpfError::from)?;
+    }
+    let entry_pc = executable.get_entrypoint_instruction_offset();
+    let migrated = Executable::new_from_segments(
+        &rodata,
+        &migrated_text,
+        loader,
+        SBPFVersion::V3,
+        map_pc(entry_pc, entry_pc as i64)?,
+        function_registry,
+    );
+    migrated.verify::<RequisiteVerifier>()?;
+    Ok(migrated)
+}
+
+struct Migration<'a, C: ContextObject> {
+    executable: &'a Executable<C>,
+    analysis: &'a Analysis<'a>,
+    source: SBPFVersion,
+    target: SBPFVersion,
+    issues: Vec<MigrationIssue>,
+}
+
+impl<C: ContextObject> Migration<'_, C> {
+    fn disassemble(&self, insn: &ebpf::Insn, sbpf_version: SBPFVersion) -> String {
+        disassemble_instruction(
+            insn,
+            insn.ptr,
+            &self.analysis.cfg_nodes,
+            self.executable.get_function_registry(),
+            self.executable.get_loader(),
+            sbpf_version,
+        )
+    }
+
+    fn report(
+        &mut self,
+        insn: &ebpf::Insn,
+        kind: MigrationIssueKind,
+        reason: String,
+        replacement: Option<Replacement>,
+    ) {
+        self.issues.push(MigrationIssue {
+            pc: insn.ptr,
+            kind,
+            instruction: self.disassemble(insn, self.source),
+            reason,
+            replacement,
+        });
+    }
+
+    fn analyze_insn(&mut self, insn: &ebpf::Insn) {
+        let (_program_vm_addr, program) = self.executable.get_text_bytes();
+        if insn.opc == ebpf::LD_DW_IMM && !self.source.disable_lddw() {
+            self.analyze_lddw_address(insn);
+        }
+        if insn.opc == ebpf::CALL_IMM {
+            return self.analyze_call(insn);
+        }
+        let program_range = 0..program.len() / ebpf::INSN_SIZE;
+        if let Err(err) = verify_insn(
+            program,
+            insn.ptr,
+            &program_range,
+            self.target,
+            self.executable.get_config(),
+        ) {
+            let reason = format!("{:?} rejects it, {}", self.target, err);
+            let replacement = self.suggest_replacement(insn);
+            return self.report(insn, MigrationIssueKind::Rejected, reason, replacement);
+        }
+        let decoded = self.disassemble(insn, self.target);
+        if decoded != self.disassemble(insn, self.source) {
+            let reason = format!("{:?} decodes it as `{}`", self.target, decoded);
+            let replacement = self.suggest_replacement(insn);
+            return self.report(
+                insn,
+                MigrationIssueKind::ChangedSemantics,
+                reason,
+                replacement,
+            );
+        }
+        let mut reasons = Vec::new();
+        if self.source.swap_sub_reg_imm_operands() != self.target.swap_sub_reg_imm_operands()
+            && (insn.opc == ebpf::SUB32_IMM || insn.opc == ebpf::SUB64_IMM)
+        {
+            reasons.push(if self.target.swap_sub_reg_imm_operands() {
+                "the register is subtracted from the immediate"
+            } else {
+                "the immediate is subtracted from the register"
+            });
+        }
+        if let Some(sign_extended) = result_is_sign_extended(insn.opc, self.source) {
+            if result_is_sign_extended(insn.opc, self.target) != Some(sign_extended) {
+                reasons.push(if sign_extended {
+                    "the result is zero extended"
+                } else {
+                    "the result is sign extended"
+                });
+            }
+        }
+        if insn.opc == ebpf::CALL_REG {
+            reasons.extend(self.stack_frame_change());
+        }
+        if !reasons.is_empty() {
+            let reason = reasons.join(" and ");
+            let replacement = self.suggest_replacement(insn);
+            self.report(
+                insn,
+                MigrationIssueKind::ChangedSemantics,
+                reason,
+                replacement,
+            );
+        }
+    }
+
+    /// Addresses into the program image are patched by relocations of the legacy ELF format
+    fn analyze_lddw_address(&mut self, insn: &ebpf::Insn) {
+        if self.source.enable_stricter_elf_headers() || !self.target.enable_stricter_elf_headers() {
+            return;
+        }
+        let address = insn.imm as u64;
+        let in_image = self
+            .executable
+            .get_ro_regions()
+            .iter()
+            .map(|region| region.vm_addr..region.vm_addr.saturating_add(region.len))
+            .chain(
+                self.executable
+                    .get_writable_sections()
+                    .iter()
+                    .map(|section| {
+                        section.vm_addr..section.vm_addr.saturating_add(section.len as u64)
+                    }),
+            )
+            .any(|range| range.contains(&address));
+        if in_image {
+            let reason = format!(
+                "{:#x} points into the program image, which {:?} maps elsewhere without relocations",
+                address, self.target
+            );
+            self.report(insn, MigrationIssueKind::Relocation, reason, None);
+        }
+    }
+
+    fn analyze_call(&mut self, insn: &ebpf::Insn) {
+        let syscall = if !self.source.static_syscalls() || insn.src == 0 {
+            self.executable
+                .get_loader()
+                .get_function_registry()
+                .lookup_by_key(insn.imm as u32)
+                .map(|(name, _function)| String::from_utf8_lossy(name).to_string())
+        } else {
+            None
+        };
+        if let Some(name) = syscall {
+            if !self.source.enable_stricter_elf_headers()
+                && self.target.enable_stricter_elf_headers()
+            {
+                let reason = format!(
+                    "syscall {} is resolved by a relocation, which {:?} does not support",
+                    name, self.target
+                );
+                let mut static_syscall = insn.clone();
+                static_syscall.src = 0;
+                let replacement = self.replacement(insn.ptr..insn.ptr + 1, vec![static_syscall]);
+                self.report(insn, MigrationIssueKind::Relocation, reason, replacement);
+            }
+            return;
+        }
+        let target_pc = if self.source.static_syscalls() {
+            let target_pc = (insn.ptr as i64).saturating_add(insn.imm).saturating_add(1);
+            (insn.src == 1 && (0..self.analysis.super_root as i64).contains(&target_pc))
+                .then_some(target_pc as usize)
+        } else {
+            self.executable
+                .get_function_registry()
+                .lookup_by_key(insn.imm as u32)
+                .map(|(_name, target_pc)| target_pc)
+        };
+        let Some(target_pc) = target_pc else {
+            return;
+        };
+        let mut reasons = Vec::new();
+        let mut replacement = None;
+        if self.source.static_syscalls() != self.target.static_syscalls() {
+            let offset = target_pc as i64 - insn.ptr as i64 - 1;
+            reasons.push(if self.target.static_syscalls() {
+                "the immediate is a relative offset instead of a function key"
+            } else {
+                "the immediate is a function key instead of a relative offset"
+            });
+            // The legacy ELF loader converts relative calls into function keys itself
+            let call = make_insn(
+                ebpf::CALL_IMM,
+                0,
+                self.target.static_syscalls() as u8,
+                0,
+                offset,
+            );
+            replacement = self.replacement(insn.ptr..insn.ptr + 1, vec![call]);
+        }
+        reasons.extend(self.stack_frame_change());
+        if !reasons.is_empty() {
+            let reason = reasons.join(" and ");
+            self.report(
+                insn,
+                MigrationIssueKind::ChangedSemantics,
+                reason,
+                replacement,
+            );
+        }
+    }
+
+    fn stack_frame_change(&self) -> Option<&'static str> {
+        match (
+            self.source.manual_stack_frame_bump(),
+            self.target.manual_stack_frame_bump(),
+        ) {
+            (true, false) => Some("the callee gets a new stack frame instead of bumping r10"),
+            (false, true) => Some("the callee has to bump r10 to get a new stack frame"),
+            _ => None,
+        }
+    }
+
+    /// Instructions which extend the 32 bit result in `dst` the way the source version did
+    fn extend_result(&self, dst: u8, sign_extend: bool) -> ebpf::Insn {
+        if sign_extend == self.target.explicit_sign_extension_of_results() {
+            make_insn(ebpf::MOV32_REG, dst, dst, 0, 0)
+        } else {
+            make_insn(ebpf::ADD32_IMM, dst, 0, 0, 0)
+        }
+    }
+
+    fn suggest_replacement(&self, insn: &ebpf::Insn) -> Option<Replacement> {
+        let (source, target) = (self.source, self.target);
+        let pc = insn.ptr;
+        let dst = insn.dst;
+        let mut pcs = pc..pc + 1;
+        let mut instructions = match insn.opc {
+            ebpf::LD_DW_IMM if !source.disable_lddw() => {
+                pcs = pc..pc + 2;
+                let value = insn.imm as u64;
+                if value as i64 == value as i32 as i64 {
+                    vec![make_insn(ebpf::MOV64_IMM, dst, 0, 0, value as i32 as i64)]
+                } else {
+                    let mut instructions = vec![make_insn(
+                        ebpf::MOV32_IMM,
+                        dst,
+                        0,
+                        0,
+                        value as u32 as i32 as i64,
+                    )];
+                    if value >> 32 != 0 {
+                        instructions.push(make_insn(
+                            ebpf::HOR64_IMM,
+                            dst,
+                            0,
+                            0,
+                            (value >> 32) as u32 as i32 as i64,
+                        ));
+                    }
+                    instructions
+                }
+            }
+            ebpf::HOR64_IMM if source.disable_lddw() => {
+                let previous = pc
+                    .checked_sub(1)
+                    .and_then(|pc| self.analysis.instructions.get(pc))?;
+                let low = match previous.opc {
+                    ebpf::MOV32_IMM if previous.dst == dst => previous.imm as u32 as u64,
+                    ebpf::MOV64_IMM if previous.dst == dst => previous.imm as u64,
+                    _ => return None,
+                };
+                pcs = pc - 1..pc + 1;
+                let value = low | (insn.imm as u64).wrapping_shl(32);
+                vec![
+                    make_insn(ebpf::LD_DW_IMM, dst, 0, 0, value as u32 as i32 as i64),
+                    make_insn(0, 0, 0, 0, (value >> 32) as u32 as i32 as i64),
+                ]
+            }
+            ebpf::NEG32 | ebpf::NEG64 if target.disable_neg() => {
+                let mut instructions = vec![make_insn(
+                    insn.opc & ebpf::BPF_CLS_MASK | ebpf::BPF_SUB,
+                    dst,
+                    0,
+                    0,
+                    0,
+                )];
+                if insn.opc == ebpf::NEG32 && !target.explicit_sign_extension_of_results() {
+                    instructions.push(self.extend_result(dst, false));
+                }
+                instructions
+            }
+            ebpf::LE if target.disable_le() => vec![match insn.imm {
+                16 => make_insn(ebpf::AND64_IMM, dst, 0, 0, 0xffff),
+                32 => make_insn(ebpf::AND32_IMM, dst, 0, 0, -1),
+                _ => make_insn(ebpf::MOV64_REG, dst, dst, 0, 0),
+            }],
+            ebpf::SUB32_IMM | ebpf::SUB64_IMM
+                if source.swap_sub_reg_imm_operands() != target.swap_sub_reg_imm_operands() =>
+            {
+                let class = insn.opc & ebpf::BPF_CLS_MASK;
+                let mut instructions = if source.swap_sub_reg_imm_operands() {
+                    vec![
+                        make_insn(class | ebpf::BPF_NEG, dst, 0, 0, 0),
+                        make_insn(class | ebpf::BPF_ADD, dst, 0, 0, insn.imm),
+                    ]
+                } else if insn.opc == ebpf::SUB32_IMM {
+                    let imm = (insn.imm as i32).wrapping_neg() as i64;
+                    vec![make_insn(ebpf::ADD32_IMM, dst, 0, 0, imm)]
+                } else {
+                    let imm = insn
+                        .imm
+                        .checked_neg()
+                        .filter(|imm| *imm == *imm as i32 as i64)?;
+                    vec![make_insn(ebpf::ADD64_IMM, dst, 0, 0, imm)]
+                };
+                if insn.opc == ebpf::SUB32_IMM {
+                    let sign_extended = result_is_sign_extended(insn.opc, source)?;
+                    if result_is_sign_extended(insn.opc, target) != Some(sign_extended) {
+                        instructions.push(self.extend_result(dst, sign_extended));
+                    }
+                }
+                instructions
+            }
+            ebpf::CALL_REG => {
+                let reg = if source.callx_uses_src_reg() {
+                    insn.src
+                } else if source.callx_uses_dst_reg() {
+                    insn.dst
+                } else {
+                    insn.imm as u8
+                };
+                vec![if target.callx_uses_src_reg() {
+                    make_insn(ebpf::CALL_REG, 0, reg, 0, 0)
+                } else if target.callx_uses_dst_reg() {
+                    make_insn(ebpf::CALL_REG, reg, 0, 0, 0)
+                } else {
+                    make_insn(ebpf::CALL_REG, 0, 0, 0, reg as i64)
+                }]
+            }
+            opc => {
+                let mut replacement = insn.clone();
+                let mut sign_extended = result_is_sign_extended(opc, source);
+                if source.enable_pqr() != target.enable_pqr() {
+                    if let Some(counterpart) = counterpart(&PQR_OPCODES, opc, source.enable_pqr()) {
+                        // The SIMD-0174 immediates are not sign extended to 64 bit
+                        if matches!(
+                            opc,
+                            ebpf::DIV64_IMM | ebpf::MOD64_IMM | ebpf::UDIV64_IMM | ebpf::UREM64_IMM
+                        ) && insn.imm < 0
+                        {
+                            return None;
+                        }
+                        replacement.opc = counterpart;
+                        sign_extended = sign_extended.or(Some(false));
+                    } else if source.enable_pqr() && opc & ebpf::BPF_CLS_MASK == ebpf::BPF_PQR {
+                        return None;
+                    }
+                }
+                if source.move_memory_instruction_classes()
+                    != target.move_memory_instruction_classes()
+                {
+                    if let Some(counterpart) = counterpart(
+                        &MEMORY_OPCODES,
+                        opc,
+                        source.move_memory_instruction_classes(),
+                    ) {
+                        replacement.opc = counterpart;
+                    }
+                }
+                let mut instructions = vec![replacement];
+                if let Some(sign_extended) = sign_extended {
+                    let opc = instructions[0].opc;
+                    if result_is_sign_extended(opc, target).unwrap_or(false) != sign_extended {
+                        instructions.push(self.extend_result(dst, sign_extended));
+                    }
+                }
+                instructions
+            }
+        };
+        for (offset, instruction) in instructions.iter_mut().enumerate() {
+            instruction.ptr = pcs.start + offset;
+        }
+        if instructions.len() == 1 && instructions[0] == *insn {
+            return None;
+        }
+        self.replacement(pcs, instructions)
+    }
+
+    fn replacement(&self, pcs: Range<usize>, instructions: Vec<ebpf::Insn>) -> Option<Replacement> {
+        let mut assembly = Vec::new();
+        let mut iter = instructions.iter();
+        while let Some(insn) = iter.next() {
+            let mut insn = insn.clone();
+            insn.ptr = pcs.start;
+            if insn.opc == ebpf::LD_DW_IMM {
+                let high = iter.next()?;
+                insn.imm = (insn.imm as u32 as u64 | (high.imm as u64).wrapping_shl(32)) as i64;
+            }
+            assembly.push(self.disassemble(&insn, self.target));
+        }
+        Some(Replacement {
+            pcs,
+            instructions,
+            assembly: assembly.join("; "),
+        })
+    }
+}
+
+/// Whether the 32 bit result of an instruction is sign extended, if that depends on the version
+fn result_is_sign_extended(opc: u8, sbpf_version: SBPFVersion) -> Option<bool> {
+    match opc {
+        ebpf::ADD32_IMM | ebpf::ADD32_REG | ebpf::SUB32_IMM | ebpf::SUB32_REG => {
+            Some(!sbpf_version.explicit_sign_extension_of_results())
+        }
+        ebpf::MUL32_IMM | ebpf::MUL32_REG if !sbpf_version.enable_pqr() => {
+            Some(!sbpf_version.explicit_sign_extension_of_results())
+        }
+        ebpf::MOV32_REG => Some(sbpf_version.explicit_sign_extension_of_results()),
+        _ => None,
+    }
+}
+
+/// Looks up the other opcode of a pair, `reverse` if `opc` is the newer one
+fn counterpart(pairs: &[(u8, u8)], opc: u8, reverse: bool) -> Option<u8> {
+    pairs.iter().find_map(|&(legacy, new)| {
+        if reverse {
+            (new == opc).then_some(legacy)
+        } else {
+            (legacy == opc).then_some(new)
+        }
+    })
+}
+
+fn make_insn(opc: u8, dst: u8, src: u8, off: i16, imm: i64) -> ebpf::Insn {
+    ebpf::Insn {
+        ptr: 0,
+        opc,
+        dst,
+        src,
+        off,
+        imm,
+    }
+}
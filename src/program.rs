@@ -5,7 +5,7 @@ use {
         elf::ElfError,
         vm::{Config, ContextObject, EbpfVm},
     },
-    std::collections::{btree_map::Entry, BTreeMap},
+    std::collections::{btree_map::Entry, BTreeMap, BTreeSet},
 };
 
 /// Defines a set of sbpf_version of an executable
@@ -21,6 +21,15 @@ pub enum SBPFVersion {
     V3,
     /// SIMD-0177
     V4,
+    /// Linux kernel eBPF dialect
+    ///
+    /// Builds on top of V4 (static syscalls, jmp32, aligned memory mapping) and
+    /// adds the upstream semantics which SBPF deviates from: 32-bit results are
+    /// zero-extended, division never traps, `sdiv` / `smod`, `movsx`, `ldsx`
+    /// and `bswap`. It is never selected by the ELF loader and is not part of
+    /// the default `enabled_sbpf_versions`, so it has to be opted into.
+    /// `ld_abs` / `ld_ind` and `gotol` remain unsupported.
+    Linux,
     /// Used for future versions
     Reserved,
 }
@@ -92,6 +101,40 @@ impl SBPFVersion {
         self >= SBPFVersion::V3
     }
 
+    /// The results of `add32`, `sub32` and `mul32` are sign-extended implicitly
+    ///
+    /// True for the versions before `explicit_sign_extension_of_results`, except for the
+    /// Linux eBPF dialect, which zero-extends all 32-bit results.
+    pub fn implicit_sign_extension_of_results(self) -> bool {
+        !self.explicit_sign_extension_of_results() && self != SBPFVersion::Linux
+    }
+    /// ... Linux eBPF: division by zero yields zero, modulo by zero keeps the dividend
+    pub fn non_trapping_division(self) -> bool {
+        self == SBPFVersion::Linux
+    }
+    /// ... Linux eBPF: `div` / `mod` with `off = 1` are signed
+    pub fn enable_signed_division(self) -> bool {
+        self == SBPFVersion::Linux
+    }
+    /// ... Linux eBPF: `mov` with `off = 8 | 16 | 32` sign-extends the source
+    pub fn enable_movsx(self) -> bool {
+        self == SBPFVersion::Linux
+    }
+    /// ... Linux eBPF: sign-extending loads (`BPF_MEMSX`)
+    pub fn enable_ldsx(self) -> bool {
+        self == SBPFVersion::Linux
+    }
+    /// ... Linux eBPF: unconditional byte swap (`BPF_ALU64 | BPF_END`)
+    pub fn enable_bswap(self) -> bool {
+        self == SBPFVersion::Linux
+    }
+    /// ... Linux eBPF: atomic read-modify-write of memory (`BPF_ATOMIC`)
+    ///
+    /// Also requires `Config::enable_atomic_instructions`.
+    pub fn enable_atomics(self) -> bool {
+        self == SBPFVersion::Linux
+    }
+
     /// Calculate the target program counter for a CALL_IMM instruction depending on
     /// the SBPF version.
     pub fn calculate_call_imm_target_pc(self, pc: usize, imm: i64) -> u32 {
@@ -104,7 +147,7 @@ impl SBPFVersion {
 }
 
 /// Holds the function symbols of an Executable
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct FunctionRegistry<T> {
     pub(crate) map: BTreeMap<u32, (Vec<u8>, T)>,
 }
@@ -234,11 +277,15 @@ pub struct BuiltinProgram<C: ContextObject> {
     config: Option<Box<Config>>,
     /// Function pointers by symbol with sparse indexing
     sparse_registry: FunctionRegistry<BuiltinFunction<C>>,
+    /// Keys of the functions which may write through their pointer arguments
+    writing_functions: BTreeSet<u32>,
 }
 
 impl<C: ContextObject> PartialEq for BuiltinProgram<C> {
     fn eq(&self, other: &Self) -> bool {
-        self.config.eq(&other.config) && self.sparse_registry.eq(&other.sparse_registry)
+        self.config.eq(&other.config)
+            && self.sparse_registry.eq(&other.sparse_registry)
+            && self.writing_functions.eq(&other.writing_functions)
     }
 }
 
@@ -248,6 +295,19 @@ impl<C: ContextObject> BuiltinProgram<C> {
         Self {
             config: Some(Box::new(config)),
             sparse_registry: FunctionRegistry::default(),
+            writing_functions: BTreeSet::new(),
+        }
+    }
+
+    /// Constructs a loader built-in program with the given functions
+    pub(crate) fn new_loader_with_registry(
+        config: Config,
+        sparse_registry: FunctionRegistry<BuiltinFunction<C>>,
+    ) -> Self {
+        Self {
+            config: Some(Box::new(config)),
+            sparse_registry,
+            writing_functions: BTreeSet::new(),
         }
     }
 
@@ -256,6 +316,7 @@ impl<C: ContextObject> BuiltinProgram<C> {
         Self {
             config: None,
             sparse_registry: FunctionRegistry::default(),
+            writing_functions: BTreeSet::new(),
         }
     }
 
@@ -264,6 +325,7 @@ impl<C: ContextObject> BuiltinProgram<C> {
         Self {
             config: Some(Box::default()),
             sparse_registry: FunctionRegistry::default(),
+            writing_functions: BTreeSet::new(),
         }
     }
 
@@ -286,6 +348,11 @@ impl<C: ContextObject> BuiltinProgram<C> {
                 0
             })
             .saturating_add(self.sparse_registry.mem_size())
+            .saturating_add(
+                self.writing_functions
+                    .len()
+                    .saturating_mul(std::mem::size_of::<u32>()),
+            )
     }
 
     /// Register a function both in the sparse and dense registries
@@ -299,6 +366,41 @@ impl<C: ContextObject> BuiltinProgram<C> {
             .register_function(key, name, value)
             .map(|_| ())
     }
+
+    /// Register a function which may write through the pointers it is passed in its arguments
+    ///
+    /// With `Config::enable_stack_shadow` the stack memory from such a pointer up to the frame
+    /// pointer counts as written by the call. Functions registered otherwise are assumed to only
+    /// read it.
+    pub fn register_writing_function(
+        &mut self,
+        name: &str,
+        value: BuiltinFunction<C>,
+    ) -> Result<(), ElfError> {
+        self.register_function(name, value)?;
+        self.writing_functions
+            .insert(ebpf::hash_symbol_name(name.as_bytes()));
+        Ok(())
+    }
+
+    /// Whether the function with the given key was registered as writing through its arguments
+    pub fn is_writing_function(&self, key: u32) -> bool {
+        self.writing_functions.contains(&key)
+    }
+
+    /// Register a function under an explicit key instead of the hash of its name
+    ///
+    /// Used for Linux eBPF helpers, which are called by their numeric helper id.
+    pub fn register_helper(
+        &mut self,
+        helper_id: u32,
+        name: &str,
+        value: BuiltinFunction<C>,
+    ) -> Result<(), ElfError> {
+        self.sparse_registry
+            .register_function(helper_id, name, value)
+            .map(|_| ())
+    }
 }
 
 impl<C: ContextObject> std::fmt::Debug for BuiltinProgram<C> {
@@ -0,0 +1,317 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Static call depth and stack usage
+//!
+//! Builds the call graph from the `call` instructions, the resolved `callx` targets and the
+//! function registry, measures how much stack every function addresses below its frame pointer
+//! and checks the deepest chain of calls against [Config::max_call_depth],
+//! [Config::stack_frame_size] and [Config::stack_size].
+
+use crate::{
+    abstract_interpretation::{
+        AbstractInterpretation, Interval, MemoryAccess, PointerRegion, RegisterType,
+    },
+    ebpf,
+    static_analysis::Analysis,
+    vm::Config,
+};
+use rustc_demangle::demangle;
+use std::collections::{BTreeMap, BTreeSet};
+use thiserror::Error;
+
+/// Configured limit which the program can exceed
+#[derive(Debug, Error, Eq, PartialEq)]
+pub enum StackLimitViolation {
+    /// The deepest chain of calls needs more frames than [Config::max_call_depth]
+    #[error("call depth of {depth} exceeds the limit of {limit}")]
+    CallDepthExceeded {
+        /// Number of frames
+        depth: usize,
+        /// Configured limit
+        limit: usize,
+    },
+    /// A function addresses more stack than fits into a fixed size frame
+    #[error(
+        "function at insn #{function} uses {usage} bytes of stack, but a frame has {frame_size}"
+    )]
+    FrameTooLarge {
+        /// First pc of the function
+        function: usize,
+        /// Bytes below the frame pointer
+        usage: u64,
+        /// Configured frame size
+        frame_size: usize,
+    },
+    /// The deepest chain of calls needs more stack than [Config::stack_size] (manual frame bumps)
+    #[error("stack usage of {usage} bytes exceeds the stack size of {stack_size}")]
+    StackSizeExceeded {
+        /// Bytes of stack
+        usage: u64,
+        /// Configured stack size
+        stack_size: usize,
+    },
+}
+
+/// Stack related properties of a single function
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FunctionStack {
+    /// Name of the function
+    pub name: String,
+    /// Bytes below the frame pointer at the start of the function which are addressed or reserved
+    pub frame_usage: u64,
+    /// Called functions with the frame pointer offset (in bytes below the entry value) at the call
+    pub callees: BTreeMap<usize, u64>,
+    /// Contains `callx` instructions whose targets could not be resolved
+    pub indirect_calls: bool,
+    /// Frames needed by the deepest chain of calls starting here, [None] if it reaches recursion
+    pub max_call_depth: Option<usize>,
+    /// Stack needed by the deepest chain of calls starting here, [None] if it reaches recursion
+    pub max_stack_usage: Option<u64>,
+}
+
+/// Result of the stack analysis
+#[derive(Debug)]
+pub struct StackAnalysis {
+    /// Properties of all functions, keyed by their first pc
+    pub functions: BTreeMap<usize, FunctionStack>,
+    /// Groups of functions which call each other (or themselves) recursively
+    pub recursion: Vec<BTreeSet<usize>>,
+    /// Limits which the entrypoint can exceed
+    pub violations: Vec<StackLimitViolation>,
+}
+
+impl StackAnalysis {
+    /// Analyzes the call graph and stack usage of the analyzed executable
+    pub fn new(analysis: &Analysis) -> Self {
+        let executable = analysis.executable;
+        let config = executable.get_config();
+        let sbpf_version = executable.get_sbpf_version();
+        let function_registry = executable.get_function_registry();
+        let mut functions = function_registry
+            .iter()
+            .filter(|(_key, (_name, pc))| analysis.cfg_nodes.contains_key(pc))
+            .map(|(_key, (name, pc))| {
+                let name = demangle(&String::from_utf8_lossy(name)).to_string();
+                (
+                    pc,
+                    FunctionStack {
+                        name,
+                        ..FunctionStack::default()
+                    },
+                )
+            })
+            .collect::<BTreeMap<_, _>>();
+        if analysis.cfg_nodes.contains_key(&analysis.entrypoint) {
+            functions
+                .entry(analysis.entrypoint)
+                .or_insert_with(|| FunctionStack {
+                    name: "entrypoint".to_string(),
+                    ..FunctionStack::default()
+                });
+        }
+        for function_start in analysis.callable_functions() {
+            functions
+                .entry(function_start)
+                .or_insert_with(|| FunctionStack {
+                    name: analysis.functions[&function_start].1.clone(),
+                    ..FunctionStack::default()
+                });
+        }
+
+        // Bytes below the frame pointer at the start of the function
+        let usage_below = |offset: i128| offset.saturating_neg().clamp(0, u64::MAX as i128) as u64;
+        let abstract_interpretation = AbstractInterpretation::run(analysis);
+        abstract_interpretation.replay(analysis, |insn, state| {
+            let Some((_function_start, function)) = functions.range_mut(..=insn.ptr).next_back()
+            else {
+                return;
+            };
+            let frame_pointer = match state.registers[ebpf::FRAME_PTR_REG] {
+                RegisterType::Pointer(PointerRegion::Stack, offset) => offset,
+                _ => Interval::OFFSET,
+            };
+            // Bumping the frame pointer under `manual_stack_frame_bump` reserves stack
+            function.frame_usage = function.frame_usage.max(usage_below(frame_pointer.min));
+            if let Some(access) = MemoryAccess::decode(insn, sbpf_version) {
+                if let RegisterType::Pointer(PointerRegion::Stack, offset) =
+                    state.registers[access.base as usize]
+                {
+                    let usage = usage_below(offset.min + insn.off as i128);
+                    function.frame_usage = function.frame_usage.max(usage);
+                }
+            }
+            match insn.opc {
+                ebpf::CALL_IMM if !sbpf_version.static_syscalls() || insn.src != 0 => {
+                    let key = sbpf_version.calculate_call_imm_target_pc(insn.ptr, insn.imm);
+                    if let Some((_name, target_pc)) = function_registry.lookup_by_key(key) {
+                        let call_site = function.callees.entry(target_pc).or_default();
+                        *call_site = (*call_site).max(usage_below(frame_pointer.min));
+                    }
+                }
+                ebpf::CALL_REG => match analysis.indirect_call_targets.get(&insn.ptr) {
+                    Some(Some(target_pcs)) => {
+                        for target_pc in target_pcs {
+                            let call_site = function.callees.entry(*target_pc).or_default();
+                            *call_site = (*call_site).max(usage_below(frame_pointer.min));
+                        }
+                    }
+                    _ => function.indirect_calls = true,
+                },
+                _ => {}
+            }
+        });
+        for function in functions.values_mut() {
+            function
+                .callees
+                .retain(|callee, _offset| analysis.cfg_nodes.contains_key(callee));
+        }
+
+        let recursion = find_recursion(&functions);
+        let recursive = recursion.iter().flatten().copied().collect::<BTreeSet<_>>();
+        let mut totals = BTreeMap::new();
+        for function_start in functions.keys() {
+            compute_totals(
+                *function_start,
+                &functions,
+                &recursive,
+                config,
+                sbpf_version.manual_stack_frame_bump(),
+                &mut totals,
+            );
+        }
+        for (function_start, (max_call_depth, max_stack_usage)) in totals {
+            if let Some(function) = functions.get_mut(&function_start) {
+                function.max_call_depth = max_call_depth;
+                function.max_stack_usage = max_stack_usage;
+            }
+        }
+
+        let mut violations = Vec::new();
+        if let Some(entrypoint) = functions.get(&analysis.entrypoint) {
+            if let Some(depth) = entrypoint.max_call_depth {
+                if depth > config.max_call_depth {
+                    violations.push(StackLimitViolation::CallDepthExceeded {
+                        depth,
+                        limit: config.max_call_depth,
+                    });
+                }
+            }
+            // Fixed frames are covered by the call depth already
+            if let (true, Some(usage)) = (
+                sbpf_version.manual_stack_frame_bump(),
+                entrypoint.max_stack_usage,
+            ) {
+                if usage > config.stack_size() as u64 {
+                    violations.push(StackLimitViolation::StackSizeExceeded {
+                        usage,
+                        stack_size: config.stack_size(),
+                    });
+                }
+            }
+        }
+        if !sbpf_version.manual_stack_frame_bump() {
+            for (function_start, function) in functions.iter() {
+                if function.frame_usage > config.stack_frame_size as u64 {
+                    violations.push(StackLimitViolation::FrameTooLarge {
+                        function: *function_start,
+                        usage: function.frame_usage,
+                        frame_size: config.stack_frame_size,
+                    });
+                }
+            }
+        }
+        Self {
+            functions,
+            recursion,
+            violations,
+        }
+    }
+}
+
+/// Groups the functions which can reach themselves through calls
+fn find_recursion(functions: &BTreeMap<usize, FunctionStack>) -> Vec<BTreeSet<usize>> {
+    let reachable = functions
+        .keys()
+        .map(|function_start| {
+            let mut visited = BTreeSet::new();
+            let mut stack = functions[function_start]
+                .callees
+                .keys()
+                .copied()
+                .collect::<Vec<_>>();
+            while let Some(callee) = stack.pop() {
+                if visited.insert(callee) {
+                    if let Some(function) = functions.get(&callee) {
+                        stack.extend(function.callees.keys().copied());
+                    }
+                }
+            }
+            (*function_start, visited)
+        })
+        .collect::<BTreeMap<_, _>>();
+    let mut recursion = Vec::new();
+    let mut grouped = BTreeSet::new();
+    for (function_start, reaches) in reachable.iter() {
+        if !reaches.contains(function_start) || grouped.contains(function_start) {
+            continue;
+        }
+        let group = reaches
+            .iter()
+            .filter(|other| reachable[*other].contains(function_start))
+            .copied()
+            .collect::<BTreeSet<_>>();
+        grouped.extend(group.iter().copied());
+        recursion.push(group);
+    }
+    recursion
+}
+
+/// Computes the frames and bytes of stack needed by the deepest chain of calls
+fn compute_totals(
+    function_start: usize,
+    functions: &BTreeMap<usize, FunctionStack>,
+    recursive: &BTreeSet<usize>,
+    config: &Config,
+    manual_stack_frame_bump: bool,
+    totals: &mut BTreeMap<usize, (Option<usize>, Option<u64>)>,
+) -> (Option<usize>, Option<u64>) {
+    if let Some(total) = totals.get(&function_start) {
+        return *total;
+    }
+    if recursive.contains(&function_start) {
+        totals.insert(function_start, (None, None));
+        return (None, None);
+    }
+    let function = &functions[&function_start];
+    let own_usage = if manual_stack_frame_bump {
+        function.frame_usage
+    } else {
+        config.stack_frame_size as u64
+    };
+    let mut total = (Some(1), Some(own_usage));
+    for (callee, frame_pointer_offset) in function.callees.iter() {
+        let (callee_depth, callee_usage) = compute_totals(
+            *callee,
+            functions,
+            recursive,
+            config,
+            manual_stack_frame_bump,
+            totals,
+        );
+        let call_offset = if manual_stack_frame_bump {
+            // The callee starts at the frame pointer of the call site
+            *frame_pointer_offset
+        } else {
+            config.stack_frame_size as u64
+        };
+        total.0 = total
+            .0
+            .zip(callee_depth)
+            .map(|(depth, callee_depth)| depth.max(callee_depth + 1));
+        total.1 = total
+            .1
+            .zip(callee_usage)
+            .map(|(usage, callee_usage)| usage.max(call_offset.saturating_add(callee_usage)));
+    }
+    totals.insert(function_start, total);
+    total
+}
@@ -0,0 +1,238 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Reads of stack bytes before they are written
+//!
+//! Tracks the byte ranges of the current stack frame which are written on every path through the
+//! control-flow graph of a function, starting with none at its entry. The offsets of the accesses
+//! come from the [AbstractInterpretation], so stores and loads through copies of the frame pointer
+//! are covered too, but only those at a single known offset. Internal calls and the syscalls
+//! registered with [crate::program::BuiltinProgram::register_writing_function] may write through
+//! pointers into the frame which they get in `r1` to `r5`, so these count as writing everything
+//! from the pointer to the top of the frame.
+//!
+//! The dynamic counterpart is [crate::vm::Config::enable_stack_shadow].
+
+use crate::{
+    abstract_interpretation::{AbstractInterpretation, MemoryAccess, PointerRegion, RegisterType},
+    ebpf,
+    static_analysis::Analysis,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    fmt,
+};
+
+/// Stack offsets as disjoint, non-adjacent half-open ranges, by their start
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct ByteRanges(BTreeMap<i64, i64>);
+
+impl ByteRanges {
+    fn insert(&mut self, mut start: i64, mut end: i64) {
+        let touching = self
+            .0
+            .range(..=end)
+            .rev()
+            .take_while(|(_start, other_end)| **other_end >= start)
+            .map(|(other_start, other_end)| (*other_start, *other_end))
+            .collect::<Vec<_>>();
+        for (other_start, other_end) in touching {
+            self.0.remove(&other_start);
+            start = start.min(other_start);
+            end = end.max(other_end);
+        }
+        self.0.insert(start, end);
+    }
+
+    fn contains(&self, start: i64, end: i64) -> bool {
+        self.0
+            .range(..=start)
+            .next_back()
+            .is_some_and(|(_start, other_end)| *other_end >= end)
+    }
+
+    fn intersection(&self, other: &Self) -> Self {
+        let mut result = Self::default();
+        for (start, end) in self.0.iter() {
+            for (other_start, other_end) in other
+                .0
+                .range(..*end)
+                .rev()
+                .take_while(|(_start, other_end)| **other_end > *start)
+            {
+                result
+                    .0
+                    .insert((*start).max(*other_start), (*end).min(*other_end));
+            }
+        }
+        result
+    }
+}
+
+/// Stack bytes an instruction reads or writes, as offsets relative to the frame pointer at the
+/// start of the function
+struct StackAccess {
+    start: i64,
+    end: i64,
+    is_read: bool,
+    is_write: bool,
+}
+
+/// Loads of stack bytes which are not written on every path leading to them
+#[derive(Debug)]
+pub struct StackInitializationAnalysis {
+    /// Names of the functions by their first pc
+    functions: BTreeMap<usize, String>,
+    /// The pcs of the loads and the offsets they read from, relative to the frame pointer
+    pub uninitialized_reads: BTreeSet<(usize, i64)>,
+}
+
+impl StackInitializationAnalysis {
+    /// Finds the loads of uninitialized stack bytes
+    pub fn new(analysis: &Analysis) -> Self {
+        let executable = analysis.executable;
+        let sbpf_version = executable.get_sbpf_version();
+        let abstract_interpretation = AbstractInterpretation::run(analysis);
+        let mut accesses = BTreeMap::new();
+        abstract_interpretation.replay(analysis, |insn, state| {
+            let stack_offset = |register: u8| match state.registers[register as usize] {
+                RegisterType::Pointer(PointerRegion::Stack, offset) => Some(offset),
+                _ => None,
+            };
+            if let Some(access) = MemoryAccess::decode(insn, sbpf_version) {
+                let Some(start) = stack_offset(access.base)
+                    .and_then(|offset| offset.as_constant())
+                    .and_then(|offset| i64::try_from(offset + insn.off as i128).ok())
+                else {
+                    return;
+                };
+                let is_atomic = insn.opc == ebpf::ST_W_ATOMIC || insn.opc == ebpf::ST_DW_ATOMIC;
+                accesses.insert(
+                    insn.ptr,
+                    StackAccess {
+                        start,
+                        end: start + access.len as i64,
+                        is_read: access.is_load || is_atomic,
+                        is_write: !access.is_load,
+                    },
+                );
+            } else if insn.opc == ebpf::CALL_IMM || insn.opc == ebpf::CALL_REG {
+                let is_syscall = insn.opc == ebpf::CALL_IMM
+                    && if sbpf_version.static_syscalls() {
+                        insn.src == 0
+                    } else {
+                        executable
+                            .get_function_registry()
+                            .lookup_by_key(insn.imm as u32)
+                            .is_none()
+                    };
+                if is_syscall && !executable.get_loader().is_writing_function(insn.imm as u32) {
+                    return;
+                }
+                if let Some(start) = (1..=5)
+                    .filter_map(stack_offset)
+                    .map(|offset| i64::try_from(offset.min).unwrap_or(i64::MIN))
+                    .filter(|start| *start < 0)
+                    .min()
+                {
+                    accesses.insert(
+                        insn.ptr,
+                        StackAccess {
+                            start,
+                            end: 0,
+                            is_read: false,
+                            is_write: true,
+                        },
+                    );
+                }
+            }
+        });
+
+        let callable_functions = analysis.callable_functions();
+        let reachable = &abstract_interpretation.block_states;
+        let transfer = |start: usize,
+                        mut initialized: ByteRanges,
+                        uninitialized_reads: &mut BTreeSet<(usize, i64)>| {
+            for insn in &analysis.instructions[analysis.cfg_nodes[&start].instructions.clone()] {
+                if let Some(access) = accesses.get(&insn.ptr) {
+                    if access.is_read && !initialized.contains(access.start, access.end) {
+                        uninitialized_reads.insert((insn.ptr, access.start));
+                    }
+                    if access.is_write {
+                        initialized.insert(access.start, access.end);
+                    }
+                }
+            }
+            initialized
+        };
+        let block_input = |start: usize, outputs: &BTreeMap<usize, ByteRanges>| {
+            if analysis.functions.contains_key(&start) || callable_functions.contains(&start) {
+                return Some(ByteRanges::default());
+            }
+            analysis.cfg_nodes[&start]
+                .sources
+                .iter()
+                .filter_map(|source| outputs.get(source))
+                .fold(None, |result: Option<ByteRanges>, output| {
+                    Some(match result {
+                        Some(result) => result.intersection(output),
+                        None => output.clone(),
+                    })
+                })
+        };
+
+        // Blocks which were not reached yet have no output, which acts as every byte being written
+        let mut outputs = BTreeMap::<usize, ByteRanges>::new();
+        let mut worklist = reachable.keys().copied().collect::<BTreeSet<usize>>();
+        while let Some(start) = worklist.iter().next().copied() {
+            worklist.remove(&start);
+            let Some(input) = block_input(start, &outputs) else {
+                continue;
+            };
+            let output = transfer(start, input, &mut BTreeSet::new());
+            if outputs.get(&start) != Some(&output) {
+                outputs.insert(start, output);
+                worklist.extend(
+                    analysis.cfg_nodes[&start]
+                        .destinations
+                        .iter()
+                        .filter(|destination| reachable.contains_key(destination)),
+                );
+            }
+        }
+        let mut uninitialized_reads = BTreeSet::new();
+        for start in reachable.keys() {
+            if let Some(input) = block_input(*start, &outputs) {
+                transfer(*start, input, &mut uninitialized_reads);
+            }
+        }
+
+        Self {
+            functions: analysis
+                .functions
+                .iter()
+                .map(|(pc, (_key, name))| (*pc, name.clone()))
+                .collect(),
+            uninitialized_reads,
+        }
+    }
+}
+
+impl fmt::Display for StackInitializationAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} reads of uninitialized stack bytes",
+            self.uninitialized_reads.len()
+        )?;
+        for (pc, offset) in self.uninitialized_reads.iter() {
+            let function = self
+                .functions
+                .range(..=pc)
+                .next_back()
+                .map(|(_pc, name)| name.as_str())
+                .unwrap_or_default();
+            writeln!(f, "{function}: insn #{pc} reads frame offset {offset}")?;
+        }
+        Ok(())
+    }
+}
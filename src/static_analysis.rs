@@ -3,14 +3,19 @@
 
 use crate::disassembler::disassemble_instruction;
 use crate::{
+    abstract_interpretation::MemoryAccess,
     ebpf,
     elf::Executable,
+    elf_parser::dwarf::SourceLocation,
     error::EbpfError,
     program::SBPFVersion,
     vm::{ContextObject, DynamicAnalysis},
 };
 use rustc_demangle::demangle;
-use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    convert::{TryFrom, TryInto},
+};
 
 /// Register state recorded after executing one instruction
 ///
@@ -137,7 +142,7 @@ impl ContextObject for DummyContextObject {
 /// Result of the executable analysis
 pub struct Analysis<'a> {
     /// The program which is analyzed
-    executable: &'a Executable<DummyContextObject>,
+    pub(crate) executable: &'a Executable<DummyContextObject>,
     /// Plain list of instructions as they occur in the executable
     pub instructions: Vec<ebpf::Insn>,
     /// Functions in the executable
@@ -150,6 +155,8 @@ pub struct Analysis<'a> {
     pub entrypoint: usize,
     /// Virtual CfgNode that reaches all functions
     pub super_root: usize,
+    /// Targets of the `callx` instructions, [None] if they could not be resolved
+    pub indirect_call_targets: BTreeMap<usize, Option<BTreeSet<usize>>>,
     /// Data flow edges (the keys are DfgEdge sources)
     pub dfg_forward_edges: BTreeMap<DfgNode, BTreeSet<DfgEdge>>,
     /// Data flow edges (the keys are DfgEdge destinations)
@@ -161,6 +168,10 @@ impl<'a> Analysis<'a> {
     pub fn from_executable<C: ContextObject>(
         executable: &'a Executable<C>,
     ) -> Result<Self, EbpfError> {
+        Ok(Self::new(executable))
+    }
+
+    pub(crate) fn new<C: ContextObject>(executable: &'a Executable<C>) -> Self {
         let (_program_vm_addr, program) = executable.get_text_bytes();
         let mut functions = BTreeMap::new();
         for (key, (function_name, pc)) in executable.get_function_registry().iter() {
@@ -200,16 +211,22 @@ impl<'a> Analysis<'a> {
             topological_order: Vec::new(),
             entrypoint: executable.get_entrypoint_instruction_offset(),
             super_root: insn_ptr,
+            indirect_call_targets: BTreeMap::new(),
             dfg_forward_edges: BTreeMap::new(),
             dfg_reverse_edges: BTreeMap::new(),
         };
         result.split_into_basic_blocks(false, executable.get_sbpf_version());
+        if result.resolve_indirect_calls(executable.get_sbpf_version()) {
+            // Functions which are only called indirectly split the basic blocks further
+            result.cfg_nodes.clear();
+            result.split_into_basic_blocks(false, executable.get_sbpf_version());
+        }
         result.control_flow_graph_tarjan();
         result.control_flow_graph_dominance_hierarchy();
         result.label_basic_blocks();
         let basic_block_outputs = result.intra_basic_block_data_flow(executable.get_sbpf_version());
         result.inter_basic_block_data_flow(basic_block_outputs);
-        Ok(result)
+        result
     }
 
     fn link_cfg_edges(&mut self, cfg_edges: Vec<(usize, Vec<usize>)>, both_directions: bool) {
@@ -259,13 +276,15 @@ impl<'a> Analysis<'a> {
                     }
                 }
                 ebpf::CALL_REG => {
-                    // Abnormal CFG edge
                     self.cfg_nodes.entry(insn.ptr + 1).or_default();
-                    let destinations = if flatten_call_graph {
-                        vec![insn.ptr + 1, self.super_root]
-                    } else {
-                        vec![insn.ptr + 1]
-                    };
+                    let mut destinations = vec![insn.ptr + 1];
+                    if flatten_call_graph {
+                        match self.indirect_call_targets.get(&insn.ptr) {
+                            Some(Some(target_pcs)) => destinations.extend(target_pcs.iter()),
+                            // Abnormal CFG edge
+                            _ => destinations.push(self.super_root),
+                        }
+                    }
                     cfg_edges.insert(insn.ptr, (insn.opc, destinations));
                 }
                 ebpf::EXIT => {
@@ -367,7 +386,7 @@ impl<'a> Analysis<'a> {
             let mut instruction_index = 0;
             let mut cfg_node_iter = self.cfg_nodes.iter_mut().peekable();
             let mut cfg_edge_iter = cfg_edges.iter_mut().peekable();
-            while let Some((cfg_node_start, cfg_node)) = cfg_node_iter.next() {
+            while let Some((_cfg_node_start, cfg_node)) = cfg_node_iter.next() {
                 let cfg_node_end = if let Some(next_cfg_node) = cfg_node_iter.peek() {
                     *next_cfg_node.0 - 1
                 } else {
@@ -390,7 +409,7 @@ impl<'a> Analysis<'a> {
                     }
                 }
                 if let Some(next_cfg_node) = cfg_node_iter.peek() {
-                    if !self.functions.contains_key(cfg_node_start) {
+                    if !self.functions.contains_key(next_cfg_node.0) {
                         cfg_node.destinations.push(*next_cfg_node.0);
                     }
                 }
@@ -428,6 +447,202 @@ impl<'a> Analysis<'a> {
         }
     }
 
+    /// First pcs of the registered and the indirectly called functions and of the entrypoint
+    ///
+    /// Unlike `functions` this leaves out unreachable code, which is labeled as function too.
+    pub fn callable_functions(&self) -> BTreeSet<usize> {
+        self.executable
+            .get_function_registry()
+            .iter()
+            .map(|(_key, (_name, pc))| pc)
+            .chain(
+                self.indirect_call_targets
+                    .values()
+                    .flatten()
+                    .flatten()
+                    .copied(),
+            )
+            .chain(std::iter::once(self.entrypoint))
+            .filter(|pc| self.cfg_nodes.contains_key(pc))
+            .collect()
+    }
+
+    /// Resolves the targets of `callx` instructions by value-set analysis
+    ///
+    /// Tracks the sets of constants which registers can hold inside of each function: Immediates
+    /// (`lddw`, `mov`, `hor64`), sums of those and 8 byte words loaded from read-only sections at
+    /// constant addresses, which covers tables of function pointers.
+    ///
+    /// Returns true if a target was not a function yet.
+    fn resolve_indirect_calls(&mut self, sbpf_version: SBPFVersion) -> bool {
+        /// Possible values of a register, [None] if unknown or too many
+        type ValueSets = [Option<BTreeSet<u64>>; 11];
+        const MAX_VALUES: usize = 16;
+        let (program_vm_addr, _program) = self.executable.get_text_bytes();
+        let ro_sections = self.executable.get_ro_section_contents();
+        let read_word = |vm_addr: u64| -> Option<u64> {
+            ro_sections.iter().find_map(|(section_vm_addr, data)| {
+                let offset = usize::try_from(vm_addr.checked_sub(*section_vm_addr)?).ok()?;
+                let bytes = data.get(offset..offset.checked_add(8)?)?;
+                Some(u64::from_le_bytes(bytes.try_into().ok()?))
+            })
+        };
+        let limit = |values: BTreeSet<u64>| (values.len() <= MAX_VALUES).then_some(values);
+        let map = |values: &Option<BTreeSet<u64>>, f: &dyn Fn(u64) -> Option<u64>| {
+            values
+                .as_ref()
+                .and_then(|values| values.iter().map(|value| f(*value)).collect())
+        };
+        let transfer = |state: &mut ValueSets, insn: &ebpf::Insn| {
+            let dst = insn.dst as usize;
+            let src = insn.src as usize;
+            let imm = insn.imm as u64;
+            if insn.opc == ebpf::LD_DW_IMM && !sbpf_version.disable_lddw() {
+                state[dst] = Some(BTreeSet::from([imm]));
+                return;
+            }
+            if let Some(access) = MemoryAccess::decode(insn, sbpf_version) {
+                if access.is_load {
+                    let offset = insn.off as i64 as u64;
+                    state[dst] = if access.len == 8 {
+                        map(&state[access.base as usize], &|vm_addr| {
+                            read_word(vm_addr.wrapping_add(offset))
+                        })
+                    } else {
+                        None
+                    };
+                } else if insn.opc == ebpf::ST_W_ATOMIC || insn.opc == ebpf::ST_DW_ATOMIC {
+                    state[src] = None;
+                    state[0] = None;
+                }
+                return;
+            }
+            let class = insn.opc & ebpf::BPF_CLS_MASK;
+            match insn.opc {
+                ebpf::MOV32_IMM => state[dst] = Some(BTreeSet::from([imm as u32 as u64])),
+                ebpf::MOV64_IMM => state[dst] = Some(BTreeSet::from([imm])),
+                ebpf::MOV64_REG if !sbpf_version.enable_movsx() || insn.off == 0 => {
+                    state[dst] = state[src].clone();
+                }
+                ebpf::ADD64_IMM => {
+                    state[dst] = map(&state[dst], &|value| Some(value.wrapping_add(imm)));
+                }
+                ebpf::ADD64_REG => {
+                    state[dst] = match (&state[dst], &state[src]) {
+                        (Some(a), Some(b)) if a.len() * b.len() <= MAX_VALUES => Some(
+                            a.iter()
+                                .flat_map(|a| b.iter().map(move |b| a.wrapping_add(*b)))
+                                .collect(),
+                        ),
+                        _ => None,
+                    };
+                }
+                ebpf::HOR64_IMM if sbpf_version.disable_lddw() => {
+                    state[dst] = map(&state[dst], &|value| {
+                        Some(value | (imm as u32 as u64).wrapping_shl(32))
+                    });
+                }
+                ebpf::CALL_IMM | ebpf::CALL_REG => {
+                    for register in state.iter_mut().take(6) {
+                        *register = None;
+                    }
+                }
+                _ if class == ebpf::BPF_ALU32_LOAD
+                    || class == ebpf::BPF_ALU64_STORE
+                    || (class == ebpf::BPF_PQR && sbpf_version.enable_pqr()) =>
+                {
+                    state[dst] = None;
+                }
+                _ => {}
+            }
+        };
+
+        // Every function starts with unknown registers
+        let mut block_states = BTreeMap::<usize, ValueSets>::new();
+        let mut worklist = Vec::new();
+        for (cfg_node_start, cfg_node) in self.cfg_nodes.iter() {
+            if cfg_node.sources.is_empty() {
+                block_states.insert(*cfg_node_start, ValueSets::default());
+                worklist.push(*cfg_node_start);
+            }
+        }
+        while let Some(cfg_node_start) = worklist.pop() {
+            let cfg_node = &self.cfg_nodes[&cfg_node_start];
+            let mut state = block_states[&cfg_node_start].clone();
+            for insn in &self.instructions[cfg_node.instructions.clone()] {
+                transfer(&mut state, insn);
+            }
+            for destination in cfg_node.destinations.iter() {
+                let joined = match block_states.get(destination) {
+                    None => state.clone(),
+                    Some(previous) => {
+                        let mut joined = previous.clone();
+                        for (register, incoming) in joined.iter_mut().zip(state.iter()) {
+                            *register = match (register.take(), incoming) {
+                                (Some(a), Some(b)) => limit(a.union(b).copied().collect()),
+                                _ => None,
+                            };
+                        }
+                        if joined == *previous {
+                            continue;
+                        }
+                        joined
+                    }
+                };
+                block_states.insert(*destination, joined);
+                worklist.push(*destination);
+            }
+        }
+
+        let instruction_count = self
+            .instructions
+            .last()
+            .map(|insn| insn.ptr + 1)
+            .unwrap_or(0);
+        let mut indirect_call_targets = BTreeMap::new();
+        for (cfg_node_start, state) in block_states.iter() {
+            let mut state = state.clone();
+            for insn in &self.instructions[self.cfg_nodes[cfg_node_start].instructions.clone()] {
+                if insn.opc == ebpf::CALL_REG {
+                    let register = if sbpf_version.callx_uses_src_reg() {
+                        insn.src
+                    } else if sbpf_version.callx_uses_dst_reg() {
+                        insn.dst
+                    } else {
+                        insn.imm as u8
+                    };
+                    let target_pcs = state.get(register as usize).cloned().flatten().and_then(
+                        |target_addresses| {
+                            target_addresses
+                                .iter()
+                                .map(|target_address| {
+                                    let offset = target_address.checked_sub(program_vm_addr)?;
+                                    let target_pc = (offset / ebpf::INSN_SIZE as u64) as usize;
+                                    (offset % ebpf::INSN_SIZE as u64 == 0
+                                        && target_pc < instruction_count)
+                                        .then_some(target_pc)
+                                })
+                                .collect::<Option<BTreeSet<_>>>()
+                        },
+                    );
+                    indirect_call_targets.insert(insn.ptr, target_pcs);
+                }
+                transfer(&mut state, insn);
+            }
+        }
+        let mut added_functions = false;
+        for target_pc in indirect_call_targets.values().flatten().flatten() {
+            self.functions.entry(*target_pc).or_insert_with(|| {
+                added_functions = true;
+                let name = format!("function_{target_pc}");
+                let hash = ebpf::hash_symbol_name(name.as_bytes());
+                (hash, name)
+            });
+        }
+        self.indirect_call_targets = indirect_call_targets;
+        added_functions
+    }
+
     /// Gives the basic blocks names
     pub fn label_basic_blocks(&mut self) {
         for (pc, cfg_node) in self.cfg_nodes.iter_mut() {
@@ -480,9 +695,17 @@ impl<'a> Analysis<'a> {
         )
     }
 
+    /// Source location of the instruction at `pc`, if the executable has a line table
+    pub fn source_location(&self, pc: usize) -> Option<SourceLocation<'_>> {
+        self.executable.get_source_location(pc)
+    }
+
     /// Generates assembler code for the analyzed executable
+    ///
+    /// With a line table the source line is added as a comment wherever it changes.
     pub fn disassemble<W: std::io::Write>(&self, output: &mut W) -> std::io::Result<()> {
         let mut last_basic_block = usize::MAX;
+        let mut last_line = None;
         for (pc, insn) in self.instructions.iter().enumerate() {
             self.disassemble_label(
                 output,
@@ -490,6 +713,12 @@ impl<'a> Analysis<'a> {
                 insn.ptr,
                 &mut last_basic_block,
             )?;
+            if let Some(location) = self.source_location(insn.ptr) {
+                if last_line != Some((location.file, location.line)) {
+                    last_line = Some((location.file, location.line));
+                    writeln!(output, "    ; {location}")?;
+                }
+            }
             writeln!(output, "    {}", self.disassemble_instruction(insn, pc))?;
         }
         Ok(())
@@ -515,7 +744,7 @@ impl<'a> Analysis<'a> {
         for (index, entry) in register_trace.iter().enumerate() {
             let pc = entry[11] as usize;
             let insn = &self.instructions[pc_to_insn_index[pc]];
-            writeln!(
+            write!(
                 output,
                 "{:5?} {:016X?} {:5?}: {}",
                 index,
@@ -523,6 +752,10 @@ impl<'a> Analysis<'a> {
                 pc,
                 self.disassemble_instruction(insn, pc),
             )?;
+            if let Some(location) = self.source_location(pc) {
+                write!(output, " ; {location}")?;
+            }
+            writeln!(output)?;
         }
         Ok(())
     }
@@ -1069,7 +1302,13 @@ impl<'a> Analysis<'a> {
                         ebpf::LD_DW_IMM => {
                             bind(&mut state, insn, true, DataResource::Register(insn.dst));
                         }
-                        ebpf::LD_B_REG | ebpf::LD_H_REG | ebpf::LD_W_REG | ebpf::LD_DW_REG => {
+                        ebpf::LD_B_REG
+                        | ebpf::LD_H_REG
+                        | ebpf::LD_W_REG
+                        | ebpf::LD_DW_REG
+                        | ebpf::LD_B_SX_REG
+                        | ebpf::LD_H_SX_REG
+                        | ebpf::LD_W_SX_REG => {
                             bind(&mut state, insn, false, DataResource::Memory);
                             bind(&mut state, insn, false, DataResource::Register(insn.src));
                             bind(&mut state, insn, true, DataResource::Register(insn.dst));
@@ -1083,6 +1322,22 @@ impl<'a> Analysis<'a> {
                             bind(&mut state, insn, false, DataResource::Register(insn.dst));
                             bind(&mut state, insn, true, DataResource::Memory);
                         }
+                        ebpf::ST_W_ATOMIC | ebpf::ST_DW_ATOMIC => {
+                            bind(&mut state, insn, false, DataResource::Register(insn.src));
+                            bind(&mut state, insn, false, DataResource::Register(insn.dst));
+                            bind(&mut state, insn, false, DataResource::Memory);
+                            match insn.imm {
+                                ebpf::ATOMIC_FETCH_ADD | ebpf::ATOMIC_XCHG => {
+                                    bind(&mut state, insn, true, DataResource::Register(insn.src));
+                                }
+                                ebpf::ATOMIC_CMPXCHG => {
+                                    bind(&mut state, insn, false, DataResource::Register(0));
+                                    bind(&mut state, insn, true, DataResource::Register(0));
+                                }
+                                _ => {}
+                            }
+                            bind(&mut state, insn, true, DataResource::Memory);
+                        }
                         ebpf::ADD32_IMM
                         | ebpf::SUB32_IMM
                         | ebpf::MUL32_IMM
@@ -1111,7 +1366,8 @@ impl<'a> Analysis<'a> {
                         | ebpf::NEG32
                         | ebpf::NEG64
                         | ebpf::LE
-                        | ebpf::BE => {
+                        | ebpf::BE
+                        | ebpf::BSWAP => {
                             bind(&mut state, insn, false, DataResource::Register(insn.dst));
                             bind(&mut state, insn, true, DataResource::Register(insn.dst));
                         }
@@ -13,12 +13,30 @@
 //! Verifies that the bytecode is valid for the given config.
 
 use crate::{
+    abstract_interpretation::{
+        AbstractInterpretation, Interval, MemoryAccess, PointerRegion, RegisterType,
+    },
+    disassembler::disassemble_instruction,
     ebpf,
-    program::{BuiltinFunction, FunctionRegistry, SBPFVersion},
+    elf::{ElfError, Executable},
+    elf_linter::Severity,
+    loop_analysis::LoopAnalysis,
+    program::{BuiltinFunction, BuiltinProgram, FunctionRegistry, SBPFVersion},
+    static_analysis::{Analysis, CfgNode},
     vm::{Config, ContextObject},
 };
+use rustc_demangle::demangle;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
 use thiserror::Error;
 
+#[cfg(feature = "shuttle-test")]
+use shuttle::sync::Arc;
+#[cfg(not(feature = "shuttle-test"))]
+use std::sync::Arc;
+
 /// Error definitions
 #[derive(Debug, Error, Eq, PartialEq)]
 pub enum VerifierError {
@@ -79,6 +97,88 @@ pub enum VerifierError {
     /// Unaligned immediate
     #[error("Unaligned immediate (insn #{0})")]
     UnalignedImmediate(usize),
+    /// Offset field does not select a valid variant of the instruction
+    #[error("Invalid offset (insn #{0})")]
+    InvalidOffset(usize),
+    /// Immediate does not select a supported atomic operation
+    #[error("Unsupported atomic operation (insn #{0})")]
+    UnsupportedAtomicOperation(usize),
+    /// Register is read before it is written on at least one path
+    #[error("read of uninitialized register r{1} (insn #{0})")]
+    UninitializedRegister(usize, u8),
+    /// Memory access lies entirely outside of the region it is based on
+    #[error("out of bounds access to {1} (insn #{0})")]
+    OutOfBoundsAccess(usize, &'static str),
+    /// Register used as divisor is not known to be non-zero
+    #[error("divisor may be zero (insn #{0})")]
+    DivisorMayBeZero(usize),
+    /// Syscall is not allowed by the [VerifierPolicy]
+    #[error("syscall {1:#x} is not allowed by the policy (insn #{0})")]
+    SyscallNotAllowed(usize, u32),
+    /// Opcode is forbidden by the [VerifierPolicy]
+    #[error("opcode {0:#2x} is forbidden by the policy (insn #{1})")]
+    ForbiddenOpcode(u8, usize),
+    /// First instruction beyond the size limit of the [VerifierPolicy]
+    #[error("program exceeds the size limit of the policy (insn #{0})")]
+    ProgramSizeExceeded(usize),
+    /// First function beyond the function count limit of the [VerifierPolicy]
+    #[error("program exceeds the function count limit of the policy (insn #{0})")]
+    FunctionCountExceeded(usize),
+    /// Store into the input region, which the [VerifierPolicy] forbids
+    #[error("store into the input region (insn #{0})")]
+    InputRegionWrite(usize),
+    /// Header of a loop which only the instruction meter ends, which the [VerifierPolicy] forbids
+    #[error("unbounded loop (insn #{0})")]
+    UnboundedLoop(usize),
+    /// The program could not be prepared for the analysis of a verifier
+    #[error("invalid executable: {0}")]
+    InvalidExecutable(ElfError),
+}
+
+/// Syscalls a program may call, identified by the hashes of their names
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum SyscallPolicy {
+    /// Every registered syscall
+    #[default]
+    AllowAll,
+    /// Only the listed syscalls
+    Allow(BTreeSet<u32>),
+    /// All but the listed syscalls
+    Deny(BTreeSet<u32>),
+}
+
+impl SyscallPolicy {
+    /// Whether the syscall with the given hash may be called
+    pub fn allows(&self, hash: u32) -> bool {
+        match self {
+            Self::AllowAll => true,
+            Self::Allow(hashes) => hashes.contains(&hash),
+            Self::Deny(hashes) => !hashes.contains(&hash),
+        }
+    }
+}
+
+/// Restrictions which a deployment places on the programs it accepts
+///
+/// Enforced by the [PolicyVerifier] and set in [Config::verifier_policy]. The default policy
+/// restricts nothing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifierPolicy {
+    /// Syscalls which may be called
+    pub syscalls: SyscallPolicy,
+    /// Opcodes which must not occur, e.g. [ebpf::CALL_REG] to rule out indirect calls
+    pub forbidden_opcodes: BTreeSet<u8>,
+    /// Reject stores which may write into the input region
+    pub forbid_input_writes: bool,
+    /// Maximum number of instruction slots, `lddw` takes two
+    pub max_instructions: Option<usize>,
+    /// Maximum number of functions, counting the registered ones and the targets of calls
+    pub max_functions: Option<usize>,
+    /// Reject loops which are bounded neither by a constant nor by the result of a syscall
+    ///
+    /// Nested loops are classified on their own, see [crate::loop_analysis]. Only loops which are
+    /// entered through more than one basic block are rejected together with their nested loops.
+    pub forbid_unbounded_loops: bool,
 }
 
 /// eBPF Verifier
@@ -98,6 +198,37 @@ pub trait Verifier {
         function_registry: &FunctionRegistry<usize>,
         syscall_registry: &FunctionRegistry<BuiltinFunction<C>>,
     ) -> Result<(), VerifierError>;
+
+    /// Verifies an executable, which gives the verifier access to its loader
+    ///
+    /// Defaults to [Verifier::verify] on the parts of the executable.
+    fn verify_executable<C: ContextObject>(
+        executable: &Executable<C>,
+    ) -> Result<(), VerifierError> {
+        Self::verify(
+            executable.get_text_bytes().1,
+            executable.get_config(),
+            executable.get_sbpf_version(),
+            executable.get_function_registry(),
+            executable.get_loader().get_function_registry(),
+        )
+    }
+}
+
+/// Assembles an executable for the verifiers which analyze the program but are only given its parts
+fn executable_from_parts<C: ContextObject>(
+    prog: &[u8],
+    config: &Config,
+    sbpf_version: SBPFVersion,
+    function_registry: &FunctionRegistry<usize>,
+    syscall_registry: &FunctionRegistry<BuiltinFunction<C>>,
+) -> Result<Executable<C>, VerifierError> {
+    let loader = Arc::new(BuiltinProgram::new_loader_with_registry(
+        config.clone(),
+        syscall_registry.clone(),
+    ));
+    Executable::new_from_text_bytes(prog, loader, sbpf_version, function_registry.clone())
+        .map_err(VerifierError::InvalidExecutable)
 }
 
 fn check_prog_len(prog: &[u8]) -> Result<(), VerifierError> {
@@ -124,6 +255,17 @@ fn check_imm_endian(insn: &ebpf::Insn, insn_ptr: usize) -> Result<(), VerifierEr
     }
 }
 
+fn check_imm_atomic(insn: &ebpf::Insn, insn_ptr: usize) -> Result<(), VerifierError> {
+    match insn.imm {
+        ebpf::ATOMIC_ADD => Ok(()),
+        ebpf::ATOMIC_FETCH_ADD | ebpf::ATOMIC_XCHG if insn.src == 10 => {
+            Err(VerifierError::CannotWriteR10(insn_ptr))
+        }
+        ebpf::ATOMIC_FETCH_ADD | ebpf::ATOMIC_XCHG | ebpf::ATOMIC_CMPXCHG => Ok(()),
+        _ => Err(VerifierError::UnsupportedAtomicOperation(insn_ptr)),
+    }
+}
+
 fn check_imm_aligned(
     insn: &ebpf::Insn,
     insn_ptr: usize,
@@ -192,6 +334,18 @@ fn check_registers(
     }
 }
 
+/// Check that the offset of an ALU instruction selects one of its variants
+fn check_alu_offset(
+    insn: &ebpf::Insn,
+    insn_ptr: usize,
+    variants: &[i16],
+) -> Result<(), VerifierError> {
+    if !variants.contains(&insn.off) {
+        return Err(VerifierError::InvalidOffset(insn_ptr));
+    }
+    Ok(())
+}
+
 /// Check that the imm is a valid shift operand
 fn check_imm_shift(insn: &ebpf::Insn, insn_ptr: usize, imm_bits: u64) -> Result<(), VerifierError> {
     let shift_by = insn.imm as u64;
@@ -222,210 +376,701 @@ fn check_callx_register(
     Ok(())
 }
 
+/// Check a single instruction, returns the pc of the next one
+#[rustfmt::skip]
+pub(crate) fn verify_insn(
+    prog: &[u8],
+    mut insn_ptr: usize,
+    program_range: &std::ops::Range<usize>,
+    sbpf_version: SBPFVersion,
+    config: &Config,
+) -> Result<usize, VerifierError> {
+    let insn = ebpf::get_insn(prog, insn_ptr);
+    let mut store = false;
+
+    match insn.opc {
+        ebpf::LD_DW_IMM if !sbpf_version.disable_lddw() => {
+            check_load_dw(prog, insn_ptr)?;
+            insn_ptr += 1;
+        },
+
+        // Linux eBPF
+        ebpf::LD_B_SX_REG | ebpf::LD_H_SX_REG | ebpf::LD_W_SX_REG if sbpf_version.enable_ldsx() => {},
+        ebpf::DIV32_IMM | ebpf::MOD32_IMM | ebpf::DIV64_IMM | ebpf::MOD64_IMM if sbpf_version.enable_signed_division() => {
+            check_imm_nonzero(&insn, insn_ptr)?;
+            check_alu_offset(&insn, insn_ptr, &[0, 1])?;
+        },
+        ebpf::DIV32_REG | ebpf::MOD32_REG | ebpf::DIV64_REG | ebpf::MOD64_REG if sbpf_version.enable_signed_division() => {
+            check_alu_offset(&insn, insn_ptr, &[0, 1])?;
+        },
+        ebpf::MOV32_REG  if sbpf_version.enable_movsx() => { check_alu_offset(&insn, insn_ptr, &[0, 8, 16])?; },
+        ebpf::MOV64_REG  if sbpf_version.enable_movsx() => { check_alu_offset(&insn, insn_ptr, &[0, 8, 16, 32])?; },
+        ebpf::BSWAP      if sbpf_version.enable_bswap() => { check_imm_endian(&insn, insn_ptr)?; },
+        ebpf::ST_W_ATOMIC | ebpf::ST_DW_ATOMIC if sbpf_version.enable_atomics() && config.enable_atomic_instructions => {
+            store = true;
+            check_imm_atomic(&insn, insn_ptr)?;
+        },
+
+        // BPF_LDX class
+        ebpf::LD_B_REG  if !sbpf_version.move_memory_instruction_classes() => {},
+        ebpf::LD_H_REG  if !sbpf_version.move_memory_instruction_classes() => {},
+        ebpf::LD_W_REG  if !sbpf_version.move_memory_instruction_classes() => {},
+        ebpf::LD_DW_REG if !sbpf_version.move_memory_instruction_classes() => {},
+
+        // BPF_ST class
+        ebpf::ST_B_IMM  if !sbpf_version.move_memory_instruction_classes() => store = true,
+        ebpf::ST_H_IMM  if !sbpf_version.move_memory_instruction_classes() => store = true,
+        ebpf::ST_W_IMM  if !sbpf_version.move_memory_instruction_classes() => store = true,
+        ebpf::ST_DW_IMM if !sbpf_version.move_memory_instruction_classes() => store = true,
+
+        // BPF_STX class
+        ebpf::ST_B_REG  if !sbpf_version.move_memory_instruction_classes() => store = true,
+        ebpf::ST_H_REG  if !sbpf_version.move_memory_instruction_classes() => store = true,
+        ebpf::ST_W_REG  if !sbpf_version.move_memory_instruction_classes() => store = true,
+        ebpf::ST_DW_REG if !sbpf_version.move_memory_instruction_classes() => store = true,
+
+        // BPF_ALU32_LOAD class
+        ebpf::ADD32_IMM  => {},
+        ebpf::ADD32_REG  => {},
+        ebpf::SUB32_IMM  => {},
+        ebpf::SUB32_REG  => {},
+        ebpf::MUL32_IMM  if !sbpf_version.enable_pqr() => {},
+        ebpf::MUL32_REG  if !sbpf_version.enable_pqr() => {},
+        ebpf::LD_1B_REG  if sbpf_version.move_memory_instruction_classes() => {},
+        ebpf::DIV32_IMM  if !sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+        ebpf::DIV32_REG  if !sbpf_version.enable_pqr() => {},
+        ebpf::LD_2B_REG  if sbpf_version.move_memory_instruction_classes() => {},
+        ebpf::OR32_IMM   => {},
+        ebpf::OR32_REG   => {},
+        ebpf::AND32_IMM  => {},
+        ebpf::AND32_REG  => {},
+        ebpf::LSH32_IMM  => { check_imm_shift(&insn, insn_ptr, 32)?; },
+        ebpf::LSH32_REG  => {},
+        ebpf::RSH32_IMM  => { check_imm_shift(&insn, insn_ptr, 32)?; },
+        ebpf::RSH32_REG  => {},
+        ebpf::NEG32      if !sbpf_version.disable_neg() => {},
+        ebpf::LD_4B_REG  if sbpf_version.move_memory_instruction_classes() => {},
+        ebpf::MOD32_IMM  if !sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+        ebpf::MOD32_REG  if !sbpf_version.enable_pqr() => {},
+        ebpf::LD_8B_REG  if sbpf_version.move_memory_instruction_classes() => {},
+        ebpf::XOR32_IMM  => {},
+        ebpf::XOR32_REG  => {},
+        ebpf::MOV32_IMM  => {},
+        ebpf::MOV32_REG  => {},
+        ebpf::ARSH32_IMM => { check_imm_shift(&insn, insn_ptr, 32)?; },
+        ebpf::ARSH32_REG => {},
+        ebpf::LE         if !sbpf_version.disable_le() => { check_imm_endian(&insn, insn_ptr)?; },
+        ebpf::BE         => { check_imm_endian(&insn, insn_ptr)?; },
+
+        // BPF_ALU64_STORE class
+        ebpf::ADD64_IMM  if insn.dst == ebpf::FRAME_PTR_REG as u8 && sbpf_version.manual_stack_frame_bump() => {
+            check_imm_aligned(&insn, insn_ptr, 64)?;
+        },
+        ebpf::ADD64_IMM  => {},
+        ebpf::ADD64_REG  => {},
+        ebpf::SUB64_IMM  => {},
+        ebpf::SUB64_REG  => {},
+        ebpf::MUL64_IMM  if !sbpf_version.enable_pqr() => {},
+        ebpf::ST_1B_IMM  if sbpf_version.move_memory_instruction_classes() => store = true,
+        ebpf::MUL64_REG  if !sbpf_version.enable_pqr() => {},
+        ebpf::ST_1B_REG  if sbpf_version.move_memory_instruction_classes() => store = true,
+        ebpf::DIV64_IMM  if !sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+        ebpf::ST_2B_IMM  if sbpf_version.move_memory_instruction_classes() => store = true,
+        ebpf::DIV64_REG  if !sbpf_version.enable_pqr() => {},
+        ebpf::ST_2B_REG  if sbpf_version.move_memory_instruction_classes() => store = true,
+        ebpf::OR64_IMM   => {},
+        ebpf::OR64_REG   => {},
+        ebpf::AND64_IMM  => {},
+        ebpf::AND64_REG  => {},
+        ebpf::LSH64_IMM  => { check_imm_shift(&insn, insn_ptr, 64)?; },
+        ebpf::LSH64_REG  => {},
+        ebpf::RSH64_IMM  => { check_imm_shift(&insn, insn_ptr, 64)?; },
+        ebpf::RSH64_REG  => {},
+        ebpf::ST_4B_IMM  if sbpf_version.move_memory_instruction_classes() => store = true,
+        ebpf::NEG64      if !sbpf_version.disable_neg() => {},
+        ebpf::ST_4B_REG  if sbpf_version.move_memory_instruction_classes() => store = true,
+        ebpf::MOD64_IMM  if !sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+        ebpf::ST_8B_IMM  if sbpf_version.move_memory_instruction_classes() => store = true,
+        ebpf::MOD64_REG  if !sbpf_version.enable_pqr() => {},
+        ebpf::ST_8B_REG  if sbpf_version.move_memory_instruction_classes() => store = true,
+        ebpf::XOR64_IMM  => {},
+        ebpf::XOR64_REG  => {},
+        ebpf::MOV64_IMM  => {},
+        ebpf::MOV64_REG  => {},
+        ebpf::ARSH64_IMM => { check_imm_shift(&insn, insn_ptr, 64)?; },
+        ebpf::ARSH64_REG => {},
+        ebpf::HOR64_IMM  if sbpf_version.disable_lddw() => {},
+
+        // BPF_PQR class
+        ebpf::LMUL32_IMM if sbpf_version.enable_pqr() => {},
+        ebpf::LMUL32_REG if sbpf_version.enable_pqr() => {},
+        ebpf::LMUL64_IMM if sbpf_version.enable_pqr() => {},
+        ebpf::LMUL64_REG if sbpf_version.enable_pqr() => {},
+        ebpf::UHMUL64_IMM if sbpf_version.enable_pqr() => {},
+        ebpf::UHMUL64_REG if sbpf_version.enable_pqr() => {},
+        ebpf::SHMUL64_IMM if sbpf_version.enable_pqr() => {},
+        ebpf::SHMUL64_REG if sbpf_version.enable_pqr() => {},
+        ebpf::UDIV32_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+        ebpf::UDIV32_REG if sbpf_version.enable_pqr() => {},
+        ebpf::UDIV64_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+        ebpf::UDIV64_REG if sbpf_version.enable_pqr() => {},
+        ebpf::UREM32_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+        ebpf::UREM32_REG if sbpf_version.enable_pqr() => {},
+        ebpf::UREM64_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+        ebpf::UREM64_REG if sbpf_version.enable_pqr() => {},
+        ebpf::SDIV32_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+        ebpf::SDIV32_REG if sbpf_version.enable_pqr() => {},
+        ebpf::SDIV64_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+        ebpf::SDIV64_REG if sbpf_version.enable_pqr() => {},
+        ebpf::SREM32_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+        ebpf::SREM32_REG if sbpf_version.enable_pqr() => {},
+        ebpf::SREM64_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+        ebpf::SREM64_REG if sbpf_version.enable_pqr() => {},
+
+        // BPF_JMP32 class
+        ebpf::JEQ32_IMM
+        | ebpf::JEQ32_REG
+        | ebpf::JGT32_IMM
+        | ebpf::JGT32_REG
+        | ebpf::JGE32_IMM
+        | ebpf::JGE32_REG
+        | ebpf::JLT32_IMM
+        | ebpf::JLT32_REG
+        | ebpf::JLE32_IMM
+        | ebpf::JLE32_REG
+        | ebpf::JSET32_IMM
+        | ebpf::JSET32_REG
+        | ebpf::JNE32_IMM
+        | ebpf::JNE32_REG
+        | ebpf::JSGT32_IMM
+        | ebpf::JSGT32_REG
+        | ebpf::JSGE32_IMM
+        | ebpf::JSGE32_REG
+        | ebpf::JSLT32_IMM
+        | ebpf::JSLT32_REG
+        | ebpf::JSLE32_IMM
+        | ebpf::JSLE32_REG if sbpf_version.enable_jmp32() => { check_jmp_offset(prog, insn_ptr, program_range)?; },
+
+        // BPF_JMP64 class
+        ebpf::JA
+        | ebpf::JEQ64_IMM
+        | ebpf::JEQ64_REG
+        | ebpf::JGT64_IMM
+        | ebpf::JGT64_REG
+        | ebpf::JGE64_IMM
+        | ebpf::JGE64_REG
+        | ebpf::JLT64_IMM
+        | ebpf::JLT64_REG
+        | ebpf::JLE64_IMM
+        | ebpf::JLE64_REG
+        | ebpf::JSET64_IMM
+        | ebpf::JSET64_REG
+        | ebpf::JNE64_IMM
+        | ebpf::JNE64_REG
+        | ebpf::JSGT64_IMM
+        | ebpf::JSGT64_REG
+        | ebpf::JSGE64_IMM
+        | ebpf::JSGE64_REG
+        | ebpf::JSLT64_IMM
+        | ebpf::JSLT64_REG
+        | ebpf::JSLE64_IMM
+        | ebpf::JSLE64_REG   => { check_jmp_offset(prog, insn_ptr, program_range)?; },
+        ebpf::CALL_IMM   => {},
+        ebpf::CALL_REG   => { check_callx_register(&insn, insn_ptr, sbpf_version)?; },
+        ebpf::EXIT       => {},
+
+        _                => {
+            return Err(VerifierError::UnknownOpCode(insn.opc, insn_ptr));
+        }
+    }
+
+    check_registers(&insn, store, insn_ptr, sbpf_version)?;
+
+    Ok(insn_ptr + 1)
+}
+
 /// Mandatory verifier for solana programs to run on-chain
 #[derive(Debug)]
 pub struct RequisiteVerifier {}
 impl Verifier for RequisiteVerifier {
     /// Check the program against the verifier's rules
     #[rustfmt::skip]
-    fn verify<C: ContextObject>(prog: &[u8], _config: &Config, sbpf_version: SBPFVersion, _function_registry: &FunctionRegistry<usize>, _syscall_registry: &FunctionRegistry<BuiltinFunction<C>>) -> Result<(), VerifierError> {
+    fn verify<C: ContextObject>(prog: &[u8], config: &Config, sbpf_version: SBPFVersion, _function_registry: &FunctionRegistry<usize>, _syscall_registry: &FunctionRegistry<BuiltinFunction<C>>) -> Result<(), VerifierError> {
         check_prog_len(prog)?;
 
         let program_range = 0..prog.len() / ebpf::INSN_SIZE;
         let mut insn_ptr: usize = 0;
         while (insn_ptr + 1) * ebpf::INSN_SIZE <= prog.len() {
-            let insn = ebpf::get_insn(prog, insn_ptr);
-            let mut store = false;
+            insn_ptr = verify_insn(prog, insn_ptr, &program_range, sbpf_version, config)?;
+        }
 
-            match insn.opc {
-                ebpf::LD_DW_IMM if !sbpf_version.disable_lddw() => {
-                    check_load_dw(prog, insn_ptr)?;
-                    insn_ptr += 1;
-                },
+        // insn_ptr should now be equal to number of instructions.
+        if insn_ptr != prog.len() / ebpf::INSN_SIZE {
+            return Err(VerifierError::JumpOutOfCode(insn_ptr, insn_ptr));
+        }
 
-                // BPF_LDX class
-                ebpf::LD_B_REG  if !sbpf_version.move_memory_instruction_classes() => {},
-                ebpf::LD_H_REG  if !sbpf_version.move_memory_instruction_classes() => {},
-                ebpf::LD_W_REG  if !sbpf_version.move_memory_instruction_classes() => {},
-                ebpf::LD_DW_REG if !sbpf_version.move_memory_instruction_classes() => {},
-
-                // BPF_ST class
-                ebpf::ST_B_IMM  if !sbpf_version.move_memory_instruction_classes() => store = true,
-                ebpf::ST_H_IMM  if !sbpf_version.move_memory_instruction_classes() => store = true,
-                ebpf::ST_W_IMM  if !sbpf_version.move_memory_instruction_classes() => store = true,
-                ebpf::ST_DW_IMM if !sbpf_version.move_memory_instruction_classes() => store = true,
-
-                // BPF_STX class
-                ebpf::ST_B_REG  if !sbpf_version.move_memory_instruction_classes() => store = true,
-                ebpf::ST_H_REG  if !sbpf_version.move_memory_instruction_classes() => store = true,
-                ebpf::ST_W_REG  if !sbpf_version.move_memory_instruction_classes() => store = true,
-                ebpf::ST_DW_REG if !sbpf_version.move_memory_instruction_classes() => store = true,
-
-                // BPF_ALU32_LOAD class
-                ebpf::ADD32_IMM  => {},
-                ebpf::ADD32_REG  => {},
-                ebpf::SUB32_IMM  => {},
-                ebpf::SUB32_REG  => {},
-                ebpf::MUL32_IMM  if !sbpf_version.enable_pqr() => {},
-                ebpf::MUL32_REG  if !sbpf_version.enable_pqr() => {},
-                ebpf::LD_1B_REG  if sbpf_version.move_memory_instruction_classes() => {},
-                ebpf::DIV32_IMM  if !sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
-                ebpf::DIV32_REG  if !sbpf_version.enable_pqr() => {},
-                ebpf::LD_2B_REG  if sbpf_version.move_memory_instruction_classes() => {},
-                ebpf::OR32_IMM   => {},
-                ebpf::OR32_REG   => {},
-                ebpf::AND32_IMM  => {},
-                ebpf::AND32_REG  => {},
-                ebpf::LSH32_IMM  => { check_imm_shift(&insn, insn_ptr, 32)?; },
-                ebpf::LSH32_REG  => {},
-                ebpf::RSH32_IMM  => { check_imm_shift(&insn, insn_ptr, 32)?; },
-                ebpf::RSH32_REG  => {},
-                ebpf::NEG32      if !sbpf_version.disable_neg() => {},
-                ebpf::LD_4B_REG  if sbpf_version.move_memory_instruction_classes() => {},
-                ebpf::MOD32_IMM  if !sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
-                ebpf::MOD32_REG  if !sbpf_version.enable_pqr() => {},
-                ebpf::LD_8B_REG  if sbpf_version.move_memory_instruction_classes() => {},
-                ebpf::XOR32_IMM  => {},
-                ebpf::XOR32_REG  => {},
-                ebpf::MOV32_IMM  => {},
-                ebpf::MOV32_REG  => {},
-                ebpf::ARSH32_IMM => { check_imm_shift(&insn, insn_ptr, 32)?; },
-                ebpf::ARSH32_REG => {},
-                ebpf::LE         if !sbpf_version.disable_le() => { check_imm_endian(&insn, insn_ptr)?; },
-                ebpf::BE         => { check_imm_endian(&insn, insn_ptr)?; },
-
-                // BPF_ALU64_STORE class
-                ebpf::ADD64_IMM  if insn.dst == ebpf::FRAME_PTR_REG as u8 && sbpf_version.manual_stack_frame_bump() => {
-                    check_imm_aligned(&insn, insn_ptr, 64)?;
-                },
-                ebpf::ADD64_IMM  => {},
-                ebpf::ADD64_REG  => {},
-                ebpf::SUB64_IMM  => {},
-                ebpf::SUB64_REG  => {},
-                ebpf::MUL64_IMM  if !sbpf_version.enable_pqr() => {},
-                ebpf::ST_1B_IMM  if sbpf_version.move_memory_instruction_classes() => store = true,
-                ebpf::MUL64_REG  if !sbpf_version.enable_pqr() => {},
-                ebpf::ST_1B_REG  if sbpf_version.move_memory_instruction_classes() => store = true,
-                ebpf::DIV64_IMM  if !sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
-                ebpf::ST_2B_IMM  if sbpf_version.move_memory_instruction_classes() => store = true,
-                ebpf::DIV64_REG  if !sbpf_version.enable_pqr() => {},
-                ebpf::ST_2B_REG  if sbpf_version.move_memory_instruction_classes() => store = true,
-                ebpf::OR64_IMM   => {},
-                ebpf::OR64_REG   => {},
-                ebpf::AND64_IMM  => {},
-                ebpf::AND64_REG  => {},
-                ebpf::LSH64_IMM  => { check_imm_shift(&insn, insn_ptr, 64)?; },
-                ebpf::LSH64_REG  => {},
-                ebpf::RSH64_IMM  => { check_imm_shift(&insn, insn_ptr, 64)?; },
-                ebpf::RSH64_REG  => {},
-                ebpf::ST_4B_IMM  if sbpf_version.move_memory_instruction_classes() => store = true,
-                ebpf::NEG64      if !sbpf_version.disable_neg() => {},
-                ebpf::ST_4B_REG  if sbpf_version.move_memory_instruction_classes() => store = true,
-                ebpf::MOD64_IMM  if !sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
-                ebpf::ST_8B_IMM  if sbpf_version.move_memory_instruction_classes() => store = true,
-                ebpf::MOD64_REG  if !sbpf_version.enable_pqr() => {},
-                ebpf::ST_8B_REG  if sbpf_version.move_memory_instruction_classes() => store = true,
-                ebpf::XOR64_IMM  => {},
-                ebpf::XOR64_REG  => {},
-                ebpf::MOV64_IMM  => {},
-                ebpf::MOV64_REG  => {},
-                ebpf::ARSH64_IMM => { check_imm_shift(&insn, insn_ptr, 64)?; },
-                ebpf::ARSH64_REG => {},
-                ebpf::HOR64_IMM  if sbpf_version.disable_lddw() => {},
-
-                // BPF_PQR class
-                ebpf::LMUL32_IMM if sbpf_version.enable_pqr() => {},
-                ebpf::LMUL32_REG if sbpf_version.enable_pqr() => {},
-                ebpf::LMUL64_IMM if sbpf_version.enable_pqr() => {},
-                ebpf::LMUL64_REG if sbpf_version.enable_pqr() => {},
-                ebpf::UHMUL64_IMM if sbpf_version.enable_pqr() => {},
-                ebpf::UHMUL64_REG if sbpf_version.enable_pqr() => {},
-                ebpf::SHMUL64_IMM if sbpf_version.enable_pqr() => {},
-                ebpf::SHMUL64_REG if sbpf_version.enable_pqr() => {},
-                ebpf::UDIV32_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
-                ebpf::UDIV32_REG if sbpf_version.enable_pqr() => {},
-                ebpf::UDIV64_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
-                ebpf::UDIV64_REG if sbpf_version.enable_pqr() => {},
-                ebpf::UREM32_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
-                ebpf::UREM32_REG if sbpf_version.enable_pqr() => {},
-                ebpf::UREM64_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
-                ebpf::UREM64_REG if sbpf_version.enable_pqr() => {},
-                ebpf::SDIV32_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
-                ebpf::SDIV32_REG if sbpf_version.enable_pqr() => {},
-                ebpf::SDIV64_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
-                ebpf::SDIV64_REG if sbpf_version.enable_pqr() => {},
-                ebpf::SREM32_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
-                ebpf::SREM32_REG if sbpf_version.enable_pqr() => {},
-                ebpf::SREM64_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
-                ebpf::SREM64_REG if sbpf_version.enable_pqr() => {},
-
-                // BPF_JMP32 class
-                ebpf::JEQ32_IMM
-                | ebpf::JEQ32_REG
-                | ebpf::JGT32_IMM
-                | ebpf::JGT32_REG
-                | ebpf::JGE32_IMM
-                | ebpf::JGE32_REG
-                | ebpf::JLT32_IMM
-                | ebpf::JLT32_REG
-                | ebpf::JLE32_IMM
-                | ebpf::JLE32_REG
-                | ebpf::JSET32_IMM
-                | ebpf::JSET32_REG
-                | ebpf::JNE32_IMM
-                | ebpf::JNE32_REG
-                | ebpf::JSGT32_IMM
-                | ebpf::JSGT32_REG
-                | ebpf::JSGE32_IMM
-                | ebpf::JSGE32_REG
-                | ebpf::JSLT32_IMM
-                | ebpf::JSLT32_REG
-                | ebpf::JSLE32_IMM
-                | ebpf::JSLE32_REG if sbpf_version.enable_jmp32() => { check_jmp_offset(prog, insn_ptr, &program_range)?; },
-
-                // BPF_JMP64 class
-                ebpf::JA
-                | ebpf::JEQ64_IMM
-                | ebpf::JEQ64_REG
-                | ebpf::JGT64_IMM
-                | ebpf::JGT64_REG
-                | ebpf::JGE64_IMM
-                | ebpf::JGE64_REG
-                | ebpf::JLT64_IMM
-                | ebpf::JLT64_REG
-                | ebpf::JLE64_IMM
-                | ebpf::JLE64_REG
-                | ebpf::JSET64_IMM
-                | ebpf::JSET64_REG
-                | ebpf::JNE64_IMM
-                | ebpf::JNE64_REG
-                | ebpf::JSGT64_IMM
-                | ebpf::JSGT64_REG
-                | ebpf::JSGE64_IMM
-                | ebpf::JSGE64_REG
-                | ebpf::JSLT64_IMM
-                | ebpf::JSLT64_REG
-                | ebpf::JSLE64_IMM
-                | ebpf::JSLE64_REG   => { check_jmp_offset(prog, insn_ptr, &program_range)?; },
-                ebpf::CALL_IMM   => {},
-                ebpf::CALL_REG   => { check_callx_register(&insn, insn_ptr, sbpf_version)?; },
-                ebpf::EXIT       => {},
-
-                _                => {
-                    return Err(VerifierError::UnknownOpCode(insn.opc, insn_ptr));
+        Ok(())
+    }
+}
+
+/// Verifier which additionally tracks register types and value ranges by abstract interpretation
+///
+/// Runs the [RequisiteVerifier] first and then reports the first of the following violations:
+/// - A register is read before it was written on at least one path leading to the instruction
+/// - A load or store is provably outside of the stack or input region its base pointer refers to
+/// - A quotient or remainder instruction divides by a register which may be zero
+#[derive(Debug)]
+pub struct AbstractInterpretationVerifier {}
+impl Verifier for AbstractInterpretationVerifier {
+    fn verify<C: ContextObject>(
+        prog: &[u8],
+        config: &Config,
+        sbpf_version: SBPFVersion,
+        function_registry: &FunctionRegistry<usize>,
+        syscall_registry: &FunctionRegistry<BuiltinFunction<C>>,
+    ) -> Result<(), VerifierError> {
+        RequisiteVerifier::verify(
+            prog,
+            config,
+            sbpf_version,
+            function_registry,
+            syscall_registry,
+        )?;
+        let executable = executable_from_parts(
+            prog,
+            config,
+            sbpf_version,
+            function_registry,
+            syscall_registry,
+        )?;
+        Self::verify_analysis(&Analysis::new(&executable))
+    }
+
+    fn verify_executable<C: ContextObject>(
+        executable: &Executable<C>,
+    ) -> Result<(), VerifierError> {
+        RequisiteVerifier::verify_executable(executable)?;
+        Self::verify_analysis(&Analysis::new(executable))
+    }
+}
+
+impl AbstractInterpretationVerifier {
+    fn verify_analysis(analysis: &Analysis) -> Result<(), VerifierError> {
+        match AbstractInterpretation::run(analysis)
+            .violations
+            .into_iter()
+            .next()
+        {
+            Some(violation) => Err(violation),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Verifier which enforces the [VerifierPolicy] of the [Config]
+///
+/// Runs the [RequisiteVerifier] first. Stores into the input region are found by abstract
+/// interpretation, so besides those through pointers derived from the input pointer in `r1`, all
+/// stores through numbers which may address the input region and through registers whose value is
+/// unknown, like the arguments of functions, are rejected.
+#[derive(Debug)]
+pub struct PolicyVerifier {}
+impl Verifier for PolicyVerifier {
+    fn verify<C: ContextObject>(
+        prog: &[u8],
+        config: &Config,
+        sbpf_version: SBPFVersion,
+        function_registry: &FunctionRegistry<usize>,
+        syscall_registry: &FunctionRegistry<BuiltinFunction<C>>,
+    ) -> Result<(), VerifierError> {
+        Self::verify_instructions(
+            prog,
+            config,
+            sbpf_version,
+            function_registry,
+            syscall_registry,
+        )?;
+        if !Self::requires_analysis(&config.verifier_policy) {
+            return Ok(());
+        }
+        let executable = executable_from_parts(
+            prog,
+            config,
+            sbpf_version,
+            function_registry,
+            syscall_registry,
+        )?;
+        Self::verify_analysis(&Analysis::new(&executable), config, sbpf_version)
+    }
+
+    fn verify_executable<C: ContextObject>(
+        executable: &Executable<C>,
+    ) -> Result<(), VerifierError> {
+        Self::verify_instructions(
+            executable.get_text_bytes().1,
+            executable.get_config(),
+            executable.get_sbpf_version(),
+            executable.get_function_registry(),
+            executable.get_loader().get_function_registry(),
+        )?;
+        if !Self::requires_analysis(&executable.get_config().verifier_policy) {
+            return Ok(());
+        }
+        Self::verify_analysis(
+            &Analysis::new(executable),
+            executable.get_config(),
+            executable.get_sbpf_version(),
+        )
+    }
+}
+
+impl PolicyVerifier {
+    fn requires_analysis(policy: &VerifierPolicy) -> bool {
+        policy.forbid_input_writes || policy.forbid_unbounded_loops
+    }
+
+    fn verify_instructions<C: ContextObject>(
+        prog: &[u8],
+        config: &Config,
+        sbpf_version: SBPFVersion,
+        function_registry: &FunctionRegistry<usize>,
+        syscall_registry: &FunctionRegistry<BuiltinFunction<C>>,
+    ) -> Result<(), VerifierError> {
+        RequisiteVerifier::verify(
+            prog,
+            config,
+            sbpf_version,
+            function_registry,
+            syscall_registry,
+        )?;
+        let policy = &config.verifier_policy;
+        let slots = prog.len() / ebpf::INSN_SIZE;
+        if let Some(max_instructions) = policy.max_instructions {
+            if slots > max_instructions {
+                return Err(VerifierError::ProgramSizeExceeded(max_instructions));
+            }
+        }
+        let mut function_starts = function_registry
+            .iter()
+            .map(|(_key, (_name, pc))| pc)
+            .collect::<BTreeSet<_>>();
+        let mut insn_ptr = 0;
+        while insn_ptr < slots {
+            let insn = ebpf::get_insn(prog, insn_ptr);
+            if policy.forbidden_opcodes.contains(&insn.opc) {
+                return Err(VerifierError::ForbiddenOpcode(insn.opc, insn_ptr));
+            }
+            if insn.opc == ebpf::CALL_IMM {
+                let is_syscall = if sbpf_version.static_syscalls() {
+                    insn.src == 0
+                } else {
+                    syscall_registry.lookup_by_key(insn.imm as u32).is_some()
+                };
+                if is_syscall {
+                    if !policy.syscalls.allows(insn.imm as u32) {
+                        return Err(VerifierError::SyscallNotAllowed(insn_ptr, insn.imm as u32));
+                    }
+                } else if sbpf_version.static_syscalls() {
+                    // Relative calls do not need to target registered functions
+                    function_starts.insert(
+                        sbpf_version.calculate_call_imm_target_pc(insn_ptr, insn.imm) as usize,
+                    );
                 }
             }
+            insn_ptr += if insn.opc == ebpf::LD_DW_IMM && !sbpf_version.disable_lddw() {
+                2
+            } else {
+                1
+            };
+        }
+        if let Some(max_functions) = policy.max_functions {
+            if let Some(function_start) = function_starts.iter().nth(max_functions) {
+                return Err(VerifierError::FunctionCountExceeded(*function_start));
+            }
+        }
+        Ok(())
+    }
 
-            check_registers(&insn, store, insn_ptr, sbpf_version)?;
+    fn verify_analysis(
+        analysis: &Analysis,
+        config: &Config,
+        sbpf_version: SBPFVersion,
+    ) -> Result<(), VerifierError> {
+        let policy = &config.verifier_policy;
+        if policy.forbid_input_writes {
+            // Addresses beyond the end of the layout are never mapped
+            let input_start = config
+                .address_space_layout
+                .input_start(0)
+                .unwrap_or_else(|| config.address_space_layout.end());
+            let mut input_writes = BTreeSet::new();
+            AbstractInterpretation::run(analysis).replay(analysis, |insn, state| {
+                if let Some(access) = MemoryAccess::decode(insn, sbpf_version) {
+                    let may_write_input = match state.registers[access.base as usize] {
+                        RegisterType::Pointer(region, _) => region == PointerRegion::Input,
+                        // The address could wrap around into the input region
+                        RegisterType::Scalar(interval) => {
+                            interval.min + (insn.off as i128) < 0
+                                || interval.max + insn.off as i128 + access.len as i128
+                                    > input_start as i128
+                        }
+                        RegisterType::Uninitialized | RegisterType::Unknown => true,
+                    };
+                    if !access.is_load && may_write_input {
+                        input_writes.insert(insn.ptr);
+                    }
+                }
+            });
+            if let Some(pc) = input_writes.into_iter().next() {
+                return Err(VerifierError::InputRegionWrite(pc));
+            }
+        }
+        if policy.forbid_unbounded_loops {
+            if let Some((header, _loop)) = LoopAnalysis::new(analysis).unbounded_loops().next() {
+                return Err(VerifierError::UnboundedLoop(*header));
+            }
+        }
+        Ok(())
+    }
+}
 
-            insn_ptr += 1;
+/// Properties of individual instructions which hold in every execution
+///
+/// Proven by abstract interpretation and the value-set analysis of `callx` targets, so there are
+/// no facts about instructions which the [Analysis] does not reach. Both assume that functions are
+/// only entered at their start, so there are no facts at all if the targets of a `callx` could not
+/// be resolved. The JIT uses them to leave out the corresponding runtime checks, unless
+/// `Config::assert_verified_facts` is set.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct VerifiedFacts {
+    /// Quotient and remainder instructions whose divisor register is never zero
+    pub nonzero_divisors: BTreeSet<usize>,
+    /// Loads and stores which stay inside of the stack frame of their function
+    pub in_frame_stack_accesses: BTreeSet<usize>,
+    /// `callx` instructions which always call the same registered function, by the pc of it
+    pub callx_targets: BTreeMap<usize, usize>,
+}
+
+impl VerifiedFacts {
+    /// Collects the facts about an analyzed executable
+    pub fn new(analysis: &Analysis) -> Self {
+        let executable = analysis.executable;
+        let sbpf_version = executable.get_sbpf_version();
+        // With dynamic stack frames the frame of a function has no fixed size
+        let frame = (!sbpf_version.manual_stack_frame_bump())
+            .then(|| Interval::new(-(executable.get_config().stack_frame_size as i128), 0));
+        let mut facts = Self::default();
+        // An unresolved callx could enter any function in the middle
+        if analysis.indirect_call_targets.values().any(Option::is_none) {
+            return facts;
+        }
+        AbstractInterpretation::run(analysis).replay(analysis, |insn, state| {
+            if let Some(access) = MemoryAccess::decode(insn, sbpf_version) {
+                if let (Some(frame), RegisterType::Pointer(PointerRegion::Stack, offset)) =
+                    (frame, state.registers[access.base as usize])
+                {
+                    let first = offset.min + insn.off as i128;
+                    let last = offset.max + insn.off as i128;
+                    if frame.min <= first && last + access.len as i128 <= frame.max {
+                        facts.in_frame_stack_accesses.insert(insn.ptr);
+                    }
+                }
+            } else if let Some(bits) = register_divisor_width(insn, sbpf_version) {
+                if let RegisterType::Scalar(range) = state.registers[insn.src as usize] {
+                    if Interval::wrap(range.min, range.max, bits).min > 0 {
+                        facts.nonzero_divisors.insert(insn.ptr);
+                    }
+                }
+            }
+        });
+        let registered_functions = executable
+            .get_function_registry()
+            .iter()
+            .map(|(_key, (_name, pc))| pc)
+            .collect::<BTreeSet<_>>();
+        for (pc, targets) in analysis.indirect_call_targets.iter() {
+            if let Some(targets) = targets {
+                if let (1, Some(target)) = (targets.len(), targets.first()) {
+                    if registered_functions.contains(target) {
+                        facts.callx_targets.insert(*pc, *target);
+                    }
+                }
+            }
         }
+        facts
+    }
+}
 
-        // insn_ptr should now be equal to number of instructions.
-        if insn_ptr != prog.len() / ebpf::INSN_SIZE {
-            return Err(VerifierError::JumpOutOfCode(insn_ptr, insn_ptr));
+/// Width of the divisor in bits if the instruction divides by a register
+fn register_divisor_width(insn: &ebpf::Insn, sbpf_version: SBPFVersion) -> Option<u32> {
+    if insn.opc & ebpf::BPF_X == 0 {
+        return None;
+    }
+    let operation = insn.opc & ebpf::BPF_ALU_OP_MASK;
+    match insn.opc & ebpf::BPF_CLS_MASK {
+        ebpf::BPF_ALU32_LOAD
+            if !sbpf_version.enable_pqr() && matches!(operation, ebpf::BPF_DIV | ebpf::BPF_MOD) =>
+        {
+            Some(32)
         }
+        ebpf::BPF_ALU64_STORE
+            if !sbpf_version.enable_pqr() && matches!(operation, ebpf::BPF_DIV | ebpf::BPF_MOD) =>
+        {
+            Some(64)
+        }
+        ebpf::BPF_PQR
+            if sbpf_version.enable_pqr()
+                && matches!(
+                    operation,
+                    ebpf::BPF_UDIV | ebpf::BPF_UREM | ebpf::BPF_SDIV | ebpf::BPF_SREM
+                ) =>
+        {
+            Some(if insn.opc & ebpf::BPF_B != 0 { 64 } else { 32 })
+        }
+        _ => None,
+    }
+}
 
+/// A violation found by [diagnose]
+#[derive(Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// [Severity::Error] for the rules of the [RequisiteVerifier], [Severity::Warning] otherwise
+    pub severity: Severity,
+    /// Instruction which violates the rule
+    pub pc: usize,
+    /// Disassembly of the instruction
+    pub instruction: String,
+    /// Name of the function the instruction belongs to
+    pub function: Option<String>,
+    /// The violated rule
+    pub error: VerifierError,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.error)?;
+        if !self.instruction.is_empty() {
+            write!(f, "\n    {}", self.instruction)?;
+            if let Some(function) = &self.function {
+                write!(f, " in {function}")?;
+            }
+        }
         Ok(())
     }
 }
+
+/// Verifies an executable and returns all violations, ordered by their pc
+///
+/// Unlike [Verifier::verify], this does not stop at the first problem. The rules of the
+/// [RequisiteVerifier] are reported as errors and only if there are none the
+/// [AbstractInterpretationVerifier] runs, whose findings are reported as warnings.
+pub fn diagnose<C: ContextObject>(executable: &Executable<C>) -> Vec<Diagnostic> {
+    let (_program_vm_addr, prog) = executable.get_text_bytes();
+    let sbpf_version = executable.get_sbpf_version();
+    let function_registry = executable.get_function_registry();
+    if let Err(error) = check_prog_len(prog) {
+        return vec![Diagnostic {
+            severity: Severity::Error,
+            pc: 0,
+            instruction: String::new(),
+            function: None,
+            error,
+        }];
+    }
+    let program_range = 0..prog.len() / ebpf::INSN_SIZE;
+    let mut violations = Vec::new();
+    let mut insn_ptr: usize = 0;
+    while insn_ptr < program_range.end {
+        insn_ptr = match verify_insn(
+            prog,
+            insn_ptr,
+            &program_range,
+            sbpf_version,
+            executable.get_config(),
+        ) {
+            Ok(next_insn_ptr) => next_insn_ptr,
+            Err(error) => {
+                violations.push((Severity::Error, insn_ptr, error));
+                let insn = ebpf::get_insn(prog, insn_ptr);
+                if insn.opc == ebpf::LD_DW_IMM && !sbpf_version.disable_lddw() {
+                    insn_ptr + 2
+                } else {
+                    insn_ptr + 1
+                }
+            }
+        };
+    }
+    if violations.is_empty() {
+        if let Ok(analysis) = Analysis::from_executable(executable) {
+            for error in AbstractInterpretation::run(&analysis).violations {
+                let pc = match error {
+                    VerifierError::UninitializedRegister(pc, _)
+                    | VerifierError::OutOfBoundsAccess(pc, _)
+                    | VerifierError::DivisorMayBeZero(pc) => pc,
+                    _ => 0,
+                };
+                violations.push((Severity::Warning, pc, error));
+            }
+        }
+    }
+    violations.sort_by_key(|(_severity, pc, _error)| *pc);
+
+    // Label the jump targets the same way the disassembler of the static analysis does
+    let function_names = function_registry
+        .iter()
+        .map(|(_key, (name, pc))| (pc, demangle(&String::from_utf8_lossy(name)).to_string()))
+        .collect::<BTreeMap<_, _>>();
+    let mut cfg_nodes = BTreeMap::new();
+    for pc in program_range.clone() {
+        let insn = ebpf::get_insn_unchecked(prog, pc);
+        let class = insn.opc & ebpf::BPF_CLS_MASK;
+        let is_jump = (class == ebpf::BPF_JMP64
+            || (class == ebpf::BPF_JMP32 && sbpf_version.enable_jmp32()))
+            && !matches!(
+                insn.opc & ebpf::BPF_ALU_OP_MASK,
+                ebpf::BPF_CALL | ebpf::BPF_EXIT
+            );
+        let target_pc = (pc as isize + insn.off as isize + 1) as usize;
+        if is_jump && program_range.contains(&target_pc) {
+            cfg_nodes.insert(
+                target_pc,
+                CfgNode {
+                    label: function_names
+                        .get(&target_pc)
+                        .cloned()
+                        .unwrap_or_else(|| format!("lbb_{target_pc}")),
+                    ..CfgNode::default()
+                },
+            );
+        }
+    }
+    violations
+        .into_iter()
+        .map(|(severity, pc, error)| {
+            let mut insn = ebpf::get_insn_unchecked(prog, pc);
+            if insn.opc == ebpf::LD_DW_IMM
+                && !sbpf_version.disable_lddw()
+                && program_range.contains(&(pc + 1))
+            {
+                ebpf::augment_lddw_unchecked(prog, &mut insn);
+            }
+            Diagnostic {
+                severity,
+                pc,
+                instruction: disassemble_instruction(
+                    &insn,
+                    pc,
+                    &cfg_nodes,
+                    function_registry,
+                    executable.get_loader(),
+                    sbpf_version,
+                ),
+                function: function_names
+                    .range(..=pc)
+                    .next_back()
+                    .map(|(_function_pc, name)| name.clone()),
+                error,
+            }
+        })
+        .collect()
+}
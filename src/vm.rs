@@ -15,13 +15,19 @@
 use crate::{
     ebpf,
     elf::Executable,
+    elf_parser::dwarf::SourceLocation,
     error::{EbpfError, ProgramResult},
     interpreter::Interpreter,
-    memory_region::MemoryMapping,
+    memory_region::{AddressSpaceLayout, MemoryMapping},
     program::{BuiltinFunction, BuiltinProgram, FunctionRegistry, SBPFVersion},
     static_analysis::{Analysis, DummyContextObject, RegisterTraceEntry},
+    verifier::VerifierPolicy,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Debug,
+    mem::offset_of,
 };
-use std::{collections::BTreeMap, fmt::Debug, mem::offset_of};
 
 #[cfg(feature = "shuttle-test")]
 use shuttle::sync::Arc;
@@ -69,24 +75,45 @@ pub struct Config {
     pub enable_instruction_meter: bool,
     /// Enable instruction tracing
     pub enable_register_tracing: bool,
+    /// Enable tracking the written stack bytes in the interpreter, see
+    /// [EbpfVm::uninitialized_stack_reads]
+    pub enable_stack_shadow: bool,
+    /// Enable recording the call frames in the JIT too, to capture a [Backtrace] on errors
+    pub enable_backtrace: bool,
     /// Enable dynamic string allocation for labels
     pub enable_symbol_and_section_labels: bool,
     /// Reject ELF files containing issues that the verifier did not catch before (up to v0.2.21)
     pub reject_broken_elfs: bool,
+    /// Accept writable `.data` / `.bss` sections, which must then be mapped from
+    /// [crate::elf::WritableSections] next to [crate::elf::Executable::get_ro_regions]
+    pub enable_writable_sections: bool,
+    /// Accept the `R_BPF_64_ABS64`, `R_BPF_64_ABS32` and `R_BPF_64_NODYLD32` relocations of
+    /// upstream BPF toolchains and apply the static relocation tables of unlinked ELFs
+    pub enable_upstream_relocations: bool,
+    /// Accept the atomic read-modify-write instructions (`BPF_ATOMIC`) of the Linux eBPF
+    /// dialect, which fail on memory accesses that are not naturally aligned
+    pub enable_atomic_instructions: bool,
     #[cfg(feature = "jit")]
     /// Ratio of native host instructions per random no-op in JIT (0 = OFF)
     pub noop_instruction_rate: u32,
     #[cfg(feature = "jit")]
     /// Enable disinfection of immediate values and offsets provided by the user in JIT
     pub sanitize_user_provided_values: bool,
+    #[cfg(feature = "jit")]
+    /// Check the [crate::verifier::VerifiedFacts] given to the JIT at runtime, for debugging
+    pub assert_verified_facts: bool,
     /// Avoid copying read only sections when possible
     pub optimize_rodata: bool,
     /// Allow a memory region at age zero in the aligned memory mapping
     pub allow_memory_region_zero: bool,
     /// Use aligned memory mapping
     pub aligned_memory_mapping: bool,
+    /// Placement of the stack, heap and input regions in the virtual address space
+    pub address_space_layout: AddressSpaceLayout,
     /// Allowed [SBPFVersion]s
     pub enabled_sbpf_versions: std::ops::RangeInclusive<SBPFVersion>,
+    /// Restrictions enforced by the [crate::verifier::PolicyVerifier]
+    pub verifier_policy: VerifierPolicy,
 }
 
 impl Config {
@@ -106,16 +133,25 @@ impl Default for Config {
             instruction_meter_checkpoint_distance: 10000,
             enable_instruction_meter: true,
             enable_register_tracing: false,
+            enable_stack_shadow: false,
+            enable_backtrace: false,
             enable_symbol_and_section_labels: false,
             reject_broken_elfs: false,
+            enable_writable_sections: false,
+            enable_upstream_relocations: false,
+            enable_atomic_instructions: false,
             #[cfg(feature = "jit")]
             noop_instruction_rate: 256,
             #[cfg(feature = "jit")]
             sanitize_user_provided_values: true,
+            #[cfg(feature = "jit")]
+            assert_verified_facts: false,
             optimize_rodata: true,
             allow_memory_region_zero: true,
             aligned_memory_mapping: false,
+            address_space_layout: AddressSpaceLayout::default(),
             enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V4,
+            verifier_policy: VerifierPolicy::default(),
         }
     }
 }
@@ -153,6 +189,8 @@ pub struct DynamicAnalysis {
     pub edge_counter_max: usize,
     /// src_node, dst_node, edge_counter
     pub edges: BTreeMap<usize, BTreeMap<usize, usize>>,
+    /// Executed instructions per source file and line, if the executable has a line table
+    pub source_lines: BTreeMap<(String, u32), usize>,
 }
 
 impl DynamicAnalysis {
@@ -161,10 +199,17 @@ impl DynamicAnalysis {
         let mut result = Self {
             edge_counter_max: 0,
             edges: BTreeMap::new(),
+            source_lines: BTreeMap::new(),
         };
         let mut last_basic_block = usize::MAX;
         for traced_instruction in register_trace.iter() {
             let pc = traced_instruction[11] as usize;
+            if let Some(location) = analysis.source_location(pc) {
+                *result
+                    .source_lines
+                    .entry((location.file.to_string(), location.line))
+                    .or_insert(0) += 1;
+            }
             if analysis.cfg_nodes.contains_key(&pc) {
                 let counter = result
                     .edges
@@ -182,6 +227,9 @@ impl DynamicAnalysis {
 }
 
 /// A call frame used for function calls inside the Interpreter
+///
+/// The JIT only records the `frame_pointer` and `target_pc`, and only if
+/// [Config::enable_backtrace] is set.
 #[derive(Clone, Default)]
 pub struct CallFrame {
     /// The caller saved registers
@@ -192,6 +240,100 @@ pub struct CallFrame {
     pub target_pc: u64,
 }
 
+/// A frame of a [Backtrace]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BacktraceFrame {
+    /// The faulting instruction in the innermost frame, the call instruction in all others
+    pub pc: usize,
+    /// The frame pointer, unknown for the innermost frame
+    pub frame_pointer: Option<u64>,
+    /// The demangled name of the function containing `pc`
+    pub function_name: Option<String>,
+    /// The first instruction of the function containing `pc`
+    pub function_pc: Option<usize>,
+    /// The source location of `pc`, if the executable has debug info
+    pub source_location: Option<String>,
+}
+
+impl std::fmt::Display for BacktraceFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "pc {}", self.pc)?;
+        match (&self.function_name, self.function_pc) {
+            (Some(name), Some(function_pc)) => write!(f, " in {}+{}", name, self.pc - function_pc)?,
+            _ => write!(f, " in ??")?,
+        }
+        if let Some(frame_pointer) = self.frame_pointer {
+            write!(f, " (frame pointer {frame_pointer:#x})")?;
+        }
+        if let Some(source_location) = &self.source_location {
+            write!(f, " at {source_location}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The call stack of a failed program, innermost frame first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Backtrace {
+    /// The frames, from the faulting instruction up to the entrypoint
+    pub frames: Vec<BacktraceFrame>,
+}
+
+impl Backtrace {
+    fn capture<C: ContextObject>(
+        vm: &EbpfVm<C>,
+        executable: &Executable<C>,
+        error: &EbpfError,
+    ) -> Self {
+        let mut functions = executable
+            .get_function_registry()
+            .iter()
+            .map(|(_key, (name, pc))| (pc, name))
+            .collect::<Vec<_>>();
+        functions.sort_unstable();
+        let symbolize = |pc: usize, frame_pointer: Option<u64>| {
+            let function = functions
+                .partition_point(|(function_pc, _name)| *function_pc <= pc)
+                .checked_sub(1)
+                .map(|index| functions[index]);
+            BacktraceFrame {
+                pc,
+                frame_pointer,
+                function_name: function
+                    .map(|(_pc, name)| String::from_utf8_lossy(name))
+                    .filter(|name| !name.is_empty())
+                    .map(|name| format!("{:#}", rustc_demangle::demangle(&name))),
+                function_pc: function.map(|(function_pc, _name)| function_pc),
+                source_location: executable
+                    .get_source_location(pc)
+                    .map(|location| location.to_string()),
+            }
+        };
+        let mut call_depth = (vm.call_depth as usize).min(vm.call_frames.len());
+        if matches!(error, EbpfError::CallDepthExceeded) {
+            // The frame of the call which was not entered anymore
+            call_depth = call_depth.saturating_sub(1);
+        }
+        let mut frames = vec![symbolize(vm.registers[11] as usize, None)];
+        frames.extend(vm.call_frames[..call_depth].iter().rev().map(|frame| {
+            symbolize(
+                frame.target_pc.saturating_sub(1) as usize,
+                Some(frame.frame_pointer),
+            )
+        }));
+        Self { frames }
+    }
+}
+
+impl std::fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (index, frame) in self.frames.iter().enumerate() {
+            writeln!(f, "#{index} {frame}")?;
+        }
+        Ok(())
+    }
+}
+
 /// Indices of slots inside [EbpfVm]
 pub enum RuntimeEnvironmentSlot {
     /// [EbpfVm::host_stack_pointer]
@@ -217,6 +359,11 @@ pub enum RuntimeEnvironmentSlot {
     MemoryMapping = offset_of!(EbpfVm<DummyContextObject>, memory_mapping) as isize,
     /// [EbpfVm::register_trace]
     RegisterTrace = offset_of!(EbpfVm<DummyContextObject>, register_trace) as isize,
+    /// [EbpfVm::call_frames]
+    CallFrames = offset_of!(EbpfVm<DummyContextObject>, call_frames) as isize,
+    /// [EbpfVm::stack_translation_offset]
+    StackTranslationOffset =
+        offset_of!(EbpfVm<DummyContextObject>, stack_translation_offset) as isize,
 }
 
 /// A virtual machine to run eBPF programs.
@@ -297,12 +444,27 @@ pub struct EbpfVm<'a, C: ContextObject> {
     pub program_result: ProgramResult,
     /// MemoryMapping inlined
     pub memory_mapping: MemoryMapping,
+    /// Host address minus vm address of the stack, used by the JIT for in-frame accesses
+    ///
+    /// Updated after every syscall which changed the memory mapping.
+    pub stack_translation_offset: u64,
+    /// [MemoryMapping::generation] for which the stack_translation_offset was computed
+    pub stack_translation_generation: Option<u64>,
     /// Stack of CallFrames used by the Interpreter
     pub call_frames: Vec<CallFrame>,
     /// Loader built-in program
     pub loader: Arc<BuiltinProgram<C>>,
     /// Collector for the instruction trace
     pub register_trace: Vec<RegisterTraceEntry>,
+    /// Collector for loads from stack bytes which were not written before in the same call,
+    /// by pc and offset relative to the frame pointer
+    ///
+    /// Requires [Config::enable_stack_shadow] and the interpreter. Every call starts with an
+    /// uninitialized frame. Stack memory passed to a syscall which was registered with
+    /// [BuiltinProgram::register_writing_function] counts as written from the passed pointer to
+    /// the frame pointer. Other syscalls are assumed to not write, so loads of stack memory
+    /// written by them are reported too and have to be filtered by the caller.
+    pub uninitialized_stack_reads: BTreeSet<(usize, i64)>,
     /// TCP port for the debugger interface
     #[cfg(feature = "debugger")]
     pub debug_port: Option<u16>,
@@ -320,11 +482,14 @@ impl<'a, C: ContextObject> EbpfVm<'a, C> {
         let config = loader.get_config();
         let mut registers = [0u64; 12];
         registers[ebpf::FRAME_PTR_REG] =
-            ebpf::MM_STACK_START.saturating_add(if !sbpf_version.manual_stack_frame_bump() {
-                config.stack_frame_size
-            } else {
-                stack_len
-            } as u64);
+            config
+                .address_space_layout
+                .stack_start()
+                .saturating_add(if !sbpf_version.manual_stack_frame_bump() {
+                    config.stack_frame_size
+                } else {
+                    stack_len
+                } as u64);
         if !config.enable_address_translation {
             memory_mapping = MemoryMapping::new_identity();
         }
@@ -339,6 +504,8 @@ impl<'a, C: ContextObject> EbpfVm<'a, C> {
             registers,
             program_result: ProgramResult::Ok(0),
             memory_mapping,
+            stack_translation_offset: 0,
+            stack_translation_generation: None,
             call_frames: vec![CallFrame::default(); config.max_call_depth],
             loader,
             #[cfg(feature = "debugger")]
@@ -346,9 +513,21 @@ impl<'a, C: ContextObject> EbpfVm<'a, C> {
                 .ok()
                 .and_then(|v| v.parse::<u16>().ok()),
             register_trace: Vec::default(),
+            uninitialized_stack_reads: BTreeSet::default(),
         }
     }
 
+    /// Source location of the instruction which raised the error of the last execution
+    ///
+    /// Only meaningful if the execution failed, otherwise it is the location of the entrypoint
+    /// or of an earlier error.
+    pub fn get_error_location<'b>(
+        &self,
+        executable: &'b Executable<C>,
+    ) -> Option<SourceLocation<'b>> {
+        executable.get_source_location(self.registers[11] as usize)
+    }
+
     /// Execute the program
     ///
     /// If interpreted = `false` then the JIT compiled executable is used.
@@ -394,6 +573,15 @@ impl<'a, C: ContextObject> EbpfVm<'a, C> {
                     Ok(compiled_program) => compiled_program,
                     Err(error) => return (0, ProgramResult::Err(error)),
                 };
+                if compiled_program.translates_stack_accesses_inline() {
+                    self.stack_translation_generation = None;
+                    self.update_stack_translation_offset();
+                    if self.program_result.is_err() {
+                        let mut result = ProgramResult::Ok(0);
+                        std::mem::swap(&mut result, &mut self.program_result);
+                        return (0, result);
+                    }
+                }
                 compiled_program.invoke(config, self, self.registers);
             }
             #[cfg(not(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64")))]
@@ -412,6 +600,49 @@ impl<'a, C: ContextObject> EbpfVm<'a, C> {
         (instruction_count, result)
     }
 
+    /// Maps the stack for the accesses which the JIT translates inline
+    ///
+    /// Does nothing if the memory mapping did not change since the last time,
+    /// otherwise a builtin could have moved or removed the stack region.
+    /// Errors are stored in [EbpfVm::program_result].
+    pub(crate) fn update_stack_translation_offset(&mut self) {
+        let generation = self.memory_mapping.generation();
+        if self.stack_translation_generation == Some(generation) {
+            return;
+        }
+        let config = self.loader.get_config();
+        let stack_start = config.address_space_layout.stack_start();
+        match self.memory_mapping.map(
+            crate::memory_region::AccessType::Store,
+            stack_start,
+            config.stack_size() as u64,
+        ) {
+            ProgramResult::Ok(host_addr) => {
+                self.stack_translation_offset = host_addr.wrapping_sub(stack_start);
+                self.stack_translation_generation = Some(generation);
+            }
+            ProgramResult::Err(error) => self.program_result = ProgramResult::Err(error),
+        }
+    }
+
+    /// Execute the program and capture a [Backtrace] if it fails
+    ///
+    /// The backtrace is only captured if [Config::enable_backtrace] is set.
+    pub fn execute_program_with_backtrace(
+        &mut self,
+        executable: &Executable<C>,
+        interpreted: bool,
+    ) -> (u64, ProgramResult, Option<Backtrace>) {
+        let (instruction_count, result) = self.execute_program(executable, interpreted);
+        let backtrace = match &result {
+            ProgramResult::Err(error) if executable.get_config().enable_backtrace => {
+                Some(Backtrace::capture(self, executable, error))
+            }
+            _ => None,
+        };
+        (instruction_count, result, backtrace)
+    }
+
     /// Invokes a built-in function
     pub fn invoke_function(&mut self, function: BuiltinFunction<C>) {
         function(
@@ -305,6 +305,30 @@ impl X86Instruction {
         }
     }
 
+    /// Sign extend the lower `source_size` bits of source into all 64 bits of destination
+    pub const fn sign_extend(
+        source_size: OperandSize,
+        source: X86Register,
+        destination: X86Register,
+    ) -> Self {
+        exclude_operand_sizes!(source_size, OperandSize::S0 | OperandSize::S64);
+        match source_size {
+            OperandSize::S8 | OperandSize::S16 => Self {
+                size: OperandSize::S64, // REX.W also selects SPL, BPL, SIL and DIL as byte registers
+                opcode_escape_sequence: 1,
+                opcode: if let OperandSize::S8 = source_size {
+                    0xbe
+                } else {
+                    0xbf
+                },
+                first_operand: destination as u8,
+                second_operand: source as u8,
+                ..Self::DEFAULT
+            },
+            _ => Self::mov_with_sign_extension(OperandSize::S64, source, destination),
+        }
+    }
+
     /// Move to / from / between MMX (float mantissa)
     #[allow(dead_code)]
     pub const fn mov_mmx(size: OperandSize, source: X86Register, destination: X86Register) -> Self {
@@ -359,10 +383,7 @@ impl X86Instruction {
         destination: X86Register,
         indirect: Option<X86IndirectAccess>,
     ) -> Self {
-        exclude_operand_sizes!(
-            size,
-            OperandSize::S0 | OperandSize::S8 | OperandSize::S16 | OperandSize::S32,
-        );
+        exclude_operand_sizes!(size, OperandSize::S0 | OperandSize::S8 | OperandSize::S16,);
         Self {
             size,
             opcode: 0x87,
@@ -373,6 +394,26 @@ impl X86Instruction {
         }
     }
 
+    /// Swap source and destination, then store their sum in destination
+    pub const fn xadd(
+        size: OperandSize,
+        source: X86Register,
+        destination: X86Register,
+        indirect: Option<X86IndirectAccess>,
+    ) -> Self {
+        Self::alu_escaped(size, 1, 0xc1, source, destination, indirect)
+    }
+
+    /// Compare RAX with destination and if equal store source in destination, otherwise load destination into RAX
+    pub const fn cmpxchg(
+        size: OperandSize,
+        source: X86Register,
+        destination: X86Register,
+        indirect: Option<X86IndirectAccess>,
+    ) -> Self {
+        Self::alu_escaped(size, 1, 0xb1, source, destination, indirect)
+    }
+
     /// Swap byte order of destination
     pub const fn bswap(size: OperandSize, destination: X86Register) -> Self {
         exclude_operand_sizes!(size, OperandSize::S0 | OperandSize::S8);
@@ -10,7 +10,7 @@
 
 use solana_sbpf::{
     aligned_memory::AlignedMemory,
-    ebpf::{self, HOST_ALIGN},
+    ebpf::HOST_ALIGN,
     elf::Executable,
     error::EbpfError,
     memory_region::{AccessViolationHandler, MemoryMapping, MemoryRegion},
@@ -214,14 +214,17 @@ pub fn create_memory_mapping<'a, C: ContextObject>(
         executable.get_ro_region(),
         MemoryRegion::new_writable_gapped(
             stack.as_slice_mut(),
-            ebpf::MM_STACK_START,
+            config.address_space_layout.stack_start(),
             if sbpf_version.stack_frame_gaps() && config.enable_stack_frame_gaps {
                 config.stack_frame_size as u64
             } else {
                 0
             },
         ),
-        MemoryRegion::new_writable(heap.as_slice_mut(), ebpf::MM_HEAP_START),
+        MemoryRegion::new_writable(
+            heap.as_slice_mut(),
+            config.address_space_layout.heap_start(),
+        ),
     ]
     .into_iter()
     .chain(additional_regions.into_iter())
@@ -264,7 +267,11 @@ macro_rules! create_vm {
             memory_mapping,
             stack_len,
         );
-        $vm_name.registers[1] = solana_sbpf::ebpf::MM_INPUT_START;
+        $vm_name.registers[1] = $verified_executable
+            .get_config()
+            .address_space_layout
+            .input_start(0)
+            .unwrap_or(solana_sbpf::ebpf::MM_INPUT_START);
     };
 }
 
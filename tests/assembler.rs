@@ -14,7 +14,7 @@ use solana_sbpf::program::SBPFVersion;
 use solana_sbpf::vm::Config;
 use solana_sbpf::{assembler::assemble, ebpf, program::BuiltinProgram};
 use std::sync::Arc;
-use test_utils::{TestContextObject, TCP_SACK_ASM, TCP_SACK_BIN};
+use test_utils::{syscalls, TestContextObject, TCP_SACK_ASM, TCP_SACK_BIN};
 
 fn asm(src: &str) -> Result<Vec<ebpf::Insn>, String> {
     asm_with_config(src, Config::default())
@@ -82,6 +82,30 @@ fn test_static_syscall() {
     );
 }
 
+#[test]
+fn test_syscall_helper_id() {
+    // Only the Linux eBPF dialect calls builtins by their helper id
+    for (sbpf_version, imm) in [
+        (
+            SBPFVersion::V3,
+            ebpf::hash_symbol_name(b"bpf_gather_bytes") as i32 as i64,
+        ),
+        (SBPFVersion::Linux, 1),
+    ] {
+        let mut loader = BuiltinProgram::new_loader(Config {
+            enabled_sbpf_versions: sbpf_version..=sbpf_version,
+            ..Config::default()
+        });
+        loader
+            .register_helper(1, "bpf_gather_bytes", syscalls::SyscallGatherBytes::vm)
+            .unwrap();
+        let executable =
+            assemble::<TestContextObject>("syscall bpf_gather_bytes", Arc::new(loader)).unwrap();
+        let (_program_vm_addr, program) = executable.get_text_bytes();
+        assert_eq!(ebpf::get_insn(program, 0).imm, imm);
+    }
+}
+
 // Example for InstructionType::AluBinary.
 #[test]
 fn test_add64() {
@@ -0,0 +1,112 @@
+#![allow(clippy::arithmetic_side_effects)]
+
+// Runs the test cases in `tests/conformance` against the Linux eBPF dialect.
+//
+// The file format follows the one of the bpf_conformance suite
+// <https://github.com/Alan-Jowett/bpf_conformance>: a program in the `-- asm` section, an optional
+// `-- mem` section of hex bytes which is mapped at `MM_INPUT_START` and passed in r1, and either the
+// expected value of r0 in `-- result` or the expected failure in `-- error`. The programs are written
+// in the syntax of this crates assembler instead of the one of the upstream suite.
+
+extern crate solana_sbpf;
+extern crate test_utils;
+
+use solana_sbpf::{
+    assembler::assemble,
+    ebpf,
+    error::ProgramResult,
+    memory_region::MemoryRegion,
+    program::{BuiltinProgram, SBPFVersion},
+    static_analysis::Analysis,
+    verifier::RequisiteVerifier,
+    vm::{Config, ContextObject},
+};
+use std::{collections::BTreeMap, path::Path, sync::Arc};
+use test_utils::{
+    compare_register_trace, create_vm, syscalls, test_interpreter_and_jit, TestContextObject,
+};
+
+fn parse_sections(source: &str) -> BTreeMap<&str, String> {
+    let mut sections = BTreeMap::new();
+    let mut current = None;
+    for line in source.lines() {
+        if let Some(name) = line.strip_prefix("-- ") {
+            current = Some(name.trim());
+            sections.insert(name.trim(), String::new());
+        } else if let Some(name) = current {
+            let section = sections.get_mut(name).unwrap();
+            section.push_str(line);
+            section.push('\n');
+        }
+    }
+    sections
+}
+
+fn run_conformance_test(path: &Path) {
+    let source = std::fs::read_to_string(path).unwrap();
+    let sections = parse_sections(&source);
+    let mem = sections
+        .get("mem")
+        .map(|bytes| {
+            bytes
+                .split_whitespace()
+                .map(|byte| u8::from_str_radix(byte, 16).unwrap())
+                .collect::<Vec<u8>>()
+        })
+        .unwrap_or_default();
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::Linux..=SBPFVersion::Linux,
+        enable_instruction_meter: false,
+        enable_register_tracing: true,
+        enable_atomic_instructions: true,
+        ..Config::default()
+    };
+    let mut loader = BuiltinProgram::new_loader(config);
+    loader
+        .register_helper(1, "bpf_gather_bytes", syscalls::SyscallGatherBytes::vm)
+        .unwrap();
+    #[allow(unused_mut)]
+    let mut executable = assemble::<TestContextObject>(&sections["asm"], Arc::new(loader))
+        .unwrap_or_else(|err| panic!("{}: {err}", path.display()));
+    if let Some(expected_error) = sections.get("error") {
+        let result = executable.verify::<RequisiteVerifier>();
+        let error = format!("{:?}", result.unwrap_err());
+        assert!(
+            error.contains(expected_error.trim()),
+            "{}: {error}",
+            path.display(),
+        );
+        return;
+    }
+    let expected_result =
+        u64::from_str_radix(sections["result"].trim().trim_start_matches("0x"), 16).unwrap();
+    let result = test_interpreter_and_jit!(
+        override_budget => true,
+        executable,
+        { mem.clone() },
+        TestContextObject::default(),
+    );
+    assert_eq!(
+        format!("{result:?}"),
+        format!("{:?}", ProgramResult::Ok(expected_result)),
+        "{}",
+        path.display(),
+    );
+}
+
+#[test]
+fn test_conformance() {
+    let mut paths = std::fs::read_dir("tests/conformance")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "data")
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty());
+    for path in paths {
+        run_conformance_test(&path);
+    }
+}
@@ -0,0 +1,100 @@
+extern crate solana_sbpf;
+extern crate test_utils;
+
+use solana_sbpf::{
+    assembler::assemble,
+    cost_analysis::{Cost, CostAnalysis, CostModel},
+    program::BuiltinProgram,
+    static_analysis::Analysis,
+    vm::Config,
+};
+use std::{collections::BTreeMap, sync::Arc};
+use test_utils::{syscalls, TestContextObject};
+
+fn analyze(src: &str, cost_model: &CostModel) -> CostAnalysis {
+    let mut loader = BuiltinProgram::new_loader(Config::default());
+    loader
+        .register_function("bpf_syscall_u64", syscalls::SyscallU64::vm)
+        .unwrap();
+    let executable = assemble::<TestContextObject>(src, Arc::new(loader)).unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    CostAnalysis::new(&analysis, cost_model)
+}
+
+#[test]
+fn test_cost_of_calls_and_syscalls() {
+    let cost_model = CostModel {
+        syscall_costs: BTreeMap::from([("bpf_syscall_u64".to_string(), 100)]),
+        default_syscall_cost: 1000,
+    };
+    let cost_analysis = analyze(
+        "
+        entrypoint:
+        mov64 r0, 0
+        call function_foo
+        syscall bpf_syscall_u64
+        exit
+        function_foo:
+        mov64 r0, 1
+        exit",
+        &cost_model,
+    );
+    let foo = &cost_analysis.functions[&4];
+    assert_eq!(foo.name, "function_foo");
+    assert_eq!(foo.cost, Cost::constant(2));
+    let entrypoint = &cost_analysis.functions[&0];
+    assert_eq!(entrypoint.cost, Cost::constant(106));
+    assert_eq!(cost_analysis.worst_case(&BTreeMap::new()), Some(106));
+}
+
+#[test]
+fn test_cost_of_branches_and_loops() {
+    let cost_analysis = analyze(
+        "
+        mov64 r0, 0
+        mov64 r1, 0
+        loop:
+        add64 r0, 1
+        add64 r1, 1
+        jlt r1, 10, loop
+        jeq r0, 5, skip
+        mov64 r0, 1
+        skip:
+        exit",
+        &CostModel::default(),
+    );
+    assert_eq!(cost_analysis.loops, BTreeMap::from([(2, vec![2])]));
+    let entrypoint = &cost_analysis.functions[&0];
+    assert_eq!(entrypoint.cost.to_string(), "5 + 3 * n_2");
+    assert_eq!(entrypoint.hot_path, vec![0, 2, 5, 6, 7]);
+    assert_eq!(cost_analysis.worst_case(&BTreeMap::new()), None);
+    assert_eq!(
+        cost_analysis.worst_case(&BTreeMap::from([(2, 10)])),
+        Some(35)
+    );
+}
+
+#[test]
+fn test_cost_unbounded() {
+    let cost_analysis = analyze(
+        "
+        entrypoint:
+        call function_foo
+        exit
+        function_foo:
+        call function_foo
+        exit",
+        &CostModel::default(),
+    );
+    assert!(cost_analysis.functions[&0].cost.unbounded);
+    assert_eq!(cost_analysis.functions[&0].cost.to_string(), "unbounded");
+
+    let cost_analysis = analyze(
+        "
+        mov64 r1, 0
+        callx r1
+        exit",
+        &CostModel::default(),
+    );
+    assert_eq!(cost_analysis.worst_case(&BTreeMap::new()), None);
+}
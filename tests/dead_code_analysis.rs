@@ -0,0 +1,174 @@
+extern crate solana_sbpf;
+extern crate test_utils;
+
+use solana_sbpf::{
+    assembler::assemble,
+    dead_code_analysis::{strip_dead_code, DeadCodeAnalysis},
+    ebpf,
+    elf::Executable,
+    program::{BuiltinProgram, SBPFVersion},
+    static_analysis::Analysis,
+    vm::Config,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+use test_utils::{assert_error, create_vm, TestContextObject};
+
+const SOURCE: &str = "
+    ja +1
+    mov64 r0, 2
+    lddw r1, 0x100000058
+    callx r1
+    call function_used
+    exit
+    function_used:
+    add64 r0, 2
+    exit
+    function_unused:
+    call function_used
+    exit
+    function_pointer:
+    mov64 r0, 40
+    exit";
+
+fn execute(executable: &Executable<TestContextObject>) -> u64 {
+    let mut context_object = TestContextObject::new(100);
+    create_vm!(
+        vm,
+        executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    vm.execute_program(executable, true).1.unwrap()
+}
+
+#[test]
+fn test_dead_code_report() {
+    let executable = assemble::<TestContextObject>(
+        SOURCE,
+        Arc::new(BuiltinProgram::new_loader(Config::default())),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let dead_code = DeadCodeAnalysis::new(&analysis);
+    assert_eq!(
+        dead_code.unreachable_blocks,
+        BTreeMap::from([(1, 1), (9, 2)])
+    );
+    assert_eq!(dead_code.address_taken, BTreeSet::from([11]));
+    // The dead block after the jump belongs to the entrypoint
+    let entrypoint = &dead_code.functions[&0];
+    assert_eq!(entrypoint.pcs, 0..7);
+    assert!(!entrypoint.dead);
+    assert_eq!(entrypoint.dead_bytes, 8);
+    let unused = &dead_code.functions[&9];
+    assert_eq!(unused.name, "function_unused");
+    assert!(unused.registered);
+    assert!(unused.dead);
+    assert_eq!(unused.pcs, 9..11);
+    assert_eq!(unused.dead_bytes, 16);
+    assert!(!dead_code.functions[&11].dead);
+    assert_eq!(dead_code.dead_bytes(), 24);
+    assert_eq!(
+        dead_code.to_string(),
+        "24 bytes of dead code\nentrypoint: 8 bytes unreachable\nfunction_unused: dead, 16 bytes\n"
+    );
+}
+
+#[test]
+fn test_strip_dead_code() {
+    let executable = assemble::<TestContextObject>(
+        SOURCE,
+        Arc::new(BuiltinProgram::new_loader(Config::default())),
+    )
+    .unwrap();
+    assert_eq!(execute(&executable), 42);
+    let stripped = strip_dead_code(&executable).unwrap();
+    let (_text_vaddr, text) = stripped.get_text_bytes();
+    assert_eq!(text.len(), 10 * ebpf::INSN_SIZE);
+    assert!(stripped
+        .get_function_registry()
+        .lookup_by_name(b"function_unused")
+        .is_none());
+    assert_eq!(
+        stripped
+            .get_function_registry()
+            .lookup_by_name(b"function_pointer"),
+        Some((&b"function_pointer"[..], 8))
+    );
+    // The function pointer moved along with its function
+    let lddw = ebpf::get_insn(text, 1);
+    assert_eq!(lddw.imm, 0x40);
+    assert_eq!(execute(&stripped), 42);
+
+    let analysis = Analysis::from_executable(&stripped).unwrap();
+    assert_eq!(DeadCodeAnalysis::new(&analysis).dead_bytes(), 0);
+}
+
+#[test]
+fn test_strip_dead_code_unsupported_version() {
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        ..Config::default()
+    };
+    let executable = assemble::<TestContextObject>(
+        "
+        call function_used
+        exit
+        function_used:
+        exit
+        function_unused:
+        exit",
+        Arc::new(BuiltinProgram::new_loader(config)),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    assert!(DeadCodeAnalysis::new(&analysis).functions[&3].dead);
+    assert_error!(
+        strip_dead_code(&executable),
+        "ElfError(UnsupportedSBPFVersion)"
+    );
+}
+
+#[test]
+fn test_strip_dead_code_unresolved_callx() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxdw r2, [r1+0]
+        callx r2
+        exit
+        function_unused:
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config::default())),
+    )
+    .unwrap();
+    assert_error!(strip_dead_code(&executable), "UnresolvedCallTarget(1)");
+}
+
+#[test]
+fn test_strip_dead_code_computed_callx_target() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ja +1
+        mov64 r0, 2
+        lddw r1, 0x100000038
+        add64 r1, 8
+        callx r1
+        exit
+        function_unused:
+        exit
+        function_pointer:
+        mov64 r0, 42
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config::default())),
+    )
+    .unwrap();
+    assert_eq!(execute(&executable), 42);
+    // The address in the lddw is not a callx target, so it stays and the sum misses the function
+    assert_error!(strip_dead_code(&executable), "UnrelocatableCallTarget(5)");
+}
@@ -11,9 +11,14 @@
 extern crate solana_sbpf;
 use solana_sbpf::program::SBPFVersion;
 use solana_sbpf::{
-    assembler::assemble, program::BuiltinProgram, static_analysis::Analysis, vm::Config,
+    assembler::assemble,
+    disassembler::disassemble_instruction,
+    ebpf,
+    program::{BuiltinProgram, FunctionRegistry},
+    static_analysis::Analysis,
+    vm::Config,
 };
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 use test_utils::TestContextObject;
 
 // Using a macro to keep actual line numbers in failure output
@@ -111,6 +116,44 @@ function_1:
     exit
 "
     );
+    let config = Config {
+        enable_symbol_and_section_labels: true,
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        ..Config::default()
+    };
+    disasm!(
+        "entrypoint:
+    call function_1
+
+function_1:
+    exit
+",
+        config
+    );
+}
+
+#[test]
+fn test_unresolved_call() {
+    // With static syscalls an internal call stays one even if its target is unknown
+    let loader = BuiltinProgram::<TestContextObject>::new_loader(Config::default());
+    let insn = ebpf::Insn {
+        ptr: 0,
+        opc: ebpf::CALL_IMM,
+        src: 1,
+        imm: 5,
+        ..ebpf::Insn::default()
+    };
+    assert_eq!(
+        disassemble_instruction(
+            &insn,
+            0,
+            &BTreeMap::new(),
+            &FunctionRegistry::default(),
+            &loader,
+            SBPFVersion::V3,
+        ),
+        "call 5"
+    );
 }
 
 // Example for InstructionType::Endian.
@@ -2,24 +2,40 @@
 
 use byteorder::{ByteOrder, LittleEndian};
 use solana_sbpf::{
+    aligned_memory::is_memory_aligned,
+    assembler::assemble,
     ebpf,
-    elf::{get_ro_region, ElfError, Executable, Section},
+    elf::{get_ro_region, ElfError, Executable, Section, WritableSection, WritableSections},
+    elf_linter::{lint_elf, Finding, Severity},
     elf_parser::{
-        consts::{ELFCLASS32, ELFCLASS64, ELFDATA2LSB, ELFDATA2MSB, ELFOSABI_NONE, EM_BPF, ET_REL},
-        types::{Elf64Ehdr, Elf64Phdr, Elf64Shdr},
+        consts::{
+            ELFCLASS32, ELFCLASS64, ELFDATA2LSB, ELFDATA2MSB, ELFOSABI_NONE, EM_BPF, EM_SBPF,
+            ET_DYN, ET_REL, R_BPF_64_32, R_BPF_64_64, R_BPF_64_ABS32, R_BPF_64_ABS64,
+            R_BPF_64_NODYLD32, SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE, SHN_UNDEF, SHT_NOBITS,
+            SHT_PROGBITS, SHT_REL, SHT_SYMTAB,
+        },
+        dwarf::SourceLocation,
+        types::{Elf64Ehdr, Elf64Phdr, Elf64Rel, Elf64Shdr, Elf64Sym},
         Elf64, ElfParserError, SECTION_NAME_LENGTH_MAXIMUM,
     },
+    elf_writer::write_elf,
+    error::{EbpfError, ProgramResult},
     memory_region::{AccessType, MemoryMapping},
     program::{BuiltinProgram, SBPFVersion},
-    vm::Config,
+    static_analysis::Analysis,
+    vm::{Config, DynamicAnalysis},
 };
-use std::{fs::File, io::Read, sync::Arc};
-use test_utils::{assert_error, syscalls, TestContextObject};
+use std::{fs::File, io::Read, mem, sync::Arc};
+use test_utils::{assert_error, create_vm, syscalls, TestContextObject};
 
 type ElfExecutable = Executable<TestContextObject>;
 
 fn loader() -> Arc<BuiltinProgram<TestContextObject>> {
-    let mut loader = BuiltinProgram::new_loader(Config::default());
+    loader_with_config(Config::default())
+}
+
+fn loader_with_config(config: Config) -> Arc<BuiltinProgram<TestContextObject>> {
+    let mut loader = BuiltinProgram::new_loader(config);
     loader
         .register_function("log", syscalls::SyscallString::vm)
         .unwrap();
@@ -639,20 +655,433 @@ fn test_borrowed_ro_region_initial_gap() {
         .unwrap_err();
 }
 
+fn run_with_writable_sections(
+    executable: &ElfExecutable,
+    writable_sections: &mut WritableSections,
+    interpreted: bool,
+) -> u64 {
+    let mut regions = executable.get_ro_regions();
+    regions.extend(writable_sections.get_regions());
+    let mut context_object = TestContextObject::new(16);
+    create_vm!(
+        vm,
+        executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    vm.memory_mapping = MemoryMapping::new(
+        regions,
+        executable.get_config(),
+        executable.get_sbpf_version(),
+    )
+    .unwrap();
+    let (_instruction_count, result) = vm.execute_program(executable, interpreted);
+    result.unwrap()
+}
+
 #[test]
-#[should_panic(expected = r#"validation failed: WritableSectionNotSupported(".data")"#)]
 fn test_writable_data_section() {
     let elf_bytes =
         std::fs::read("tests/elfs/data_section_sbpfv0.so").expect("failed to read elf file");
-    ElfExecutable::load(&elf_bytes, loader()).expect("validation failed");
+    // Writable sections are opt-in, as they have to be mapped by the caller
+    assert_eq!(
+        ElfExecutable::load(&elf_bytes, loader()).unwrap_err(),
+        ElfError::WritableSectionNotSupported(".data".to_string())
+    );
+    let config = Config {
+        enable_writable_sections: true,
+        ..Config::default()
+    };
+    #[allow(unused_mut)]
+    let mut executable = ElfExecutable::load(&elf_bytes, loader_with_config(config.clone()))
+        .expect("validation failed");
+    assert_eq!(
+        executable.get_writable_sections(),
+        &[WritableSection {
+            vm_addr: ebpf::MM_REGION_SIZE + 0x248,
+            initial_contents: 0x248..0x250,
+            len: 8,
+        }]
+    );
+    #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
+    executable.jit_compile().unwrap();
+    let mut writable_sections = WritableSections::new(&executable);
+    for interpreted in [true, false] {
+        if !interpreted && executable.get_compiled_program().is_none() {
+            continue;
+        }
+        assert_eq!(writable_sections.get_regions()[0].len, 8);
+        let val = writable_sections.get_regions()[0].host_addr as *const u64;
+        assert_eq!(unsafe { val.read() }, 42);
+        assert_eq!(
+            run_with_writable_sections(&executable, &mut writable_sections, interpreted),
+            0
+        );
+        assert_eq!(unsafe { val.read() }, 0);
+        writable_sections.reset(&executable);
+    }
+
+    // The aligned memory mapping has no room for writable sections next to the bytecode
+    let loader = Arc::new(BuiltinProgram::new_loader(Config {
+        aligned_memory_mapping: true,
+        ..config
+    }));
+    assert_eq!(
+        ElfExecutable::load(&elf_bytes, loader).unwrap_err(),
+        ElfError::WritableSectionNotSupported(".data".to_string())
+    );
 }
 
 #[test]
-#[should_panic(expected = r#"validation failed: WritableSectionNotSupported(".bss")"#)]
 fn test_bss_section() {
     let elf_bytes =
         std::fs::read("tests/elfs/bss_section_sbpfv0.so").expect("failed to read elf file");
-    ElfExecutable::load(&elf_bytes, loader()).expect("validation failed");
+    assert_eq!(
+        ElfExecutable::load(&elf_bytes, loader()).unwrap_err(),
+        ElfError::WritableSectionNotSupported(".bss".to_string())
+    );
+    #[allow(unused_mut)]
+    let mut executable = ElfExecutable::load(
+        &elf_bytes,
+        loader_with_config(Config {
+            enable_writable_sections: true,
+            ..Config::default()
+        }),
+    )
+    .expect("validation failed");
+    assert_eq!(
+        executable.get_writable_sections(),
+        &[WritableSection {
+            vm_addr: ebpf::MM_REGION_SIZE + 0x248,
+            initial_contents: 0..0,
+            len: 8,
+        }]
+    );
+    #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
+    executable.jit_compile().unwrap();
+    let mut writable_sections = WritableSections::new(&executable);
+    for interpreted in [true, false] {
+        if !interpreted && executable.get_compiled_program().is_none() {
+            continue;
+        }
+        let val = writable_sections.get_regions()[0].host_addr as *const u64;
+        assert_eq!(unsafe { val.read() }, 0);
+        assert_eq!(
+            run_with_writable_sections(&executable, &mut writable_sections, interpreted),
+            0
+        );
+        assert_eq!(unsafe { val.read() }, 42);
+        writable_sections.reset(&executable);
+    }
+}
+
+#[test]
+fn test_ro_sections_separately() {
+    let config = Config {
+        enable_writable_sections: true,
+        ..Config::default()
+    };
+    let elf_bytes = [0u8; 512];
+    let s1 = new_section(10, 10);
+    let s2 = new_section(20, 10);
+    let mut s3 = new_section(30, 10);
+    s3.sh_offset = 100;
+    let mut s4 = new_section(50, 10);
+    s4.sh_flags = SHF_ALLOC | SHF_WRITE;
+    s4.sh_type = SHT_NOBITS;
+    let sections: [(Option<&[u8]>, &Elf64Shdr); 4] = [
+        (Some(b".text"), &s1),
+        (Some(b".dynamic"), &s2),
+        (Some(b".rodata"), &s3),
+        (Some(b".bss"), &s4),
+    ];
+    assert_eq!(
+        ElfExecutable::parse_sections_separately(&config, sections, &elf_bytes),
+        Ok((
+            vec![
+                Section::Borrowed(ebpf::MM_REGION_SIZE as usize + 10, 10..20),
+                Section::Borrowed(ebpf::MM_REGION_SIZE as usize + 30, 100..110),
+            ],
+            vec![WritableSection {
+                vm_addr: ebpf::MM_REGION_SIZE + 50,
+                initial_contents: 0..0,
+                len: 10,
+            }]
+        ))
+    );
+
+    // overlapping sections are rejected
+    let s4 = new_section(35, 10);
+    let sections: [(Option<&[u8]>, &Elf64Shdr); 2] =
+        [(Some(b".rodata"), &s3), (Some(b".eh_frame"), &s4)];
+    assert_eq!(
+        ElfExecutable::parse_sections_separately(&config, sections, &elf_bytes),
+        Err(ElfError::ValueOutOfBounds)
+    );
+}
+
+#[test]
+fn test_load_shared() {
+    // Loaded without copying, the read-only data is borrowed from the shared bytes
+    let elf_bytes: Arc<[u8]> = std::fs::read("tests/elfs/rodata_section.so")
+        .unwrap()
+        .into();
+    // The data of an `Arc` follows its two counters, so this holds for 16 byte aligned allocations
+    assert!(
+        is_memory_aligned(elf_bytes.as_ptr() as usize, ebpf::HOST_ALIGN),
+        "the allocator returned bytes which are not aligned to HOST_ALIGN"
+    );
+    let executable = ElfExecutable::load_shared(elf_bytes.clone(), loader()).unwrap();
+    let copied_executable = ElfExecutable::load(&elf_bytes, loader()).unwrap();
+    assert_eq!(
+        executable.get_text_bytes(),
+        copied_executable.get_text_bytes()
+    );
+    assert_eq!(
+        executable.get_ro_section(),
+        copied_executable.get_ro_section()
+    );
+    assert!(elf_bytes
+        .as_ptr_range()
+        .contains(&executable.get_text_bytes().1.as_ptr()));
+    assert_eq!(executable.mem_size_shared(), elf_bytes.len());
+    assert!(executable.mem_size() + elf_bytes.len() <= copied_executable.mem_size());
+    assert_eq!(copied_executable.mem_size_shared(), 0);
+    let mut context_object = TestContextObject::new(3);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    assert_eq!(vm.execute_program(&executable, true).1.unwrap(), 42);
+
+    // Legacy ELFs are relocated, which requires a copy
+    let elf_bytes: Arc<[u8]> = std::fs::read("tests/elfs/reloc_64_64_sbpfv0.so")
+        .unwrap()
+        .into();
+    let executable = ElfExecutable::load_shared(elf_bytes.clone(), loader()).unwrap();
+    let copied_executable = ElfExecutable::load(&elf_bytes, loader()).unwrap();
+    assert_eq!(
+        executable.get_ro_section(),
+        copied_executable.get_ro_section()
+    );
+    assert_eq!(executable.mem_size(), copied_executable.mem_size());
+    assert_eq!(executable.mem_size_shared(), 0);
+}
+
+#[test]
+fn test_write_elf_round_trip() {
+    for enable_symbol_and_section_labels in [false, true] {
+        let mut loader = BuiltinProgram::new_loader(Config {
+            enable_symbol_and_section_labels,
+            enable_writable_sections: true,
+            ..Config::default()
+        });
+        loader
+            .register_function("log", syscalls::SyscallString::vm)
+            .unwrap();
+        let loader = Arc::new(loader);
+        for file in [
+            "bss_section_sbpfv0.so",
+            "data_section_sbpfv0.so",
+            "relative_call_sbpfv0.so",
+            "reloc_64_64_sbpfv0.so",
+            "reloc_64_relative_data_sbpfv0.so",
+            "rodata_section_sbpfv0.so",
+            "struct_func_pointer_sbpfv0.so",
+            "syscall_reloc_64_32_sbpfv0.so",
+            "relative_call.so",
+            "rodata_section.so",
+            "strict_header.so",
+            "syscall_static.so",
+        ] {
+            if enable_symbol_and_section_labels && file == "relative_call.so" {
+                // Has no symbol table, which the strict parser requires for labels
+                continue;
+            }
+            let elf_bytes = std::fs::read(format!("tests/elfs/{file}")).unwrap();
+            let executable = ElfExecutable::load(&elf_bytes, loader.clone()).unwrap();
+            let written = write_elf(&executable).unwrap();
+            let reloaded = ElfExecutable::load(&written, loader.clone()).unwrap();
+            assert_eq!(
+                reloaded.get_sbpf_version(),
+                executable.get_sbpf_version(),
+                "{file}"
+            );
+            assert_eq!(
+                reloaded.get_text_bytes(),
+                executable.get_text_bytes(),
+                "{file}"
+            );
+            assert_eq!(
+                reloaded.get_entrypoint_instruction_offset(),
+                executable.get_entrypoint_instruction_offset(),
+                "{file}"
+            );
+            assert_eq!(
+                reloaded.get_function_registry(),
+                executable.get_function_registry(),
+                "{file}"
+            );
+            assert_eq!(
+                reloaded.get_ro_region().vm_addr,
+                executable.get_ro_region().vm_addr,
+                "{file}"
+            );
+            assert_eq!(
+                reloaded.get_ro_section(),
+                executable.get_ro_section(),
+                "{file}"
+            );
+            assert_eq!(
+                reloaded
+                    .get_writable_sections()
+                    .iter()
+                    .map(|section| (section.vm_addr, section.len))
+                    .collect::<Vec<_>>(),
+                executable
+                    .get_writable_sections()
+                    .iter()
+                    .map(|section| (section.vm_addr, section.len))
+                    .collect::<Vec<_>>(),
+                "{file}"
+            );
+            // Writing is deterministic
+            assert_eq!(write_elf(&reloaded).unwrap(), written, "{file}");
+        }
+    }
+
+    // Assembled executables are moved up in the file, but keep their addresses
+    let loader = Arc::new(BuiltinProgram::new_loader(Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        ..Config::default()
+    }));
+    let executable = assemble::<TestContextObject>(
+        "
+        lddw r0, 0x100000008
+        exit",
+        loader.clone(),
+    )
+    .unwrap();
+    let written = write_elf(&executable).unwrap();
+    let reloaded = ElfExecutable::load(&written, loader).unwrap();
+    assert_eq!(reloaded.get_text_bytes(), executable.get_text_bytes());
+    assert_eq!(
+        reloaded.get_ro_region().vm_addr,
+        executable.get_ro_region().vm_addr
+    );
+    let mut context_object = TestContextObject::new(2);
+    create_vm!(
+        vm,
+        &reloaded,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&reloaded, true);
+    assert_eq!(result.unwrap(), 0x100000008);
+
+    // Syscalls have to be known to the loader to be written as relocations
+    let elf_bytes = std::fs::read("tests/elfs/syscall_reloc_64_32_sbpfv0.so").unwrap();
+    let loader = Arc::new(BuiltinProgram::new_loader(Config {
+        reject_broken_elfs: false,
+        ..Config::default()
+    }));
+    let executable = ElfExecutable::load(&elf_bytes, loader).unwrap();
+    assert_eq!(
+        write_elf(&executable),
+        Err(ElfError::UnresolvedSymbol(
+            format!("{:#x}", ebpf::hash_symbol_name(b"log")),
+            3,
+            0x138
+        ))
+    );
+}
+
+#[test]
+fn test_lint_elf() {
+    let loader = Arc::new(BuiltinProgram::new_loader(Config::default()));
+
+    // A canonical file has no findings
+    let mut elf_bytes =
+        std::fs::read("tests/elfs/strict_header.so").expect("failed to read elf file");
+    assert_eq!(lint_elf(&elf_bytes, loader.clone()), Vec::new());
+
+    // The loader stops at the first problem, the linter reports all of them
+    elf_bytes[mem::offset_of!(Elf64Ehdr, e_ident) + 7] = 1;
+    LittleEndian::write_u16(
+        &mut elf_bytes[mem::offset_of!(Elf64Ehdr, e_machine)..],
+        EM_SBPF,
+    );
+    let bytecode_header_offset = mem::size_of::<Elf64Ehdr>() + mem::size_of::<Elf64Phdr>();
+    LittleEndian::write_u64(
+        &mut elf_bytes[bytecode_header_offset + mem::offset_of!(Elf64Phdr, p_vaddr)..],
+        ebpf::MM_BYTECODE_START + 8,
+    );
+    assert_eq!(
+        ElfExecutable::load(&elf_bytes, loader.clone()).unwrap_err(),
+        ElfError::FailedToParse("invalid file header".to_string())
+    );
+    assert_eq!(
+        lint_elf(&elf_bytes, loader.clone()),
+        vec![
+            Finding {
+                severity: Severity::Error,
+                offset: 7,
+                message: "OS ABI is not ELFOSABI_NONE".to_string(),
+            },
+            Finding {
+                severity: Severity::Error,
+                offset: mem::offset_of!(Elf64Ehdr, e_machine),
+                message: "machine is not EM_BPF".to_string(),
+            },
+            Finding {
+                severity: Severity::Error,
+                offset: bytecode_header_offset,
+                message: "non-canonical bytecode program header (p_vaddr)".to_string(),
+            },
+        ]
+    );
+
+    // Legacy files are accepted, but would not be under newer versions
+    let elf_bytes =
+        std::fs::read("tests/elfs/syscall_reloc_64_32_sbpfv0.so").expect("failed to read elf file");
+    let findings = lint_elf(&elf_bytes, loader);
+    assert!(findings
+        .iter()
+        .all(|finding| finding.severity == Severity::Warning));
+    assert_eq!(
+        findings
+            .iter()
+            .map(|finding| finding.to_string())
+            .collect::<Vec<_>>(),
+        [
+            "warning at 0x12: machine is not EM_BPF, which SBPFv3 and later reject",
+            "warning at 0x40: non-canonical bytecode program header (p_flags, p_vaddr, p_paddr), which SBPFv3 and later reject",
+            "warning at 0x138: unresolved symbol log",
+            "warning at 0x260: 2 dynamic relocations, which SBPFv3 and later do not support",
+        ]
+    );
+
+    // Unresolved symbols are errors if the loader rejects them
+    let loader = Arc::new(BuiltinProgram::<TestContextObject>::new_loader(Config {
+        reject_broken_elfs: true,
+        ..Config::default()
+    }));
+    assert!(lint_elf(&elf_bytes, loader).contains(&Finding {
+        severity: Severity::Error,
+        offset: 0x138,
+        message: "unresolved symbol log".to_string(),
+    }));
 }
 
 #[test]
@@ -706,3 +1135,423 @@ fn test_long_section_name() {
         SECTION_NAME_LENGTH_MAXIMUM
     );
 }
+
+#[test]
+fn test_static_relocations() {
+    let object = std::fs::read("tests/elfs/reloc_static.o").expect("failed to read elf file");
+    let elf_bytes = link_relocatable(&object);
+    let elf = Elf64::parse(&elf_bytes).unwrap();
+    let relocation_types = elf
+        .static_relocation_tables()
+        .unwrap()
+        .iter()
+        .flat_map(|(_section_header, relocations)| relocations.iter())
+        .map(|relocation| relocation.r_type())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        relocation_types,
+        [
+            R_BPF_64_64,
+            R_BPF_64_32,
+            R_BPF_64_32,
+            R_BPF_64_ABS64,
+            R_BPF_64_NODYLD32,
+            R_BPF_64_ABS32,
+            R_BPF_64_ABS32,
+            R_BPF_64_ABS64,
+        ]
+    );
+    let data_section = elf
+        .section_header_table()
+        .iter()
+        .find(|section_header| elf.section_name(section_header.sh_name).unwrap() == b".data")
+        .unwrap()
+        .clone();
+
+    // Without the flag the static relocations are not applied
+    let executable = ElfExecutable::load(
+        &elf_bytes,
+        loader_with_config(Config {
+            enable_writable_sections: true,
+            ..Config::default()
+        }),
+    )
+    .unwrap();
+    let mut writable_sections = WritableSections::new(&executable);
+    let data = writable_sections.get_regions()[0].host_addr as *const u8;
+    let data = unsafe { std::slice::from_raw_parts(data, data_section.sh_size as usize) };
+    assert_eq!(LittleEndian::read_u64(&data[8..]), 0);
+
+    #[allow(unused_mut)]
+    let mut executable = ElfExecutable::load(
+        &elf_bytes,
+        loader_with_config(Config {
+            enable_writable_sections: true,
+            enable_upstream_relocations: true,
+            ..Config::default()
+        }),
+    )
+    .expect("validation failed");
+    #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
+    executable.jit_compile().unwrap();
+    let mut writable_sections = WritableSections::new(&executable);
+    let data = writable_sections.get_regions()[0].host_addr as *const u8;
+    let data = unsafe { std::slice::from_raw_parts(data, data_section.sh_size as usize) };
+    // The pointer to `value` was moved into the VM address space, the R_BPF_64_NODYLD32 is untouched
+    assert_eq!(
+        LittleEndian::read_u64(&data[8..]),
+        ebpf::MM_REGION_SIZE + data_section.sh_addr
+    );
+    assert_eq!(LittleEndian::read_u32(&data[16..]), 0);
+    for interpreted in [true, false] {
+        if !interpreted && executable.get_compiled_program().is_none() {
+            continue;
+        }
+        assert_eq!(
+            run_with_writable_sections(&executable, &mut writable_sections, interpreted),
+            49
+        );
+    }
+}
+
+/// Appends sections which are not loaded, like debug info, to an ELF whose last section is `.shstrtab`
+fn append_sections(elf_bytes: &[u8], sections: &[(&str, Vec<u8>)]) -> Vec<u8> {
+    let elf = Elf64::parse(elf_bytes).unwrap();
+    let file_header = elf.file_header();
+    let mut section_headers = elf.section_header_table().to_vec();
+    assert_eq!(
+        file_header.e_shstrndx as usize,
+        section_headers.len() - 1,
+        ".shstrtab has to be the last section"
+    );
+    let shstrtab_header = section_headers.pop().unwrap();
+    let mut shstrtab = elf_bytes[shstrtab_header.file_range().unwrap()].to_vec();
+    let mut output = elf_bytes[..file_header.e_shoff as usize].to_vec();
+    for (name, contents) in sections {
+        section_headers.push(Elf64Shdr {
+            sh_name: shstrtab.len() as u32,
+            sh_type: SHT_PROGBITS,
+            sh_flags: 0,
+            sh_addr: 0,
+            sh_offset: output.len() as u64,
+            sh_size: contents.len() as u64,
+            sh_link: 0,
+            sh_info: 0,
+            sh_addralign: 1,
+            sh_entsize: 0,
+        });
+        shstrtab.extend_from_slice(name.as_bytes());
+        shstrtab.push(0);
+        output.extend_from_slice(contents);
+    }
+    section_headers.push(Elf64Shdr {
+        sh_offset: output.len() as u64,
+        sh_size: shstrtab.len() as u64,
+        ..shstrtab_header
+    });
+    output.extend_from_slice(&shstrtab);
+    output.resize(output.len().next_multiple_of(8), 0);
+    let e_shoff = output.len() as u64;
+    for section_header in section_headers.iter() {
+        write_section_header(&mut output, section_header);
+    }
+    LittleEndian::write_u64(&mut output[0x28..], e_shoff);
+    LittleEndian::write_u16(&mut output[0x3c..], section_headers.len() as u16);
+    LittleEndian::write_u16(&mut output[0x3e..], (section_headers.len() - 1) as u16);
+    output
+}
+
+fn write_section_header(output: &mut Vec<u8>, section_header: &Elf64Shdr) {
+    let mut bytes = [0u8; mem::size_of::<Elf64Shdr>()];
+    LittleEndian::write_u32(&mut bytes[0x00..], section_header.sh_name);
+    LittleEndian::write_u32(&mut bytes[0x04..], section_header.sh_type);
+    LittleEndian::write_u64(&mut bytes[0x08..], section_header.sh_flags);
+    LittleEndian::write_u64(&mut bytes[0x10..], section_header.sh_addr);
+    LittleEndian::write_u64(&mut bytes[0x18..], section_header.sh_offset);
+    LittleEndian::write_u64(&mut bytes[0x20..], section_header.sh_size);
+    LittleEndian::write_u32(&mut bytes[0x28..], section_header.sh_link);
+    LittleEndian::write_u32(&mut bytes[0x2c..], section_header.sh_info);
+    LittleEndian::write_u64(&mut bytes[0x30..], section_header.sh_addralign);
+    LittleEndian::write_u64(&mut bytes[0x38..], section_header.sh_entsize);
+    output.extend_from_slice(&bytes);
+}
+
+fn read_section_header(bytes: &[u8]) -> Elf64Shdr {
+    Elf64Shdr {
+        sh_name: LittleEndian::read_u32(&bytes[0x00..]),
+        sh_type: LittleEndian::read_u32(&bytes[0x04..]),
+        sh_flags: LittleEndian::read_u64(&bytes[0x08..]),
+        sh_addr: LittleEndian::read_u64(&bytes[0x10..]),
+        sh_offset: LittleEndian::read_u64(&bytes[0x18..]),
+        sh_size: LittleEndian::read_u64(&bytes[0x20..]),
+        sh_link: LittleEndian::read_u32(&bytes[0x28..]),
+        sh_info: LittleEndian::read_u32(&bytes[0x2c..]),
+        sh_addralign: LittleEndian::read_u64(&bytes[0x30..]),
+        sh_entsize: LittleEndian::read_u64(&bytes[0x38..]),
+    }
+}
+
+/// Lays out a relocatable object like a linker would, but leaves the relocations unresolved
+///
+/// Every section is placed in the order of the section header table and the allocated ones
+/// get their file offset as address. Symbols and relocations are moved along with them.
+fn link_relocatable(object: &[u8]) -> Vec<u8> {
+    let e_shoff = LittleEndian::read_u64(&object[0x28..]) as usize;
+    let e_shnum = LittleEndian::read_u16(&object[0x3c..]) as usize;
+    let mut section_headers = (0..e_shnum)
+        .map(|index| read_section_header(&object[e_shoff + index * mem::size_of::<Elf64Shdr>()..]))
+        .collect::<Vec<_>>();
+    let mut output = object[..mem::size_of::<Elf64Ehdr>()].to_vec();
+    for section_header in section_headers.iter_mut().skip(1) {
+        let file_range = section_header.file_range().unwrap();
+        output.resize(
+            output
+                .len()
+                .next_multiple_of(section_header.sh_addralign.max(1) as usize),
+            0,
+        );
+        section_header.sh_offset = output.len() as u64;
+        if section_header.sh_flags & SHF_ALLOC != 0 {
+            section_header.sh_addr = section_header.sh_offset;
+        }
+        output.extend_from_slice(&object[file_range]);
+    }
+    for section_header in section_headers.iter() {
+        let range = section_header.file_range().unwrap_or_default();
+        match section_header.sh_type {
+            SHT_SYMTAB => {
+                for symbol in output[range].chunks_exact_mut(mem::size_of::<Elf64Sym>()) {
+                    let st_shndx = LittleEndian::read_u16(&symbol[0x06..]) as usize;
+                    if st_shndx != SHN_UNDEF as usize && st_shndx < section_headers.len() {
+                        let st_value = LittleEndian::read_u64(&symbol[0x08..]);
+                        LittleEndian::write_u64(
+                            &mut symbol[0x08..],
+                            st_value + section_headers[st_shndx].sh_addr,
+                        );
+                    }
+                }
+            }
+            SHT_REL => {
+                let target_address = section_headers[section_header.sh_info as usize].sh_addr;
+                for relocation in output[range].chunks_exact_mut(mem::size_of::<Elf64Rel>()) {
+                    let r_offset = LittleEndian::read_u64(relocation);
+                    LittleEndian::write_u64(relocation, r_offset + target_address);
+                }
+            }
+            _ => {}
+        }
+    }
+    let text_section = section_headers
+        .iter()
+        .find(|section_header| section_header.sh_flags & SHF_EXECINSTR != 0)
+        .unwrap();
+    LittleEndian::write_u16(&mut output[0x10..], ET_DYN);
+    LittleEndian::write_u64(&mut output[0x18..], text_section.sh_addr);
+    LittleEndian::write_u16(&mut output[0x36..], mem::size_of::<Elf64Phdr>() as u16);
+    output.resize(output.len().next_multiple_of(8), 0);
+    let e_shoff = output.len() as u64;
+    LittleEndian::write_u64(&mut output[0x28..], e_shoff);
+    for section_header in section_headers.iter() {
+        write_section_header(&mut output, section_header);
+    }
+    output
+}
+
+/// Prepends the 32-bit initial length of a unit
+fn dwarf_unit(contents: Vec<u8>) -> Vec<u8> {
+    let mut unit = (contents.len() as u32).to_le_bytes().to_vec();
+    unit.extend_from_slice(&contents);
+    unit
+}
+
+/// Hand encoded debug info of a text section with six instructions at `text_address`
+///
+/// - pc 0..=3 are in line 10 to 12 of `src/lib.rs` and the function `test::entrypoint`
+/// - pc 4..=5 are in line 20, column 5 of `/lib/foo.rs` and the function `foo`
+fn dwarf_sections(version: u16, text_address: u64) -> Vec<(&'static str, Vec<u8>)> {
+    const DW_FORM_UDATA: u8 = 0x0f;
+    const DW_FORM_LINE_STRP: u8 = 0x1f;
+    const DW_LNCT_PATH: u8 = 0x1;
+    const DW_LNCT_DIRECTORY_INDEX: u8 = 0x2;
+    let debug_line_str = b"/work\0/lib\0src/lib.rs\0foo.rs\0".to_vec();
+    let debug_str = b"_ZN4test10entrypoint17h0123456789abcdefE\0".to_vec();
+
+    let mut header = vec![
+        1, 1, 1, -5i8 as u8, 14, 13, 0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1,
+    ];
+    if version >= 5 {
+        header.extend_from_slice(&[1, DW_LNCT_PATH, DW_FORM_LINE_STRP, 2]);
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&6u32.to_le_bytes());
+        header.extend_from_slice(&[
+            2,
+            DW_LNCT_PATH,
+            DW_FORM_LINE_STRP,
+            DW_LNCT_DIRECTORY_INDEX,
+            DW_FORM_UDATA,
+            3,
+        ]);
+        for (path, directory) in [(11u32, 0u8), (11, 0), (22, 1)] {
+            header.extend_from_slice(&path.to_le_bytes());
+            header.push(directory);
+        }
+    } else {
+        header.extend_from_slice(b"/lib\0\0src/lib.rs\0\0\0\0foo.rs\0\x01\0\0\0");
+    }
+    let mut program = vec![0, 9, 2];
+    program.extend_from_slice(&text_address.to_le_bytes());
+    program.extend_from_slice(&[
+        0x03, 9,    // advance_line to 10
+        0x01, // copy: pc 0
+        131,  // special opcode, address + 8, line + 1: pc 1
+        131,  // pc 2
+        0x04, 2, // set_file foo.rs
+        0x05, 5, // set_column
+        0x03, 8,   // advance_line to 20
+        242, // special opcode, address + 16, line + 0: pc 4
+        0x02, 16, // advance_pc to pc 6
+        0, 1, 1, // end_sequence
+    ]);
+    let mut debug_line = version.to_le_bytes().to_vec();
+    if version >= 5 {
+        debug_line.extend_from_slice(&[8, 0]);
+    }
+    debug_line.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    debug_line.extend_from_slice(&header);
+    debug_line.extend_from_slice(&program);
+
+    let debug_abbrev = vec![
+        1, 0x11, 1, 0x03, 0x08, 0, 0, // compile unit: name
+        2, 0x2e, 0, 0x11, 0x01, 0x12, 0x06, 0x6e, 0x0e, 0, 0, // low_pc, high_pc, linkage_name
+        3, 0x2e, 0, 0x03, 0x08, 0x3c, 0x19, 0, 0, // name, declaration
+        4, 0x2e, 0, 0x47, 0x13, 0x11, 0x01, 0x12, 0x06, 0,
+        0, // specification, low_pc, high_pc
+        0,
+    ];
+    let mut debug_info = version.to_le_bytes().to_vec();
+    if version >= 5 {
+        debug_info.extend_from_slice(&[1, 8]);
+        debug_info.extend_from_slice(&0u32.to_le_bytes());
+    } else {
+        debug_info.extend_from_slice(&0u32.to_le_bytes());
+        debug_info.push(8);
+    }
+    debug_info.extend_from_slice(b"\x01test\0\x02");
+    debug_info.extend_from_slice(&text_address.to_le_bytes());
+    debug_info.extend_from_slice(&32u32.to_le_bytes());
+    debug_info.extend_from_slice(&0u32.to_le_bytes());
+    // Offsets of entries are relative to the start of the unit, including its initial length
+    let declaration_offset = debug_info.len() as u32 + 4;
+    debug_info.extend_from_slice(b"\x03foo\0\x04");
+    debug_info.extend_from_slice(&declaration_offset.to_le_bytes());
+    debug_info.extend_from_slice(&(text_address + 32).to_le_bytes());
+    debug_info.extend_from_slice(&16u32.to_le_bytes());
+    debug_info.push(0);
+
+    vec![
+        (".debug_abbrev", debug_abbrev),
+        (".debug_info", dwarf_unit(debug_info)),
+        (".debug_line", dwarf_unit(debug_line)),
+        (".debug_line_str", debug_line_str),
+        (".debug_str", debug_str),
+    ]
+}
+
+#[test]
+fn test_debug_info() {
+    for (sbpf_version, dwarf_version, file) in [
+        (SBPFVersion::V0, 4, "src/lib.rs"),
+        (SBPFVersion::V3, 5, "/work/src/lib.rs"),
+    ] {
+        let loader = |enable_symbol_and_section_labels| {
+            Arc::new(BuiltinProgram::new_loader(Config {
+                enabled_sbpf_versions: sbpf_version..=sbpf_version,
+                enable_symbol_and_section_labels,
+                enable_register_tracing: true,
+                ..Config::default()
+            }))
+        };
+        let executable = assemble::<TestContextObject>(
+            "
+            mov64 r1, -8
+            mov64 r0, 1
+            call function_foo
+            exit
+            function_foo:
+            ldxdw r0, [r1]
+            exit",
+            loader(true),
+        )
+        .unwrap();
+        let elf_bytes = write_elf(&executable).unwrap();
+        let (text_address, _text) = ElfExecutable::load(&elf_bytes, loader(true))
+            .unwrap()
+            .get_text_bytes();
+        // Legacy executables are relocated, but the debug info refers to the ELF addresses
+        let text_address = if sbpf_version.enable_lower_rodata_vaddr() {
+            text_address
+        } else {
+            text_address - ebpf::MM_BYTECODE_START
+        };
+        let elf_bytes = append_sections(&elf_bytes, &dwarf_sections(dwarf_version, text_address));
+
+        let executable = ElfExecutable::load(&elf_bytes, loader(false)).unwrap();
+        assert!(executable.get_debug_info().is_none());
+        let executable = ElfExecutable::load(&elf_bytes, loader(true)).unwrap();
+        let debug_info = executable.get_debug_info().unwrap();
+        let location = |line, column| SourceLocation { file, line, column };
+        let foo_location = SourceLocation {
+            file: "/lib/foo.rs",
+            line: 20,
+            column: 5,
+        };
+        assert_eq!(debug_info.source_location(0), Some(location(10, 0)));
+        assert_eq!(debug_info.source_location(1), Some(location(11, 0)));
+        assert_eq!(debug_info.source_location(3), Some(location(12, 0)));
+        assert_eq!(debug_info.source_location(5), Some(foo_location));
+        assert_eq!(debug_info.source_location(6), None);
+        assert_eq!(foo_location.to_string(), "/lib/foo.rs:20:5");
+        assert_eq!(debug_info.function_name(1), Some("test::entrypoint"));
+        assert_eq!(debug_info.function_name(5), Some("foo"));
+        assert_eq!(debug_info.function_name(6), None);
+
+        let analysis = Analysis::from_executable(&executable).unwrap();
+        let mut disassembly = Vec::new();
+        analysis.disassemble(&mut disassembly).unwrap();
+        let disassembly = String::from_utf8(disassembly).unwrap();
+        assert!(
+            disassembly.contains("    ; /lib/foo.rs:20:5\n"),
+            "{}",
+            disassembly
+        );
+
+        let mut context_object = TestContextObject::new(6);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            Vec::new(),
+            None
+        );
+        let (_instruction_count, result) = vm.execute_program(&executable, true);
+        assert!(
+            matches!(result, ProgramResult::Err(EbpfError::AccessViolation(..))),
+            "{:?}",
+            result
+        );
+        assert_eq!(vm.get_error_location(&executable), Some(foo_location));
+        let dynamic_analysis = DynamicAnalysis::new(&vm.register_trace, &analysis);
+        assert_eq!(
+            dynamic_analysis.source_lines.get(&(file.to_string(), 11)),
+            Some(&1)
+        );
+        assert_eq!(
+            dynamic_analysis
+                .source_lines
+                .get(&("/lib/foo.rs".to_string(), 20)),
+            Some(&1)
+        );
+    }
+}
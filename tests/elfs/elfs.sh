@@ -79,3 +79,7 @@ $LD_V3 -o syscall_static.so syscall_static.o
 # $LD_V1 -o callx_unaligned.so callx_unaligned.o
 
 rm *.o
+
+# Relocatable objects as emitted by upstream LLVM, which are not linked
+llvm-mc -triple bpfel -filetype=obj -o reloc_static.o reloc_static.s
+llvm-mc -triple bpfel -filetype=obj -o linux_object.o linux_object.s
@@ -0,0 +1,44 @@
+# Relocatable object in the layout of the Linux eBPF toolchain: the program
+# in a section named after its type, a subprogram in .text and a map
+# definition in the legacy maps section
+
+	.section socket,"ax",@progbits
+	.globl filter
+	.type filter,@function
+filter:
+	r6 = counters ll
+	r1 = 1
+	r2 = 2
+	r3 = 3
+	r4 = 4
+	r5 = 5
+	call 1
+	r7 = r0
+	r1 = 21
+	call double
+	r0 += r7
+	r0 += r6
+	exit
+
+	.text
+	.globl double
+	.type double,@function
+double:
+	r0 = r1
+	call add_to_self
+	exit
+add_to_self:
+	r0 += r0
+	exit
+
+	.section maps,"aw",@progbits
+	.globl counters
+	.p2align 2
+counters:
+	.long 2
+	.long 4
+	.long 8
+	.long 1
+
+	.section license,"aw",@progbits
+	.asciz "GPL"
@@ -0,0 +1,37 @@
+# Relocatable object as emitted by upstream toolchains, with static
+# relocations of the types R_BPF_64_64, R_BPF_64_32, R_BPF_64_ABS64,
+# R_BPF_64_ABS32 and R_BPF_64_NODYLD32
+
+	.text
+	.globl entrypoint
+	.type entrypoint,@function
+entrypoint:
+	r1 = data_ptr ll
+	r1 = *(u64 *)(r1 + 0)
+	r6 = *(u64 *)(r1 + 0)
+	call log_64
+	call helper
+	r0 += r6
+	exit
+
+	.globl helper
+	.type helper,@function
+helper:
+	r0 = 42
+	exit
+
+	.data
+	.p2align 3
+value:
+	.quad 7
+	.globl data_ptr
+data_ptr:
+	.quad value
+	.long value
+
+	.section .BTF.ext,"",@progbits
+	.long helper
+
+	.section .debug_info,"",@progbits
+	.long helper
+	.quad helper
@@ -21,6 +21,7 @@ use solana_sbpf::{
     assembler::assemble,
     declare_builtin_function, ebpf,
     elf::Executable,
+    elf_writer::write_elf,
     error::{EbpfError, ProgramResult},
     memory_region::{AccessType, MemoryMapping, MemoryRegion},
     program::{BuiltinProgram, FunctionRegistry, SBPFVersion},
@@ -1100,6 +1101,66 @@ fn test_err_ldxdw_nomem() {
     );
 }
 
+#[test]
+fn test_store_without_address_translation() {
+    let mut memory = [0u64; 2];
+    let config = Config {
+        enable_address_translation: false,
+        ..Config::default()
+    };
+    // Without address translation the vm addresses are host addresses
+    test_interpreter_and_jit_asm!(
+        &format!(
+            "
+            lddw r1, {:#x}
+            mov64 r2, 0x11
+            stxdw [r1+0], r2
+            stdw [r1+8], 0x22
+            ldxdw r0, [r1+0]
+            ldxdw r3, [r1+8]
+            lsh64 r0, 8
+            or64 r0, r3
+            exit",
+            memory.as_mut_ptr() as u64
+        ),
+        config,
+        [],
+        TestContextObject::new(9),
+        ProgramResult::Ok(0x1122),
+    );
+}
+
+#[test]
+fn test_err_atomic_unaligned() {
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::Linux..=SBPFVersion::Linux,
+        enable_atomic_instructions: true,
+        ..Config::default()
+    };
+    test_interpreter_and_jit_asm!(
+        "
+        mov32 r2, 1
+        lockadd32 [r1+2], r2
+        exit",
+        config.clone(),
+        [
+            0xaa, 0xbb, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, //
+        ],
+        TestContextObject::new(2),
+        ProgramResult::Err(EbpfError::UnalignedAtomicAccess(0x400000002)),
+    );
+    test_interpreter_and_jit_asm!(
+        "
+        mov32 r2, 1
+        lockadd64 [r10-4], r2
+        exit",
+        config,
+        [],
+        TestContextObject::new(2),
+        ProgramResult::Err(EbpfError::UnalignedAtomicAccess(0x200000ffc)),
+    );
+}
+
 #[test]
 fn test_ldxb_all() {
     test_interpreter_and_jit_asm!(
@@ -2679,6 +2740,57 @@ fn test_syscall_reloc_64_32() {
     );
 }
 
+#[test]
+fn test_write_elf() {
+    let mut config = Config {
+        enable_register_tracing: true,
+        ..Config::default()
+    };
+    for (sbpf_version, ro_section_vaddr) in [
+        (SBPFVersion::V0, ebpf::MM_BYTECODE_START),
+        (SBPFVersion::V3, ebpf::MM_RODATA_START),
+    ] {
+        config.enabled_sbpf_versions = sbpf_version..=sbpf_version;
+        let mut loader = BuiltinProgram::new_loader(config.clone());
+        loader
+            .register_function("bpf_syscall_u64", syscalls::SyscallU64::vm)
+            .unwrap();
+        let loader = Arc::new(loader);
+        // Reads the first opcode of the program through the readonly section
+        let mut executable = assemble(
+            &format!(
+                "
+                lddw r1, {ro_section_vaddr:#x}
+                ldxb r6, [r1]
+                syscall bpf_syscall_u64
+                call function_foo
+                add64 r0, r6
+                exit
+                function_foo:
+                mov64 r0, 2
+                exit"
+            ),
+            loader.clone(),
+        )
+        .unwrap();
+        test_interpreter_and_jit!(
+            executable,
+            [],
+            TestContextObject::new(8),
+            ProgramResult::Ok(ebpf::LD_DW_IMM as u64 + 2),
+        );
+
+        let elf = write_elf(&executable).unwrap();
+        let mut executable = Executable::<TestContextObject>::from_elf(&elf, loader).unwrap();
+        test_interpreter_and_jit!(
+            executable,
+            [],
+            TestContextObject::new(8),
+            ProgramResult::Ok(ebpf::LD_DW_IMM as u64 + 2),
+        );
+    }
+}
+
 #[test]
 fn test_reloc_64_64_sbpfv0() {
     // Tests the correctness of R_BPF_64_64 relocations. The program returns the
@@ -3,20 +3,27 @@
 
 use byteorder::{ByteOrder, LittleEndian};
 use solana_sbpf::{
+    assembler::assemble,
+    declare_builtin_function,
     disassembler::disassemble_instruction,
     ebpf,
     elf::Executable,
-    error::EbpfError,
+    error::{EbpfError, ProgramResult},
     jit::{
         MACHINE_CODE_PER_INSTRUCTION_METER_CHECKPOINT, MAX_EMPTY_PROGRAM_MACHINE_CODE_LENGTH,
         MAX_MACHINE_CODE_LENGTH_PER_INSTRUCTION,
     },
+    memory_region::{MemoryMapping, MemoryRegion},
     program::{BuiltinProgram, FunctionRegistry, SBPFVersion},
-    static_analysis::CfgNode,
+    static_analysis::{Analysis, CfgNode},
+    verifier::VerifiedFacts,
     vm::Config,
 };
-use std::{collections::BTreeMap, sync::Arc};
-use test_utils::{syscalls, TestContextObject};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+use test_utils::{assert_error, create_vm, syscalls, TestContextObject};
 
 fn create_mockup_executable(config: Config, program: &[u8]) -> Executable<TestContextObject> {
     let sbpf_version = *config.enabled_sbpf_versions.end();
@@ -45,12 +52,13 @@ fn test_code_length_estimate() {
         prog[pc * ebpf::INSN_SIZE] = ebpf::ADD64_IMM;
     }
 
-    let mut empty_program_machine_code_length_per_version = [0; 4];
-    for sbpf_version in [SBPFVersion::V0, SBPFVersion::V3] {
+    let mut empty_program_machine_code_length_per_version = [0; 6];
+    for sbpf_version in [SBPFVersion::V0, SBPFVersion::V3, SBPFVersion::Linux] {
         let empty_program_machine_code_length = {
             let config = Config {
                 noop_instruction_rate: 0,
                 enabled_sbpf_versions: sbpf_version..=sbpf_version,
+                enable_atomic_instructions: true,
                 ..Config::default()
             };
             let mut executable = create_mockup_executable(config, &prog[0..0]);
@@ -102,7 +110,7 @@ fn test_code_length_estimate() {
         },
     );
 
-    for sbpf_version in [SBPFVersion::V0, SBPFVersion::V3] {
+    for sbpf_version in [SBPFVersion::V0, SBPFVersion::V3, SBPFVersion::Linux] {
         println!("opcode;machine_code_length_per_instruction;assembly");
         let empty_program_machine_code_length =
             empty_program_machine_code_length_per_version[sbpf_version as usize];
@@ -121,7 +129,8 @@ fn test_code_length_estimate() {
                     opcode = 0x85;
                     (0x00, Some(0x91020CD0))
                 }
-                0xD4 | 0xDC => (0x88, Some(16)),
+                0xD4 | 0xD7 | 0xDC => (0x88, Some(16)),
+                0xC3 | 0xDB => (0x88, Some(ebpf::ATOMIC_CMPXCHG as u32)),
                 _ => (0x88, Some(0x11223344)),
             };
             for pc in 0..INSTRUCTION_COUNT {
@@ -135,6 +144,7 @@ fn test_code_length_estimate() {
             let config = Config {
                 noop_instruction_rate: 0,
                 enabled_sbpf_versions: sbpf_version..=sbpf_version,
+                enable_atomic_instructions: true,
                 ..Config::default()
             };
             let mut executable = create_mockup_executable(config, &prog);
@@ -173,3 +183,167 @@ fn test_code_length_estimate() {
         }
     }
 }
+
+fn execute(executable: &Executable<TestContextObject>) -> ProgramResult {
+    let mut context_object = TestContextObject::new(100);
+    create_vm!(
+        vm,
+        executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    vm.execute_program(executable, false).1
+}
+
+#[test]
+fn test_verified_facts() {
+    let source = "
+        mov64 r2, 3
+        stdw [r10-8], 3
+        ldxdw r3, [r10-8]
+        mov64 r0, 126
+        div64 r0, r2
+        lddw r4, 0x100000050
+        callx r4
+        add64 r0, r3
+        exit
+        function_foo:
+        stb [r10-1], 7
+        exit";
+    for assert_verified_facts in [false, true] {
+        let config = Config {
+            assert_verified_facts,
+            ..Config::default()
+        };
+        let mut executable =
+            assemble::<TestContextObject>(source, Arc::new(BuiltinProgram::new_loader(config)))
+                .unwrap();
+        let analysis = Analysis::from_executable(&executable).unwrap();
+        let facts = VerifiedFacts::new(&analysis);
+        assert_eq!(facts.nonzero_divisors, BTreeSet::from([4]));
+        assert_eq!(facts.in_frame_stack_accesses, BTreeSet::from([1, 2, 10]));
+        assert_eq!(facts.callx_targets, BTreeMap::from([(7, 10)]));
+
+        executable.jit_compile().unwrap();
+        assert_eq!(execute(&executable).unwrap(), 45);
+        unsafe { executable.jit_compile_with_verified_facts(&facts) }.unwrap();
+        assert_eq!(execute(&executable).unwrap(), 45);
+    }
+}
+
+declare_builtin_function!(
+    /// Moves the stack to a new buffer or removes it, for test_verified_facts_stack_region_changed()
+    SyscallMoveStack,
+    fn rust(
+        _context_object: &mut TestContextObject,
+        remove: u64,
+        _arg2: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let (index, region) = memory_mapping.find_region(ebpf::MM_STACK_START).unwrap();
+        let old_stack = unsafe {
+            std::slice::from_raw_parts_mut(region.host_addr as *mut u8, region.len as usize)
+        };
+        let new_stack = old_stack.to_vec().leak();
+        old_stack.fill(0);
+        if remove != 0 {
+            memory_mapping.remove_region(index)?;
+        } else {
+            memory_mapping.replace_region(
+                index,
+                MemoryRegion::new_writable(new_stack, ebpf::MM_STACK_START),
+            )?;
+        }
+        Ok(0)
+    }
+);
+
+#[test]
+fn test_verified_facts_stack_region_changed() {
+    let source = "
+        stdw [r10-8], 5
+        mov64 r1, 0
+        syscall move_stack
+        ldxdw r0, [r10-8]
+        stdw [r10-16], 6
+        ldxdw r2, [r10-16]
+        add64 r0, r2
+        exit";
+    let mut loader = BuiltinProgram::new_loader(Config::default());
+    loader
+        .register_function("move_stack", SyscallMoveStack::vm)
+        .unwrap();
+    let mut executable = assemble::<TestContextObject>(source, Arc::new(loader)).unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let facts = VerifiedFacts::new(&analysis);
+    assert_eq!(facts.in_frame_stack_accesses, BTreeSet::from([0, 3, 4, 5]));
+    unsafe { executable.jit_compile_with_verified_facts(&facts) }.unwrap();
+    assert_eq!(execute(&executable).unwrap(), 11);
+
+    let source = source.replace("mov64 r1, 0", "mov64 r1, 1");
+    let mut executable =
+        assemble::<TestContextObject>(&source, executable.get_loader().clone()).unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let facts = VerifiedFacts::new(&analysis);
+    unsafe { executable.jit_compile_with_verified_facts(&facts) }.unwrap();
+    assert_error!(execute(&executable), "AccessViolation");
+}
+
+#[test]
+fn test_verified_facts_assertions() {
+    let config = Config {
+        assert_verified_facts: true,
+        ..Config::default()
+    };
+    for (source, facts) in [
+        (
+            "
+            mov64 r0, 1
+            mov64 r2, 0
+            div64 r0, r2
+            exit",
+            VerifiedFacts {
+                nonzero_divisors: BTreeSet::from([2]),
+                ..VerifiedFacts::default()
+            },
+        ),
+        (
+            "
+            mov64 r0, 0
+            stxdw [r10+8], r0
+            exit",
+            VerifiedFacts {
+                in_frame_stack_accesses: BTreeSet::from([1]),
+                ..VerifiedFacts::default()
+            },
+        ),
+        (
+            "
+            lddw r4, 0x100000028
+            callx r4
+            exit
+            function_foo:
+            exit
+            function_bar:
+            exit",
+            VerifiedFacts {
+                callx_targets: BTreeMap::from([(2, 4)]),
+                ..VerifiedFacts::default()
+            },
+        ),
+    ] {
+        let mut executable = assemble::<TestContextObject>(
+            source,
+            Arc::new(BuiltinProgram::new_loader(config.clone())),
+        )
+        .unwrap();
+        unsafe { executable.jit_compile_with_verified_facts(&facts) }.unwrap();
+        assert_error!(execute(&executable), "VerifiedFactViolated");
+    }
+}
@@ -0,0 +1,110 @@
+extern crate solana_sbpf;
+extern crate test_utils;
+
+use solana_sbpf::{
+    ebpf,
+    elf::ElfError,
+    error::ProgramResult,
+    linux_object::{load_linux_object, LinuxImports},
+    memory_region::MemoryRegion,
+    program::{BuiltinProgram, SBPFVersion},
+    static_analysis::Analysis,
+    verifier::RequisiteVerifier,
+    vm::{Config, ContextObject},
+};
+use std::{collections::BTreeMap, sync::Arc};
+use test_utils::{
+    compare_register_trace, create_vm, syscalls, test_interpreter_and_jit, TestContextObject,
+};
+
+const MAP_ADDRESS: u64 = 0x5_0000_0000;
+
+fn loader(config: Config) -> Arc<BuiltinProgram<TestContextObject>> {
+    let mut loader = BuiltinProgram::new_loader(config);
+    loader
+        .register_function("bpf_gather_bytes", syscalls::SyscallGatherBytes::vm)
+        .unwrap();
+    Arc::new(loader)
+}
+
+fn imports() -> LinuxImports {
+    LinuxImports {
+        helpers: BTreeMap::from([(1, b"bpf_gather_bytes".to_vec())]),
+        maps: BTreeMap::from([(b"counters".to_vec(), MAP_ADDRESS)]),
+    }
+}
+
+#[test]
+fn test_load_linux_object() {
+    let object = std::fs::read("tests/elfs/linux_object.o").unwrap();
+    let loader = loader(Config {
+        enable_symbol_and_section_labels: true,
+        ..Config::default()
+    });
+    #[allow(unused_mut)]
+    let mut executable = load_linux_object(&object, b"socket", loader, &imports()).unwrap();
+    assert_eq!(executable.get_sbpf_version(), SBPFVersion::V0);
+    assert_eq!(executable.get_entrypoint_instruction_offset(), 0);
+    // The subprograms in .text follow the 14 instruction slots of the program
+    let function_registry = executable.get_function_registry();
+    assert_eq!(
+        function_registry.lookup_by_name(b"double"),
+        Some((&b"double"[..], 14))
+    );
+    assert_eq!(
+        function_registry.lookup_by_name(b"add_to_self"),
+        Some((&b"add_to_self"[..], 17))
+    );
+    test_interpreter_and_jit!(
+        executable,
+        [],
+        TestContextObject::new(18),
+        ProgramResult::Ok(0x0102030405 + 42 + MAP_ADDRESS),
+    );
+}
+
+#[test]
+fn test_load_linux_object_errors() {
+    let object = std::fs::read("tests/elfs/linux_object.o").unwrap();
+    let loader = loader(Config::default());
+
+    assert_eq!(
+        load_linux_object(&object, b"xdp", loader.clone(), &imports()).unwrap_err(),
+        ElfError::SectionNotFound("xdp".to_string()),
+    );
+
+    // The socket section starts at file offset 0x68
+    let mut missing = imports();
+    missing.maps.clear();
+    assert_eq!(
+        load_linux_object(&object, b"socket", loader.clone(), &missing).unwrap_err(),
+        ElfError::UnresolvedSymbol("counters".to_string(), 0, 0x68),
+    );
+    let mut missing = imports();
+    missing.helpers.clear();
+    assert_eq!(
+        load_linux_object(&object, b"socket", loader.clone(), &missing).unwrap_err(),
+        ElfError::UnresolvedSymbol("helper #1".to_string(), 7, 0x68 + 7 * 8),
+    );
+    let mut missing = imports();
+    missing.helpers.insert(1, b"bpf_trace_printf".to_vec());
+    assert_eq!(
+        load_linux_object(&object, b"socket", loader.clone(), &missing).unwrap_err(),
+        ElfError::UnresolvedSymbol("bpf_trace_printf".to_string(), 7, 0x68 + 7 * 8),
+    );
+
+    let shared_object = std::fs::read("tests/elfs/relative_call_sbpfv0.so").unwrap();
+    assert_eq!(
+        load_linux_object(&shared_object, b".text", loader, &imports()).unwrap_err(),
+        ElfError::WrongType,
+    );
+
+    let loader = self::loader(Config {
+        enabled_sbpf_versions: SBPFVersion::V3..=SBPFVersion::V3,
+        ..Config::default()
+    });
+    assert_eq!(
+        load_linux_object(&object, b"socket", loader, &imports()).unwrap_err(),
+        ElfError::UnsupportedSBPFVersion,
+    );
+}
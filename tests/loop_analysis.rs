@@ -0,0 +1,235 @@
+extern crate solana_sbpf;
+extern crate test_utils;
+
+use solana_sbpf::{
+    assembler::assemble,
+    loop_analysis::{LoopAnalysis, LoopBound},
+    program::BuiltinProgram,
+    static_analysis::Analysis,
+    vm::Config,
+};
+use std::{collections::BTreeSet, sync::Arc};
+use test_utils::{syscalls, TestContextObject};
+
+fn loop_analysis(src: &str) -> LoopAnalysis {
+    let mut loader = BuiltinProgram::new_loader(Config::default());
+    loader
+        .register_function("bpf_syscall_u64", syscalls::SyscallU64::vm)
+        .unwrap();
+    let executable = assemble::<TestContextObject>(src, Arc::new(loader)).unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    LoopAnalysis::new(&analysis)
+}
+
+#[test]
+fn test_constant_bounds() {
+    let loop_analysis = loop_analysis(
+        "
+        mov64 r0, 0
+        mov64 r2, 0
+        add64 r0, r2
+        add64 r2, 1
+        jlt r2, 10, -3
+        mov64 r3, 100
+        sub64 r3, 3
+        jsgt r3, 0, -2
+        mov64 r5, 0
+        add32 r5, 4
+        jlt32 r5, 100, -2
+        mov64 r4, -1
+        add64 r4, 2
+        jle r4, -1, -2
+        exit",
+    );
+    let bounds = loop_analysis
+        .loops
+        .iter()
+        .map(|(header, r#loop)| (*header, r#loop.bound))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        bounds,
+        vec![
+            (
+                2,
+                LoopBound::Constant {
+                    exit: 4,
+                    register: 2,
+                    step: 1,
+                    bound: 10,
+                }
+            ),
+            (
+                6,
+                LoopBound::Constant {
+                    exit: 7,
+                    register: 3,
+                    step: -3,
+                    bound: 0,
+                }
+            ),
+            (
+                9,
+                LoopBound::Constant {
+                    exit: 10,
+                    register: 5,
+                    step: 4,
+                    bound: 100,
+                }
+            ),
+            // Would have to wrap around to leave the loop
+            (12, LoopBound::Unknown),
+        ]
+    );
+    assert_eq!(loop_analysis.loops[&2].blocks, BTreeSet::from([2]));
+    assert_eq!(
+        loop_analysis
+            .unbounded_loops()
+            .map(|(header, _loop)| *header)
+            .collect::<Vec<_>>(),
+        vec![12]
+    );
+}
+
+#[test]
+fn test_register_bounds() {
+    let loop_analysis = loop_analysis(
+        "
+        mov64 r2, 8
+        mov64 r3, 0
+        ja +1
+        add64 r3, 2
+        jlt r3, r2, -2
+        mov64 r4, 0
+        add64 r4, 1
+        add64 r2, 1
+        jgt r2, r4, -3
+        exit",
+    );
+    assert_eq!(
+        loop_analysis.loops[&4].bound,
+        LoopBound::Constant {
+            exit: 4,
+            register: 3,
+            step: 2,
+            bound: 8,
+        }
+    );
+    // Entered through the jump to the comparison
+    assert_eq!(loop_analysis.loops[&4].blocks, BTreeSet::from([3, 4]));
+    // Neither side of the comparison is invariant
+    assert_eq!(loop_analysis.loops[&6].bound, LoopBound::Unknown);
+}
+
+#[test]
+fn test_syscall_bounds() {
+    let loop_analysis = loop_analysis(
+        "
+        syscall bpf_syscall_u64
+        mov64 r6, r0
+        mov64 r7, 0
+        add64 r7, 1
+        jlt r7, r6, -2
+        syscall bpf_syscall_u64
+        jeq r0, 0, -2
+        ldxdw r8, [r1+0]
+        jne r8, 0, -2
+        exit",
+    );
+    assert_eq!(
+        loop_analysis.loops[&3].bound,
+        LoopBound::Syscall {
+            exit: 4,
+            syscall: 0,
+        }
+    );
+    assert_eq!(
+        loop_analysis.loops[&5].bound,
+        LoopBound::Syscall {
+            exit: 6,
+            syscall: 5,
+        }
+    );
+    assert_eq!(loop_analysis.loops[&7].bound, LoopBound::Unknown);
+    assert_eq!(
+        loop_analysis.to_string(),
+        "3 loops: 0 bounded by a constant, 2 by a syscall, 1 unknown\n\
+        entrypoint: loop at insn #3 bounded by the syscall at insn #0 (insn #4)\n\
+        entrypoint: loop at insn #5 bounded by the syscall at insn #5 (insn #6)\n\
+        entrypoint: loop at insn #7 unknown\n"
+    );
+}
+
+#[test]
+fn test_nested_loops() {
+    let loop_analysis = loop_analysis(
+        "
+        mov64 r2, 0
+        mov64 r3, 0
+        add64 r3, 1
+        jlt r3, 4, -2
+        add64 r2, 1
+        jlt r2, 4, -5
+        exit",
+    );
+    assert_eq!(loop_analysis.loops.len(), 2);
+    assert_eq!(loop_analysis.loops[&1].blocks, BTreeSet::from([1, 2, 4]));
+    assert_eq!(
+        loop_analysis.loops[&1].bound,
+        LoopBound::Constant {
+            exit: 5,
+            register: 2,
+            step: 1,
+            bound: 4,
+        }
+    );
+    assert_eq!(loop_analysis.loops[&2].blocks, BTreeSet::from([2]));
+    assert_eq!(
+        loop_analysis.loops[&2].bound,
+        LoopBound::Constant {
+            exit: 3,
+            register: 3,
+            step: 1,
+            bound: 4,
+        }
+    );
+}
+
+#[test]
+fn test_nested_loop_steps_outer_induction_variable() {
+    let loop_analysis = loop_analysis(
+        "
+        mov64 r2, 0
+        mov64 r3, 0
+        add64 r3, 1
+        add64 r2, 1
+        jlt r3, 4, -3
+        jlt r2, 8, -5
+        exit",
+    );
+    assert_eq!(loop_analysis.loops.len(), 2);
+    assert_eq!(loop_analysis.loops[&1].bound, LoopBound::Unknown);
+    assert!(matches!(
+        loop_analysis.loops[&2].bound,
+        LoopBound::Constant { register: 3, .. }
+    ));
+}
+
+#[test]
+fn test_loop_with_two_entries() {
+    // Without a single header the cycles are not split into nested loops
+    let loop_analysis = loop_analysis(
+        "
+        mov64 r2, 0
+        mov64 r3, 0
+        jeq r1, 0, +2
+        add64 r2, 1
+        jgt r2, 8, +2
+        add64 r3, 1
+        jlt r3, 4, -4
+        jlt r2, 4, -5
+        exit",
+    );
+    assert_eq!(loop_analysis.loops.len(), 1);
+    assert_eq!(loop_analysis.loops[&3].blocks, BTreeSet::from([3, 5, 7]));
+    assert_eq!(loop_analysis.loops[&3].bound, LoopBound::Unknown);
+}
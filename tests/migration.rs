@@ -0,0 +1,442 @@
+extern crate solana_sbpf;
+extern crate test_utils;
+
+use solana_sbpf::{
+    assembler::assemble,
+    ebpf,
+    elf::Executable,
+    memory_region::MemoryRegion,
+    migration::{
+        analyze_migration, migrate_to_v3, MigrationError, MigrationIssueKind, MigrationReport,
+    },
+    program::{BuiltinProgram, FunctionRegistry, SBPFVersion},
+    vm::Config,
+};
+use std::sync::Arc;
+use test_utils::{create_vm, syscalls, TestContextObject};
+
+fn loader(sbpf_version: SBPFVersion) -> Arc<BuiltinProgram<TestContextObject>> {
+    let mut loader = BuiltinProgram::new_loader(Config {
+        enabled_sbpf_versions: sbpf_version..=sbpf_version,
+        ..Config::default()
+    });
+    loader
+        .register_function("bpf_gather_bytes", syscalls::SyscallGatherBytes::vm)
+        .unwrap();
+    loader
+        .register_function("log", syscalls::SyscallString::vm)
+        .unwrap();
+    Arc::new(loader)
+}
+
+fn run(executable: &Executable<TestContextObject>) -> u64 {
+    run_with_input(executable, &mut [])
+}
+
+fn run_with_input(executable: &Executable<TestContextObject>, mem: &mut [u8]) -> u64 {
+    let mut context_object = TestContextObject::new(100);
+    let mem_region = MemoryRegion::new_writable(mem, ebpf::MM_INPUT_START);
+    create_vm!(
+        vm,
+        executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![mem_region],
+        None
+    );
+    vm.execute_program(executable, true).1.unwrap()
+}
+
+fn load_elf(path: &str) -> Executable<TestContextObject> {
+    let elf_bytes = std::fs::read(path).expect("failed to read elf file");
+    Executable::load(&elf_bytes, loader(SBPFVersion::V0)).unwrap()
+}
+
+/// Builds the text section of the target version by applying all suggested replacements
+fn migrate(
+    executable: &Executable<TestContextObject>,
+    report: &MigrationReport,
+) -> Executable<TestContextObject> {
+    let (_program_vm_addr, text) = executable.get_text_bytes();
+    let mut replacements = report
+        .issues
+        .iter()
+        .filter_map(|issue| issue.replacement.as_ref())
+        .peekable();
+    let mut migrated = Vec::new();
+    let mut pc = 0;
+    while pc * ebpf::INSN_SIZE < text.len() {
+        if let Some(replacement) = replacements.next_if(|replacement| replacement.pcs.start == pc) {
+            for insn in replacement.instructions.iter() {
+                migrated.extend_from_slice(&insn.to_array());
+            }
+            pc = replacement.pcs.end;
+        } else {
+            migrated.extend_from_slice(&text[pc * ebpf::INSN_SIZE..(pc + 1) * ebpf::INSN_SIZE]);
+            pc += 1;
+        }
+    }
+    assert!(replacements.next().is_none());
+    Executable::new_from_text_bytes(
+        &migrated,
+        loader(report.target_version),
+        report.target_version,
+        FunctionRegistry::default(),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_migration_to_v2() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r1, -7
+        mov32 r2, 3
+        mul32 r1, r2
+        lddw r3, 0x1122334455667788
+        neg64 r3
+        xor64 r1, r3
+        add32 r2, -5
+        xor64 r1, r2
+        sub64 r1, 11
+        stxdw [r10-8], r1
+        ldxh r0, [r10-8]
+        le16 r1
+        add64 r0, r1
+        exit",
+        loader(SBPFVersion::V0),
+    )
+    .unwrap();
+    let report = analyze_migration(&executable, SBPFVersion::V2).unwrap();
+    assert_eq!(
+        report
+            .issues
+            .iter()
+            .map(|issue| (issue.pc, issue.kind, issue.replacement.is_some()))
+            .collect::<Vec<_>>(),
+        vec![
+            (2, MigrationIssueKind::ChangedSemantics, true),
+            (3, MigrationIssueKind::Rejected, true),
+            (5, MigrationIssueKind::ChangedSemantics, true),
+            (7, MigrationIssueKind::ChangedSemantics, true),
+            (9, MigrationIssueKind::ChangedSemantics, true),
+            (10, MigrationIssueKind::Rejected, true),
+            (11, MigrationIssueKind::Rejected, true),
+            (12, MigrationIssueKind::Rejected, true),
+        ]
+    );
+    assert_eq!(
+        report.issues[0].to_string(),
+        "changed at pc 2: `mul32 r1, r2`: V2 decodes it as `ldxb r1, [r2+0x0]`, use `lmul32 r1, r2; mov32 r1, r1`"
+    );
+    assert_eq!(
+        report.issues[1].to_string(),
+        "rejected at pc 3: `lddw r3, 0x1122334455667788`: V2 rejects it, unknown eBPF opcode 0x18 (insn #3), use `mov32 r3, 1432778632; hor64 r3, 287454020`"
+    );
+    assert_eq!(
+        report.issues[3].to_string(),
+        "changed at pc 7: `add32 r2, -5`: the result is zero extended, use `add32 r2, -5; mov32 r2, r2`"
+    );
+    assert_eq!(
+        report.issues[4].to_string(),
+        "changed at pc 9: `sub64 r1, 11`: the register is subtracted from the immediate, use `add64 r1, -11`"
+    );
+    assert_eq!(
+        format!("{report}").lines().next(),
+        Some("V0 -> V2: 4 rejected, 4 changed, 0 relocations")
+    );
+
+    let expected = run(&executable);
+    let migrated = migrate(&executable, &report);
+    assert!(analyze_migration(&migrated, SBPFVersion::V2)
+        .unwrap()
+        .issues
+        .is_empty());
+    assert_eq!(run(&migrated), expected);
+}
+
+#[test]
+fn test_migration_to_v3() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r1, 1
+        mov64 r2, 2
+        mov64 r3, 3
+        mov64 r4, 4
+        mov64 r5, 5
+        syscall bpf_gather_bytes
+        mov64 r6, r0
+        call function_foo
+        add64 r6, r0
+        mov64 r8, 1
+        lsh64 r8, 32
+        add64 r8, 136
+        callx r8
+        add64 r0, r6
+        exit
+        function_foo:
+        mov64 r0, 6
+        exit
+        function_bar:
+        mov64 r0, 7
+        exit",
+        loader(SBPFVersion::V0),
+    )
+    .unwrap();
+    let report = analyze_migration(&executable, SBPFVersion::V3).unwrap();
+    assert_eq!(
+        report
+            .issues
+            .iter()
+            .map(|issue| issue.to_string())
+            .collect::<Vec<_>>(),
+        [
+            "relocation at pc 5: `syscall bpf_gather_bytes`: syscall bpf_gather_bytes is resolved by a relocation, which V3 does not support, use `syscall bpf_gather_bytes`",
+            "changed at pc 7: `call function_foo`: the immediate is a relative offset instead of a function key, use `call function_foo`",
+            "changed at pc 12: `callx r8`: V3 decodes it as `callx r0`, use `callx r8`",
+        ]
+    );
+    let call = &report.issues[1].replacement.as_ref().unwrap().instructions[0];
+    assert_eq!((call.src, call.imm), (1, 7));
+
+    let expected = run(&executable);
+    assert_eq!(expected, 0x0102030405 + 6 + 7);
+    assert_eq!(run(&migrate(&executable, &report)), expected);
+}
+
+#[test]
+fn test_migration_from_v2_to_v3() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov32 r1, 0x55667788
+        hor64 r1, 0x11223344
+        mov64 r2, 1000
+        udiv32 r2, 7
+        sub64 r2, 5
+        mov64 r3, -9
+        mov32 r4, r3
+        add32 r4, 3
+        lmul64 r4, r2
+        xor64 r1, r4
+        stxdw [r10-8], r1
+        ldxw r0, [r10-8]
+        add64 r0, r2
+        exit",
+        loader(SBPFVersion::V2),
+    )
+    .unwrap();
+    let report = analyze_migration(&executable, SBPFVersion::V3).unwrap();
+    assert_eq!(
+        report
+            .issues
+            .iter()
+            .map(|issue| (issue.pc, issue.kind))
+            .collect::<Vec<_>>(),
+        vec![
+            (1, MigrationIssueKind::Rejected),
+            (3, MigrationIssueKind::ChangedSemantics),
+            (4, MigrationIssueKind::ChangedSemantics),
+            (6, MigrationIssueKind::ChangedSemantics),
+            (7, MigrationIssueKind::ChangedSemantics),
+            (8, MigrationIssueKind::Rejected),
+            (10, MigrationIssueKind::Rejected),
+            (11, MigrationIssueKind::Rejected),
+        ]
+    );
+    assert_eq!(
+        report.issues[0].replacement.as_ref().unwrap().assembly,
+        "lddw r1, 0x1122334455667788"
+    );
+    assert_eq!(
+        report.issues[1].reason,
+        "V3 decodes it as `jset32 r2, 7, [invalid]`"
+    );
+
+    let expected = run(&executable);
+    assert_eq!(run(&migrate(&executable, &report)), expected);
+}
+
+#[test]
+fn test_migration_stack_frames() {
+    let executable = assemble::<TestContextObject>(
+        "
+        call function_foo
+        exit
+        function_foo:
+        add64 r10, -64
+        mov64 r0, 0
+        exit",
+        loader(SBPFVersion::V1),
+    )
+    .unwrap();
+    let report = analyze_migration(&executable, SBPFVersion::V3).unwrap();
+    assert_eq!(
+        report
+            .issues
+            .iter()
+            .map(|issue| issue.to_string())
+            .collect::<Vec<_>>(),
+        [
+            "changed at pc 0: `call function_foo`: the immediate is a relative offset instead of a function key and the callee gets a new stack frame instead of bumping r10, use `call function_foo`",
+            "rejected at pc 2: `add64 r10, -64`: V3 rejects it, cannot write into register r10 (insn #2)",
+        ]
+    );
+    assert!(analyze_migration(&executable, SBPFVersion::V2)
+        .unwrap()
+        .issues
+        .is_empty());
+}
+
+#[test]
+fn test_migration_relocations() {
+    let elf_bytes =
+        std::fs::read("tests/elfs/reloc_64_64_sbpfv0.so").expect("failed to read elf file");
+    let executable = Executable::load(&elf_bytes, loader(SBPFVersion::V0)).unwrap();
+    let report = analyze_migration(&executable, SBPFVersion::V3).unwrap();
+    assert_eq!(
+        report
+            .issues
+            .iter()
+            .map(|issue| issue.to_string())
+            .collect::<Vec<_>>(),
+        [format!(
+            "relocation at pc 0: `lddw r0, {:#x}`: {:#x} points into the program image, which V3 maps elsewhere without relocations",
+            ebpf::MM_BYTECODE_START + 0x120,
+            ebpf::MM_BYTECODE_START + 0x120,
+        )]
+    );
+    assert!(analyze_migration(&executable, SBPFVersion::V1)
+        .unwrap()
+        .issues
+        .is_empty());
+}
+
+#[test]
+fn test_migrate_to_v3() {
+    for (source_version, expanded, source) in [
+        (
+            SBPFVersion::V0,
+            0,
+            "
+            mov64 r1, 5
+            mov64 r0, 0
+            loop:
+            jeq r1, 0, done
+            lddw r2, 0x300000000
+            add64 r0, r2
+            neg64 r0
+            sub64 r1, 1
+            ja loop
+            done:
+            call function_foo
+            exit
+            function_foo:
+            mov32 r3, -1
+            add32 r0, r3
+            exit",
+        ),
+        (
+            SBPFVersion::V2,
+            3,
+            "
+            mov64 r1, 5
+            mov64 r0, 0
+            loop:
+            jeq r1, 0, done
+            mov32 r2, 0
+            hor64 r2, 3
+            add64 r0, r2
+            add32 r4, -3
+            lmul64 r0, -1
+            add64 r1, -1
+            ja loop
+            done:
+            xor64 r0, r4
+            call function_foo
+            exit
+            function_foo:
+            mov32 r3, -1
+            add32 r0, r3
+            mov32 r0, r0
+            exit",
+        ),
+    ] {
+        let executable = assemble::<TestContextObject>(source, loader(source_version)).unwrap();
+        let migrated = migrate_to_v3(&executable, loader(SBPFVersion::V3)).unwrap();
+        assert_eq!(migrated.get_sbpf_version(), SBPFVersion::V3);
+        assert_eq!(
+            migrated.get_text_bytes().1.len() - executable.get_text_bytes().1.len(),
+            expanded * ebpf::INSN_SIZE,
+        );
+        assert!(analyze_migration(&migrated, SBPFVersion::V3)
+            .unwrap()
+            .issues
+            .is_empty());
+        assert_eq!(run(&migrated), run(&executable));
+    }
+}
+
+#[test]
+fn test_migrate_elf_to_v3() {
+    for (path, mut mem, expected) in [
+        ("tests/elfs/relative_call_sbpfv0.so", vec![1], 3),
+        ("tests/elfs/rodata_section_sbpfv0.so", vec![], 42),
+        (
+            "tests/elfs/struct_func_pointer_sbpfv0.so",
+            vec![],
+            0x0102030405060708,
+        ),
+        ("tests/elfs/syscall_reloc_64_32_sbpfv0.so", vec![], 0),
+    ] {
+        let executable = load_elf(path);
+        let migrated = migrate_to_v3(&executable, loader(SBPFVersion::V3)).unwrap();
+        assert_eq!(run_with_input(&executable, &mut mem.clone()), expected);
+        assert_eq!(run_with_input(&migrated, &mut mem), expected);
+    }
+
+    // The program returns the address of its entrypoint, which moves from the ELF offset into the
+    // program image to the start of the bytecode region
+    let executable = load_elf("tests/elfs/reloc_64_64_sbpfv0.so");
+    let migrated = migrate_to_v3(&executable, loader(SBPFVersion::V3)).unwrap();
+    assert_eq!(run(&executable), ebpf::MM_BYTECODE_START + 0x120);
+    assert_eq!(run(&migrated), ebpf::MM_BYTECODE_START);
+}
+
+#[test]
+fn test_migrate_to_v3_errors() {
+    let executable = assemble::<TestContextObject>(
+        "
+        add64 r10, -64
+        exit",
+        loader(SBPFVersion::V1),
+    )
+    .unwrap();
+    assert!(matches!(
+        migrate_to_v3(&executable, loader(SBPFVersion::V3)),
+        Err(MigrationError::Untranslatable(0, _))
+    ));
+
+    // Without relocations it is unknown whether a value in the program image is an address
+    for address in [0x100000004, 0x100000008] {
+        let executable = assemble::<TestContextObject>(
+            &format!(
+                "
+                lddw r1, {address:#x}
+                exit"
+            ),
+            loader(SBPFVersion::V0),
+        )
+        .unwrap();
+        assert!(matches!(
+            migrate_to_v3(&executable, loader(SBPFVersion::V3)),
+            Err(MigrationError::UnrelocatableAddress(0, value)) if value == address
+        ));
+    }
+
+    let executable = assemble::<TestContextObject>("exit", loader(SBPFVersion::V3)).unwrap();
+    assert!(matches!(
+        migrate_to_v3(&executable, loader(SBPFVersion::V3)),
+        Err(MigrationError::UnsupportedVersion(SBPFVersion::V3))
+    ));
+}
@@ -0,0 +1,144 @@
+extern crate solana_sbpf;
+extern crate test_utils;
+
+use solana_sbpf::{
+    assembler::assemble,
+    program::{BuiltinProgram, SBPFVersion},
+    stack_analysis::{StackAnalysis, StackLimitViolation},
+    static_analysis::Analysis,
+    vm::Config,
+};
+use std::{collections::BTreeSet, sync::Arc};
+use test_utils::TestContextObject;
+
+fn analyze(src: &str, config: Config) -> StackAnalysis {
+    let executable =
+        assemble::<TestContextObject>(src, Arc::new(BuiltinProgram::new_loader(config))).unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    StackAnalysis::new(&analysis)
+}
+
+#[test]
+fn test_call_depth_and_frame_usage() {
+    let src = "
+        entrypoint:
+        stxdw [r10-8], r1
+        call function_foo
+        exit
+        function_foo:
+        mov64 r1, r10
+        add64 r1, -24
+        stxw [r1+0], r1
+        call function_bar
+        exit
+        function_bar:
+        mov64 r0, 0
+        exit";
+    let stack_analysis = analyze(src, Config::default());
+    assert!(stack_analysis.violations.is_empty());
+    assert!(stack_analysis.recursion.is_empty());
+    let entrypoint = &stack_analysis.functions[&0];
+    assert_eq!(entrypoint.frame_usage, 8);
+    assert_eq!(entrypoint.max_call_depth, Some(3));
+    assert_eq!(entrypoint.callees.keys().copied().collect::<Vec<_>>(), [3]);
+    let foo = &stack_analysis.functions[&3];
+    assert_eq!(foo.name, "function_foo");
+    assert_eq!(foo.frame_usage, 24);
+    assert_eq!(foo.max_call_depth, Some(2));
+    assert_eq!(stack_analysis.functions[&8].max_call_depth, Some(1));
+
+    let stack_analysis = analyze(
+        src,
+        Config {
+            max_call_depth: 2,
+            ..Config::default()
+        },
+    );
+    assert_eq!(
+        stack_analysis.violations,
+        [StackLimitViolation::CallDepthExceeded { depth: 3, limit: 2 }]
+    );
+}
+
+#[test]
+fn test_frame_too_large() {
+    let stack_analysis = analyze(
+        "
+        stdw [r10-5000], 0
+        exit",
+        Config::default(),
+    );
+    assert_eq!(
+        stack_analysis.violations,
+        [StackLimitViolation::FrameTooLarge {
+            function: 0,
+            usage: 5000,
+            frame_size: 4096,
+        }]
+    );
+}
+
+#[test]
+fn test_recursion() {
+    let stack_analysis = analyze(
+        "
+        entrypoint:
+        call function_foo
+        call function_baz
+        exit
+        function_foo:
+        call function_bar
+        exit
+        function_bar:
+        call function_foo
+        exit
+        function_baz:
+        call function_baz
+        exit",
+        Config::default(),
+    );
+    assert_eq!(
+        stack_analysis.recursion,
+        [BTreeSet::from([3, 5]), BTreeSet::from([7])]
+    );
+    assert_eq!(stack_analysis.functions[&0].max_call_depth, None);
+    assert_eq!(stack_analysis.functions[&0].max_stack_usage, None);
+    assert!(stack_analysis.violations.is_empty());
+}
+
+#[test]
+fn test_manual_stack_frame_bump() {
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V2..=SBPFVersion::V2,
+        max_call_depth: 1,
+        stack_frame_size: 64,
+        ..Config::default()
+    };
+    let stack_analysis = analyze(
+        "
+        entrypoint:
+        add64 r10, -64
+        stxdw [r10+0], r1
+        call function_foo
+        exit
+        function_foo:
+        stxdw [r10-32], r1
+        callx r1
+        exit",
+        config,
+    );
+    let entrypoint = &stack_analysis.functions[&0];
+    assert_eq!(entrypoint.frame_usage, 64);
+    assert_eq!(entrypoint.max_stack_usage, Some(96));
+    assert!(stack_analysis.functions[&4].indirect_calls);
+    assert_eq!(
+        stack_analysis.violations,
+        [
+            StackLimitViolation::CallDepthExceeded { depth: 2, limit: 1 },
+            StackLimitViolation::StackSizeExceeded {
+                usage: 96,
+                stack_size: 64,
+            },
+        ]
+    );
+}
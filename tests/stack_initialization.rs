@@ -0,0 +1,163 @@
+extern crate solana_sbpf;
+extern crate test_utils;
+
+use solana_sbpf::{
+    assembler::assemble,
+    program::{BuiltinProgram, SBPFVersion},
+    stack_initialization::StackInitializationAnalysis,
+    static_analysis::Analysis,
+    vm::Config,
+};
+use std::{collections::BTreeSet, sync::Arc};
+use test_utils::{create_vm, syscalls, TestContextObject};
+
+const SOURCE: &str = "
+    mov64 r2, 1
+    stxdw [r10-8], r2
+    ldxdw r0, [r10-8]
+    ldxw r3, [r10-12]
+    jeq r1, 0, +1
+    stw [r10-16], 7
+    ldxw r4, [r10-16]
+    mov64 r5, r10
+    add64 r5, -24
+    stxb [r5+0], r2
+    ldxh r6, [r10-24]
+    mov64 r1, r10
+    add64 r1, -40
+    call function_foo
+    ldxdw r7, [r10-40]
+    mov64 r1, r10
+    add64 r1, -48
+    syscall bpf_syscall_u64
+    ldxdw r8, [r10-48]
+    exit
+    function_foo:
+    stdw [r1+0], 3
+    ldxdw r0, [r10-8]
+    exit";
+
+#[test]
+fn test_uninitialized_stack_reads() {
+    let mut loader = BuiltinProgram::new_loader(Config {
+        enable_stack_shadow: true,
+        ..Config::default()
+    });
+    loader
+        .register_writing_function("bpf_syscall_u64", syscalls::SyscallU64::vm)
+        .unwrap();
+    let executable = assemble::<TestContextObject>(SOURCE, Arc::new(loader)).unwrap();
+
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let stack_initialization = StackInitializationAnalysis::new(&analysis);
+    assert_eq!(
+        stack_initialization.uninitialized_reads,
+        BTreeSet::from([(3, -12), (6, -16), (10, -24), (21, -8)])
+    );
+    assert_eq!(
+        stack_initialization.to_string(),
+        "4 reads of uninitialized stack bytes\n\
+        entrypoint: insn #3 reads frame offset -12\n\
+        entrypoint: insn #6 reads frame offset -16\n\
+        entrypoint: insn #10 reads frame offset -24\n\
+        function_foo: insn #21 reads frame offset -8\n"
+    );
+
+    let mut context_object = TestContextObject::new(100);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    vm.execute_program(&executable, true).1.unwrap();
+    // The input pointer is not null, so the store to offset -16 does happen
+    assert_eq!(
+        vm.uninitialized_stack_reads,
+        BTreeSet::from([(3, -12), (10, -24), (21, -8)])
+    );
+}
+
+#[test]
+fn test_uninitialized_stack_reads_after_read_only_syscall() {
+    let mut loader = BuiltinProgram::new_loader(Config {
+        enable_stack_shadow: true,
+        ..Config::default()
+    });
+    loader
+        .register_function("bpf_syscall_u64", syscalls::SyscallU64::vm)
+        .unwrap();
+    let executable = assemble::<TestContextObject>(SOURCE, Arc::new(loader)).unwrap();
+
+    // The syscall is not registered as writing, so the memory passed to it stays uninitialized
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let stack_initialization = StackInitializationAnalysis::new(&analysis);
+    assert_eq!(
+        stack_initialization.uninitialized_reads,
+        BTreeSet::from([(3, -12), (6, -16), (10, -24), (18, -48), (21, -8)])
+    );
+
+    let mut context_object = TestContextObject::new(100);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    vm.execute_program(&executable, true).1.unwrap();
+    assert_eq!(
+        vm.uninitialized_stack_reads,
+        BTreeSet::from([(3, -12), (10, -24), (18, -48), (21, -8)])
+    );
+}
+
+#[test]
+fn test_uninitialized_stack_reads_per_call() {
+    // Only the first call initializes the frame before reading it
+    let source = "
+        mov64 r1, 1
+        call function_foo
+        mov64 r1, 0
+        call function_foo
+        exit
+        function_foo:
+        jeq r1, 0, +1
+        stdw [r10-8], 1
+        ldxdw r0, [r10-8]
+        exit";
+    for sbpf_version in [SBPFVersion::V0, SBPFVersion::V3] {
+        let loader = BuiltinProgram::new_loader(Config {
+            enabled_sbpf_versions: sbpf_version..=sbpf_version,
+            enable_stack_shadow: true,
+            ..Config::default()
+        });
+        let executable = assemble::<TestContextObject>(source, Arc::new(loader)).unwrap();
+
+        let analysis = Analysis::from_executable(&executable).unwrap();
+        let stack_initialization = StackInitializationAnalysis::new(&analysis);
+        assert_eq!(
+            stack_initialization.uninitialized_reads,
+            BTreeSet::from([(7, -8)])
+        );
+
+        let mut context_object = TestContextObject::new(100);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            Vec::new(),
+            None
+        );
+        vm.execute_program(&executable, true).1.unwrap();
+        // The second call does not see the store of the first one
+        assert_eq!(vm.uninitialized_stack_reads, BTreeSet::from([(7, -8)]));
+    }
+}
@@ -0,0 +1,145 @@
+extern crate solana_sbpf;
+extern crate test_utils;
+
+use solana_sbpf::{
+    assembler::assemble,
+    ebpf,
+    elf::Executable,
+    program::{BuiltinProgram, SBPFVersion},
+    stack_analysis::StackAnalysis,
+    static_analysis::Analysis,
+    vm::Config,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+use test_utils::TestContextObject;
+
+#[test]
+fn test_resolve_callx_from_immediates() {
+    let executable = assemble::<TestContextObject>(
+        "
+        lddw r1, 0x100000038
+        jeq r2, 0, +1
+        add64 r1, 8
+        callx r1
+        callx r1
+        exit
+        function_foo:
+        mov64 r0, 1
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config::default())),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    // The first call clobbers r1 for the second one
+    assert_eq!(
+        analysis.indirect_call_targets,
+        BTreeMap::from([(4, Some(BTreeSet::from([7, 8]))), (5, None)])
+    );
+    assert_eq!(analysis.functions[&8].1, "function_8");
+    assert!(analysis.cfg_nodes.contains_key(&8));
+    assert_eq!(analysis.callable_functions(), BTreeSet::from([0, 7, 8]));
+
+    let stack_analysis = StackAnalysis::new(&analysis);
+    let entrypoint = &stack_analysis.functions[&0];
+    assert_eq!(
+        entrypoint.callees.keys().copied().collect::<Vec<_>>(),
+        [7, 8]
+    );
+    assert!(entrypoint.indirect_calls);
+    assert_eq!(entrypoint.max_call_depth, Some(2));
+}
+
+#[test]
+fn test_resolve_callx_from_hor64() {
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V2..=SBPFVersion::V2,
+        ..Config::default()
+    };
+    let executable = assemble::<TestContextObject>(
+        "
+        mov32 r1, 0x28
+        hor64 r1, 1
+        mov64 r2, r1
+        callx r2
+        exit
+        function_foo:
+        exit",
+        Arc::new(BuiltinProgram::new_loader(config)),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    assert_eq!(
+        analysis.indirect_call_targets,
+        BTreeMap::from([(3, Some(BTreeSet::from([5])))])
+    );
+}
+
+#[test]
+fn test_resolve_callx_from_rodata() {
+    // Turn the program which loads a constant from .rodata into one which calls through it
+    let mut elf_bytes = std::fs::read("tests/elfs/rodata_section.so").unwrap();
+    let config = Config::default();
+    let loader = Arc::new(BuiltinProgram::new_loader(config.clone()));
+    let original = Executable::<TestContextObject>::load(&elf_bytes, loader.clone()).unwrap();
+    let (_text_vaddr, original_text) = original.get_text_bytes();
+    let original_text = original_text.to_vec();
+    let original_rodata = original.get_ro_section().to_vec();
+    let patched = assemble::<TestContextObject>(
+        "
+        mov64 r1, 0
+        ldxdw r1, [r1+0]
+        callx r1
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enabled_sbpf_versions: original.get_sbpf_version()..=original.get_sbpf_version(),
+            ..config
+        })),
+    )
+    .unwrap();
+    let (_text_vaddr, patched_text) = patched.get_text_bytes();
+    assert_eq!(patched_text.len(), original_text.len());
+    let function_pointer = ebpf::MM_BYTECODE_START + 3 * ebpf::INSN_SIZE as u64;
+    for (original, patched) in [
+        (original_text.as_slice(), patched_text),
+        (
+            original_rodata.as_slice(),
+            function_pointer.to_le_bytes().as_slice(),
+        ),
+    ] {
+        let offset = elf_bytes
+            .windows(original.len())
+            .position(|window| window == original)
+            .unwrap();
+        elf_bytes[offset..offset + patched.len()].copy_from_slice(patched);
+    }
+
+    let executable = Executable::<TestContextObject>::load(&elf_bytes, loader).unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    assert_eq!(
+        analysis.indirect_call_targets,
+        BTreeMap::from([(2, Some(BTreeSet::from([3])))])
+    );
+}
+
+#[test]
+fn test_fallthrough_out_of_function_entry() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r0, 0
+        add64 r0, 1
+        jlt r0, 10, -2
+        exit
+        function_foo:
+        mov64 r0, 1
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config::default())),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    // The block which starts the function falls through into the loop
+    assert_eq!(analysis.cfg_nodes[&0].destinations, vec![1]);
+    assert_eq!(analysis.cfg_nodes[&1].sources, vec![0, 1]);
+}
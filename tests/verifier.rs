@@ -27,12 +27,22 @@ extern crate thiserror;
 use solana_sbpf::{
     assembler::assemble,
     ebpf,
-    elf::Executable,
+    elf::{ElfError, Executable},
+    elf_linter::Severity,
+    error::EbpfError,
+    memory_region::AddressSpaceLayout,
     program::{BuiltinFunction, BuiltinProgram, FunctionRegistry, SBPFVersion},
-    verifier::{RequisiteVerifier, Verifier, VerifierError},
+    static_analysis::Analysis,
+    verifier::{
+        diagnose, AbstractInterpretationVerifier, PolicyVerifier, RequisiteVerifier, SyscallPolicy,
+        VerifiedFacts, Verifier, VerifierError, VerifierPolicy,
+    },
     vm::{Config, ContextObject},
 };
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
 use test_utils::{assert_error, create_vm, syscalls, TestContextObject};
 use thiserror::Error;
 
@@ -495,3 +505,645 @@ fn exit() {
     let result = executable.verify::<RequisiteVerifier>();
     assert!(result.is_ok());
 }
+
+#[test]
+fn test_verifier_err_invalid_alu_offset() {
+    for (prog, insn_ptr) in [
+        // sdiv64 r0, r1 with off = 2
+        (&[0x3f, 0x10, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00], 0),
+        // movsx64 r0, r1 with off = 7
+        (&[0xbf, 0x10, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00], 0),
+        // movsx32 r0, r1 with off = 32
+        (&[0xbc, 0x10, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00], 0),
+    ] {
+        let executable = Executable::<TestContextObject>::from_text_bytes(
+            prog,
+            Arc::new(BuiltinProgram::new_mock()),
+            SBPFVersion::Linux,
+            FunctionRegistry::default(),
+        )
+        .unwrap();
+        let result = executable.verify::<RequisiteVerifier>();
+        assert_error!(result, "VerifierError(InvalidOffset({}))", insn_ptr);
+    }
+}
+
+#[test]
+fn test_verifier_err_unsupported_atomic_operation() {
+    for prog in [
+        // atomic and of [r1] with r2, not supported
+        &[0xdb, 0x21, 0x00, 0x00, 0x50, 0x00, 0x00, 0x00],
+        // xchg32 [r1], r2 without BPF_FETCH
+        &[0xc3, 0x21, 0x00, 0x00, 0xe0, 0x00, 0x00, 0x00],
+    ] {
+        let executable = Executable::<TestContextObject>::from_text_bytes(
+            prog,
+            Arc::new(BuiltinProgram::new_loader(Config {
+                enable_atomic_instructions: true,
+                ..Config::default()
+            })),
+            SBPFVersion::Linux,
+            FunctionRegistry::default(),
+        )
+        .unwrap();
+        let result = executable.verify::<RequisiteVerifier>();
+        assert_error!(result, "VerifierError(UnsupportedAtomicOperation(0))");
+    }
+}
+
+#[test]
+fn test_verifier_linux_instructions() {
+    let instructions = [
+        (ebpf::LD_B_SX_REG, "ldxsb r0, [r1+0]"),
+        (ebpf::LD_H_SX_REG, "ldxsh r0, [r1+0]"),
+        (ebpf::LD_W_SX_REG, "ldxsw r0, [r1+0]"),
+        (ebpf::BSWAP, "bswap64 r0"),
+    ];
+    for (opc, instruction) in instructions {
+        let assembly = format!("{instruction}\nexit");
+        let executable = assemble::<TestContextObject>(
+            &assembly,
+            Arc::new(BuiltinProgram::new_loader(Config {
+                enabled_sbpf_versions: SBPFVersion::Linux..=SBPFVersion::Linux,
+                ..Config::default()
+            })),
+        )
+        .unwrap();
+        assert!(executable.verify::<RequisiteVerifier>().is_ok());
+
+        // The same encoding is unknown to the SBPF versions
+        let executable = Executable::<TestContextObject>::from_text_bytes(
+            executable.get_text_bytes().1,
+            Arc::new(BuiltinProgram::new_mock()),
+            SBPFVersion::V4,
+            FunctionRegistry::default(),
+        )
+        .unwrap();
+        let result = executable.verify::<RequisiteVerifier>();
+        assert_error!(result, "VerifierError(UnknownOpCode({}, {}))", opc, 0);
+    }
+}
+
+#[test]
+fn test_verifier_atomic_instructions() {
+    let instructions = [
+        (ebpf::ST_W_ATOMIC, "lockadd32 [r1+0], r2"),
+        (ebpf::ST_DW_ATOMIC, "cmpxchg64 [r10-8], r2"),
+    ];
+    for (opc, instruction) in instructions {
+        let assembly = format!("{instruction}\nexit");
+        let executable = assemble::<TestContextObject>(
+            &assembly,
+            Arc::new(BuiltinProgram::new_loader(Config {
+                enabled_sbpf_versions: SBPFVersion::Linux..=SBPFVersion::Linux,
+                enable_atomic_instructions: true,
+                ..Config::default()
+            })),
+        )
+        .unwrap();
+        assert!(executable.verify::<RequisiteVerifier>().is_ok());
+
+        // Without the config flag or in the SBPF versions the encoding is unknown
+        for (sbpf_version, enable_atomic_instructions) in
+            [(SBPFVersion::Linux, false), (SBPFVersion::V4, true)]
+        {
+            let executable = Executable::<TestContextObject>::from_text_bytes(
+                executable.get_text_bytes().1,
+                Arc::new(BuiltinProgram::new_loader(Config {
+                    enabled_sbpf_versions: sbpf_version..=sbpf_version,
+                    enable_atomic_instructions,
+                    ..Config::default()
+                })),
+                sbpf_version,
+                FunctionRegistry::default(),
+            )
+            .unwrap();
+            let result = executable.verify::<RequisiteVerifier>();
+            assert_error!(result, "VerifierError(UnknownOpCode({}, {}))", opc, 0);
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "CannotWriteR10(0)")]
+fn test_verifier_err_linux_write_r10() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r10, r1
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enabled_sbpf_versions: SBPFVersion::Linux..=SBPFVersion::Linux,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    executable.verify::<RequisiteVerifier>().unwrap();
+}
+
+fn verify_abstract_interpretation(src: &str) -> Result<(), solana_sbpf::error::EbpfError> {
+    let executable =
+        assemble::<TestContextObject>(src, Arc::new(BuiltinProgram::new_mock())).unwrap();
+    executable.verify::<AbstractInterpretationVerifier>()
+}
+
+#[test]
+fn test_abstract_interpretation_success() {
+    verify_abstract_interpretation(
+        "
+        ldxdw r2, [r1+0]
+        stxdw [r10-8], r2
+        ldxdw r0, [r10-8]
+        exit",
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_abstract_interpretation_err_uninitialized_register() {
+    let result = verify_abstract_interpretation(
+        "
+        mov64 r0, r3
+        exit",
+    );
+    assert_error!(result, "VerifierError(UninitializedRegister(0, 3))");
+
+    let result = verify_abstract_interpretation(
+        "
+        jeq r1, 0, +1
+        mov64 r0, 1
+        exit",
+    );
+    assert_error!(result, "VerifierError(UninitializedRegister(2, 0))");
+}
+
+#[test]
+fn test_abstract_interpretation_err_out_of_bounds() {
+    let result = verify_abstract_interpretation(
+        "
+        mov64 r0, 0
+        stxdw [r10+8], r0
+        exit",
+    );
+    assert_error!(result, "VerifierError(OutOfBoundsAccess(1, \"stack\"))");
+
+    let result = verify_abstract_interpretation(
+        "
+        ldxb r0, [r1-1]
+        exit",
+    );
+    assert_error!(result, "VerifierError(OutOfBoundsAccess(0, \"input\"))");
+}
+
+#[test]
+fn test_abstract_interpretation_divisor() {
+    let result = verify_abstract_interpretation(
+        "
+        ldxdw r2, [r1+0]
+        mov64 r0, 100
+        div64 r0, r2
+        exit",
+    );
+    assert_error!(result, "VerifierError(DivisorMayBeZero(2))");
+
+    verify_abstract_interpretation(
+        "
+        ldxdw r2, [r1+0]
+        mov64 r0, 100
+        jeq r2, 0, +1
+        div64 r0, r2
+        exit",
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_abstract_interpretation_loop() {
+    verify_abstract_interpretation(
+        "
+        mov64 r0, 0
+        mov64 r2, 0
+        add64 r2, 1
+        stxb [r10-1], r2
+        add64 r0, r2
+        jlt r2, 1000, -4
+        exit",
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_abstract_interpretation_err_invalid_executable() {
+    // The syscall collides with the implicitly registered entrypoint
+    let mut loader = BuiltinProgram::<TestContextObject>::new_mock();
+    loader
+        .register_function("entrypoint", syscalls::SyscallString::vm)
+        .unwrap();
+    let result = AbstractInterpretationVerifier::verify(
+        &[0x95, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        loader.get_config(),
+        SBPFVersion::V0,
+        &FunctionRegistry::default(),
+        loader.get_function_registry(),
+    );
+    assert!(matches!(
+        result,
+        Err(VerifierError::InvalidExecutable(
+            ElfError::SymbolHashCollision(_)
+        ))
+    ));
+}
+
+#[test]
+fn test_diagnose_collects_all_violations() {
+    let prog = &[
+        0x37, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // div64 r0, 0
+        0xb7, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // mov64 r0, 0
+        0x05, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, // ja +16
+        0x95, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // exit
+    ];
+    let executable = Executable::<TestContextObject>::from_text_bytes(
+        prog,
+        Arc::new(BuiltinProgram::new_mock()),
+        SBPFVersion::V3,
+        FunctionRegistry::default(),
+    )
+    .unwrap();
+    let diagnostics = diagnose(&executable);
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0].severity, Severity::Error);
+    assert_eq!(diagnostics[0].pc, 0);
+    assert_eq!(diagnostics[0].instruction, "div64 r0, 0");
+    assert_eq!(diagnostics[0].function.as_deref(), Some("entrypoint"));
+    assert_eq!(diagnostics[0].error, VerifierError::DivisionByZero(0));
+    assert_eq!(diagnostics[1].pc, 2);
+    assert_eq!(diagnostics[1].error, VerifierError::JumpOutOfCode(19, 2));
+}
+
+#[test]
+fn test_diagnose_warnings() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r0, r3
+        ldxb r0, [r10+0]
+        exit",
+        Arc::new(BuiltinProgram::new_mock()),
+    )
+    .unwrap();
+    executable.verify::<RequisiteVerifier>().unwrap();
+    let diagnostics = diagnose(&executable);
+    assert_eq!(
+        diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.severity, diagnostic.pc, &diagnostic.error))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                Severity::Warning,
+                0,
+                &VerifierError::UninitializedRegister(0, 3)
+            ),
+            (
+                Severity::Warning,
+                1,
+                &VerifierError::OutOfBoundsAccess(1, "stack")
+            ),
+        ]
+    );
+    assert_eq!(
+        diagnostics[1].to_string(),
+        "warning: out of bounds access to stack (insn #1)\n    ldxb r0, [r10+0x0] in entrypoint"
+    );
+}
+
+fn verify_policy(src: &str, verifier_policy: VerifierPolicy) -> Result<(), EbpfError> {
+    verify_policy_with_config(
+        src,
+        Config {
+            verifier_policy,
+            ..Config::default()
+        },
+    )
+}
+
+fn verify_policy_with_config(src: &str, config: Config) -> Result<(), EbpfError> {
+    let mut loader = BuiltinProgram::new_loader(config);
+    loader
+        .register_function("bpf_syscall_u64", syscalls::SyscallU64::vm)
+        .unwrap();
+    loader
+        .register_function("bpf_syscall_string", syscalls::SyscallString::vm)
+        .unwrap();
+    let executable = assemble::<TestContextObject>(src, Arc::new(loader)).unwrap();
+    executable.verify::<PolicyVerifier>()
+}
+
+#[test]
+fn test_policy_syscalls() {
+    let src = "
+        syscall bpf_syscall_u64
+        syscall bpf_syscall_string
+        exit";
+    let u64_hash = ebpf::hash_symbol_name(b"bpf_syscall_u64");
+    let string_hash = ebpf::hash_symbol_name(b"bpf_syscall_string");
+    verify_policy(src, VerifierPolicy::default()).unwrap();
+    verify_policy(
+        src,
+        VerifierPolicy {
+            syscalls: SyscallPolicy::Allow(BTreeSet::from([u64_hash, string_hash])),
+            ..VerifierPolicy::default()
+        },
+    )
+    .unwrap();
+    let result = verify_policy(
+        src,
+        VerifierPolicy {
+            syscalls: SyscallPolicy::Allow(BTreeSet::from([u64_hash])),
+            ..VerifierPolicy::default()
+        },
+    );
+    assert_error!(
+        result,
+        "VerifierError(SyscallNotAllowed(1, {}))",
+        string_hash
+    );
+    let result = verify_policy(
+        src,
+        VerifierPolicy {
+            syscalls: SyscallPolicy::Deny(BTreeSet::from([u64_hash])),
+            ..VerifierPolicy::default()
+        },
+    );
+    assert_error!(result, "VerifierError(SyscallNotAllowed(0, {}))", u64_hash);
+}
+
+#[test]
+fn test_policy_forbidden_opcodes() {
+    let result = verify_policy(
+        "
+        mov64 r0, 0
+        callx r1
+        exit",
+        VerifierPolicy {
+            forbidden_opcodes: BTreeSet::from([ebpf::CALL_REG]),
+            ..VerifierPolicy::default()
+        },
+    );
+    assert_error!(
+        result,
+        "VerifierError(ForbiddenOpcode({}, 1))",
+        ebpf::CALL_REG
+    );
+}
+
+#[test]
+fn test_policy_limits() {
+    let src = "
+        call function_foo
+        call function_bar
+        exit
+        function_foo:
+        exit
+        function_bar:
+        exit";
+    let policy = VerifierPolicy {
+        max_instructions: Some(5),
+        max_functions: Some(3),
+        ..VerifierPolicy::default()
+    };
+    verify_policy(src, policy.clone()).unwrap();
+    let result = verify_policy(
+        src,
+        VerifierPolicy {
+            max_instructions: Some(4),
+            ..policy.clone()
+        },
+    );
+    assert_error!(result, "VerifierError(ProgramSizeExceeded(4))");
+    let result = verify_policy(
+        src,
+        VerifierPolicy {
+            max_functions: Some(2),
+            ..policy
+        },
+    );
+    assert_error!(result, "VerifierError(FunctionCountExceeded(4))");
+}
+
+#[test]
+fn test_policy_input_writes() {
+    let policy = VerifierPolicy {
+        forbid_input_writes: true,
+        ..VerifierPolicy::default()
+    };
+    verify_policy(
+        "
+        ldxdw r2, [r1+0]
+        stxdw [r10-8], r2
+        mov64 r0, 0
+        exit",
+        policy.clone(),
+    )
+    .unwrap();
+    let result = verify_policy(
+        "
+        mov64 r2, r1
+        add64 r2, 16
+        stw [r2+0], 1
+        mov64 r0, 0
+        exit",
+        policy.clone(),
+    );
+    assert_error!(result, "VerifierError(InputRegionWrite(2))");
+    // Numbers are allowed as addresses if they can not reach the input region
+    verify_policy(
+        "
+        lddw r2, 0x300000000
+        stw [r2+8], 1
+        mov64 r0, 0
+        exit",
+        policy.clone(),
+    )
+    .unwrap();
+    let result = verify_policy(
+        "
+        lddw r2, 0x3fffffff8
+        stw [r2+8], 1
+        mov64 r0, 0
+        exit",
+        policy.clone(),
+    );
+    assert_error!(result, "VerifierError(InputRegionWrite(2))");
+    // The return value of a function is unknown
+    let result = verify_policy(
+        "
+        call function_foo
+        stw [r0+0], 1
+        mov64 r0, 0
+        exit
+        function_foo:
+        mov64 r0, r10
+        exit",
+        policy.clone(),
+    );
+    assert_error!(result, "VerifierError(InputRegionWrite(1))");
+    // The input region can be placed elsewhere
+    let config = Config {
+        verifier_policy: policy,
+        address_space_layout: AddressSpaceLayout {
+            number_of_regions: 6,
+            first_input_region_index: 5,
+            ..AddressSpaceLayout::default()
+        },
+        ..Config::default()
+    };
+    verify_policy_with_config(
+        "
+        lddw r2, 0x4fffffff8
+        stw [r2+0], 1
+        mov64 r0, 0
+        exit",
+        config.clone(),
+    )
+    .unwrap();
+    let result = verify_policy_with_config(
+        "
+        lddw r2, 0x4fffffff8
+        stw [r2+8], 1
+        mov64 r0, 0
+        exit",
+        config,
+    );
+    assert_error!(result, "VerifierError(InputRegionWrite(2))");
+}
+
+#[test]
+fn test_policy_unbounded_loops() {
+    let policy = VerifierPolicy {
+        forbid_unbounded_loops: true,
+        ..VerifierPolicy::default()
+    };
+    verify_policy(
+        "
+        mov64 r0, 0
+        mov64 r2, 0
+        add64 r0, r2
+        add64 r2, 1
+        jlt r2, 10, -3
+        exit",
+        policy.clone(),
+    )
+    .unwrap();
+    let result = verify_policy(
+        "
+        mov64 r0, 0
+        ldxdw r2, [r1+0]
+        add64 r0, 1
+        jne r0, r2, -2
+        exit",
+        policy.clone(),
+    );
+    assert_error!(result, "VerifierError(UnboundedLoop(2))");
+    // Nested loops are accepted if every loop is bounded by a constant
+    verify_policy(
+        "
+        mov64 r0, 0
+        mov64 r2, 0
+        mov64 r3, 0
+        add64 r0, 1
+        add64 r3, 1
+        jlt r3, 10, -3
+        add64 r2, 1
+        jlt r2, 10, -6
+        exit",
+        policy.clone(),
+    )
+    .unwrap();
+    let result = verify_policy(
+        "
+        mov64 r0, 0
+        mov64 r2, 0
+        mov64 r3, 0
+        add64 r0, 1
+        add64 r3, 1
+        jlt r3, r0, -3
+        add64 r2, 1
+        jlt r2, 10, -6
+        exit",
+        policy,
+    );
+    assert_error!(result, "VerifierError(UnboundedLoop(3))");
+}
+
+#[test]
+fn test_policy_err_invalid_executable() {
+    // The syscall collides with the implicitly registered entrypoint
+    let mut loader = BuiltinProgram::<TestContextObject>::new_loader(Config {
+        verifier_policy: VerifierPolicy {
+            forbid_input_writes: true,
+            ..VerifierPolicy::default()
+        },
+        ..Config::default()
+    });
+    loader
+        .register_function("entrypoint", syscalls::SyscallString::vm)
+        .unwrap();
+    let result = PolicyVerifier::verify(
+        &[0x95, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        loader.get_config(),
+        SBPFVersion::V0,
+        &FunctionRegistry::default(),
+        loader.get_function_registry(),
+    );
+    assert!(matches!(
+        result,
+        Err(VerifierError::InvalidExecutable(
+            ElfError::SymbolHashCollision(_)
+        ))
+    ));
+}
+
+#[test]
+fn test_verified_facts() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r2, 3
+        stxdw [r10-8], r2
+        stxdw [r10-4100], r2
+        mov64 r0, 126
+        div64 r0, r2
+        mod64 r0, r1
+        lddw r4, 0x100000050
+        callx r4
+        exit
+        function_foo:
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config::default())),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let facts = VerifiedFacts::new(&analysis);
+    assert_eq!(facts.nonzero_divisors, BTreeSet::from([4]));
+    // The second store is below the frame
+    assert_eq!(facts.in_frame_stack_accesses, BTreeSet::from([1]));
+    assert_eq!(facts.callx_targets, BTreeMap::from([(8, 10)]));
+
+    // The first call clobbers r4, so the second one could enter any function in the middle
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r2, 3
+        stxdw [r10-8], r2
+        mov64 r0, 126
+        div64 r0, r2
+        lddw r4, 0x100000048
+        callx r4
+        callx r4
+        exit
+        function_foo:
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config::default())),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    assert_eq!(analysis.indirect_call_targets[&7], None);
+    assert_eq!(VerifiedFacts::new(&analysis), VerifiedFacts::default());
+}
@@ -1,9 +1,12 @@
 #![allow(clippy::literal_string_with_formatting_args)]
 
 use solana_sbpf::{
+    assembler::assemble,
     elf::Executable,
+    error::EbpfError,
+    memory_region::{AccessType, AddressSpaceLayout, MemoryRegion},
     program::BuiltinProgram,
-    vm::{Config, RuntimeEnvironmentSlot},
+    vm::{Backtrace, Config, RuntimeEnvironmentSlot},
 };
 use std::{fs::File, io::Read, sync::Arc};
 use test_utils::{create_vm, syscalls, TestContextObject};
@@ -51,6 +54,114 @@ fn test_runtime_environment_slots() {
     check_slot!(env, program_result, ProgramResult);
     check_slot!(env, memory_mapping, MemoryMapping);
     check_slot!(env, register_trace, RegisterTrace);
+    check_slot!(env, call_frames, CallFrames);
+}
+
+#[test]
+fn test_custom_address_space_layout() {
+    let layout = AddressSpaceLayout {
+        number_of_regions: 7,
+        stack_region_index: 5,
+        heap_region_index: 6,
+        first_input_region_index: 2,
+        number_of_input_regions: 3,
+    };
+    let config = Config {
+        address_space_layout: layout,
+        ..Config::default()
+    };
+    let loader = Arc::new(BuiltinProgram::new_loader(config));
+    #[allow(unused_mut)]
+    let mut executable = assemble::<TestContextObject>(
+        "
+        lddw r2, 0x300000000
+        ldxdw r0, [r1]
+        ldxdw r3, [r2]
+        add64 r0, r3
+        stxdw [r10-8], r0
+        ldxdw r0, [r10-8]
+        lddw r4, 0x400000000
+        stxdw [r4], r0
+        ldxdw r0, [r4]
+        exit",
+        loader,
+    )
+    .unwrap();
+    #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
+    executable.jit_compile().unwrap();
+    let interpreted_modes: &[bool] = if cfg!(all(
+        feature = "jit",
+        not(target_os = "windows"),
+        target_arch = "x86_64"
+    )) {
+        &[true, false]
+    } else {
+        &[true]
+    };
+    for interpreted in interpreted_modes.iter().copied() {
+        let mut input_a = 0x1122u64.to_le_bytes();
+        let mut input_b = 0x3300u64.to_le_bytes();
+        let mut input_c = [0u8; 8];
+        let regions = vec![
+            MemoryRegion::new_writable(&mut input_a, layout.input_start(0).unwrap()),
+            MemoryRegion::new_writable(&mut input_b, layout.input_start(1).unwrap()),
+            MemoryRegion::new_writable(&mut input_c, layout.input_start(2).unwrap()),
+        ];
+        let mut context_object = TestContextObject::new(10);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            regions,
+            None
+        );
+        assert_eq!(vm.registers[1], 2 << 32);
+        assert_eq!(
+            vm.registers[10],
+            layout.stack_start() + executable.get_config().stack_frame_size as u64
+        );
+        let (_instruction_count, result) = vm.execute_program(&executable, interpreted);
+        assert_eq!(result.unwrap(), 0x4422);
+        assert_eq!(u64::from_le_bytes(input_c), 0x4422);
+    }
+
+    // The heap is empty, accessing it names the right region
+    let mut context_object = TestContextObject::new(10);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    assert!(matches!(
+        vm.memory_mapping
+            .map(AccessType::Load, layout.heap_start(), 1)
+            .unwrap_err(),
+        EbpfError::AccessViolation(_, _, _, "heap")
+    ));
+
+    // Slots must not collide with each other or the fixed rodata and bytecode regions
+    let invalid_layout = AddressSpaceLayout {
+        heap_region_index: 1,
+        ..layout
+    };
+    assert!(matches!(
+        invalid_layout.validate(),
+        Err(EbpfError::InvalidMemoryRegion(1))
+    ));
+    let invalid_layout = AddressSpaceLayout {
+        number_of_input_regions: 4,
+        ..layout
+    };
+    assert!(matches!(
+        invalid_layout.validate(),
+        Err(EbpfError::InvalidMemoryRegion(5))
+    ));
 }
 
 #[test]
@@ -177,3 +288,161 @@ fn test_gdbstub_architecture() {
         }
     });
 }
+
+#[test]
+fn test_backtrace() {
+    let config = Config {
+        enable_symbol_and_section_labels: true,
+        enable_backtrace: true,
+        max_call_depth: 4,
+        ..Config::default()
+    };
+    let stack_frame_size = config.stack_frame_size as u64;
+    let loader = Arc::new(BuiltinProgram::new_loader(config));
+    #[allow(unused_mut)]
+    let mut executable = assemble::<TestContextObject>(
+        "
+        call function_outer
+        exit
+        function_outer:
+        mov64 r0, 0
+        call function_inner
+        exit
+        function_inner:
+        mov64 r1, -8
+        ldxdw r0, [r1]
+        exit",
+        loader.clone(),
+    )
+    .unwrap();
+    #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
+    executable.jit_compile().unwrap();
+    let interpreted_modes: &[bool] = if cfg!(all(
+        feature = "jit",
+        not(target_os = "windows"),
+        target_arch = "x86_64"
+    )) {
+        &[true, false]
+    } else {
+        &[true]
+    };
+    for interpreted in interpreted_modes.iter().copied() {
+        let mut context_object = TestContextObject::new(100);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            Vec::new(),
+            None
+        );
+        let frame_pointer = vm.registers[10];
+        let (_instruction_count, result, backtrace) =
+            vm.execute_program_with_backtrace(&executable, interpreted);
+        assert!(matches!(
+            result.unwrap_err(),
+            EbpfError::AccessViolation(..)
+        ));
+        let backtrace = backtrace.unwrap();
+        assert_eq!(
+            backtrace
+                .frames
+                .iter()
+                .map(|frame| (
+                    frame.pc,
+                    frame.frame_pointer,
+                    frame.function_name.as_deref(),
+                    frame.function_pc,
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                (6, None, Some("function_inner"), Some(5)),
+                (
+                    3,
+                    Some(frame_pointer + stack_frame_size),
+                    Some("function_outer"),
+                    Some(2)
+                ),
+                (0, Some(frame_pointer), Some("entrypoint"), Some(0)),
+            ],
+        );
+        assert_eq!(
+            backtrace.to_string(),
+            format!(
+                "#0 pc 6 in function_inner+1\n\
+                 #1 pc 3 in function_outer+1 (frame pointer {:#x})\n\
+                 #2 pc 0 in entrypoint+0 (frame pointer {:#x})\n",
+                frame_pointer + stack_frame_size,
+                frame_pointer,
+            ),
+        );
+    }
+
+    // The call which exceeds the call depth is not part of the backtrace
+    #[allow(unused_mut)]
+    let mut executable = assemble::<TestContextObject>(
+        "
+        call function_recursive
+        exit
+        function_recursive:
+        call function_recursive
+        exit",
+        loader,
+    )
+    .unwrap();
+    #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
+    executable.jit_compile().unwrap();
+    for interpreted in interpreted_modes.iter().copied() {
+        let mut context_object = TestContextObject::new(100);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            Vec::new(),
+            None
+        );
+        let (_instruction_count, result, backtrace) =
+            vm.execute_program_with_backtrace(&executable, interpreted);
+        assert!(matches!(result.unwrap_err(), EbpfError::CallDepthExceeded));
+        let Backtrace { frames } = backtrace.unwrap();
+        assert_eq!(
+            frames
+                .iter()
+                .map(|frame| (frame.pc, frame.function_name.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![
+                (2, Some("function_recursive")),
+                (2, Some("function_recursive")),
+                (2, Some("function_recursive")),
+                (0, Some("entrypoint")),
+            ],
+        );
+    }
+
+    // Without the config no backtrace is captured
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r1, -8
+        ldxdw r0, [r1]
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config::default())),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::new(100);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    let (_instruction_count, result, backtrace) =
+        vm.execute_program_with_backtrace(&executable, true);
+    assert!(result.is_err());
+    assert_eq!(backtrace, None);
+}